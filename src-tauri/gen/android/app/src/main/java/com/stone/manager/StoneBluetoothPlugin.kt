@@ -59,6 +59,13 @@ class StoneBluetoothPlugin(private val hostActivity: Activity) : Plugin(hostActi
     @Volatile var outcome: Int? = null,
   )
 
+  // `code` is the StoneError kind the Rust side rebuilds the error from.
+  private class StoneBluetoothException(
+    val code: String,
+    message: String,
+    cause: Throwable? = null,
+  ) : IOException(message, cause)
+
   private data class ScanSession(
     val results: ConcurrentHashMap<String, Map<String, Any?>> = ConcurrentHashMap(),
     val latch: CountDownLatch = CountDownLatch(1),
@@ -119,7 +126,7 @@ class StoneBluetoothPlugin(private val hostActivity: Activity) : Plugin(hostActi
         try {
           readyInvoke.resolveObject(buildBondedDeviceList())
         } catch (err: Exception) {
-          readyInvoke.reject(err.message ?: "Failed to list devices", err)
+          readyInvoke.reject(err.message ?: "Failed to list devices", errorCode(err), err)
         }
       }
     }
@@ -132,7 +139,7 @@ class StoneBluetoothPlugin(private val hostActivity: Activity) : Plugin(hostActi
         try {
           readyInvoke.resolveObject(scanUnpairedStoneDevicesBlocking())
         } catch (err: Exception) {
-          readyInvoke.reject(err.message ?: "Failed to scan devices", err)
+          readyInvoke.reject(err.message ?: "Failed to scan devices", errorCode(err), err)
         }
       }
     }
@@ -147,7 +154,7 @@ class StoneBluetoothPlugin(private val hostActivity: Activity) : Plugin(hostActi
   fun connectDevice(invoke: Invoke) {
     val address = normalizedAddress(invoke.getArgs().getString("address"))
     if (address.isEmpty()) {
-      invoke.reject("Invalid address")
+      invoke.reject("Invalid Bluetooth address", "invalid_address")
       return
     }
     ensureBluetoothReady(invoke, requireEnabledAdapter = true) { readyInvoke ->
@@ -156,7 +163,9 @@ class StoneBluetoothPlugin(private val hostActivity: Activity) : Plugin(hostActi
           connectDeviceBlocking(address)
           readyInvoke.resolve()
         } catch (err: Exception) {
-          readyInvoke.reject(err.message ?: "Connect failed", err)
+          // Socket errors ("read failed, socket might closed") are worth a retry.
+          val code = errorCode(err) ?: if (err is IOException) "connect_failed" else null
+          readyInvoke.reject(err.message ?: "Connect failed", code, err)
         }
       }
     }
@@ -166,7 +175,7 @@ class StoneBluetoothPlugin(private val hostActivity: Activity) : Plugin(hostActi
   fun disconnectDevice(invoke: Invoke) {
     val address = normalizedAddress(invoke.getArgs().getString("address"))
     if (address.isEmpty()) {
-      invoke.reject("Invalid address")
+      invoke.reject("Invalid Bluetooth address", "invalid_address")
       return
     }
     ioExecutor.execute {
@@ -174,7 +183,7 @@ class StoneBluetoothPlugin(private val hostActivity: Activity) : Plugin(hostActi
         closeSession(address, emitEvent = true)
         invoke.resolve()
       } catch (err: Exception) {
-        invoke.reject(err.message ?: "Disconnect failed", err)
+        invoke.reject(err.message ?: "Disconnect failed", errorCode(err), err)
       }
     }
   }
@@ -184,7 +193,7 @@ class StoneBluetoothPlugin(private val hostActivity: Activity) : Plugin(hostActi
     val args = invoke.getArgs()
     val address = normalizedAddress(args.getString("address"))
     if (address.isEmpty()) {
-      invoke.reject("Invalid address")
+      invoke.reject("Invalid Bluetooth address", "invalid_address")
       return
     }
     val payloadJson = args.optJSONArray("data")
@@ -198,7 +207,7 @@ class StoneBluetoothPlugin(private val hostActivity: Activity) : Plugin(hostActi
           readyInvoke.resolve()
         } catch (err: Exception) {
           closeSession(address, emitEvent = true)
-          readyInvoke.reject(err.message ?: "Write failed", err)
+          readyInvoke.reject(err.message ?: "Write failed", errorCode(err), err)
         }
       }
     }
@@ -208,7 +217,7 @@ class StoneBluetoothPlugin(private val hostActivity: Activity) : Plugin(hostActi
   fun onBluetoothPermissionsResult(invoke: Invoke) {
     if (!hasRequiredRuntimePermissions()) {
       pendingActions.remove(invoke.id)
-      invoke.reject("Bluetooth permission denied")
+      invoke.reject("Bluetooth permission denied", "permission_denied")
       return
     }
     resumePendingAction(invoke)
@@ -218,7 +227,7 @@ class StoneBluetoothPlugin(private val hostActivity: Activity) : Plugin(hostActi
   fun onBluetoothEnableResult(invoke: Invoke, result: ActivityResult) {
     if (result.resultCode != Activity.RESULT_OK || !isAdapterEnabled()) {
       pendingActions.remove(invoke.id)
-      invoke.reject("Bluetooth must be enabled")
+      invoke.reject("Bluetooth must be enabled", "bluetooth_disabled")
       return
     }
     resumePendingAction(invoke)
@@ -234,7 +243,7 @@ class StoneBluetoothPlugin(private val hostActivity: Activity) : Plugin(hostActi
     action: (Invoke) -> Unit,
   ) {
     if (getAdapter() == null) {
-      invoke.reject("Bluetooth adapter unavailable")
+      invoke.reject("Bluetooth adapter unavailable", "adapter_unavailable")
       return
     }
     pendingActions[invoke.id] = PendingInvokeAction(
@@ -399,7 +408,7 @@ class StoneBluetoothPlugin(private val hostActivity: Activity) : Plugin(hostActi
     val adapter = requireAdapter()
     val session = synchronized(scanLock) {
       if (pendingScan != null) {
-        throw IOException("Scan already in progress")
+        throw StoneBluetoothException("busy", "Scan already in progress")
       }
       ScanSession().also { pendingScan = it }
     }
@@ -452,7 +461,7 @@ class StoneBluetoothPlugin(private val hostActivity: Activity) : Plugin(hostActi
     val device = try {
       adapter.getRemoteDevice(address)
     } catch (err: IllegalArgumentException) {
-      throw IOException("Invalid Bluetooth address", err)
+      throw StoneBluetoothException("invalid_address", "Invalid Bluetooth address", err)
     }
 
     pendingConnects.add(address)
@@ -465,7 +474,11 @@ class StoneBluetoothPlugin(private val hostActivity: Activity) : Plugin(hostActi
         socket.connect()
       } catch (err: IOException) {
         closeQuietly(socket)
-        throw IOException("RFCOMM connection failed: ${err.message ?: "unknown error"}", err)
+        throw StoneBluetoothException(
+          "connect_failed",
+          "RFCOMM connection failed: ${err.message ?: "unknown error"}",
+          err
+        )
       }
 
       val session = sessions[address]
@@ -503,7 +516,7 @@ class StoneBluetoothPlugin(private val hostActivity: Activity) : Plugin(hostActi
     pendingBonds[address] = session
     if (!device.createBond()) {
       pendingBonds.remove(address)
-      throw IOException("Failed to start pairing")
+      throw StoneBluetoothException("pairing_failed", "Failed to start pairing")
     }
     waitForBond(address, session)
   }
@@ -513,10 +526,10 @@ class StoneBluetoothPlugin(private val hostActivity: Activity) : Plugin(hostActi
     val completed = session.latch.await(BOND_TIMEOUT_MS, TimeUnit.MILLISECONDS)
     pendingBonds.remove(address, session)
     if (!completed) {
-      throw IOException("Pairing timed out")
+      throw StoneBluetoothException("pairing_timeout", "Pairing timed out")
     }
     if (session.outcome != BluetoothDevice.BOND_BONDED) {
-      throw IOException("Pairing was cancelled")
+      throw StoneBluetoothException("pairing_cancelled", "Pairing was cancelled")
     }
   }
 
@@ -544,7 +557,7 @@ class StoneBluetoothPlugin(private val hostActivity: Activity) : Plugin(hostActi
     val session = sessions[address]
     val socket = session?.socket
     if (session?.rfcomm != true || socket == null || !socket.isConnected) {
-      throw IOException("Target device is not connected")
+      throw StoneBluetoothException("not_connected", "Target device is not connected")
     }
     socket.outputStream.write(data)
     socket.outputStream.flush()
@@ -607,13 +620,21 @@ class StoneBluetoothPlugin(private val hostActivity: Activity) : Plugin(hostActi
     }
   }
 
+  private fun errorCode(err: Exception): String? {
+    return when (err) {
+      is StoneBluetoothException -> err.code
+      is SecurityException -> "permission_denied"
+      else -> null
+    }
+  }
+
   private fun hasPermission(permission: String): Boolean {
     return hostActivity.checkSelfPermission(permission) == android.content.pm.PackageManager.PERMISSION_GRANTED
   }
 
   @Throws(IOException::class)
   private fun requireAdapter(): BluetoothAdapter {
-    return getAdapter() ?: throw IOException("Bluetooth adapter unavailable")
+    return getAdapter() ?: throw StoneBluetoothException("adapter_unavailable", "Bluetooth adapter unavailable")
   }
 
   private fun getAdapter(): BluetoothAdapter? {
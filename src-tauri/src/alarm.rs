@@ -0,0 +1,869 @@
+use chrono::{Datelike, Local, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::Emitter;
+use tokio::sync::Notify;
+
+use crate::backend::BackendState;
+use crate::cancel::CancelToken;
+use crate::connection::ConnectionPhase;
+use crate::cron::CronExpr;
+use crate::effects::gradient;
+use crate::error::StoneError;
+use crate::jobs::{now, JobList, JobStore};
+use crate::pt::{self, LampSettings, PtCommand, Rgb};
+use crate::transaction::DEFAULT_REQUEST_TIMEOUT_MS;
+use crate::{back_log, APP_HANDLE};
+
+const ALARMS_FILE: &str = "alarms.json";
+const ALARMS_VERSION: u32 = 1;
+// How often a ringing alarm moves each speaker along; a phase change moves it sooner.
+const TICK_MS: u64 = 1_000;
+const MIN_WINDOW_MS: u64 = 60_000;
+const MAX_WINDOW_MS: u64 = 2 * 60 * 60 * 1000;
+const MIN_SNOOZE_MS: u64 = 60_000;
+// An alarm nobody dismisses goes quiet on its own after ringing this long.
+const RING_MS: u64 = 30 * 60 * 1000;
+const WEEKDAYS: [&str; 7] = ["일", "월", "화", "수", "목", "금", "토"];
+
+// Deep red at first light, through orange and warm white to full white at the alarm time.
+const SUNRISE: [Rgb; 4] = [
+    Rgb::new(120, 10, 0),
+    Rgb::new(255, 80, 0),
+    Rgb::new(255, 170, 60),
+    Rgb::WHITE,
+];
+
+fn default_window_ms() -> u64 {
+    20 * 60 * 1000
+}
+
+fn default_volume() -> u8 {
+    15
+}
+
+fn default_brightness() -> u8 {
+    100
+}
+
+fn default_snooze_ms() -> u64 {
+    9 * 60 * 1000
+}
+
+/// When and where an alarm rings. The lamp and volume rise over `window_ms` before
+/// `hour:minute` on the given `weekdays` (0 is Sunday; none means every day). `target` is an
+/// address or a group name, resolved when the rise starts.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct AlarmSpec {
+    #[serde(default)]
+    pub name: Option<String>,
+    pub target: String,
+    pub hour: u8,
+    pub minute: u8,
+    #[serde(default)]
+    pub weekdays: Vec<u8>,
+    #[serde(default = "default_window_ms")]
+    pub window_ms: u64,
+    #[serde(default = "default_volume")]
+    pub volume: u8,
+    #[serde(default = "default_brightness")]
+    pub brightness: u8,
+    #[serde(default = "default_snooze_ms")]
+    pub snooze_ms: u64,
+}
+
+impl AlarmSpec {
+    fn validate(&self) -> Result<(), StoneError> {
+        if self.target.trim().is_empty() {
+            return Err(StoneError::InvalidAddress);
+        }
+        let invalid = |message: String| Err(StoneError::InvalidParameter { message });
+        if !(MIN_WINDOW_MS..=MAX_WINDOW_MS).contains(&self.window_ms) {
+            return invalid(format!(
+                "window_ms must be between {} and {}",
+                MIN_WINDOW_MS, MAX_WINDOW_MS
+            ));
+        }
+        if self.snooze_ms < MIN_SNOOZE_MS {
+            return invalid(format!("snooze_ms must be at least {}", MIN_SNOOZE_MS));
+        }
+        if self.brightness == 0 {
+            return invalid("brightness must be at least 1".to_string());
+        }
+        PtCommand::SetVolume(self.volume).validate()?;
+        PtCommand::SetLampBrightness(self.brightness).validate()?;
+        self.schedule().map(drop)
+    }
+
+    fn schedule(&self) -> Result<CronExpr, StoneError> {
+        let weekdays = if self.weekdays.is_empty() {
+            "*".to_string()
+        } else {
+            let days: Vec<String> = self.weekdays.iter().map(u8::to_string).collect();
+            days.join(",")
+        };
+        CronExpr::parse(&format!("{} {} * * {}", self.minute, self.hour, weekdays))
+    }
+
+    /// The first alarm time strictly after `after`.
+    fn ring_after(&self, after: u64) -> Option<u64> {
+        let after = Local.timestamp_millis_opt(after as i64).single()?;
+        let next = self.schedule().ok()?.next_after(after)?;
+        Some(next.timestamp_millis() as u64)
+    }
+
+    /// The next alarm time whose rise has not finished by `now`, so one already under way
+    /// still counts.
+    fn upcoming(&self, now: u64) -> Option<u64> {
+        self.ring_after(now.saturating_sub(self.window_ms))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct Alarm {
+    id: u64,
+    #[serde(flatten)]
+    spec: AlarmSpec,
+    enabled: bool,
+    next_ring_at: Option<u64>,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum AlarmPhase {
+    Rising,
+    Ringing,
+    Snoozed,
+}
+
+/// Emitted as `alarm_state_changed`, or `null` once the alarm is dismissed or has gone quiet.
+#[derive(Serialize, Clone, Debug)]
+pub(crate) struct AlarmStatus {
+    id: u64,
+    name: Option<String>,
+    addresses: Vec<String>,
+    phase: AlarmPhase,
+    ring_at: u64,
+    snoozed_until: Option<u64>,
+    ends_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct AlarmsFile {
+    next_id: u64,
+    alarms: Vec<Alarm>,
+}
+
+struct Session {
+    status: AlarmStatus,
+    spec: AlarmSpec,
+    stop: CancelToken,
+    // Wakes the speakers early when the phase changes, so a snooze is heard at once.
+    changed: Arc<Notify>,
+}
+
+#[derive(Default)]
+struct Alarms {
+    next_id: u64,
+    alarms: Vec<Alarm>,
+    session: Option<Session>,
+}
+
+impl JobList for Alarms {
+    type File = AlarmsFile;
+    const VERSION: u32 = ALARMS_VERSION;
+
+    fn saved(&self) -> AlarmsFile {
+        AlarmsFile {
+            next_id: self.next_id,
+            alarms: self.alarms.clone(),
+        }
+    }
+
+    fn restore(&mut self, file: AlarmsFile) {
+        self.next_id = file.next_id;
+        self.alarms = file.alarms;
+    }
+}
+
+/// Sunrise alarms, kept in `alarms.json` and announced as `alarms_changed`. Only one alarm
+/// rings at a time; a later one takes over the speakers of the one before.
+#[derive(Default)]
+pub(crate) struct AlarmClock {
+    alarms: JobStore<Alarms>,
+}
+
+pub(crate) fn path_in(data_dir: &Path) -> PathBuf {
+    data_dir.join(ALARMS_FILE)
+}
+
+fn alarm_not_found(id: u64) -> StoneError {
+    StoneError::InvalidParameter {
+        message: format!("No alarm {}", id),
+    }
+}
+
+fn clock_label(at: u64) -> String {
+    match Local.timestamp_millis_opt(at as i64).single() {
+        Some(time) => time.format("%H:%M").to_string(),
+        None => "--:--".to_string(),
+    }
+}
+
+impl AlarmClock {
+    /// Loads `path`, works out when every alarm rings next and starts the runner.
+    pub(crate) fn load(&self, path: PathBuf, backend: &BackendState) {
+        let now = now();
+        let source = path.display().to_string();
+        self.alarms.load(path, |list| {
+            for alarm in &mut list.alarms {
+                // A rise that was under way when the app closed picks up where it should be
+                // now, unless it had already been handed out before.
+                alarm.next_ring_at = match alarm.spec.upcoming(now) {
+                    _ if !alarm.enabled => None,
+                    Some(upcoming) => {
+                        Some(alarm.next_ring_at.map_or(upcoming, |at| at.max(upcoming)))
+                    }
+                    None => None,
+                };
+            }
+        });
+        back_log(
+            "RUST",
+            format!("Loaded {} alarms from {}", self.list().len(), source),
+        );
+        self.announce();
+        let backend = backend.clone();
+        self.alarms
+            .start(async move { crate::get_alarm_clock().run(backend).await });
+    }
+
+    pub(crate) fn list(&self) -> Vec<Alarm> {
+        self.alarms
+            .with(|list| list.alarms.clone())
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn status(&self) -> Option<AlarmStatus> {
+        self.alarms
+            .with(|list| list.session.as_ref().map(|session| session.status.clone()))
+            .flatten()
+    }
+
+    pub(crate) fn add(&self, spec: AlarmSpec) -> Result<Alarm, StoneError> {
+        spec.validate()?;
+        let alarm = self.update(|list| {
+            list.next_id += 1;
+            let alarm = Alarm {
+                id: list.next_id,
+                next_ring_at: spec.upcoming(now()),
+                spec,
+                enabled: true,
+            };
+            list.alarms.push(alarm.clone());
+            Ok(alarm)
+        })?;
+        back_log(
+            "RUST",
+            format!(
+                "Alarm {} at {:02}:{:02}",
+                alarm.id, alarm.spec.hour, alarm.spec.minute
+            ),
+        );
+        Ok(alarm)
+    }
+
+    /// Disabling also silences the alarm if it is ringing.
+    pub(crate) fn set_enabled(&self, id: u64, enabled: bool) -> Result<Alarm, StoneError> {
+        self.update(|list| {
+            let alarm = list
+                .alarms
+                .iter_mut()
+                .find(|alarm| alarm.id == id)
+                .ok_or_else(|| alarm_not_found(id))?;
+            alarm.enabled = enabled;
+            alarm.next_ring_at = if enabled {
+                alarm.spec.upcoming(now())
+            } else {
+                None
+            };
+            let alarm = alarm.clone();
+            if !enabled {
+                end_session(list, Some(id));
+            }
+            Ok(alarm)
+        })
+    }
+
+    pub(crate) fn remove(&self, id: u64) -> Result<Vec<Alarm>, StoneError> {
+        self.update(|list| {
+            let index = list
+                .alarms
+                .iter()
+                .position(|alarm| alarm.id == id)
+                .ok_or_else(|| alarm_not_found(id))?;
+            list.alarms.remove(index);
+            end_session(list, Some(id));
+            Ok(list.alarms.clone())
+        })
+    }
+
+    /// Mutes the ringing alarm for its snooze time; the lamp stays up.
+    pub(crate) fn snooze(&self) -> Result<AlarmStatus, StoneError> {
+        let status = self
+            .alarms
+            .with(|list| {
+                let session = list.session.as_mut()?;
+                let until = now() + session.spec.snooze_ms;
+                session.status.phase = AlarmPhase::Snoozed;
+                session.status.snoozed_until = Some(until);
+                session.status.ends_at = session.status.ends_at.max(until + RING_MS);
+                session.changed.notify_waiters();
+                Some(session.status.clone())
+            })
+            .flatten()
+            .ok_or_else(|| StoneError::InvalidParameter {
+                message: "No alarm is ringing".to_string(),
+            })?;
+        back_log(
+            "RUST",
+            format!(
+                "Alarm {} snoozed until {}",
+                status.id,
+                clock_label(status.snoozed_until.unwrap_or_default())
+            ),
+        );
+        self.announce_status();
+        self.alarms.wake();
+        Ok(status)
+    }
+
+    /// Ends the ringing alarm; lamps and volume stay where they are.
+    pub(crate) fn dismiss(&self) -> bool {
+        let ended = self
+            .alarms
+            .with(|list| end_session(list, None))
+            .unwrap_or(false);
+        if ended {
+            back_log("RUST", "Alarm dismissed".to_string());
+            self.announce_status();
+        }
+        ended
+    }
+
+    fn update<T>(
+        &self,
+        change: impl FnOnce(&mut Alarms) -> Result<T, StoneError>,
+    ) -> Result<T, StoneError> {
+        let result = self.alarms.update(change)?;
+        self.announce();
+        self.announce_status();
+        Ok(result)
+    }
+
+    fn announce(&self) {
+        if let Some(app) = APP_HANDLE.get() {
+            let _ = app.emit("alarms_changed", self.list());
+        }
+    }
+
+    fn announce_status(&self) {
+        let status = self.status();
+        crate::update_tray_alarm(self.tray_label(), status.is_some());
+        if let Some(app) = APP_HANDLE.get() {
+            let _ = app.emit("alarm_state_changed", status);
+        }
+    }
+
+    fn tray_label(&self) -> String {
+        self.alarms
+            .with(|list| tray_label(list))
+            .unwrap_or_else(|| "알람: --".to_string())
+    }
+
+    /// Hands out every alarm whose rise has begun by `now` and moves it to its next time.
+    /// Alarms that are already past ringing, say after a suspend, are skipped.
+    fn take_due(&self, now: u64) -> Vec<(Alarm, u64)> {
+        let is_due = |alarm: &Alarm| {
+            alarm.enabled
+                && alarm
+                    .next_ring_at
+                    .is_some_and(|at| at.saturating_sub(alarm.spec.window_ms) <= now)
+        };
+        let due = self.alarms.take_due(
+            |list| list.alarms.iter().any(is_due),
+            |list| {
+                let mut due = Vec::new();
+                for alarm in list.alarms.iter_mut().filter(|alarm| is_due(alarm)) {
+                    let Some(ring_at) = alarm.next_ring_at else {
+                        continue;
+                    };
+                    alarm.next_ring_at = alarm.spec.ring_after(ring_at.max(now));
+                    if ring_at + RING_MS > now {
+                        due.push((alarm.clone(), ring_at));
+                    }
+                }
+                due
+            },
+        );
+        let Some(due) = due else {
+            return Vec::new();
+        };
+        self.announce();
+        self.announce_status();
+        due
+    }
+
+    fn advance(&self, now: u64) {
+        if self.alarms.with(|list| advance(list, now)) == Some(true) {
+            self.announce_status();
+        }
+    }
+
+    fn next_due(list: &Alarms) -> Option<u64> {
+        let rises = list
+            .alarms
+            .iter()
+            .filter_map(|alarm| Some(alarm.next_ring_at?.saturating_sub(alarm.spec.window_ms)));
+        let session = list.session.as_ref().map(|session| {
+            let status = &session.status;
+            match status.phase {
+                AlarmPhase::Rising => status.ring_at,
+                AlarmPhase::Snoozed => status.snoozed_until.unwrap_or(status.ends_at),
+                AlarmPhase::Ringing => status.ends_at,
+            }
+        });
+        rises.chain(session).min()
+    }
+
+    fn ring(&self, alarm: Alarm, ring_at: u64, backend: &BackendState) {
+        let addresses = crate::get_registry().resolve(&alarm.spec.target);
+        if addresses.is_empty() {
+            back_log(
+                "RUST",
+                format!("Alarm {} has no devices in {}", alarm.id, alarm.spec.target),
+            );
+            return;
+        }
+        let stop = CancelToken::new();
+        let changed = Arc::new(Notify::new());
+        let now = now();
+        let status = AlarmStatus {
+            id: alarm.id,
+            name: alarm.spec.name.clone(),
+            addresses: addresses.clone(),
+            phase: if now < ring_at {
+                AlarmPhase::Rising
+            } else {
+                AlarmPhase::Ringing
+            },
+            ring_at,
+            snoozed_until: None,
+            ends_at: ring_at + RING_MS,
+        };
+        let session = Session {
+            status,
+            spec: alarm.spec.clone(),
+            stop: stop.clone(),
+            changed: changed.clone(),
+        };
+        let started = self.alarms.with(|list| {
+            end_session(list, None);
+            list.session = Some(session);
+        });
+        if started.is_none() {
+            return;
+        }
+        // A running effect would paint over the sunrise on its next frame.
+        crate::get_lamp_effects().release(&addresses);
+        back_log(
+            "RUST",
+            format!(
+                "Alarm {} rising on {} devices until {}",
+                alarm.id,
+                addresses.len(),
+                clock_label(ring_at)
+            ),
+        );
+        self.announce_status();
+        for address in addresses {
+            tauri::async_runtime::spawn(sunrise_device(
+                backend.clone(),
+                address,
+                stop.clone(),
+                changed.clone(),
+            ));
+        }
+    }
+
+    /// The phase and settings of the session `stop` belongs to, while it is still the current one.
+    fn session_of(&self, stop: &CancelToken) -> Option<(AlarmStatus, AlarmSpec)> {
+        self.alarms
+            .with(|list| {
+                let session = list.session.as_ref()?;
+                session
+                    .stop
+                    .is_same(stop)
+                    .then(|| (session.status.clone(), session.spec.clone()))
+            })
+            .flatten()
+    }
+
+    async fn run(&self, backend: BackendState) {
+        let tick = |now| {
+            for (alarm, ring_at) in self.take_due(now) {
+                self.ring(alarm, ring_at, &backend);
+            }
+            self.advance(crate::jobs::now());
+        };
+        self.alarms.run(tick, Self::next_due).await;
+    }
+}
+
+fn tray_label(list: &Alarms) -> String {
+    if let Some(session) = &list.session {
+        return match session.status.phase {
+            AlarmPhase::Rising => {
+                format!("알람: {} 준비 중", clock_label(session.status.ring_at))
+            }
+            AlarmPhase::Ringing => "알람 울리는 중".to_string(),
+            AlarmPhase::Snoozed => format!(
+                "다시 알림: {}",
+                clock_label(session.status.snoozed_until.unwrap_or_default())
+            ),
+        };
+    }
+    let next = list
+        .alarms
+        .iter()
+        .filter_map(|alarm| alarm.next_ring_at)
+        .min()
+        .and_then(|at| Local.timestamp_millis_opt(at as i64).single());
+    match next {
+        Some(time) => format!(
+            "다음 알람: {} {}",
+            WEEKDAYS[time.weekday().num_days_from_sunday() as usize],
+            time.format("%H:%M")
+        ),
+        None => "알람: 없음".to_string(),
+    }
+}
+
+/// Moves the ringing alarm on to its next phase, or ends it once it has rung out.
+fn advance(list: &mut Alarms, now: u64) -> bool {
+    let Some(session) = list.session.as_mut() else {
+        return false;
+    };
+    if now >= session.status.ends_at {
+        back_log("RUST", format!("Alarm {} went quiet", session.status.id));
+        return end_session(list, None);
+    }
+    let status = &mut session.status;
+    let phase = if status.snoozed_until.is_some_and(|until| now < until) {
+        AlarmPhase::Snoozed
+    } else if now < status.ring_at {
+        AlarmPhase::Rising
+    } else {
+        status.snoozed_until = None;
+        AlarmPhase::Ringing
+    };
+    if phase == status.phase {
+        return false;
+    }
+    status.phase = phase;
+    session.changed.notify_waiters();
+    true
+}
+
+// Ends the current session, or only the one of alarm `id`.
+fn end_session(list: &mut Alarms, id: Option<u64>) -> bool {
+    let ours = list
+        .session
+        .as_ref()
+        .is_some_and(|session| id.is_none_or(|id| session.status.id == id));
+    if !ours {
+        return false;
+    }
+    if let Some(session) = list.session.take() {
+        session.stop.cancel();
+    }
+    true
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Level {
+    color: Rgb,
+    brightness: u8,
+    volume: u8,
+}
+
+fn level(status: &AlarmStatus, spec: &AlarmSpec, now: u64) -> Level {
+    let full = Level {
+        color: Rgb::WHITE,
+        brightness: spec.brightness,
+        volume: spec.volume,
+    };
+    match status.phase {
+        AlarmPhase::Rising => {
+            let left = status.ring_at.saturating_sub(now) as f64;
+            let progress = (1.0 - left / spec.window_ms as f64).clamp(0.0, 1.0);
+            Level {
+                color: gradient(&SUNRISE, progress),
+                brightness: ((spec.brightness as f64 * progress).round() as u8).max(1),
+                volume: (spec.volume as f64 * progress).round() as u8,
+            }
+        }
+        AlarmPhase::Ringing => full,
+        AlarmPhase::Snoozed => Level { volume: 0, ..full },
+    }
+}
+
+/// Keeps one speaker on the alarm's current level. The lamp is started once with 0x0212 and
+/// then only moved with color, brightness and volume writes; a speaker that drops out is
+/// started again when it is back.
+async fn sunrise_device(
+    backend: BackendState,
+    address: String,
+    stop: CancelToken,
+    changed: Arc<Notify>,
+) {
+    let mut lamp_running = false;
+    let mut sent: Option<Level> = None;
+    let tick = Duration::from_millis(TICK_MS);
+    loop {
+        let Some((status, spec)) = crate::get_alarm_clock().session_of(&stop) else {
+            return;
+        };
+        if crate::get_connections().phase(&address) != ConnectionPhase::Connected {
+            lamp_running = false;
+            sent = None;
+        } else {
+            let next = level(&status, &spec, now());
+            let mut steps = vec![];
+            if !lamp_running {
+                steps.push(PtCommand::RunLamp(LampSettings {
+                    brightness: next.brightness,
+                    lamp_type: pt::SOLID_LAMP_TYPE,
+                    color: next.color,
+                }));
+            }
+            if sent.is_none_or(|sent| sent.color != next.color) {
+                steps.push(PtCommand::SetLampColor(next.color));
+            }
+            if lamp_running && sent.is_none_or(|sent| sent.brightness != next.brightness) {
+                steps.push(PtCommand::SetLampBrightness(next.brightness));
+            }
+            if sent.is_none_or(|sent| sent.volume != next.volume) {
+                steps.push(PtCommand::SetVolume(next.volume));
+            }
+            let mut all_sent = true;
+            for command in steps {
+                let send = pt::send(&*backend, &address, command, DEFAULT_REQUEST_TIMEOUT_MS);
+                let Ok(result) = stop.run(send).await else {
+                    return;
+                };
+                match (result, command) {
+                    (Ok(_), PtCommand::RunLamp(_)) => lamp_running = true,
+                    (Ok(_), _) => {}
+                    (Err(_), _) => all_sent = false,
+                }
+            }
+            // Anything that failed is sent again on the next tick.
+            sent = (lamp_running && all_sent).then_some(next);
+        }
+        let wait = tokio::time::timeout(tick, changed.notified());
+        if stop.run(wait).await.is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::tests::{backend, StubBackend};
+    use crate::persist;
+    use chrono::Timelike;
+
+    const MINUTE_MS: u64 = 60_000;
+
+    fn spec(hour: u8, minute: u8) -> AlarmSpec {
+        AlarmSpec {
+            name: None,
+            target: "00:00:5E:00:53:B0".to_string(),
+            hour,
+            minute,
+            weekdays: vec![],
+            window_ms: default_window_ms(),
+            volume: default_volume(),
+            brightness: default_brightness(),
+            snooze_ms: default_snooze_ms(),
+        }
+    }
+
+    fn alarm(id: u64, spec: AlarmSpec, next_ring_at: Option<u64>) -> Alarm {
+        Alarm {
+            id,
+            spec,
+            enabled: true,
+            next_ring_at,
+        }
+    }
+
+    fn status(phase: AlarmPhase, ring_at: u64) -> AlarmStatus {
+        AlarmStatus {
+            id: 1,
+            name: None,
+            addresses: vec!["00:00:5E:00:53:B0".to_string()],
+            phase,
+            ring_at,
+            snoozed_until: None,
+            ends_at: ring_at + RING_MS,
+        }
+    }
+
+    #[test]
+    fn level_rises_with_the_window_and_holds_once_ringing() {
+        let spec = spec(7, 0);
+        let ring_at = 10 * spec.window_ms;
+        let rising = status(AlarmPhase::Rising, ring_at);
+
+        let first = level(&rising, &spec, ring_at - spec.window_ms);
+        assert_eq!(first.color, SUNRISE[0]);
+        assert_eq!((first.brightness, first.volume), (1, 0));
+        // Too early still starts from first light rather than below it.
+        assert_eq!(level(&rising, &spec, 0), first);
+
+        let half = level(&rising, &spec, ring_at - spec.window_ms / 2);
+        assert_eq!(half.color, gradient(&SUNRISE, 0.5));
+        assert_eq!((half.brightness, half.volume), (50, 8));
+
+        let full = Level {
+            color: Rgb::WHITE,
+            brightness: spec.brightness,
+            volume: spec.volume,
+        };
+        assert_eq!(level(&rising, &spec, ring_at), full);
+        assert_eq!(level(&status(AlarmPhase::Ringing, ring_at), &spec, 0), full);
+        assert_eq!(
+            level(&status(AlarmPhase::Snoozed, ring_at), &spec, ring_at),
+            Level { volume: 0, ..full }
+        );
+    }
+
+    #[test]
+    fn take_due_skips_alarms_that_have_already_rung_out() {
+        let spec = spec(7, 0);
+        let ring_at = spec.ring_after(now()).unwrap();
+        assert_eq!(spec.upcoming(ring_at - spec.window_ms / 2), Some(ring_at));
+
+        let clock = AlarmClock::default();
+        clock.alarms.with(|list| {
+            list.alarms = vec![
+                alarm(1, spec.clone(), Some(ring_at)),
+                alarm(2, spec.clone(), Some(ring_at)),
+            ];
+            list.alarms[1].enabled = false;
+        });
+        assert!(clock.take_due(ring_at - spec.window_ms - 1).is_empty());
+
+        // Halfway through the rise the alarm is handed out once and moved on.
+        let due = clock.take_due(ring_at - spec.window_ms / 2);
+        let due: Vec<(u64, u64)> = due.iter().map(|(alarm, at)| (alarm.id, *at)).collect();
+        assert_eq!(due, [(1, ring_at)]);
+        let next = clock.list()[0].next_ring_at.unwrap();
+        assert!(next > ring_at);
+        assert!(clock.take_due(ring_at).is_empty());
+
+        // Still in time just before it would have gone quiet.
+        clock
+            .alarms
+            .with(|list| list.alarms[0].next_ring_at = Some(ring_at));
+        assert_eq!(clock.take_due(ring_at + RING_MS - 1).len(), 1);
+
+        // Past ringing, say after a suspend, it is only moved on.
+        clock
+            .alarms
+            .with(|list| list.alarms[0].next_ring_at = Some(ring_at));
+        assert!(clock.take_due(ring_at + RING_MS).is_empty());
+        assert!(clock.list()[0]
+            .next_ring_at
+            .is_some_and(|at| at > ring_at + RING_MS));
+    }
+
+    #[test]
+    fn advance_walks_the_phases_until_the_alarm_goes_quiet() {
+        let ring_at = 100 * MINUTE_MS;
+        let stop = CancelToken::new();
+        let mut list = Alarms {
+            session: Some(Session {
+                status: status(AlarmPhase::Rising, ring_at),
+                spec: spec(7, 0),
+                stop: stop.clone(),
+                changed: Arc::new(Notify::new()),
+            }),
+            ..Alarms::default()
+        };
+        let phase = |list: &Alarms| list.session.as_ref().map(|session| session.status.phase);
+
+        assert!(!advance(&mut list, ring_at - 1));
+        assert!(advance(&mut list, ring_at));
+        assert_eq!(phase(&list), Some(AlarmPhase::Ringing));
+
+        // What `snooze` leaves behind.
+        if let Some(session) = list.session.as_mut() {
+            session.status.phase = AlarmPhase::Snoozed;
+            session.status.snoozed_until = Some(ring_at + 9 * MINUTE_MS);
+        }
+        assert!(!advance(&mut list, ring_at + MINUTE_MS));
+        assert_eq!(AlarmClock::next_due(&list), Some(ring_at + 9 * MINUTE_MS));
+        assert!(advance(&mut list, ring_at + 9 * MINUTE_MS));
+        assert_eq!(phase(&list), Some(AlarmPhase::Ringing));
+        assert_eq!(list.session.as_ref().unwrap().status.snoozed_until, None);
+        assert_eq!(AlarmClock::next_due(&list), Some(ring_at + RING_MS));
+
+        assert!(advance(&mut list, ring_at + RING_MS));
+        assert!(list.session.is_none());
+        assert!(stop.is_cancelled());
+        assert!(!advance(&mut list, ring_at + RING_MS));
+    }
+
+    #[test]
+    fn load_does_not_hand_out_an_alarm_that_already_fired() {
+        let dir = std::env::temp_dir().join(format!("stone-alarms-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let path = path_in(&dir);
+        // An alarm a few minutes from now is halfway through its rise.
+        let soon = Local::now() + chrono::Duration::minutes(5);
+        let spec = spec(soon.hour() as u8, soon.minute() as u8);
+        let ring_at = spec.upcoming(now()).unwrap();
+        let mut disabled = alarm(3, spec.clone(), Some(ring_at));
+        disabled.enabled = false;
+        let file = AlarmsFile {
+            next_id: 3,
+            alarms: vec![
+                alarm(1, spec.clone(), spec.ring_after(ring_at)),
+                alarm(2, spec.clone(), None),
+                disabled,
+            ],
+        };
+        persist::save(&path, ALARMS_VERSION, &file).unwrap();
+
+        let clock = AlarmClock::default();
+        // Keeps the runner from starting.
+        clock.alarms.start(async {});
+        clock.load(path, &backend(&Arc::new(StubBackend::default())));
+        let next: Vec<Option<u64>> = clock
+            .list()
+            .iter()
+            .map(|alarm| alarm.next_ring_at)
+            .collect();
+        assert_eq!(next, [spec.ring_after(ring_at), Some(ring_at), None]);
+
+        let due = clock.take_due(now());
+        let ids: Vec<u64> = due.iter().map(|(alarm, _)| alarm.id).collect();
+        assert_eq!(ids, [2]);
+    }
+}
@@ -1,9 +1,13 @@
+use async_trait::async_trait;
 use jni::objects::{JByteArray, JObject, JString};
 use jni::JNIEnv;
 use serde::Serialize;
+use tauri::plugin::mobile::PluginInvokeError;
 use tauri::plugin::{Builder, TauriPlugin};
 use tauri::{AppHandle, Manager, Runtime, Wry};
 
+use crate::backend::BluetoothBackend;
+use crate::error::StoneError;
 use crate::{emit_backend_device_event, handle_backend_data, BluetoothDeviceInfo, ConnectionInfo};
 
 pub struct AndroidBluetoothPlugin<R: Runtime>(pub tauri::plugin::PluginHandle<R>);
@@ -33,50 +37,64 @@ pub fn init<R: Runtime>() -> TauriPlugin<R> {
         .build()
 }
 
-fn plugin_handle(app: &AppHandle) -> Result<tauri::plugin::PluginHandle<Wry>, String> {
+/// The Kotlin plugin rejects with a `StoneError` kind as the code.
+fn plugin_error(err: PluginInvokeError) -> StoneError {
+    match err {
+        PluginInvokeError::InvokeRejected(response) => {
+            let message = response.message.unwrap_or_default();
+            match response.code {
+                Some(code) => StoneError::from_code(&code, message),
+                None => StoneError::Other { message },
+            }
+        }
+        other => StoneError::other(other),
+    }
+}
+
+fn plugin_handle(app: &AppHandle) -> Result<tauri::plugin::PluginHandle<Wry>, StoneError> {
     app.try_state::<AndroidBluetoothPlugin<Wry>>()
         .map(|state| state.0.clone())
-        .ok_or_else(|| "Android Bluetooth plugin is not initialized".to_string())
+        .ok_or_else(|| StoneError::other("Android Bluetooth plugin is not initialized"))
 }
 
-pub async fn list_devices(app: &AppHandle) -> Result<Vec<BluetoothDeviceInfo>, String> {
+async fn list_devices(app: &AppHandle) -> Result<Vec<BluetoothDeviceInfo>, StoneError> {
     plugin_handle(app)?
         .run_mobile_plugin_async("listDevices", EmptyPayload {})
         .await
-        .map_err(|err| err.to_string())
+        .map_err(plugin_error)
 }
 
-pub async fn scan_unpaired_stone_devices(
+async fn scan_unpaired_stone_devices(
     app: &AppHandle,
-) -> Result<Vec<BluetoothDeviceInfo>, String> {
+) -> Result<Vec<BluetoothDeviceInfo>, StoneError> {
     plugin_handle(app)?
         .run_mobile_plugin_async("scanUnpairedStoneDevices", EmptyPayload {})
         .await
-        .map_err(|err| err.to_string())
+        .map_err(plugin_error)
 }
 
-pub async fn get_connection_infos(app: &AppHandle) -> Result<Vec<ConnectionInfo>, String> {
+async fn get_connection_infos(app: &AppHandle) -> Result<Vec<ConnectionInfo>, StoneError> {
     plugin_handle(app)?
         .run_mobile_plugin_async("getConnectionInfos", EmptyPayload {})
         .await
-        .map_err(|err| err.to_string())
+        .map_err(plugin_error)
 }
 
-pub async fn connect_device(app: &AppHandle, address: &str) -> Result<(), String> {
+async fn connect_device(app: &AppHandle, address: &str) -> Result<(), StoneError> {
     plugin_handle(app)?
         .run_mobile_plugin_async("connectDevice", AddressPayload { address })
         .await
-        .map_err(|err| err.to_string())
+        .map_err(plugin_error)
 }
 
-pub async fn disconnect_device(app: &AppHandle, address: &str) -> Result<(), String> {
+async fn disconnect_device(app: &AppHandle, address: &str) -> Result<(), StoneError> {
     plugin_handle(app)?
         .run_mobile_plugin_async("disconnectDevice", AddressPayload { address })
         .await
-        .map_err(|err| err.to_string())
+        .map_err(plugin_error)
 }
 
-pub async fn send_gaia_command(app: &AppHandle, address: &str, frame: &[u8]) -> Result<(), String> {
+async fn send_gaia_command(app: &AppHandle, address: &str, frame: &[u8]) -> Result<(), StoneError> {
     plugin_handle(app)?
         .run_mobile_plugin_async(
             "sendGaiaCommand",
@@ -86,7 +104,48 @@ pub async fn send_gaia_command(app: &AppHandle, address: &str, frame: &[u8]) ->
             },
         )
         .await
-        .map_err(|err| err.to_string())
+        .map_err(plugin_error)
+}
+
+pub(crate) struct AndroidBackend {
+    app: AppHandle,
+}
+
+impl AndroidBackend {
+    pub(crate) fn new(app: AppHandle) -> Self {
+        Self { app }
+    }
+}
+
+#[async_trait]
+impl BluetoothBackend for AndroidBackend {
+    fn name(&self) -> &'static str {
+        "android"
+    }
+
+    async fn list_devices(&self) -> Result<Vec<BluetoothDeviceInfo>, StoneError> {
+        list_devices(&self.app).await
+    }
+
+    async fn scan_unpaired_stone_devices(&self) -> Result<Vec<BluetoothDeviceInfo>, StoneError> {
+        scan_unpaired_stone_devices(&self.app).await
+    }
+
+    async fn get_connection_infos(&self) -> Result<Vec<ConnectionInfo>, StoneError> {
+        get_connection_infos(&self.app).await
+    }
+
+    async fn connect_device(&self, address: &str) -> Result<(), StoneError> {
+        connect_device(&self.app, address).await
+    }
+
+    async fn disconnect_device(&self, address: &str) -> Result<(), StoneError> {
+        disconnect_device(&self.app, address).await
+    }
+
+    async fn write_frame(&self, address: &str, frame: &[u8]) -> Result<(), StoneError> {
+        send_gaia_command(&self.app, address, frame).await
+    }
 }
 
 #[no_mangle]
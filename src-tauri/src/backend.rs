@@ -0,0 +1,124 @@
+use async_trait::async_trait;
+use std::sync::Arc;
+use tauri::AppHandle;
+
+use crate::error::StoneError;
+use crate::{BluetoothDeviceInfo, ConnectionInfo};
+
+/// Platform transport for STONE speakers. Inbound bytes and link changes are reported through
+/// `handle_backend_data` and `emit_backend_device_event` rather than through this trait.
+#[async_trait]
+pub(crate) trait BluetoothBackend: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    async fn list_devices(&self) -> Result<Vec<BluetoothDeviceInfo>, StoneError>;
+
+    async fn scan_unpaired_stone_devices(&self) -> Result<Vec<BluetoothDeviceInfo>, StoneError>;
+
+    async fn get_connection_infos(&self) -> Result<Vec<ConnectionInfo>, StoneError>;
+
+    async fn connect_device(&self, address: &str) -> Result<(), StoneError>;
+
+    async fn disconnect_device(&self, address: &str) -> Result<(), StoneError>;
+
+    async fn write_frame(&self, address: &str, frame: &[u8]) -> Result<(), StoneError>;
+}
+
+#[derive(Clone)]
+pub(crate) struct BackendState(pub Arc<dyn BluetoothBackend>);
+
+impl std::ops::Deref for BackendState {
+    type Target = dyn BluetoothBackend;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref()
+    }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "android", target_os = "linux")))]
+pub(crate) struct UnsupportedBackend;
+
+#[cfg(not(any(target_os = "macos", target_os = "android", target_os = "linux")))]
+fn not_supported() -> StoneError {
+    StoneError::NotSupported {
+        message: "Not supported on this platform".to_string(),
+    }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "android", target_os = "linux")))]
+#[async_trait]
+impl BluetoothBackend for UnsupportedBackend {
+    fn name(&self) -> &'static str {
+        "unsupported"
+    }
+
+    async fn list_devices(&self) -> Result<Vec<BluetoothDeviceInfo>, StoneError> {
+        Err(not_supported())
+    }
+
+    async fn scan_unpaired_stone_devices(&self) -> Result<Vec<BluetoothDeviceInfo>, StoneError> {
+        Err(not_supported())
+    }
+
+    async fn get_connection_infos(&self) -> Result<Vec<ConnectionInfo>, StoneError> {
+        Err(not_supported())
+    }
+
+    async fn connect_device(&self, _address: &str) -> Result<(), StoneError> {
+        Err(not_supported())
+    }
+
+    async fn disconnect_device(&self, _address: &str) -> Result<(), StoneError> {
+        Err(not_supported())
+    }
+
+    async fn write_frame(&self, _address: &str, _frame: &[u8]) -> Result<(), StoneError> {
+        Err(not_supported())
+    }
+}
+
+#[cfg(not(target_os = "android"))]
+fn platform_backend() -> Arc<dyn BluetoothBackend> {
+    #[cfg(target_os = "macos")]
+    let backend: Arc<dyn BluetoothBackend> = Arc::new(crate::macos_backend::MacosBackend);
+
+    #[cfg(target_os = "linux")]
+    let backend: Arc<dyn BluetoothBackend> = Arc::new(crate::linux_backend::LinuxBackend::new());
+
+    #[cfg(not(any(target_os = "macos", target_os = "linux")))]
+    let backend: Arc<dyn BluetoothBackend> = Arc::new(UnsupportedBackend);
+
+    backend
+}
+
+pub(crate) fn select(_app: &AppHandle) -> BackendState {
+    if crate::simulator::is_requested() {
+        return BackendState(Arc::new(crate::simulator::SimulatorBackend::new()));
+    }
+
+    #[cfg(target_os = "android")]
+    let backend: Arc<dyn BluetoothBackend> =
+        Arc::new(crate::android_backend::AndroidBackend::new(_app.clone()));
+
+    #[cfg(not(target_os = "android"))]
+    let backend = platform_backend();
+
+    BackendState(backend)
+}
+
+/// Backend for running without a Tauri app, as `stone-cli` does.
+pub(crate) fn select_headless() -> Result<BackendState, StoneError> {
+    if crate::simulator::is_requested() {
+        return Ok(BackendState(Arc::new(
+            crate::simulator::SimulatorBackend::new(),
+        )));
+    }
+
+    #[cfg(target_os = "android")]
+    return Err(StoneError::NotSupported {
+        message: "Headless mode is not supported on Android".to_string(),
+    });
+
+    #[cfg(not(target_os = "android"))]
+    Ok(BackendState(platform_backend()))
+}
@@ -0,0 +1,3 @@
+fn main() {
+    std::process::exit(stone_manager_lib::run_cli());
+}
@@ -0,0 +1,177 @@
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+use crate::backend::BackendState;
+use crate::connection::ConnectionPhase;
+use crate::error::StoneError;
+use crate::{back_log, request_gaia};
+
+/// Who a broadcast goes to: a group name, or addresses (which may name groups themselves).
+#[derive(Deserialize, Clone, Debug)]
+#[serde(untagged)]
+pub(crate) enum BroadcastTarget {
+    Group(String),
+    Addresses(Vec<String>),
+}
+
+impl BroadcastTarget {
+    /// Expands groups and drops repeats, keeping the first spelling of each address.
+    pub(crate) fn addresses(&self) -> Vec<String> {
+        let registry = crate::get_registry();
+        let targets: Vec<String> = match self {
+            Self::Group(name) => registry.resolve(name),
+            Self::Addresses(addresses) => addresses
+                .iter()
+                .flat_map(|address| registry.resolve(address))
+                .collect(),
+        };
+        let mut unique: Vec<String> = Vec::with_capacity(targets.len());
+        for address in targets {
+            if !address.is_empty()
+                && !unique
+                    .iter()
+                    .any(|existing| existing.eq_ignore_ascii_case(&address))
+            {
+                unique.push(address);
+            }
+        }
+        unique
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub(crate) struct BroadcastOutcome {
+    pub address: String,
+    pub ok: bool,
+    // False when the target was not connected and nothing was written.
+    pub sent: bool,
+    pub status: Option<u8>,
+    pub payload: Option<Vec<u8>>,
+    pub latency_ms: Option<u64>,
+    pub error: Option<StoneError>,
+}
+
+impl BroadcastOutcome {
+    fn failed(address: String, sent: bool, error: StoneError) -> Self {
+        Self {
+            address,
+            ok: false,
+            sent,
+            status: None,
+            payload: None,
+            latency_ms: None,
+            error: Some(error),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub(crate) struct BroadcastReport {
+    pub vendor_id: u16,
+    pub command_id: u16,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub results: Vec<BroadcastOutcome>,
+}
+
+/// Sends one GAIA command to every connected target at once and waits for all of the ACKs.
+/// Targets that are not connected are reported without being written to.
+pub(crate) async fn send(
+    backend: &BackendState,
+    target: &BroadcastTarget,
+    vendor_id: u16,
+    command_id: u16,
+    payload: &[u8],
+    timeout_ms: u64,
+) -> Result<BroadcastReport, StoneError> {
+    let addresses = target.addresses();
+    if addresses.is_empty() {
+        return Err(StoneError::InvalidParameter {
+            message: "Broadcast has no targets".to_string(),
+        });
+    }
+    back_log(
+        "RUST",
+        format!(
+            "Broadcast GAIA command: vendor=0x{:04X} cmd=0x{:04X} to {} targets",
+            vendor_id,
+            command_id,
+            addresses.len()
+        ),
+    );
+    Ok(send_to(
+        backend, &addresses, vendor_id, command_id, payload, timeout_ms,
+    )
+    .await)
+}
+
+/// `send` for addresses that were already resolved, without logging every call.
+pub(crate) async fn send_to(
+    backend: &BackendState,
+    addresses: &[String],
+    vendor_id: u16,
+    command_id: u16,
+    payload: &[u8],
+    timeout_ms: u64,
+) -> BroadcastReport {
+    let connections = crate::get_connections();
+    let mut pending = Vec::with_capacity(addresses.len());
+    for address in addresses.iter().cloned() {
+        if connections.phase(&address) != ConnectionPhase::Connected {
+            pending.push((address, None));
+            continue;
+        }
+        let backend = backend.clone();
+        let payload = payload.to_vec();
+        let task_address = address.clone();
+        let task = tauri::async_runtime::spawn(async move {
+            let started = Instant::now();
+            let result = request_gaia(
+                &*backend,
+                &task_address,
+                vendor_id,
+                command_id,
+                &payload,
+                timeout_ms,
+            )
+            .await;
+            (result, started.elapsed())
+        });
+        pending.push((address, Some(task)));
+    }
+
+    let mut results = Vec::with_capacity(pending.len());
+    for (address, task) in pending {
+        let Some(task) = task else {
+            results.push(BroadcastOutcome::failed(
+                address,
+                false,
+                StoneError::NotConnected,
+            ));
+            continue;
+        };
+        let outcome = match task.await {
+            Ok((Ok(response), elapsed)) => BroadcastOutcome {
+                address,
+                ok: true,
+                sent: true,
+                status: Some(response.status),
+                payload: Some(response.payload),
+                latency_ms: Some(elapsed.as_millis() as u64),
+                error: None,
+            },
+            Ok((Err(err), _)) => BroadcastOutcome::failed(address, true, err),
+            Err(err) => BroadcastOutcome::failed(address, true, StoneError::other(err)),
+        };
+        results.push(outcome);
+    }
+
+    let succeeded = results.iter().filter(|outcome| outcome.ok).count();
+    BroadcastReport {
+        vendor_id,
+        command_id,
+        succeeded,
+        failed: results.len() - succeeded,
+        results,
+    }
+}
@@ -0,0 +1,47 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::task::Poll;
+use tokio::sync::watch;
+
+use crate::error::StoneError;
+
+/// Cancellation flag shared between a connect task and whoever may call it off.
+#[derive(Clone)]
+pub(crate) struct CancelToken(Arc<watch::Sender<bool>>);
+
+impl CancelToken {
+    pub(crate) fn new() -> Self {
+        Self(Arc::new(watch::channel(false).0))
+    }
+
+    pub(crate) fn cancel(&self) {
+        self.0.send_replace(true);
+    }
+
+    pub(crate) fn is_cancelled(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    /// Whether both are clones of the same token.
+    pub(crate) fn is_same(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+
+    pub(crate) async fn cancelled(&self) {
+        let mut rx = self.0.subscribe();
+        let _ = rx.wait_for(|cancelled| *cancelled).await;
+    }
+
+    /// Drives `future` until it finishes or the token is cancelled, whichever comes first.
+    pub(crate) async fn run<F: Future>(&self, future: F) -> Result<F::Output, StoneError> {
+        let mut future = std::pin::pin!(future);
+        let mut cancelled = std::pin::pin!(self.cancelled());
+        std::future::poll_fn(|cx| {
+            if cancelled.as_mut().poll(cx).is_ready() {
+                return Poll::Ready(Err(StoneError::ConnectCancelled));
+            }
+            future.as_mut().poll(cx).map(Ok)
+        })
+        .await
+    }
+}
@@ -0,0 +1,572 @@
+//! Raw RFCOMM traffic capture in pcapng, one interface per speaker address.
+//!
+//! Packets use LINKTYPE_USER0 so Wireshark can hand them to a GAIA dissector; the
+//! direction is stored in `epb_flags` and the address in the interface's `if_name`.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::device_state::{DeviceState, DeviceStateStore};
+use crate::error::StoneError;
+use crate::gaia::GaiaParser;
+use crate::{back_log, pt, GaiaPacketEvent};
+
+// Path to start capturing into as soon as the backend comes up.
+pub(crate) const CAPTURE_ENV: &str = "STONE_CAPTURE";
+
+const LINKTYPE_USER0: u16 = 147;
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+
+const BLOCK_SECTION_HEADER: u32 = 0x0A0D_0D0A;
+const BLOCK_INTERFACE_DESCRIPTION: u32 = 0x0000_0001;
+const BLOCK_ENHANCED_PACKET: u32 = 0x0000_0006;
+
+const OPT_END: u16 = 0;
+const OPT_SHB_USERAPPL: u16 = 4;
+const OPT_IF_NAME: u16 = 2;
+const OPT_IF_TSRESOL: u16 = 9;
+const OPT_EPB_FLAGS: u16 = 2;
+
+// Replay runs in one go, so a setter is only ever waiting on an ACK further down the file.
+const REPLAY_ACK_WINDOW_MS: u64 = 60_000;
+
+const EPB_FLAG_INBOUND: u32 = 0b01;
+const EPB_FLAG_OUTBOUND: u32 = 0b10;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Direction {
+    Inbound,
+    Outbound,
+}
+
+fn pad4(len: usize) -> usize {
+    (4 - len % 4) % 4
+}
+
+fn push_option(block: &mut Vec<u8>, code: u16, value: &[u8]) {
+    block.extend_from_slice(&code.to_le_bytes());
+    block.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    block.extend_from_slice(value);
+    block.extend(std::iter::repeat_n(0, pad4(value.len())));
+}
+
+fn write_block(out: &mut impl Write, block_type: u32, body: &[u8]) -> std::io::Result<()> {
+    let total = (12 + body.len()) as u32;
+    out.write_all(&block_type.to_le_bytes())?;
+    out.write_all(&total.to_le_bytes())?;
+    out.write_all(body)?;
+    out.write_all(&total.to_le_bytes())
+}
+
+struct CaptureWriter {
+    path: PathBuf,
+    out: BufWriter<File>,
+    interfaces: HashMap<String, u32>,
+}
+
+impl CaptureWriter {
+    fn create(path: &Path) -> std::io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        let mut body = Vec::new();
+        body.extend_from_slice(&BYTE_ORDER_MAGIC.to_le_bytes());
+        body.extend_from_slice(&1u16.to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes());
+        body.extend_from_slice(&(-1i64).to_le_bytes());
+        push_option(
+            &mut body,
+            OPT_SHB_USERAPPL,
+            concat!("stone-manager ", env!("CARGO_PKG_VERSION")).as_bytes(),
+        );
+        push_option(&mut body, OPT_END, &[]);
+        write_block(&mut out, BLOCK_SECTION_HEADER, &body)?;
+        out.flush()?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            out,
+            interfaces: HashMap::new(),
+        })
+    }
+
+    fn interface_id(&mut self, address: &str) -> std::io::Result<u32> {
+        let key = address.to_ascii_uppercase();
+        if let Some(id) = self.interfaces.get(&key) {
+            return Ok(*id);
+        }
+        let mut body = Vec::new();
+        body.extend_from_slice(&LINKTYPE_USER0.to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes());
+        body.extend_from_slice(&0u32.to_le_bytes());
+        push_option(&mut body, OPT_IF_NAME, key.as_bytes());
+        push_option(&mut body, OPT_IF_TSRESOL, &[6]);
+        push_option(&mut body, OPT_END, &[]);
+        write_block(&mut self.out, BLOCK_INTERFACE_DESCRIPTION, &body)?;
+        let id = self.interfaces.len() as u32;
+        self.interfaces.insert(key, id);
+        Ok(id)
+    }
+
+    fn write_packet(
+        &mut self,
+        address: &str,
+        direction: Direction,
+        data: &[u8],
+    ) -> std::io::Result<()> {
+        let interface_id = self.interface_id(address)?;
+        let timestamp_us = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_micros() as u64)
+            .unwrap_or_default();
+        let flags = match direction {
+            Direction::Inbound => EPB_FLAG_INBOUND,
+            Direction::Outbound => EPB_FLAG_OUTBOUND,
+        };
+        let mut body = Vec::with_capacity(32 + data.len());
+        body.extend_from_slice(&interface_id.to_le_bytes());
+        body.extend_from_slice(&((timestamp_us >> 32) as u32).to_le_bytes());
+        body.extend_from_slice(&(timestamp_us as u32).to_le_bytes());
+        body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        body.extend_from_slice(data);
+        body.extend(std::iter::repeat_n(0, pad4(data.len())));
+        push_option(&mut body, OPT_EPB_FLAGS, &flags.to_le_bytes());
+        push_option(&mut body, OPT_END, &[]);
+        write_block(&mut self.out, BLOCK_ENHANCED_PACKET, &body)?;
+        self.out.flush()
+    }
+}
+
+static CAPTURE: Mutex<Option<CaptureWriter>> = Mutex::new(None);
+
+pub(crate) fn start(path: &Path) -> Result<(), StoneError> {
+    let writer = CaptureWriter::create(path).map_err(|err| StoneError::Io {
+        message: format!("Failed to create capture {}: {}", path.display(), err),
+    })?;
+    let mut capture = CAPTURE
+        .lock()
+        .map_err(|_| StoneError::other("Capture state poisoned"))?;
+    *capture = Some(writer);
+    back_log(
+        "RUST",
+        format!("Capturing GAIA traffic to {}", path.display()),
+    );
+    Ok(())
+}
+
+pub(crate) fn stop() -> Option<PathBuf> {
+    let writer = CAPTURE.lock().ok()?.take()?;
+    back_log(
+        "RUST",
+        format!("Stopped GAIA capture {}", writer.path.display()),
+    );
+    Some(writer.path)
+}
+
+pub(crate) fn start_from_env() {
+    let Some(path) = std::env::var_os(CAPTURE_ENV).filter(|path| !path.is_empty()) else {
+        return;
+    };
+    if let Err(err) = start(Path::new(&path)) {
+        back_log("RUST", err.to_string());
+    }
+}
+
+pub(crate) fn record(address: &str, direction: Direction, data: &[u8]) {
+    let Ok(mut capture) = CAPTURE.lock() else {
+        return;
+    };
+    let Some(writer) = capture.as_mut() else {
+        return;
+    };
+    if let Err(err) = writer.write_packet(address, direction, data) {
+        back_log(
+            "RUST",
+            format!("Capture write failed, stopping capture: {}", err),
+        );
+        *capture = None;
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct CapturedChunk {
+    pub timestamp_us: u64,
+    pub address: String,
+    pub direction: Direction,
+    pub data: Vec<u8>,
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl Reader<'_> {
+    fn u16_at(&self, offset: usize) -> Option<u16> {
+        let bytes: [u8; 2] = self.data.get(offset..offset + 2)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    fn u32_at(&self, offset: usize) -> Option<u32> {
+        let bytes: [u8; 4] = self.data.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+
+    fn options(&self, mut offset: usize, end: usize) -> Vec<(u16, &[u8])> {
+        let mut options = Vec::new();
+        while offset + 4 <= end {
+            let (Some(code), Some(len)) = (self.u16_at(offset), self.u16_at(offset + 2)) else {
+                break;
+            };
+            if code == OPT_END {
+                break;
+            }
+            let len = len as usize;
+            let Some(value) = self.data.get(offset + 4..offset + 4 + len) else {
+                break;
+            };
+            options.push((code, value));
+            offset += 4 + len + pad4(len);
+        }
+        options
+    }
+}
+
+struct CaptureInterface {
+    address: String,
+    ticks_per_second: u64,
+}
+
+fn ticks_per_second(tsresol: u8) -> u64 {
+    let exponent = u32::from(tsresol & 0x7F);
+    if tsresol & 0x80 != 0 {
+        2u64.checked_pow(exponent).unwrap_or(1_000_000)
+    } else {
+        10u64.checked_pow(exponent).unwrap_or(1_000_000)
+    }
+}
+
+fn malformed(message: impl Into<String>) -> StoneError {
+    StoneError::InvalidParameter {
+        message: message.into(),
+    }
+}
+
+pub(crate) fn read(path: &Path) -> Result<Vec<CapturedChunk>, StoneError> {
+    let mut bytes = Vec::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_end(&mut bytes))
+        .map_err(|err| StoneError::Io {
+            message: format!("Failed to read capture {}: {}", path.display(), err),
+        })?;
+
+    let mut reader = Reader {
+        data: &bytes,
+        big_endian: false,
+    };
+    let mut interfaces: Vec<CaptureInterface> = Vec::new();
+    let mut chunks = Vec::new();
+    let mut offset = 0;
+    while offset + 12 <= bytes.len() {
+        let block_type = u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        if block_type == BLOCK_SECTION_HEADER {
+            let magic = bytes
+                .get(offset + 8..offset + 12)
+                .ok_or_else(|| malformed("Truncated section header"))?;
+            reader.big_endian = match u32::from_le_bytes(magic.try_into().unwrap()) {
+                BYTE_ORDER_MAGIC => false,
+                0x4D3C_2B1A => true,
+                _ => return Err(malformed("Not a pcapng file")),
+            };
+            interfaces.clear();
+        } else if offset == 0 {
+            return Err(malformed("Not a pcapng file"));
+        }
+        let total = reader
+            .u32_at(offset + 4)
+            .ok_or_else(|| malformed("Truncated block"))? as usize;
+        if total < 12 || offset + total > bytes.len() {
+            return Err(malformed(format!("Corrupt block at offset {}", offset)));
+        }
+        let body = offset + 8;
+        let body_end = offset + total - 4;
+        match reader.u32_at(offset).unwrap_or_default() {
+            BLOCK_INTERFACE_DESCRIPTION => {
+                let mut interface = CaptureInterface {
+                    address: format!("if{}", interfaces.len()),
+                    ticks_per_second: 1_000_000,
+                };
+                for (code, value) in reader.options(body + 8, body_end) {
+                    match code {
+                        OPT_IF_NAME => {
+                            interface.address = String::from_utf8_lossy(value).to_string()
+                        }
+                        OPT_IF_TSRESOL if !value.is_empty() => {
+                            interface.ticks_per_second = ticks_per_second(value[0])
+                        }
+                        _ => {}
+                    }
+                }
+                interfaces.push(interface);
+            }
+            BLOCK_ENHANCED_PACKET => {
+                let fields = (
+                    reader.u32_at(body),
+                    reader.u32_at(body + 4),
+                    reader.u32_at(body + 8),
+                    reader.u32_at(body + 12),
+                );
+                let (Some(interface_id), Some(high), Some(low), Some(captured)) = fields else {
+                    return Err(malformed(format!("Truncated packet at offset {}", offset)));
+                };
+                let data_start = body + 20;
+                let data_end = data_start + captured as usize;
+                let data = bytes
+                    .get(data_start..data_end.min(body_end))
+                    .ok_or_else(|| malformed(format!("Truncated packet at offset {}", offset)))?;
+                let interface = interfaces
+                    .get(interface_id as usize)
+                    .ok_or_else(|| malformed(format!("Unknown interface {}", interface_id)))?;
+                let flags = reader
+                    .options(data_end + pad4(captured as usize), body_end)
+                    .into_iter()
+                    .find(|(code, value)| *code == OPT_EPB_FLAGS && value.len() == 4)
+                    .and_then(|(_, value)| {
+                        Reader {
+                            data: value,
+                            big_endian: reader.big_endian,
+                        }
+                        .u32_at(0)
+                    })
+                    .unwrap_or(EPB_FLAG_INBOUND);
+                let ticks = (u64::from(high) << 32) | u64::from(low);
+                chunks.push(CapturedChunk {
+                    timestamp_us: (u128::from(ticks) * 1_000_000
+                        / u128::from(interface.ticks_per_second))
+                        as u64,
+                    address: interface.address.clone(),
+                    direction: if flags & 0b11 == EPB_FLAG_OUTBOUND {
+                        Direction::Outbound
+                    } else {
+                        Direction::Inbound
+                    },
+                    data: data.to_vec(),
+                });
+            }
+            _ => {}
+        }
+        offset += total;
+    }
+    Ok(chunks)
+}
+
+#[derive(Serialize, Clone)]
+pub(crate) struct ReplayEvent {
+    timestamp_us: u64,
+    direction: Direction,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    packet: Option<GaiaPacketEvent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// What a capture decodes to: every frame, and the state the speakers were left in.
+#[derive(Serialize)]
+pub(crate) struct Replay {
+    events: Vec<ReplayEvent>,
+    states: Vec<DeviceState>,
+}
+
+/// Decodes a capture offline, reassembling each address and direction separately. The
+/// states are rebuilt in a store of their own, so nothing reaches the live app.
+pub(crate) fn decode(chunks: &[CapturedChunk]) -> Replay {
+    let store = DeviceStateStore::default();
+    let mut parsers: HashMap<(String, Direction), GaiaParser> = HashMap::new();
+    let mut addresses: Vec<String> = Vec::new();
+    let mut events = Vec::new();
+    for chunk in chunks {
+        let address = chunk.address.to_ascii_uppercase();
+        if !addresses.contains(&address) {
+            addresses.push(address.clone());
+        }
+        let parser = parsers
+            .entry((address.clone(), chunk.direction))
+            .or_default();
+        for frame in parser.push_bytes(&chunk.data) {
+            let (packet, error) = match frame {
+                Ok(frame) => {
+                    match chunk.direction {
+                        Direction::Inbound => {
+                            crate::apply_frame(&store, &address, &frame);
+                        }
+                        Direction::Outbound if frame.vendor_id == pt::PT_VENDOR_ID => {
+                            if let Ok(command) = pt::PtCommand::parse(frame.command, &frame.payload)
+                            {
+                                store.stage_command(&address, command, REPLAY_ACK_WINDOW_MS);
+                            }
+                        }
+                        Direction::Outbound => {}
+                    }
+                    (
+                        Some(GaiaPacketEvent::from_frame(&chunk.address, &frame)),
+                        None,
+                    )
+                }
+                Err(err) => (None, Some(format!("{}: {}", chunk.address, err))),
+            };
+            events.push(ReplayEvent {
+                timestamp_us: chunk.timestamp_us,
+                direction: chunk.direction,
+                packet,
+                error,
+            });
+        }
+    }
+    addresses.sort();
+    let states = addresses.iter().map(|address| store.get(address)).collect();
+    Replay { events, states }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gaia::GaiaFrame;
+
+    const SPEAKER: &str = "00:00:5e:00:53:60";
+    const OTHER: &str = "00:00:5E:00:53:61";
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("stone-capture-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join(name)
+    }
+
+    fn unix_micros() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_micros() as u64
+    }
+
+    fn write(path: &Path, packets: &[(&str, Direction, Vec<u8>)]) {
+        let mut writer = CaptureWriter::create(path).unwrap();
+        for (address, direction, data) in packets {
+            writer.write_packet(address, *direction, data).unwrap();
+        }
+    }
+
+    #[test]
+    fn round_trips_packets_through_pcapng() {
+        let path = scratch("round-trip.pcapng");
+        let before = unix_micros();
+        write(
+            &path,
+            &[
+                (SPEAKER, Direction::Outbound, vec![0xFF, 0x01, 0x00]),
+                (OTHER, Direction::Inbound, vec![1, 2, 3, 4, 5]),
+                (SPEAKER, Direction::Inbound, vec![9; 8]),
+            ],
+        );
+        let after = unix_micros();
+
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(bytes[..4], BLOCK_SECTION_HEADER.to_le_bytes());
+        assert_eq!(bytes.len() % 4, 0);
+
+        let chunks = read(&path).unwrap();
+        let packets: Vec<(&str, Direction, &[u8])> = chunks
+            .iter()
+            .map(|chunk| {
+                (
+                    chunk.address.as_str(),
+                    chunk.direction,
+                    chunk.data.as_slice(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            packets,
+            vec![
+                (
+                    "00:00:5E:00:53:60",
+                    Direction::Outbound,
+                    &[0xFF, 0x01, 0x00][..]
+                ),
+                (OTHER, Direction::Inbound, &[1, 2, 3, 4, 5][..]),
+                ("00:00:5E:00:53:60", Direction::Inbound, &[9; 8][..]),
+            ]
+        );
+        assert!(chunks
+            .iter()
+            .all(|chunk| (before..=after).contains(&chunk.timestamp_us)));
+        assert!(chunks
+            .windows(2)
+            .all(|pair| pair[0].timestamp_us <= pair[1].timestamp_us));
+    }
+
+    #[test]
+    fn rejects_truncated_and_foreign_files() {
+        let path = scratch("truncated.pcapng");
+        write(&path, &[(SPEAKER, Direction::Inbound, vec![1, 2, 3])]);
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &bytes[..bytes.len() - 6]).unwrap();
+        assert!(matches!(
+            read(&path),
+            Err(StoneError::InvalidParameter { message }) if message.starts_with("Corrupt block")
+        ));
+
+        std::fs::write(&path, b"not a capture file").unwrap();
+        assert!(matches!(
+            read(&path),
+            Err(StoneError::InvalidParameter { .. })
+        ));
+        assert!(matches!(
+            read(&scratch("missing.pcapng")),
+            Err(StoneError::Io { .. })
+        ));
+    }
+
+    #[test]
+    fn decode_rebuilds_state_without_touching_the_app() {
+        let set = GaiaFrame::from_command_id(pt::PT_VENDOR_ID, pt::SET_VOLUME, vec![12])
+            .encode()
+            .unwrap();
+        let ack = GaiaFrame::ack(pt::PT_VENDOR_ID, pt::SET_VOLUME, 0, Vec::new())
+            .encode()
+            .unwrap();
+        let chunk = |direction, data: &[u8]| CapturedChunk {
+            timestamp_us: 0,
+            address: SPEAKER.to_string(),
+            direction,
+            data: data.to_vec(),
+        };
+        // The ACK arrives split over two reads, after a byte of line noise.
+        let mut noisy = vec![0x00];
+        noisy.extend_from_slice(&ack[..3]);
+        let chunks = [
+            chunk(Direction::Outbound, &set),
+            chunk(Direction::Inbound, &noisy),
+            chunk(Direction::Inbound, &ack[3..]),
+        ];
+
+        let replay = serde_json::to_value(decode(&chunks)).unwrap();
+        let events = replay["events"].as_array().unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0]["direction"], "outbound");
+        assert_eq!(events[1]["packet"]["ack"], true);
+        assert_eq!(replay["states"][0]["volume"], 12);
+        assert_eq!(crate::get_device_states().get(SPEAKER).volume, None);
+    }
+}
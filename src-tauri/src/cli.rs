@@ -0,0 +1,548 @@
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::Ordering;
+
+use crate::backend::{self, BluetoothBackend};
+use crate::cancel::CancelToken;
+use crate::error::StoneError;
+use crate::pt::{self, LampSettings, LampState, PtCommand, PtReport, Rgb, Volume};
+use crate::registry;
+use crate::retry::{self, RetryPolicy};
+use crate::transaction::{GaiaResponse, DEFAULT_REQUEST_TIMEOUT_MS};
+use crate::{capture, get_device_states, get_registry, request_gaia, with_profile, LOG_TO_STDERR};
+
+const USAGE: &str = "Usage: stone-cli [--address <addr>] [--timeout <ms>] <command>
+
+Commands:
+  list                                   List paired devices
+  scan                                   Scan for unpaired STONE speakers
+  connect <addr>                         Open the GAIA session to a speaker
+  disconnect <addr>                      Close the GAIA session to a speaker
+  battery                                Read battery step, level and DC state
+  volume [set <0-30>]                    Read or set the volume
+  lamp [on|off]                          Read the lamp state, or switch it
+       on [--type <1-5>] [--rgb <rrggbb>] [--brightness <0-100>]
+  state                                  Read everything the speaker reports
+  raw <vendor> <command> [payload]       Send a GAIA request (hex arguments)
+  replay <file.pcapng>                   Decode a capture and rebuild device state
+  registered                             List the speakers registered in the app
+
+Without --address the only connected (or only paired) speaker is used.
+Set STONE_BACKEND=simulator to talk to simulated speakers.
+Set STONE_CAPTURE=<file.pcapng> to record the raw traffic.
+Set STONE_REGISTRY=<file.json> to read another device registry.";
+
+#[derive(Debug)]
+enum CliError {
+    Usage(String),
+    Failed(StoneError),
+}
+
+impl From<String> for CliError {
+    fn from(message: String) -> Self {
+        Self::Failed(StoneError::Other { message })
+    }
+}
+
+impl From<StoneError> for CliError {
+    fn from(err: StoneError) -> Self {
+        match err {
+            StoneError::InvalidParameter { message } => Self::Usage(message),
+            other => Self::Failed(other),
+        }
+    }
+}
+
+struct Args {
+    positional: Vec<String>,
+    options: HashMap<String, String>,
+}
+
+impl Args {
+    fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, CliError> {
+        let mut positional = Vec::new();
+        let mut options = HashMap::new();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let name = match arg.as_str() {
+                "-a" => "address".to_string(),
+                "-h" | "--help" => return Err(CliError::Usage(String::new())),
+                _ => match arg.strip_prefix("--") {
+                    Some(name) => name.to_string(),
+                    None => {
+                        positional.push(arg);
+                        continue;
+                    }
+                },
+            };
+            let value = args
+                .next()
+                .ok_or_else(|| CliError::Usage(format!("Missing value for --{}", name)))?;
+            options.insert(name, value);
+        }
+        Ok(Self {
+            positional,
+            options,
+        })
+    }
+
+    fn positional(&self, index: usize) -> Option<&str> {
+        self.positional.get(index).map(String::as_str)
+    }
+
+    fn option(&self, name: &str) -> Option<&str> {
+        self.options.get(name).map(String::as_str)
+    }
+}
+
+fn parse_number<T: TryFrom<u32>>(value: &str, what: &str, max: u32) -> Result<T, CliError> {
+    value
+        .parse::<u32>()
+        .ok()
+        .filter(|v| *v <= max)
+        .and_then(|v| T::try_from(v).ok())
+        .ok_or_else(|| CliError::Usage(format!("Invalid {}: {} (0-{})", what, value, max)))
+}
+
+fn parse_hex_u16(value: &str, what: &str) -> Result<u16, CliError> {
+    let digits = value.trim_start_matches("0x").trim_start_matches("0X");
+    u16::from_str_radix(digits, 16)
+        .map_err(|_| CliError::Usage(format!("Invalid {}: {}", what, value)))
+}
+
+fn parse_hex_bytes(value: &str) -> Result<Vec<u8>, CliError> {
+    let digits: String = value
+        .trim_start_matches("0x")
+        .trim_start_matches("0X")
+        .chars()
+        .filter(|c| !matches!(c, ' ' | ':' | '-'))
+        .collect();
+    if !digits.len().is_multiple_of(2) || !digits.is_ascii() {
+        return Err(CliError::Usage(format!("Invalid hex payload: {}", value)));
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&digits[i..i + 2], 16)
+                .map_err(|_| CliError::Usage(format!("Invalid hex payload: {}", value)))
+        })
+        .collect()
+}
+
+fn parse_rgb(value: &str) -> Result<Rgb, CliError> {
+    match parse_hex_bytes(value.trim_start_matches('#'))?.as_slice() {
+        [r, g, b] => Ok(Rgb {
+            r: *r,
+            g: *g,
+            b: *b,
+        }),
+        _ => Err(CliError::Usage(format!(
+            "Invalid color: {} (expected rrggbb)",
+            value
+        ))),
+    }
+}
+
+struct Session<'a> {
+    backend: &'a dyn BluetoothBackend,
+    address: String,
+    timeout_ms: u64,
+}
+
+impl Session<'_> {
+    async fn send(&self, command: PtCommand) -> Result<GaiaResponse, StoneError> {
+        pt::send(self.backend, &self.address, command, self.timeout_ms).await
+    }
+
+    async fn query<T: pt::PtResponse>(&self) -> Result<T, StoneError> {
+        pt::query(self.backend, &self.address, self.timeout_ms).await
+    }
+}
+
+async fn resolve_address(backend: &dyn BluetoothBackend, args: &Args) -> Result<String, CliError> {
+    if let Some(address) = args.option("address") {
+        return Ok(address.to_string());
+    }
+    let connected: Vec<String> = backend
+        .get_connection_infos()
+        .await?
+        .into_iter()
+        .filter(|info| info.rfcomm)
+        .map(|info| info.address)
+        .collect();
+    if let [address] = connected.as_slice() {
+        return Ok(address.clone());
+    }
+    let paired: Vec<String> = backend
+        .list_devices()
+        .await?
+        .into_iter()
+        .filter(|device| device.has_gaia)
+        .map(|device| device.address)
+        .collect();
+    match paired.as_slice() {
+        [address] => Ok(address.clone()),
+        [] => Err(CliError::Failed(StoneError::DeviceNotFound)),
+        _ => Err(CliError::Usage(
+            "Several speakers found; pass --address".to_string(),
+        )),
+    }
+}
+
+async fn open_session<'a>(
+    backend: &'a dyn BluetoothBackend,
+    args: &Args,
+) -> Result<Session<'a>, CliError> {
+    let address = resolve_address(backend, args).await?;
+    let timeout_ms = match args.option("timeout") {
+        Some(value) => value
+            .parse()
+            .map_err(|_| CliError::Usage(format!("Invalid timeout: {}", value)))?,
+        None => DEFAULT_REQUEST_TIMEOUT_MS,
+    };
+    let connected = backend
+        .get_connection_infos()
+        .await?
+        .iter()
+        .any(|info| info.rfcomm && info.address.eq_ignore_ascii_case(&address));
+    if !connected {
+        connect(backend, &address).await?;
+    }
+    Ok(Session {
+        backend,
+        address,
+        timeout_ms,
+    })
+}
+
+async fn connect(backend: &dyn BluetoothBackend, address: &str) -> Result<(), StoneError> {
+    let cancel = CancelToken::new();
+    retry::connect_with_retry(backend, address, &RetryPolicy::default(), |_| {}, &cancel).await
+}
+
+fn address_argument(args: &Args) -> Result<String, CliError> {
+    args.positional(1)
+        .or_else(|| args.option("address"))
+        .map(str::to_string)
+        .ok_or_else(|| CliError::Usage("Missing device address".to_string()))
+}
+
+fn to_json<T: serde::Serialize>(value: T) -> Result<Value, CliError> {
+    serde_json::to_value(value).map_err(|err| CliError::Failed(StoneError::other(err)))
+}
+
+async fn battery(session: &Session<'_>) -> Result<Value, CliError> {
+    let report = pt::battery(session.backend, &session.address, session.timeout_ms).await?;
+    let mut value = to_json(report)?;
+    value["address"] = json!(session.address);
+    Ok(value)
+}
+
+async fn volume(session: &Session<'_>, args: &Args) -> Result<Value, CliError> {
+    match args.positional(1) {
+        None | Some("get") => {
+            let volume: Volume = session.query().await?;
+            Ok(json!({ "address": session.address, "volume": volume.volume }))
+        }
+        Some("set") => {
+            let value = args
+                .positional(2)
+                .ok_or_else(|| CliError::Usage("Missing volume".to_string()))?;
+            let volume: u8 = parse_number(value, "volume", u32::from(pt::MAX_VOLUME))?;
+            session.send(PtCommand::SetVolume(volume)).await?;
+            Ok(json!({ "address": session.address, "volume": volume }))
+        }
+        Some(other) => Err(CliError::Usage(format!("Unknown volume action: {}", other))),
+    }
+}
+
+fn lamp_json(address: &str, lamp: &LampState) -> Value {
+    json!({
+        "address": address,
+        "on": lamp.on,
+        "brightness": lamp.brightness,
+        "type": lamp.lamp_type,
+        "rgb": lamp.color.to_hex(),
+    })
+}
+
+async fn lamp(session: &Session<'_>, args: &Args) -> Result<Value, CliError> {
+    match args.positional(1) {
+        None | Some("get") => {
+            let lamp: LampState = session.query().await?;
+            Ok(lamp_json(&session.address, &lamp))
+        }
+        Some("on") => {
+            let settings = LampSettings {
+                brightness: match args.option("brightness") {
+                    Some(value) => parse_number(value, "brightness", 255)?,
+                    None => pt::MAX_LAMP_BRIGHTNESS,
+                },
+                lamp_type: match args.option("type") {
+                    Some(value) => parse_number(value, "lamp type", 255)?,
+                    None => 1,
+                },
+                color: match args.option("rgb") {
+                    Some(value) => parse_rgb(value)?,
+                    None => Rgb::WHITE,
+                },
+            };
+            session.send(PtCommand::RunLamp(settings)).await?;
+            Ok(lamp_json(
+                &session.address,
+                &LampState {
+                    on: true,
+                    brightness: settings.brightness,
+                    lamp_type: settings.lamp_type,
+                    color: settings.color,
+                },
+            ))
+        }
+        Some("off") => {
+            session.send(PtCommand::StopLamp).await?;
+            Ok(json!({ "address": session.address, "on": false }))
+        }
+        Some(other) => Err(CliError::Usage(format!("Unknown lamp action: {}", other))),
+    }
+}
+
+async fn state(session: &Session<'_>) -> Result<Value, CliError> {
+    pt::battery(session.backend, &session.address, session.timeout_ms).await?;
+    session.query::<Volume>().await?;
+    session.query::<LampState>().await?;
+    pt::device_info(session.backend, &session.address, session.timeout_ms).await?;
+    to_json(get_device_states().get(&session.address))
+}
+
+async fn raw(session: &Session<'_>, args: &Args) -> Result<Value, CliError> {
+    let (Some(vendor), Some(command)) = (args.positional(1), args.positional(2)) else {
+        return Err(CliError::Usage("raw needs <vendor> <command>".to_string()));
+    };
+    let vendor_id = parse_hex_u16(vendor, "vendor id")?;
+    let command_id = parse_hex_u16(command, "command id")?;
+    let payload = match args.positional(3) {
+        Some(value) => parse_hex_bytes(value)?,
+        None => Vec::new(),
+    };
+    let response = request_gaia(
+        session.backend,
+        &session.address,
+        vendor_id,
+        command_id,
+        &payload,
+        session.timeout_ms,
+    )
+    .await?;
+    let report = (vendor_id == pt::PT_VENDOR_ID)
+        .then(|| PtReport::decode(command_id, &response.payload))
+        .flatten()
+        .and_then(Result::ok);
+    let mut value = to_json(response)?;
+    if let Some(report) = report {
+        value["report"] = to_json(report)?;
+    }
+    Ok(value)
+}
+
+fn replay(args: &Args) -> Result<Value, CliError> {
+    let path = args
+        .positional(1)
+        .ok_or_else(|| CliError::Usage("Missing capture file".to_string()))?;
+    let chunks = capture::read(Path::new(path))?;
+    to_json(capture::decode(&chunks))
+}
+
+fn load_registry() -> Result<(), CliError> {
+    let path = registry::default_path()
+        .ok_or_else(|| CliError::from("No data directory for the registry".to_string()))?;
+    get_registry().load(path);
+    Ok(())
+}
+
+async fn execute(backend: &dyn BluetoothBackend, args: &Args) -> Result<Value, CliError> {
+    match args.positional(0) {
+        Some("list") => to_json(
+            backend
+                .list_devices()
+                .await?
+                .into_iter()
+                .map(with_profile)
+                .collect::<Vec<_>>(),
+        ),
+        Some("scan") => to_json(backend.scan_unpaired_stone_devices().await?),
+        Some("connect") => {
+            let address = address_argument(args)?;
+            connect(backend, &address).await?;
+            Ok(json!({ "address": address, "connected": true }))
+        }
+        Some("disconnect") => {
+            let address = address_argument(args)?;
+            backend.disconnect_device(&address).await?;
+            Ok(json!({ "address": address, "connected": false }))
+        }
+        Some("battery") => battery(&open_session(backend, args).await?).await,
+        Some("volume") => volume(&open_session(backend, args).await?, args).await,
+        Some("lamp") => lamp(&open_session(backend, args).await?, args).await,
+        Some("state") => state(&open_session(backend, args).await?).await,
+        Some("raw") => raw(&open_session(backend, args).await?, args).await,
+        Some(other) => Err(CliError::Usage(format!("Unknown command: {}", other))),
+        None => Err(CliError::Usage(String::new())),
+    }
+}
+
+fn failure_json(err: &StoneError) -> Value {
+    json!({ "error": err.to_string(), "kind": err.kind() })
+}
+
+pub(crate) fn run(args: impl IntoIterator<Item = String>) -> i32 {
+    LOG_TO_STDERR.store(true, Ordering::Relaxed);
+    let result = Args::parse(args).and_then(|args| {
+        if args.positional(0) == Some("replay") {
+            return replay(&args);
+        }
+        load_registry()?;
+        if args.positional(0) == Some("registered") {
+            return to_json(get_registry().list());
+        }
+        let backend = backend::select_headless()?;
+        capture::start_from_env();
+        let result = tauri::async_runtime::block_on(execute(&*backend, &args));
+        capture::stop();
+        result
+    });
+    match result {
+        Ok(value) => {
+            println!("{}", value);
+            0
+        }
+        Err(CliError::Usage(message)) => {
+            if !message.is_empty() {
+                eprintln!("{}\n", message);
+            }
+            eprintln!("{}", USAGE);
+            2
+        }
+        Err(CliError::Failed(err)) => {
+            println!("{}", failure_json(&err));
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulator::SimulatorBackend;
+
+    const ADDRESS: &str = "00:00:5E:00:53:01";
+
+    fn args(line: &str) -> Result<Args, CliError> {
+        Args::parse(line.split_whitespace().map(str::to_string))
+    }
+
+    fn usage<T>(result: Result<T, CliError>) -> String {
+        match result {
+            Err(CliError::Usage(message)) => message,
+            Err(CliError::Failed(err)) => panic!("expected a usage error, got {}", err),
+            Ok(_) => panic!("expected a usage error"),
+        }
+    }
+
+    #[test]
+    fn parses_options_between_positionals() {
+        let args = args("-a 00:11 lamp --timeout 500 on --rgb ff0000").unwrap();
+        assert_eq!(args.positional(0), Some("lamp"));
+        assert_eq!(args.positional(1), Some("on"));
+        assert_eq!(args.positional(2), None);
+        assert_eq!(args.option("address"), Some("00:11"));
+        assert_eq!(args.option("timeout"), Some("500"));
+        assert_eq!(args.option("rgb"), Some("ff0000"));
+    }
+
+    #[test]
+    fn rejects_missing_option_values_and_asks_for_help() {
+        assert_eq!(
+            usage(args("volume --address")),
+            "Missing value for --address"
+        );
+        assert_eq!(usage(args("--help list")), "");
+    }
+
+    #[test]
+    fn parses_hex_payloads() {
+        assert_eq!(parse_hex_bytes("").ok(), Some(Vec::new()));
+        assert_eq!(parse_hex_bytes("0x0a0B").ok(), Some(vec![0x0A, 0x0B]));
+        assert_eq!(parse_hex_bytes("0X01").ok(), Some(vec![0x01]));
+        assert_eq!(
+            parse_hex_bytes("01 02:03-04").ok(),
+            Some(vec![0x01, 0x02, 0x03, 0x04])
+        );
+        for bad in ["abc", "0x1", "zz", "é0"] {
+            assert!(usage(parse_hex_bytes(bad)).starts_with("Invalid hex payload"));
+        }
+        assert_eq!(parse_hex_u16("0x001D", "vendor id").ok(), Some(0x001D));
+        assert!(parse_hex_u16("10000", "vendor id").is_err());
+        assert_eq!(
+            parse_rgb("#ff8000").ok(),
+            Some(Rgb {
+                r: 0xFF,
+                g: 0x80,
+                b: 0x00
+            })
+        );
+        assert!(usage(parse_rgb("ff80")).starts_with("Invalid color"));
+    }
+
+    #[test]
+    fn parses_numbers_within_range() {
+        assert_eq!(parse_number::<u8>("30", "volume", 30).ok(), Some(30));
+        assert_eq!(
+            usage(parse_number::<u8>("31", "volume", 30)),
+            "Invalid volume: 31 (0-30)"
+        );
+        assert!(parse_number::<u8>("-1", "volume", 30).is_err());
+    }
+
+    #[test]
+    fn prints_commands_as_json() {
+        let backend = SimulatorBackend::new();
+        tauri::async_runtime::block_on(async {
+            let on = execute(
+                &backend,
+                &args("lamp on --rgb 00ff00 --brightness 40").unwrap(),
+            )
+            .await
+            .ok()
+            .unwrap();
+            assert_eq!(
+                on,
+                json!({
+                    "address": ADDRESS,
+                    "on": true,
+                    "brightness": 40,
+                    "type": 1,
+                    "rgb": "00ff00",
+                })
+            );
+
+            let raw = execute(&backend, &args("raw 0x5054 0x0411").unwrap())
+                .await
+                .unwrap();
+            assert_eq!(raw["address"], ADDRESS);
+            assert_eq!(raw["vendor_id"], pt::PT_VENDOR_ID);
+            assert_eq!(raw["status"], 0);
+            assert_eq!(raw["report"]["lamp_state"]["on"], true);
+
+            let Err(CliError::Failed(err)) =
+                execute(&backend, &args("connect AA:BB:CC:DD:EE:FF").unwrap()).await
+            else {
+                panic!("expected the connect to fail");
+            };
+            assert_eq!(
+                failure_json(&err),
+                json!({ "error": err.to_string(), "kind": "device_not_found" })
+            );
+        });
+    }
+}
@@ -0,0 +1,776 @@
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use tauri::Emitter;
+use tokio::sync::watch;
+
+use crate::backend::BackendState;
+use crate::cancel::CancelToken;
+use crate::error::StoneError;
+use crate::retry::{self, ConnectProgress, RetryPolicy};
+use crate::{back_log, publish_device_state, ConnectResult, APP_HANDLE};
+
+// Overrides the default number of connects allowed to run at once.
+pub(crate) const CONCURRENCY_ENV: &str = "STONE_MAX_CONCURRENT_CONNECTS";
+const DEFAULT_CONCURRENCY: usize = 2;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ConnectionPhase {
+    Idle,
+    Queued,
+    Connecting,
+    Connected,
+    Disconnecting,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub(crate) struct ConnectionEntry {
+    address: String,
+    state: ConnectionPhase,
+    last_error: Option<StoneError>,
+}
+
+struct Inner {
+    entries: HashMap<String, ConnectionEntry>,
+    queue: VecDeque<String>,
+    active: usize,
+    limit: usize,
+    scan_pending: bool,
+    default_policy: RetryPolicy,
+    policies: HashMap<String, RetryPolicy>,
+    // One per running connect; cancelling it ends the connect with `ConnectCancelled`.
+    tokens: HashMap<String, CancelToken>,
+}
+
+/// Per-address connect state machine with a bounded number of connects in flight.
+///
+/// Scans and connects take turns: a pending scan stops new connects from starting and
+/// waits for the running ones, and queued connects resume as soon as the scan ends.
+pub(crate) struct ConnectionManager {
+    inner: Mutex<Inner>,
+    active_tx: watch::Sender<usize>,
+}
+
+fn key(address: &str) -> String {
+    address.to_ascii_lowercase()
+}
+
+fn emit_state(entry: &ConnectionEntry) {
+    if let Some(app) = APP_HANDLE.get() {
+        let _ = app.emit("bt_connection_state", entry.clone());
+    }
+}
+
+fn emit_progress(progress: ConnectProgress) {
+    if let Some(app) = APP_HANDLE.get() {
+        let _ = app.emit("bt_connect_progress", progress);
+    }
+}
+
+fn emit_result(address: String, result: Result<(), StoneError>) {
+    crate::get_supervisor().connect_finished(&address, &result);
+    #[cfg(test)]
+    tests::record_result(&address, &result);
+    if let Some(app) = APP_HANDLE.get() {
+        let payload = match result {
+            Ok(()) => ConnectResult {
+                address,
+                ok: true,
+                error: None,
+            },
+            Err(err) => ConnectResult {
+                address,
+                ok: false,
+                error: Some(err),
+            },
+        };
+        let _ = app.emit("bt_connect_result", payload);
+    }
+}
+
+async fn rfcomm_open(backend: &BackendState, address: &str) -> bool {
+    backend.get_connection_infos().await.is_ok_and(|infos| {
+        infos
+            .iter()
+            .any(|info| info.rfcomm && info.address.eq_ignore_ascii_case(address))
+    })
+}
+
+impl ConnectionManager {
+    pub(crate) fn new() -> Self {
+        let limit = std::env::var(CONCURRENCY_ENV)
+            .ok()
+            .and_then(|value| value.parse::<usize>().ok())
+            .filter(|limit| *limit > 0)
+            .unwrap_or(DEFAULT_CONCURRENCY);
+        Self {
+            inner: Mutex::new(Inner {
+                entries: HashMap::new(),
+                queue: VecDeque::new(),
+                active: 0,
+                limit,
+                scan_pending: false,
+                default_policy: RetryPolicy::default(),
+                policies: HashMap::new(),
+                tokens: HashMap::new(),
+            }),
+            active_tx: watch::channel(0).0,
+        }
+    }
+
+    fn set_phase(
+        inner: &mut Inner,
+        address: &str,
+        state: ConnectionPhase,
+        last_error: Option<StoneError>,
+    ) -> ConnectionEntry {
+        let entry = inner
+            .entries
+            .entry(key(address))
+            .or_insert_with(|| ConnectionEntry {
+                address: address.to_string(),
+                state,
+                last_error: None,
+            });
+        entry.state = state;
+        entry.last_error = last_error;
+        entry.clone()
+    }
+
+    pub(crate) fn phase(&self, address: &str) -> ConnectionPhase {
+        self.inner
+            .lock()
+            .ok()
+            .and_then(|inner| inner.entries.get(&key(address)).map(|entry| entry.state))
+            .unwrap_or(ConnectionPhase::Idle)
+    }
+
+    pub(crate) fn snapshot(&self) -> Vec<ConnectionEntry> {
+        let mut entries: Vec<ConnectionEntry> = self
+            .inner
+            .lock()
+            .map(|inner| inner.entries.values().cloned().collect())
+            .unwrap_or_default();
+        entries.sort_by(|a, b| a.address.cmp(&b.address));
+        entries
+    }
+
+    pub(crate) fn connected_addresses(&self) -> Vec<String> {
+        self.inner
+            .lock()
+            .map(|inner| {
+                inner
+                    .entries
+                    .values()
+                    .filter(|entry| entry.state == ConnectionPhase::Connected)
+                    .map(|entry| entry.address.clone())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// The policy for `address`, or the default one when none is set for it.
+    pub(crate) fn policy(&self, address: Option<&str>) -> RetryPolicy {
+        self.inner
+            .lock()
+            .map(|inner| {
+                address
+                    .and_then(|address| inner.policies.get(&key(address)))
+                    .unwrap_or(&inner.default_policy)
+                    .clone()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Sets the policy for `address`, or the default without one; `None` restores the default.
+    pub(crate) fn set_policy(
+        &self,
+        address: Option<&str>,
+        policy: Option<RetryPolicy>,
+    ) -> Result<(), StoneError> {
+        if let Some(policy) = &policy {
+            policy.validate()?;
+        }
+        let mut inner = self
+            .inner
+            .lock()
+            .map_err(|_| StoneError::other("Connection state poisoned"))?;
+        match (address, policy) {
+            (Some(address), Some(policy)) => {
+                inner.policies.insert(key(address), policy);
+            }
+            (Some(address), None) => {
+                inner.policies.remove(&key(address));
+            }
+            (None, policy) => inner.default_policy = policy.unwrap_or_default(),
+        }
+        Ok(())
+    }
+
+    pub(crate) fn set_limit(&self, limit: usize, backend: &BackendState) {
+        if let Ok(mut inner) = self.inner.lock() {
+            inner.limit = limit.max(1);
+        }
+        self.pump(backend);
+    }
+
+    /// Queues a connect; a connect already queued or running for the address is reused.
+    pub(crate) fn enqueue(&self, address: &str, backend: &BackendState) {
+        let entry = {
+            let Ok(mut inner) = self.inner.lock() else {
+                return;
+            };
+            let phase = inner
+                .entries
+                .get(&key(address))
+                .map(|entry| entry.state)
+                .unwrap_or(ConnectionPhase::Idle);
+            if matches!(phase, ConnectionPhase::Queued | ConnectionPhase::Connecting) {
+                back_log("RUST", format!("Connect already pending: {}", address));
+                return;
+            }
+            inner.queue.push_back(address.to_string());
+            Self::set_phase(&mut inner, address, ConnectionPhase::Queued, None)
+        };
+        emit_state(&entry);
+        self.pump(backend);
+    }
+
+    fn pump(&self, backend: &BackendState) {
+        loop {
+            let (address, entry, cancel) = {
+                let Ok(mut inner) = self.inner.lock() else {
+                    return;
+                };
+                if inner.scan_pending || inner.active >= inner.limit {
+                    return;
+                }
+                let Some(address) = inner.queue.pop_front() else {
+                    return;
+                };
+                inner.active += 1;
+                self.active_tx.send_replace(inner.active);
+                let cancel = CancelToken::new();
+                inner.tokens.insert(key(&address), cancel.clone());
+                let entry =
+                    Self::set_phase(&mut inner, &address, ConnectionPhase::Connecting, None);
+                (address, entry, cancel)
+            };
+            emit_state(&entry);
+
+            let backend = backend.clone();
+            tauri::async_runtime::spawn(async move {
+                back_log("RUST", format!("Connect request: {}", address));
+                let connections = crate::get_connections();
+                let policy = connections.policy(Some(&address));
+                let result =
+                    retry::connect_with_retry(&*backend, &address, &policy, emit_progress, &cancel)
+                        .await;
+                connections
+                    .finish(&address, result, &cancel, &backend)
+                    .await;
+            });
+        }
+    }
+
+    /// Settles a finished connect task. This is the only place a running connect reports its
+    /// `bt_connect_result`, so a cancelled connect reports exactly once.
+    async fn finish(
+        &self,
+        address: &str,
+        result: Result<(), StoneError>,
+        cancel: &CancelToken,
+        backend: &BackendState,
+    ) {
+        // A cancel issued mid-connect wins over a late success, even when the link outlives
+        // the cleanup: the caller asked for no connection, so it is never handed one.
+        let cancelled = cancel.is_cancelled();
+        if cancelled {
+            // The backend call has settled, so nothing connects behind this disconnect.
+            for attempt in 1..=2 {
+                match backend.disconnect_device(address).await {
+                    Ok(()) if !rfcomm_open(backend, address).await => break,
+                    Ok(()) => back_log(
+                        "RUST",
+                        format!("Cancelled connect still open after disconnect: {}", address),
+                    ),
+                    Err(err) => back_log(
+                        "RUST",
+                        format!(
+                            "Cleanup {} after cancelled connect to {}: {}",
+                            attempt, address, err
+                        ),
+                    ),
+                }
+            }
+        }
+        let entry = {
+            let Ok(mut inner) = self.inner.lock() else {
+                return;
+            };
+            inner.active = inner.active.saturating_sub(1);
+            self.active_tx.send_replace(inner.active);
+            inner.tokens.remove(&key(address));
+            match (&result, cancelled) {
+                (Ok(()), false) => {
+                    Self::set_phase(&mut inner, address, ConnectionPhase::Connected, None)
+                }
+                (_, true) => Self::set_phase(&mut inner, address, ConnectionPhase::Idle, None),
+                (Err(err), false) => Self::set_phase(
+                    &mut inner,
+                    address,
+                    ConnectionPhase::Idle,
+                    Some(err.clone()),
+                ),
+            }
+        };
+        if entry.state == ConnectionPhase::Connected {
+            publish_device_state(crate::get_device_states().set_connected(address, true));
+            crate::get_keepalive().start(address, backend);
+        }
+        emit_state(&entry);
+        emit_result(
+            address.to_string(),
+            match entry.state {
+                ConnectionPhase::Connected => Ok(()),
+                _ => Err(entry
+                    .last_error
+                    .clone()
+                    .unwrap_or(StoneError::ConnectCancelled)),
+            },
+        );
+        self.pump(backend);
+    }
+
+    /// Drops a queued connect; returns false when the address was not waiting in the queue.
+    fn dequeue(&self, address: &str) -> bool {
+        let entry = {
+            let Ok(mut inner) = self.inner.lock() else {
+                return false;
+            };
+            let before = inner.queue.len();
+            inner
+                .queue
+                .retain(|queued| !queued.eq_ignore_ascii_case(address));
+            if inner.queue.len() == before {
+                return false;
+            }
+            Self::set_phase(&mut inner, address, ConnectionPhase::Idle, None)
+        };
+        emit_state(&entry);
+        emit_result(address.to_string(), Err(StoneError::ConnectCancelled));
+        true
+    }
+
+    /// Calls off a queued or running connect; returns false when none was pending.
+    pub(crate) fn cancel(&self, address: &str) -> bool {
+        if self.dequeue(address) {
+            return true;
+        }
+        let token = self
+            .inner
+            .lock()
+            .ok()
+            .and_then(|inner| inner.tokens.get(&key(address)).cloned());
+        match token {
+            Some(token) => {
+                back_log("RUST", format!("Cancel connect: {}", address));
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) async fn disconnect(
+        &self,
+        address: &str,
+        backend: &BackendState,
+    ) -> Result<(), StoneError> {
+        let pending = matches!(
+            self.phase(address),
+            ConnectionPhase::Queued | ConnectionPhase::Connecting
+        );
+        // An interrupted connect is torn down and reported by its own task.
+        if pending && self.cancel(address) {
+            return Ok(());
+        }
+        let previous = self.phase(address);
+        crate::get_keepalive().stop(address);
+        if let Ok(mut inner) = self.inner.lock() {
+            let entry = Self::set_phase(&mut inner, address, ConnectionPhase::Disconnecting, None);
+            drop(inner);
+            emit_state(&entry);
+        }
+        back_log("RUST", format!("Disconnect request: {}", address));
+        let result = backend.disconnect_device(address).await;
+        let entry = {
+            let Ok(mut inner) = self.inner.lock() else {
+                return result;
+            };
+            match &result {
+                Ok(()) => Self::set_phase(&mut inner, address, ConnectionPhase::Idle, None),
+                Err(_) => Self::set_phase(&mut inner, address, previous, None),
+            }
+        };
+        emit_state(&entry);
+        result
+    }
+
+    /// Follows a link the speaker or the OS opened or closed on its own. Returns true when the
+    /// change dropped an established connection.
+    pub(crate) fn link_changed(&self, address: &str, connected: bool) -> bool {
+        let entry = {
+            let Ok(mut inner) = self.inner.lock() else {
+                return false;
+            };
+            let phase = inner.entries.get(&key(address)).map(|entry| entry.state);
+            match (phase, connected) {
+                (Some(ConnectionPhase::Connected), false) => {
+                    Self::set_phase(&mut inner, address, ConnectionPhase::Idle, None)
+                }
+                // A running connect settles the phase itself once it finishes.
+                (
+                    Some(
+                        ConnectionPhase::Connected
+                        | ConnectionPhase::Queued
+                        | ConnectionPhase::Connecting,
+                    ),
+                    true,
+                ) => return false,
+                (_, true) => Self::set_phase(&mut inner, address, ConnectionPhase::Connected, None),
+                (_, false) => return false,
+            }
+        };
+        emit_state(&entry);
+        !connected
+    }
+
+    /// Holds new connects back, waits for the running ones to finish and then runs `scan`.
+    /// A second scan while one is pending gets `Busy` rather than an empty list.
+    pub(crate) async fn with_scan_turn<T>(
+        &self,
+        scan: impl std::future::Future<Output = Result<Vec<T>, StoneError>>,
+        backend: &BackendState,
+    ) -> Result<Vec<T>, StoneError> {
+        if let Ok(mut inner) = self.inner.lock() {
+            if inner.scan_pending {
+                return Err(StoneError::Busy {
+                    message: "Another scan is running".to_string(),
+                });
+            }
+            inner.scan_pending = true;
+        }
+        let _turn = ScanTurn {
+            manager: self,
+            backend,
+        };
+        let mut active_rx = self.active_tx.subscribe();
+        let drained = active_rx.wait_for(|active| *active == 0).await.is_ok();
+        if drained {
+            scan.await
+        } else {
+            Err(StoneError::other("Connection state closed"))
+        }
+    }
+}
+
+/// Ends a scan turn however the scan goes, dropped and panicking ones included, so
+/// queued connects always resume.
+struct ScanTurn<'a> {
+    manager: &'a ConnectionManager,
+    backend: &'a BackendState,
+}
+
+impl Drop for ScanTurn<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut inner) = self.manager.inner.lock() {
+            inner.scan_pending = false;
+        }
+        self.manager.pump(self.backend);
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::backend::BluetoothBackend;
+    use crate::{BluetoothDeviceInfo, ConnectionInfo};
+    use async_trait::async_trait;
+    use std::collections::HashSet;
+    use std::future::Future;
+    use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    // Every `bt_connect_result` as (address, error kind or "ok").
+    static RESULTS: Mutex<Vec<(String, &'static str)>> = Mutex::new(Vec::new());
+
+    pub(super) fn record_result(address: &str, result: &Result<(), StoneError>) {
+        let kind = result.as_ref().map_or_else(StoneError::kind, |_| "ok");
+        RESULTS.lock().unwrap().push((key(address), kind));
+    }
+
+    fn results(address: &str) -> Vec<&'static str> {
+        RESULTS
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(reported, _)| *reported == key(address))
+            .map(|(_, kind)| *kind)
+            .collect()
+    }
+
+    /// Connects after `connect_ms` like a platform call that cannot be interrupted.
+    #[derive(Default)]
+    pub(crate) struct StubBackend {
+        pub connect_ms: u64,
+        pub fail: bool,
+        pub stuck: AtomicBool,
+        pub attempts: AtomicU32,
+        pub connected: Arc<Mutex<HashSet<String>>>,
+    }
+
+    #[async_trait]
+    impl BluetoothBackend for StubBackend {
+        fn name(&self) -> &'static str {
+            "stub"
+        }
+
+        async fn list_devices(&self) -> Result<Vec<BluetoothDeviceInfo>, StoneError> {
+            Ok(Vec::new())
+        }
+
+        async fn scan_unpaired_stone_devices(
+            &self,
+        ) -> Result<Vec<BluetoothDeviceInfo>, StoneError> {
+            Ok(Vec::new())
+        }
+
+        async fn get_connection_infos(&self) -> Result<Vec<ConnectionInfo>, StoneError> {
+            Ok(self
+                .connected
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|address| ConnectionInfo {
+                    address: address.clone(),
+                    link: true,
+                    rfcomm: true,
+                })
+                .collect())
+        }
+
+        async fn connect_device(&self, address: &str) -> Result<(), StoneError> {
+            self.attempts.fetch_add(1, Ordering::SeqCst);
+            let (connect_ms, fail) = (self.connect_ms, self.fail);
+            let (connected, address) = (self.connected.clone(), address.to_string());
+            // Off the task, so dropping the future does not stop it.
+            let attempt = tauri::async_runtime::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(connect_ms)).await;
+                if !fail {
+                    connected.lock().unwrap().insert(address);
+                }
+            });
+            let _ = attempt.await;
+            if fail {
+                return Err(StoneError::ConnectFailed {
+                    message: "stub".to_string(),
+                });
+            }
+            Ok(())
+        }
+
+        async fn disconnect_device(&self, address: &str) -> Result<(), StoneError> {
+            if self.stuck.load(Ordering::SeqCst) {
+                return Err(StoneError::other("stub stuck"));
+            }
+            self.connected.lock().unwrap().remove(address);
+            Ok(())
+        }
+
+        async fn write_frame(&self, _address: &str, _frame: &[u8]) -> Result<(), StoneError> {
+            Err(StoneError::NotConnected)
+        }
+    }
+
+    pub(crate) fn backend(stub: &Arc<StubBackend>) -> BackendState {
+        BackendState(stub.clone())
+    }
+
+    async fn settle(address: &str) {
+        for _ in 0..100 {
+            if !results(address).is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        // Long enough for a second report to show up if there were one.
+        tokio::time::sleep(Duration::from_millis(300)).await;
+    }
+
+    #[test]
+    fn cancel_mid_connect_reports_once_and_leaves_no_connection() {
+        let address = "00:00:5E:00:53:30";
+        let stub = Arc::new(StubBackend {
+            connect_ms: 200,
+            ..StubBackend::default()
+        });
+        tauri::async_runtime::block_on(async {
+            let connections = crate::get_connections();
+            connections.enqueue(address, &backend(&stub));
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            assert_eq!(connections.phase(address), ConnectionPhase::Connecting);
+            assert!(connections.cancel(address));
+
+            settle(address).await;
+            assert_eq!(results(address), vec!["connect_cancelled"]);
+            assert_eq!(connections.phase(address), ConnectionPhase::Idle);
+            assert!(stub.connected.lock().unwrap().is_empty());
+            assert!(!connections.cancel(address));
+        });
+    }
+
+    #[test]
+    fn cancel_during_backoff_stops_retrying() {
+        let address = "00:00:5E:00:53:31";
+        let stub = Arc::new(StubBackend {
+            fail: true,
+            ..StubBackend::default()
+        });
+        tauri::async_runtime::block_on(async {
+            let connections = crate::get_connections();
+            let policy = RetryPolicy {
+                initial_delay_ms: 500,
+                max_delay_ms: 500,
+                jitter: 0.0,
+                ..RetryPolicy::default()
+            };
+            connections.set_policy(Some(address), Some(policy)).unwrap();
+            connections.enqueue(address, &backend(&stub));
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            assert_eq!(stub.attempts.load(Ordering::SeqCst), 1);
+            assert!(connections.cancel(address));
+
+            settle(address).await;
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            assert_eq!(stub.attempts.load(Ordering::SeqCst), 1);
+            assert_eq!(results(address), vec!["connect_cancelled"]);
+            assert_eq!(connections.phase(address), ConnectionPhase::Idle);
+        });
+    }
+
+    #[test]
+    fn cancel_that_cannot_undo_the_connect_still_reports_cancelled() {
+        let address = "00:00:5E:00:53:32";
+        let stub = Arc::new(StubBackend {
+            connect_ms: 100,
+            stuck: AtomicBool::new(true),
+            ..StubBackend::default()
+        });
+        tauri::async_runtime::block_on(async {
+            let connections = crate::get_connections();
+            connections.enqueue(address, &backend(&stub));
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            assert!(connections.cancel(address));
+
+            settle(address).await;
+            assert_eq!(results(address), vec!["connect_cancelled"]);
+            assert_eq!(connections.phase(address), ConnectionPhase::Idle);
+            assert!(!crate::get_device_states().get(address).connected);
+            assert!(stub.connected.lock().unwrap().contains(address));
+        });
+    }
+
+    #[test]
+    fn links_opened_elsewhere_count_as_connected() {
+        let manager = ConnectionManager::new();
+        let address = "00:00:5E:00:53:33";
+        assert!(!manager.link_changed(address, false));
+        assert_eq!(manager.phase(address), ConnectionPhase::Idle);
+
+        assert!(!manager.link_changed(address, true));
+        assert_eq!(manager.phase(address), ConnectionPhase::Connected);
+        assert!(!manager.link_changed(address, true));
+        assert!(manager.link_changed(&address.to_lowercase(), false));
+        assert_eq!(manager.phase(address), ConnectionPhase::Idle);
+
+        // A connect in flight keeps its phase until it reports.
+        let other = "00:00:5E:00:53:34";
+        {
+            let mut inner = manager.inner.lock().unwrap();
+            ConnectionManager::set_phase(&mut inner, other, ConnectionPhase::Connecting, None);
+        }
+        assert!(!manager.link_changed(other, true));
+        assert_eq!(manager.phase(other), ConnectionPhase::Connecting);
+    }
+
+    #[test]
+    fn scan_waits_for_running_connects() {
+        let manager = ConnectionManager::new();
+        let stub = Arc::new(StubBackend::default());
+        let backend = backend(&stub);
+        tauri::async_runtime::block_on(async {
+            manager.inner.lock().unwrap().active = 1;
+            manager.active_tx.send_replace(1);
+
+            let scanned = AtomicBool::new(false);
+            let scan = manager.with_scan_turn(
+                async {
+                    scanned.store(true, Ordering::SeqCst);
+                    Ok(vec![1])
+                },
+                &backend,
+            );
+            let release = async {
+                tokio::time::sleep(Duration::from_millis(200)).await;
+                assert!(!scanned.load(Ordering::SeqCst));
+                assert!(matches!(
+                    manager
+                        .with_scan_turn(async { Ok(vec![2]) }, &backend)
+                        .await,
+                    Err(StoneError::Busy { .. })
+                ));
+                manager.inner.lock().unwrap().active = 0;
+                manager.active_tx.send_replace(0);
+            };
+            let mut scan = std::pin::pin!(scan);
+            let mut release = std::pin::pin!(release);
+            let mut released = false;
+            let result = std::future::poll_fn(|cx| {
+                if !released && release.as_mut().poll(cx).is_ready() {
+                    released = true;
+                }
+                scan.as_mut().poll(cx)
+            })
+            .await;
+            assert_eq!(result.unwrap(), vec![1]);
+            assert!(!manager.inner.lock().unwrap().scan_pending);
+        });
+    }
+
+    #[test]
+    fn dropped_scan_turn_lets_connects_and_scans_go_on() {
+        let manager = ConnectionManager::new();
+        let stub = Arc::new(StubBackend::default());
+        let backend = backend(&stub);
+        tauri::async_runtime::block_on(async {
+            manager.inner.lock().unwrap().active = 1;
+            manager.active_tx.send_replace(1);
+            let waiting = manager.with_scan_turn(async { Ok(vec![1]) }, &backend);
+            // Abandoned while it waits for the running connect, like a closed scan dialog.
+            assert!(tokio::time::timeout(Duration::from_millis(50), waiting)
+                .await
+                .is_err());
+            assert!(!manager.inner.lock().unwrap().scan_pending);
+
+            manager.inner.lock().unwrap().active = 0;
+            manager.active_tx.send_replace(0);
+            let scanned = manager
+                .with_scan_turn(async { Ok(vec![2]) }, &backend)
+                .await;
+            assert_eq!(scanned.unwrap(), vec![2]);
+        });
+    }
+}
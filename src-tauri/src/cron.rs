@@ -0,0 +1,287 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, TimeZone, Timelike};
+
+use crate::error::StoneError;
+
+/// A five-field cron expression (`minute hour day-of-month month day-of-week`) in local time.
+/// Fields take `*`, numbers, `a-b` ranges, `/step` and comma lists; Sunday is 0 or 7.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct CronExpr {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    // Standard cron: when both day fields are restricted, either one matching is enough.
+    any_day: bool,
+}
+
+fn invalid(expression: &str, reason: &str) -> StoneError {
+    StoneError::InvalidParameter {
+        message: format!("Invalid cron expression \"{}\": {}", expression, reason),
+    }
+}
+
+fn parse_field(expression: &str, field: &str, min: u32, max: u32) -> Result<u64, StoneError> {
+    let mut bits = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (
+                range,
+                step.parse::<u32>()
+                    .ok()
+                    .filter(|step| *step > 0)
+                    .ok_or_else(|| invalid(expression, "bad step"))?,
+            ),
+            None => (part, 1),
+        };
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            let parse = |value: &str| {
+                value
+                    .parse::<u32>()
+                    .map_err(|_| invalid(expression, "bad number"))
+            };
+            (parse(start)?, parse(end)?)
+        } else {
+            let value = range
+                .parse::<u32>()
+                .map_err(|_| invalid(expression, "bad number"))?;
+            // `5/15` means from 5 to the end of the field in steps of 15.
+            (value, if step > 1 { max } else { value })
+        };
+        if start < min || end > max || start > end {
+            return Err(invalid(expression, "value out of range"));
+        }
+        for value in (start..=end).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+impl CronExpr {
+    pub(crate) fn parse(expression: &str) -> Result<Self, StoneError> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(invalid(expression, "expected 5 fields"));
+        };
+        let mut weekdays = parse_field(expression, weekday, 0, 7)?;
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays | 1) & !(1 << 7);
+        }
+        Ok(Self {
+            minutes: parse_field(expression, minute, 0, 59)?,
+            hours: parse_field(expression, hour, 0, 23)?,
+            days: parse_field(expression, day, 1, 31)?,
+            months: parse_field(expression, month, 1, 12)?,
+            weekdays,
+            any_day: day != "*" && weekday != "*",
+        })
+    }
+
+    fn matches_day(&self, date: NaiveDate) -> bool {
+        let day = self.days & (1 << date.day()) != 0;
+        let weekday = self.weekdays & (1 << date.weekday().num_days_from_sunday()) != 0;
+        if self.any_day {
+            day || weekday
+        } else {
+            day && weekday
+        }
+    }
+
+    /// The first matching minute strictly after `after`, looking at most a few years ahead.
+    pub(crate) fn next_after<Tz: TimeZone>(&self, after: DateTime<Tz>) -> Option<DateTime<Tz>> {
+        let start = after.naive_local().with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let limit = start + Duration::days(366 * 5);
+        let mut candidate = start;
+        while candidate < limit {
+            let date = candidate.date();
+            if self.months & (1 << date.month()) == 0 {
+                let (year, month) = if date.month() == 12 {
+                    (date.year() + 1, 1)
+                } else {
+                    (date.year(), date.month() + 1)
+                };
+                candidate = NaiveDate::from_ymd_opt(year, month, 1)?.and_hms_opt(0, 0, 0)?;
+                continue;
+            }
+            if !self.matches_day(date) {
+                candidate = next_day(candidate)?;
+                continue;
+            }
+            if self.hours & (1 << candidate.hour()) == 0 {
+                candidate = candidate.with_minute(0)? + Duration::hours(1);
+                continue;
+            }
+            if self.minutes & (1 << candidate.minute()) == 0 {
+                candidate += Duration::minutes(1);
+                continue;
+            }
+            // A time skipped by a DST change does not exist locally; move on to the next one.
+            if let Some(time) = after.timezone().from_local_datetime(&candidate).earliest() {
+                return Some(time);
+            }
+            candidate += Duration::minutes(1);
+        }
+        None
+    }
+}
+
+fn next_day(time: NaiveDateTime) -> Option<NaiveDateTime> {
+    time.date().succ_opt()?.and_hms_opt(0, 0, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{FixedOffset, MappedLocalTime, Utc};
+
+    fn bits(values: &[u32]) -> u64 {
+        values.iter().fold(0, |bits, value| bits | 1 << value)
+    }
+
+    fn at(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, month, day, hour, minute, 0)
+            .unwrap()
+    }
+
+    fn next(expression: &str, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        CronExpr::parse(expression).unwrap().next_after(after)
+    }
+
+    /// Central European time around 2026-03-29, when clocks jump from 02:00 to 03:00.
+    #[derive(Clone, Copy, Debug)]
+    struct SpringForward;
+
+    impl SpringForward {
+        fn offset(hours: i32) -> FixedOffset {
+            FixedOffset::east_opt(hours * 3600).unwrap()
+        }
+
+        fn switch() -> NaiveDateTime {
+            NaiveDate::from_ymd_opt(2026, 3, 29)
+                .unwrap()
+                .and_hms_opt(1, 0, 0)
+                .unwrap()
+        }
+    }
+
+    impl TimeZone for SpringForward {
+        type Offset = FixedOffset;
+
+        fn from_offset(_offset: &FixedOffset) -> Self {
+            SpringForward
+        }
+
+        fn offset_from_local_date(&self, local: &NaiveDate) -> MappedLocalTime<FixedOffset> {
+            self.offset_from_local_datetime(&local.and_hms_opt(0, 0, 0).unwrap())
+        }
+
+        fn offset_from_local_datetime(
+            &self,
+            local: &NaiveDateTime,
+        ) -> MappedLocalTime<FixedOffset> {
+            let winter = *local - Duration::hours(1);
+            let summer = *local - Duration::hours(2);
+            match (winter < Self::switch(), summer >= Self::switch()) {
+                (true, _) => MappedLocalTime::Single(Self::offset(1)),
+                (_, true) => MappedLocalTime::Single(Self::offset(2)),
+                _ => MappedLocalTime::None,
+            }
+        }
+
+        fn offset_from_utc_date(&self, utc: &NaiveDate) -> FixedOffset {
+            self.offset_from_utc_datetime(&utc.and_hms_opt(0, 0, 0).unwrap())
+        }
+
+        fn offset_from_utc_datetime(&self, utc: &NaiveDateTime) -> FixedOffset {
+            Self::offset(if *utc < Self::switch() { 1 } else { 2 })
+        }
+    }
+
+    #[test]
+    fn parses_ranges_steps_and_lists() {
+        let expr = CronExpr::parse("*/20 9-17/4 1,15,31 */6 1-5").unwrap();
+        assert_eq!(expr.minutes, bits(&[0, 20, 40]));
+        assert_eq!(expr.hours, bits(&[9, 13, 17]));
+        assert_eq!(expr.days, bits(&[1, 15, 31]));
+        assert_eq!(expr.months, bits(&[1, 7]));
+        assert_eq!(expr.weekdays, bits(&[1, 2, 3, 4, 5]));
+
+        // A single value with a step runs to the end of the field.
+        let expr = CronExpr::parse("5/15 0,12-13 * * *").unwrap();
+        assert_eq!(expr.minutes, bits(&[5, 20, 35, 50]));
+        assert_eq!(expr.hours, bits(&[0, 12, 13]));
+
+        for bad in [
+            "* * * *",
+            "60 * * * *",
+            "* 24 * * *",
+            "* * 0 * *",
+            "* * * 13 *",
+            "* * * * 8",
+            "5-1 * * * *",
+            "*/0 * * * *",
+            "a * * * *",
+            "1,,2 * * * *",
+        ] {
+            assert!(CronExpr::parse(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn seven_is_sunday() {
+        let sunday = CronExpr::parse("0 0 * * 7").unwrap();
+        assert_eq!(sunday.weekdays, bits(&[0]));
+        assert_eq!(sunday, CronExpr::parse("0 0 * * 0").unwrap());
+        assert_eq!(
+            CronExpr::parse("0 0 * * 5-7").unwrap().weekdays,
+            bits(&[0, 5, 6])
+        );
+        // 2026-10-17 is a Saturday.
+        assert_eq!(
+            next("0 0 * * 7", at(2026, 10, 17, 12, 0)),
+            Some(at(2026, 10, 18, 0, 0))
+        );
+    }
+
+    #[test]
+    fn either_day_field_matches_when_both_are_restricted() {
+        // The 13th, or any Friday.
+        let after = at(2026, 10, 17, 12, 0);
+        assert_eq!(next("0 9 13 * 5", after), Some(at(2026, 10, 23, 9, 0)));
+        assert_eq!(next("0 9 19 * 5", after), Some(at(2026, 10, 19, 9, 0)));
+        // With one side left open, the other must match.
+        assert_eq!(next("0 9 13 * *", after), Some(at(2026, 11, 13, 9, 0)));
+        assert_eq!(next("0 9 * * 5", after), Some(at(2026, 10, 23, 9, 0)));
+        assert_eq!(next("0 9 13 11 *", after), Some(at(2026, 11, 13, 9, 0)));
+    }
+
+    #[test]
+    fn finds_the_next_minute_strictly_after() {
+        let after = Utc.with_ymd_and_hms(2026, 10, 17, 8, 59, 30).unwrap();
+        assert_eq!(next("* * * * *", after), Some(at(2026, 10, 17, 9, 0)));
+        assert_eq!(next("59 8 * * *", after), Some(at(2026, 10, 18, 8, 59)));
+        assert_eq!(next("0 0 1 1 *", after), Some(at(2027, 1, 1, 0, 0)));
+        assert_eq!(next("0 0 29 2 *", after), Some(at(2028, 2, 29, 0, 0)));
+        assert_eq!(next("0 0 30 2 *", after), None);
+    }
+
+    #[test]
+    fn skips_minutes_lost_to_a_clock_change() {
+        let before = SpringForward
+            .with_ymd_and_hms(2026, 3, 28, 12, 0, 0)
+            .unwrap();
+        let expected = |day, hour, minute| {
+            SpringForward
+                .with_ymd_and_hms(2026, 3, day, hour, minute, 0)
+                .unwrap()
+        };
+        let half_past_two = CronExpr::parse("30 2 * * *").unwrap();
+        assert_eq!(half_past_two.next_after(before), Some(expected(30, 2, 30)));
+        let hourly = CronExpr::parse("0 * * * *").unwrap();
+        let night = expected(29, 1, 30);
+        assert_eq!(hourly.next_after(night), Some(expected(29, 3, 0)));
+    }
+}
@@ -0,0 +1,296 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::pt::{BatteryStep, PtCommand, PtReport, Rgb};
+
+const MAX_STAGED_PER_COMMAND: usize = 16;
+
+#[derive(Serialize, Clone, Default, Debug)]
+pub(crate) struct DeviceState {
+    pub address: String,
+    pub connected: bool,
+    pub battery_step: Option<u8>,
+    pub battery_level: Option<u8>,
+    pub dc_state: Option<u8>,
+    pub volume: Option<u8>,
+    pub lamp_on: Option<bool>,
+    pub lamp_brightness: Option<u8>,
+    pub lamp_type: Option<u8>,
+    pub lamp_color: Option<Rgb>,
+    pub name: Option<String>,
+    pub firmware: Option<String>,
+    pub mac: Option<String>,
+    pub rssi: Option<i8>,
+    pub wheel_count: Option<i32>,
+}
+
+impl DeviceState {
+    fn new(address: &str) -> Self {
+        Self {
+            address: address.to_string(),
+            ..Self::default()
+        }
+    }
+
+    fn apply_report(&mut self, report: &PtReport) {
+        match report {
+            PtReport::BatteryStep(battery) => {
+                self.battery_step = Some(battery.step);
+                if battery.level.is_some() {
+                    self.battery_level = battery.level;
+                }
+            }
+            PtReport::DcState(dc) => self.dc_state = Some(dc.state),
+            PtReport::Volume(volume) => self.volume = Some(volume.volume),
+            PtReport::LampState(lamp) => {
+                self.lamp_on = Some(lamp.on);
+                self.lamp_brightness = Some(lamp.brightness);
+                self.lamp_type = Some(lamp.lamp_type);
+                self.lamp_color = Some(lamp.color);
+            }
+            PtReport::DeviceName(name) if !name.0.is_empty() => self.name = Some(name.0.clone()),
+            PtReport::Firmware(firmware) if !firmware.0.is_empty() => {
+                self.firmware = Some(firmware.0.clone())
+            }
+            PtReport::Mac(mac) if !mac.0.is_empty() => self.mac = Some(mac.0.clone()),
+            PtReport::Rssi(rssi) => self.rssi = Some(rssi.0),
+            PtReport::WheelCount(wheel) => self.wheel_count = Some(wheel.0),
+            _ => {}
+        }
+    }
+
+    fn apply_command(&mut self, command: &PtCommand) {
+        match *command {
+            PtCommand::SetVolume(volume) => self.volume = Some(volume),
+            PtCommand::SetLampBrightness(brightness) => self.lamp_brightness = Some(brightness),
+            PtCommand::SetLampType(lamp_type) => self.lamp_type = Some(lamp_type),
+            PtCommand::SetLampColor(color) => self.lamp_color = Some(color),
+            PtCommand::RunLamp(settings) => {
+                self.lamp_on = Some(true);
+                self.lamp_brightness = Some(settings.brightness);
+                self.lamp_type = Some(settings.lamp_type);
+                self.lamp_color = Some(settings.color);
+            }
+            PtCommand::StopLamp => self.lamp_on = Some(false),
+            _ => {}
+        }
+    }
+
+    /// Battery percentage as the UI shows it: the exact level when asked for and known,
+    /// otherwise the coarse step.
+    pub(crate) fn battery_percent(&self, use_level: bool) -> Option<u8> {
+        let step = self.battery_step;
+        let level = if use_level { self.battery_level } else { None };
+        match (step, level) {
+            (_, Some(level)) => Some(level.min(100)),
+            (Some(step), None) => Some(BatteryStep { step, level: None }.percent()),
+            (None, None) => None,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub(crate) struct DeviceStateChanged {
+    pub address: String,
+    pub changes: Map<String, Value>,
+}
+
+fn diff(before: &DeviceState, after: &DeviceState) -> Map<String, Value> {
+    let (Ok(Value::Object(before)), Ok(Value::Object(after))) =
+        (serde_json::to_value(before), serde_json::to_value(after))
+    else {
+        return Map::new();
+    };
+    after
+        .into_iter()
+        .filter(|(key, value)| key != "address" && before.get(key) != Some(value))
+        .collect()
+}
+
+/// A setter written to a speaker and waiting for its ACK.
+struct Staged {
+    token: u64,
+    command: PtCommand,
+    // An ACK after this belongs to a later write, if to any.
+    expires: Instant,
+}
+
+/// Latest known state per speaker, fed from decoded PT packets.
+#[derive(Default)]
+pub(crate) struct DeviceStateStore {
+    states: Mutex<HashMap<String, DeviceState>>,
+    // Setters carry no value in their ACK, so the request is parked until it is acknowledged.
+    staged: Mutex<HashMap<(String, u16), VecDeque<Staged>>>,
+    next_token: AtomicU64,
+}
+
+impl DeviceStateStore {
+    pub(crate) fn get(&self, address: &str) -> DeviceState {
+        self.states
+            .lock()
+            .ok()
+            .and_then(|states| states.get(&address.to_ascii_lowercase()).cloned())
+            .unwrap_or_else(|| DeviceState::new(address))
+    }
+
+    fn update(
+        &self,
+        address: &str,
+        apply: impl FnOnce(&mut DeviceState),
+    ) -> Option<DeviceStateChanged> {
+        let mut states = self.states.lock().ok()?;
+        let state = states
+            .entry(address.to_ascii_lowercase())
+            .or_insert_with(|| DeviceState::new(address));
+        let before = state.clone();
+        apply(state);
+        let changes = diff(&before, state);
+        (!changes.is_empty()).then(|| DeviceStateChanged {
+            address: state.address.clone(),
+            changes,
+        })
+    }
+
+    pub(crate) fn apply_report(
+        &self,
+        address: &str,
+        report: &PtReport,
+    ) -> Option<DeviceStateChanged> {
+        self.update(address, |state| {
+            state.connected = true;
+            state.apply_report(report);
+        })
+    }
+
+    pub(crate) fn set_connected(
+        &self,
+        address: &str,
+        connected: bool,
+    ) -> Option<DeviceStateChanged> {
+        if !connected {
+            if let Ok(mut staged) = self.staged.lock() {
+                staged.retain(|(key, _), _| !key.eq_ignore_ascii_case(address));
+            }
+        }
+        self.update(address, |state| state.connected = connected)
+    }
+
+    /// Parks a setter until its ACK arrives or `timeout_ms` passes. The returned token takes
+    /// it back out with `unstage` when the write fails or the request gives up.
+    pub(crate) fn stage_command(
+        &self,
+        address: &str,
+        command: PtCommand,
+        timeout_ms: u64,
+    ) -> Option<u64> {
+        if !matches!(
+            command,
+            PtCommand::SetVolume(_)
+                | PtCommand::SetLampBrightness(_)
+                | PtCommand::SetLampType(_)
+                | PtCommand::SetLampColor(_)
+                | PtCommand::RunLamp(_)
+                | PtCommand::StopLamp
+        ) {
+            return None;
+        }
+        let token = self.next_token.fetch_add(1, Ordering::Relaxed);
+        let mut staged = self.staged.lock().ok()?;
+        let queue = staged
+            .entry((address.to_ascii_lowercase(), command.id()))
+            .or_default();
+        if queue.len() >= MAX_STAGED_PER_COMMAND {
+            queue.pop_front();
+        }
+        queue.push_back(Staged {
+            token,
+            command,
+            expires: Instant::now() + Duration::from_millis(timeout_ms),
+        });
+        Some(token)
+    }
+
+    pub(crate) fn unstage(&self, address: &str, command_id: u16, token: u64) {
+        let Ok(mut staged) = self.staged.lock() else {
+            return;
+        };
+        let key = (address.to_ascii_lowercase(), command_id);
+        if let Some(queue) = staged.get_mut(&key) {
+            queue.retain(|entry| entry.token != token);
+            if queue.is_empty() {
+                staged.remove(&key);
+            }
+        }
+    }
+
+    pub(crate) fn acknowledge(
+        &self,
+        address: &str,
+        command_id: u16,
+        success: bool,
+    ) -> Option<DeviceStateChanged> {
+        let command = {
+            let mut staged = self.staged.lock().ok()?;
+            let key = (address.to_ascii_lowercase(), command_id);
+            let queue = staged.get_mut(&key)?;
+            let now = Instant::now();
+            queue.retain(|entry| entry.expires > now);
+            let command = queue.pop_front();
+            if queue.is_empty() {
+                staged.remove(&key);
+            }
+            command?.command
+        };
+        if !success {
+            return None;
+        }
+        self.update(address, |state| state.apply_command(&command))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDRESS: &str = "00:00:5E:00:53:20";
+
+    #[test]
+    fn failed_write_leaves_the_earlier_write_staged() {
+        let store = DeviceStateStore::default();
+        let sent = store.stage_command(ADDRESS, PtCommand::SetVolume(10), 1_000);
+        let failed = store
+            .stage_command(ADDRESS, PtCommand::SetVolume(20), 1_000)
+            .unwrap();
+        assert!(sent.is_some());
+        store.unstage(ADDRESS, PtCommand::SetVolume(0).id(), failed);
+
+        store.acknowledge(ADDRESS, PtCommand::SetVolume(0).id(), true);
+        assert_eq!(store.get(ADDRESS).volume, Some(10));
+    }
+
+    #[test]
+    fn unanswered_writes_expire() {
+        let store = DeviceStateStore::default();
+        store.stage_command(ADDRESS, PtCommand::SetVolume(10), 0);
+        store.stage_command(ADDRESS, PtCommand::SetVolume(20), 1_000);
+        std::thread::sleep(Duration::from_millis(5));
+
+        store.acknowledge(ADDRESS, PtCommand::SetVolume(0).id(), true);
+        assert_eq!(store.get(ADDRESS).volume, Some(20));
+        assert!(store
+            .acknowledge(ADDRESS, PtCommand::SetVolume(0).id(), true)
+            .is_none());
+    }
+
+    #[test]
+    fn queries_are_not_staged() {
+        let store = DeviceStateStore::default();
+        assert!(store
+            .stage_command(ADDRESS, PtCommand::GetVolume, 1_000)
+            .is_none());
+    }
+}
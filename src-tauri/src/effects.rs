@@ -0,0 +1,498 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use tauri::Emitter;
+use tokio::time::Instant;
+
+use crate::backend::BackendState;
+use crate::broadcast::{self, BroadcastReport, BroadcastTarget};
+use crate::cancel::CancelToken;
+use crate::connection::ConnectionPhase;
+use crate::error::StoneError;
+use crate::pt::{self, LampSettings, PtCommand, Rgb};
+use crate::retry::unix_millis;
+use crate::{back_log, APP_HANDLE};
+
+// An RFCOMM link keeps up with a couple of PT writes every 150 ms; faster frames only queue.
+const MIN_FRAME_INTERVAL_MS: u64 = 150;
+// A frame that is not acknowledged by then is given up so the next one stays on time.
+const FRAME_TIMEOUT_MS: u64 = 1_000;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub(crate) enum Effect {
+    Breathing { color: Rgb },
+    Rainbow,
+    Strobe { color: Rgb },
+    SunsetFade,
+}
+
+/// An effect with its timing. `period_ms` is one cycle, or the whole fade for `sunset_fade`,
+/// which stops the lamp when it ends.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct EffectSpec {
+    #[serde(flatten)]
+    pub effect: Effect,
+    #[serde(default)]
+    pub period_ms: Option<u64>,
+    #[serde(default = "default_brightness")]
+    pub brightness: u8,
+    #[serde(default)]
+    pub duration_ms: Option<u64>,
+    #[serde(default = "default_frame_interval")]
+    pub frame_interval_ms: u64,
+}
+
+fn default_brightness() -> u8 {
+    pt::MAX_LAMP_BRIGHTNESS
+}
+
+fn default_frame_interval() -> u64 {
+    250
+}
+
+impl EffectSpec {
+    fn period(&self) -> u64 {
+        self.period_ms.unwrap_or(match self.effect {
+            Effect::Breathing { .. } => 4_000,
+            Effect::Rainbow => 6_000,
+            Effect::Strobe { .. } => 1_000,
+            Effect::SunsetFade => 300_000,
+        })
+    }
+
+    fn validate(&self) -> Result<(), StoneError> {
+        let invalid = |message: String| Err(StoneError::InvalidParameter { message });
+        if self.frame_interval_ms < MIN_FRAME_INTERVAL_MS {
+            return invalid(format!(
+                "frame_interval_ms must be at least {}",
+                MIN_FRAME_INTERVAL_MS
+            ));
+        }
+        if self.period() < self.frame_interval_ms * 2 {
+            return invalid("period_ms must be at least two frames".to_string());
+        }
+        if self.brightness > pt::MAX_LAMP_BRIGHTNESS {
+            return invalid(format!(
+                "brightness must be at most {}",
+                pt::MAX_LAMP_BRIGHTNESS
+            ));
+        }
+        Ok(())
+    }
+
+    /// What every lamp shows `elapsed_ms` into the effect, or `None` once it is over.
+    fn frame(&self, elapsed_ms: u64) -> Option<Frame> {
+        if self
+            .duration_ms
+            .is_some_and(|duration| elapsed_ms >= duration)
+        {
+            return None;
+        }
+        let period = self.period();
+        let phase = (elapsed_ms % period) as f64 / period as f64;
+        let peak = self.brightness as f64;
+        let frame = match self.effect {
+            Effect::Breathing { color } => Frame {
+                color,
+                brightness: peak * (1.0 - (phase * std::f64::consts::TAU).cos()) / 2.0,
+            },
+            Effect::Rainbow => Frame {
+                color: hue_to_rgb(phase * 360.0),
+                brightness: peak,
+            },
+            Effect::Strobe { color } => Frame {
+                color,
+                brightness: if phase < 0.5 { peak } else { 0.0 },
+            },
+            Effect::SunsetFade => {
+                if elapsed_ms >= period {
+                    return None;
+                }
+                Frame {
+                    color: gradient(&SUNSET, phase),
+                    brightness: peak * (1.0 - phase),
+                }
+            }
+        };
+        Some(frame)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct Frame {
+    color: Rgb,
+    brightness: f64,
+}
+
+impl Frame {
+    fn brightness(&self) -> u8 {
+        self.brightness
+            .round()
+            .clamp(0.0, pt::MAX_LAMP_BRIGHTNESS as f64) as u8
+    }
+}
+
+fn hue_to_rgb(hue: f64) -> Rgb {
+    let channel = |n: f64| {
+        let k = (n + hue / 60.0) % 6.0;
+        (255.0 * (1.0 - k.min(4.0 - k).clamp(0.0, 1.0))).round() as u8
+    };
+    Rgb {
+        r: channel(5.0),
+        g: channel(3.0),
+        b: channel(1.0),
+    }
+}
+
+/// The color `progress` of the way along evenly spaced `stops`.
+pub(crate) fn gradient(stops: &[Rgb], progress: f64) -> Rgb {
+    let scaled = progress.clamp(0.0, 1.0) * (stops.len() - 1) as f64;
+    let index = (scaled.floor() as usize).min(stops.len() - 2);
+    let t = scaled - index as f64;
+    let (from, to) = (stops[index], stops[index + 1]);
+    let mix = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * t).round() as u8;
+    Rgb {
+        r: mix(from.r, to.r),
+        g: mix(from.g, to.g),
+        b: mix(from.b, to.b),
+    }
+}
+
+// Warm white through orange down to a deep red.
+const SUNSET: [Rgb; 3] = [
+    Rgb::new(255, 190, 120),
+    Rgb::new(255, 110, 20),
+    Rgb::new(170, 20, 0),
+];
+
+/// Emitted as `lamp_effects_changed` with every running effect whenever one starts or ends.
+#[derive(Serialize, Clone, Debug)]
+pub(crate) struct EffectStatus {
+    id: u64,
+    spec: EffectSpec,
+    addresses: Vec<String>,
+    started_at: u64,
+}
+
+struct Running {
+    status: EffectStatus,
+    stop: CancelToken,
+}
+
+#[derive(Default)]
+struct Inner {
+    next_id: u64,
+    running: Vec<Running>,
+}
+
+fn release(inner: &mut Inner, addresses: &[String]) -> usize {
+    let before = inner.running.len();
+    inner.running.retain(|running| {
+        let overlaps = running.status.addresses.iter().any(|address| {
+            addresses
+                .iter()
+                .any(|target| target.eq_ignore_ascii_case(address))
+        });
+        if overlaps {
+            running.stop.cancel();
+        }
+        !overlaps
+    });
+    before - inner.running.len()
+}
+
+/// Plays lamp effects across several speakers from one clock. Each frame goes to all of
+/// them at once and the next one waits for the ACKs, so a slow link drops frames instead of
+/// drifting behind the others.
+#[derive(Default)]
+pub(crate) struct LampEffects {
+    inner: Mutex<Inner>,
+}
+
+impl LampEffects {
+    pub(crate) fn snapshot(&self) -> Vec<EffectStatus> {
+        self.inner
+            .lock()
+            .map(|inner| {
+                inner
+                    .running
+                    .iter()
+                    .map(|running| running.status.clone())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Starts `spec` on every address `target` stands for. Any effect already driving one of
+    /// those lamps is stopped first.
+    pub(crate) fn start(
+        &self,
+        target: &BroadcastTarget,
+        spec: EffectSpec,
+        backend: &BackendState,
+    ) -> Result<EffectStatus, StoneError> {
+        spec.validate()?;
+        let addresses = target.addresses();
+        if addresses.is_empty() {
+            return Err(StoneError::InvalidParameter {
+                message: "Effect has no targets".to_string(),
+            });
+        }
+        let stop = CancelToken::new();
+        let status = {
+            let mut inner = self
+                .inner
+                .lock()
+                .map_err(|_| StoneError::other("Effect state poisoned"))?;
+            release(&mut inner, &addresses);
+            inner.next_id += 1;
+            let status = EffectStatus {
+                id: inner.next_id,
+                spec,
+                addresses,
+                started_at: unix_millis(SystemTime::now()),
+            };
+            inner.running.push(Running {
+                status: status.clone(),
+                stop: stop.clone(),
+            });
+            status
+        };
+        back_log(
+            "RUST",
+            format!(
+                "Start lamp effect #{} {:?} on {} lamps",
+                status.id,
+                status.spec.effect,
+                status.addresses.len()
+            ),
+        );
+        self.announce();
+        let backend = backend.clone();
+        let task_status = status.clone();
+        tauri::async_runtime::spawn(async move {
+            play(task_status, backend, stop).await;
+        });
+        Ok(status)
+    }
+
+    /// Stops effect `id`, or every effect when `id` is `None`. Lamps keep their last frame.
+    pub(crate) fn stop(&self, id: Option<u64>) -> usize {
+        let stopped = {
+            let Ok(mut inner) = self.inner.lock() else {
+                return 0;
+            };
+            let before = inner.running.len();
+            inner.running.retain(|running| {
+                let matches = id.is_none_or(|id| running.status.id == id);
+                if matches {
+                    running.stop.cancel();
+                }
+                !matches
+            });
+            before - inner.running.len()
+        };
+        if stopped > 0 {
+            back_log("RUST", format!("Stopped {} lamp effects", stopped));
+            self.announce();
+        }
+        stopped
+    }
+
+    /// Stops every effect that drives one of `addresses`, before something else sets them.
+    pub(crate) fn release(&self, addresses: &[String]) {
+        let stopped = self
+            .inner
+            .lock()
+            .map(|mut inner| release(&mut inner, addresses))
+            .unwrap_or(0);
+        if stopped > 0 {
+            self.announce();
+        }
+    }
+
+    fn finished(&self, id: u64) {
+        let removed = self
+            .inner
+            .lock()
+            .map(|mut inner| {
+                let before = inner.running.len();
+                inner.running.retain(|running| running.status.id != id);
+                before != inner.running.len()
+            })
+            .unwrap_or(false);
+        if removed {
+            self.announce();
+        }
+    }
+
+    fn announce(&self) {
+        if let Some(app) = APP_HANDLE.get() {
+            let _ = app.emit("lamp_effects_changed", self.snapshot());
+        }
+    }
+}
+
+async fn send(backend: &BackendState, addresses: &[String], command: PtCommand) -> BroadcastReport {
+    broadcast::send_to(
+        backend,
+        addresses,
+        pt::PT_VENDOR_ID,
+        command.id(),
+        &command.payload(),
+        FRAME_TIMEOUT_MS,
+    )
+    .await
+}
+
+/// Groups the lamps by what they need to show `frame`: `RunLamp` for one that has shown
+/// nothing yet (it just joined, or missed a write), only the changes for the rest.
+fn plan(
+    shown: &HashMap<String, Frame>,
+    addresses: &[String],
+    frame: &Frame,
+) -> Vec<(Vec<String>, Vec<PtCommand>)> {
+    let mut batches: Vec<(Vec<String>, Vec<PtCommand>)> = Vec::new();
+    for address in addresses {
+        let commands = match shown.get(address) {
+            None => vec![PtCommand::RunLamp(LampSettings {
+                brightness: frame.brightness(),
+                lamp_type: pt::SOLID_LAMP_TYPE,
+                color: frame.color,
+            })],
+            Some(previous) => {
+                let mut commands = Vec::new();
+                if previous.color != frame.color {
+                    commands.push(PtCommand::SetLampColor(frame.color));
+                }
+                if previous.brightness() != frame.brightness() {
+                    commands.push(PtCommand::SetLampBrightness(frame.brightness()));
+                }
+                commands
+            }
+        };
+        match batches.iter_mut().find(|(_, batch)| *batch == commands) {
+            Some((targets, _)) => targets.push(address.clone()),
+            None => batches.push((vec![address.clone()], commands)),
+        }
+    }
+    batches
+}
+
+async fn play(status: EffectStatus, backend: BackendState, stop: CancelToken) {
+    let EffectStatus {
+        id,
+        spec,
+        addresses,
+        ..
+    } = status;
+    let interval = Duration::from_millis(spec.frame_interval_ms);
+    let started = Instant::now();
+    let mut shown: HashMap<String, Frame> = HashMap::new();
+    loop {
+        let elapsed = started.elapsed();
+        let Some(frame) = spec.frame(elapsed.as_millis() as u64) else {
+            if spec.effect == Effect::SunsetFade {
+                send(&backend, &addresses, PtCommand::StopLamp).await;
+            }
+            back_log("RUST", format!("Lamp effect #{} finished", id));
+            break;
+        };
+        if addresses
+            .iter()
+            .all(|address| crate::get_connections().phase(address) != ConnectionPhase::Connected)
+        {
+            back_log(
+                "RUST",
+                format!("Lamp effect #{} has no connected lamps left, stopping", id),
+            );
+            break;
+        }
+        for (targets, commands) in plan(&shown, &addresses, &frame) {
+            let mut missed = Vec::new();
+            for command in commands {
+                let Ok(report) = stop.run(send(&backend, &targets, command)).await else {
+                    return;
+                };
+                missed.extend(
+                    report
+                        .results
+                        .into_iter()
+                        .filter(|outcome| !outcome.ok)
+                        .map(|outcome| outcome.address),
+                );
+            }
+            for address in targets {
+                if missed.contains(&address) {
+                    shown.remove(&address);
+                } else {
+                    shown.insert(address, frame);
+                }
+            }
+        }
+        // Wake on the next tick of the shared clock, skipping ticks a slow frame overran.
+        let ticks = started.elapsed().as_millis() / interval.as_millis() + 1;
+        let next = started + interval * ticks as u32;
+        if stop.run(tokio::time::sleep_until(next)).await.is_err() {
+            return;
+        }
+    }
+    crate::get_lamp_effects().finished(id);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: Rgb = Rgb { r: 255, g: 0, b: 0 };
+    const BLUE: Rgb = Rgb { r: 0, g: 0, b: 255 };
+
+    #[test]
+    fn lamps_without_a_shown_frame_get_run_lamp() {
+        let addresses = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+        let previous = Frame {
+            color: RED,
+            brightness: 10.0,
+        };
+        let frame = Frame {
+            color: BLUE,
+            brightness: 10.0,
+        };
+        let shown = HashMap::from([("a".to_string(), previous), ("c".to_string(), previous)]);
+
+        let batches = plan(&shown, &addresses, &frame);
+        assert_eq!(
+            batches,
+            vec![
+                (
+                    vec!["a".to_string(), "c".to_string()],
+                    vec![PtCommand::SetLampColor(BLUE)]
+                ),
+                (
+                    vec!["b".to_string()],
+                    vec![PtCommand::RunLamp(LampSettings {
+                        brightness: 10,
+                        lamp_type: pt::SOLID_LAMP_TYPE,
+                        color: BLUE,
+                    })]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn unchanged_frames_send_nothing() {
+        let addresses = vec!["a".to_string()];
+        let frame = Frame {
+            color: RED,
+            brightness: 40.2,
+        };
+        let shown = HashMap::from([("a".to_string(), frame)]);
+        assert_eq!(
+            plan(&shown, &addresses, &frame),
+            vec![(addresses.clone(), Vec::new())]
+        );
+    }
+}
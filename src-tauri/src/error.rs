@@ -0,0 +1,334 @@
+use serde::ser::SerializeMap;
+use serde::{Serialize, Serializer};
+use std::borrow::Cow;
+use std::fmt;
+
+use crate::gaia::GaiaStatus;
+
+/// Every `kind` an error can carry.
+pub(crate) const KINDS: [&str; 21] = [
+    "not_supported",
+    "not_connected",
+    "device_not_found",
+    "invalid_address",
+    "adapter_unavailable",
+    "bluetooth_disabled",
+    "permission_denied",
+    "pairing_cancelled",
+    "pairing_timeout",
+    "pairing_failed",
+    "connect_cancelled",
+    "connect_failed",
+    "busy",
+    "timeout",
+    "io_bluetooth",
+    "protocol",
+    "decode",
+    "io",
+    "invalid_parameter",
+    "request_dropped",
+    "other",
+];
+
+/// IOReturn statuses the macOS bridge reports, by name.
+pub(crate) const IORETURN_NAMES: [(u32, &str); 9] = [
+    (0x00000000, "kIOReturnSuccess"),
+    (0xE0020002, "kIOBluetoothConnectionAlreadyExists"),
+    (0xE00002BC, "kIOReturnError"),
+    (0xE00002C0, "kIOReturnNoDevice"),
+    (0xE00002C5, "kIOReturnExclusiveAccess"),
+    (0xE00002CD, "kIOReturnNotOpen"),
+    (0xE00002D6, "kIOReturnTimeout"),
+    (0xE00002E2, "kIOReturnNotPermitted"),
+    (0xE00002F0, "kIOReturnNotFound"),
+];
+
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub(crate) fn ioreturn_name(code: i32) -> &'static str {
+    IORETURN_NAMES
+        .iter()
+        .find(|(known, _)| *known == code as u32)
+        .map_or("kIOReturnUnknown", |(_, name)| name)
+}
+
+/// Error returned by every command and backend call.
+///
+/// Serialized as an object with a stable `kind` (snake_case variant name), a human readable
+/// `message`, and any variant fields, so callers decide on `kind` rather than on the text.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum StoneError {
+    NotSupported {
+        message: String,
+    },
+    NotConnected,
+    DeviceNotFound,
+    InvalidAddress,
+    AdapterUnavailable,
+    BluetoothDisabled,
+    PermissionDenied,
+    PairingCancelled,
+    PairingTimeout,
+    PairingFailed {
+        message: String,
+    },
+    ConnectCancelled,
+    ConnectFailed {
+        message: String,
+    },
+    Busy {
+        message: String,
+    },
+    Timeout {
+        timeout_ms: u64,
+    },
+    // IOReturn status from the macOS bridge.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    IoBluetooth {
+        code: i32,
+        name: String,
+        context: String,
+    },
+    Protocol {
+        status: u8,
+        message: String,
+    },
+    Decode {
+        message: String,
+    },
+    // A local file could not be read or written.
+    Io {
+        message: String,
+    },
+    InvalidParameter {
+        message: String,
+    },
+    RequestDropped,
+    Other {
+        message: String,
+    },
+}
+
+impl StoneError {
+    pub(crate) fn kind(&self) -> &'static str {
+        match self {
+            Self::NotSupported { .. } => "not_supported",
+            Self::NotConnected => "not_connected",
+            Self::DeviceNotFound => "device_not_found",
+            Self::InvalidAddress => "invalid_address",
+            Self::AdapterUnavailable => "adapter_unavailable",
+            Self::BluetoothDisabled => "bluetooth_disabled",
+            Self::PermissionDenied => "permission_denied",
+            Self::PairingCancelled => "pairing_cancelled",
+            Self::PairingTimeout => "pairing_timeout",
+            Self::PairingFailed { .. } => "pairing_failed",
+            Self::ConnectCancelled => "connect_cancelled",
+            Self::ConnectFailed { .. } => "connect_failed",
+            Self::Busy { .. } => "busy",
+            Self::Timeout { .. } => "timeout",
+            Self::IoBluetooth { .. } => "io_bluetooth",
+            Self::Protocol { .. } => "protocol",
+            Self::Decode { .. } => "decode",
+            Self::Io { .. } => "io",
+            Self::InvalidParameter { .. } => "invalid_parameter",
+            Self::RequestDropped => "request_dropped",
+            Self::Other { .. } => "other",
+        }
+    }
+
+    /// The kind, narrowed by the platform status where there is one, such as
+    /// `io_bluetooth:kIOReturnTimeout`.
+    pub(crate) fn code(&self) -> Cow<'static, str> {
+        match self {
+            Self::IoBluetooth { name, .. } if !name.is_empty() => {
+                Cow::Owned(format!("{}:{}", self.kind(), name))
+            }
+            _ => Cow::Borrowed(self.kind()),
+        }
+    }
+
+    /// Rebuilds an error from a `kind` code sent across the plugin boundary. Fields the code
+    /// does not carry are left empty; an unknown `protocol` status reads as 0xFF.
+    #[cfg_attr(not(any(target_os = "android", test)), allow(dead_code))]
+    pub(crate) fn from_code(code: &str, message: String) -> Self {
+        match code {
+            "not_supported" => Self::NotSupported { message },
+            "not_connected" => Self::NotConnected,
+            "device_not_found" => Self::DeviceNotFound,
+            "invalid_address" => Self::InvalidAddress,
+            "adapter_unavailable" => Self::AdapterUnavailable,
+            "bluetooth_disabled" => Self::BluetoothDisabled,
+            "permission_denied" => Self::PermissionDenied,
+            "pairing_cancelled" => Self::PairingCancelled,
+            "pairing_timeout" => Self::PairingTimeout,
+            "pairing_failed" => Self::PairingFailed { message },
+            "connect_cancelled" => Self::ConnectCancelled,
+            "connect_failed" => Self::ConnectFailed { message },
+            "busy" => Self::Busy { message },
+            "timeout" => Self::Timeout { timeout_ms: 0 },
+            "io_bluetooth" => Self::IoBluetooth {
+                code: 0,
+                name: String::new(),
+                context: message,
+            },
+            "protocol" => Self::Protocol {
+                status: 0xFF,
+                message,
+            },
+            "decode" => Self::Decode { message },
+            "io" => Self::Io { message },
+            "invalid_parameter" => Self::InvalidParameter { message },
+            "request_dropped" => Self::RequestDropped,
+            _ => Self::Other { message },
+        }
+    }
+
+    pub(crate) fn other(message: impl fmt::Display) -> Self {
+        Self::Other {
+            message: message.to_string(),
+        }
+    }
+
+    pub(crate) fn protocol(status: GaiaStatus) -> Self {
+        Self::Protocol {
+            status: status.as_byte(),
+            message: status.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for StoneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotSupported { message }
+            | Self::PairingFailed { message }
+            | Self::ConnectFailed { message }
+            | Self::Busy { message }
+            | Self::Io { message }
+            | Self::InvalidParameter { message }
+            | Self::Other { message } => f.write_str(message),
+            Self::NotConnected => f.write_str("Target device is not connected"),
+            Self::DeviceNotFound => f.write_str("Device not found"),
+            Self::InvalidAddress => f.write_str("Invalid Bluetooth address"),
+            Self::AdapterUnavailable => f.write_str("Bluetooth adapter unavailable"),
+            Self::BluetoothDisabled => f.write_str("Bluetooth must be enabled"),
+            Self::PermissionDenied => f.write_str("Bluetooth permission denied"),
+            Self::PairingCancelled => f.write_str("Pairing was cancelled"),
+            Self::PairingTimeout => f.write_str("Pairing timed out"),
+            Self::ConnectCancelled => f.write_str("Connect cancelled"),
+            Self::Timeout { timeout_ms } => {
+                write!(f, "GAIA request timed out after {} ms", timeout_ms)
+            }
+            Self::IoBluetooth {
+                code,
+                name,
+                context,
+            } => {
+                write!(f, "IOBluetooth error {} ({})", code, name)?;
+                if !context.is_empty() {
+                    write!(f, " ({})", context)?;
+                }
+                Ok(())
+            }
+            Self::Protocol { status, message } => {
+                write!(f, "GAIA command failed: {} (status {})", message, status)
+            }
+            Self::Decode { message } => write!(f, "Malformed GAIA response: {}", message),
+            Self::RequestDropped => f.write_str("GAIA request was dropped"),
+        }
+    }
+}
+
+impl std::error::Error for StoneError {}
+
+impl Serialize for StoneError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("kind", self.kind())?;
+        map.serialize_entry("message", &self.to_string())?;
+        match self {
+            Self::Timeout { timeout_ms } => map.serialize_entry("timeout_ms", timeout_ms)?,
+            Self::IoBluetooth {
+                code,
+                name,
+                context,
+            } => {
+                map.serialize_entry("code", code)?;
+                map.serialize_entry("name", name)?;
+                map.serialize_entry("context", context)?;
+            }
+            Self::Protocol { status, .. } => map.serialize_entry("status", status)?,
+            _ => {}
+        }
+        map.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_map_back_to_their_kind() {
+        let message = || "message".to_string();
+        let errors = [
+            StoneError::NotSupported { message: message() },
+            StoneError::NotConnected,
+            StoneError::DeviceNotFound,
+            StoneError::InvalidAddress,
+            StoneError::AdapterUnavailable,
+            StoneError::BluetoothDisabled,
+            StoneError::PermissionDenied,
+            StoneError::PairingCancelled,
+            StoneError::PairingTimeout,
+            StoneError::PairingFailed { message: message() },
+            StoneError::ConnectCancelled,
+            StoneError::ConnectFailed { message: message() },
+            StoneError::Busy { message: message() },
+            StoneError::Timeout { timeout_ms: 3_000 },
+            StoneError::IoBluetooth {
+                code: 1,
+                name: "kIOReturnError".to_string(),
+                context: message(),
+            },
+            StoneError::protocol(GaiaStatus::NotSupported),
+            StoneError::Decode { message: message() },
+            StoneError::Io { message: message() },
+            StoneError::InvalidParameter { message: message() },
+            StoneError::RequestDropped,
+            StoneError::other(message()),
+        ];
+        for error in errors {
+            assert!(KINDS.contains(&error.kind()));
+            let rebuilt = StoneError::from_code(error.kind(), error.to_string());
+            assert_eq!(rebuilt.kind(), error.kind());
+        }
+        assert_eq!(
+            StoneError::from_code("made_up", message()),
+            StoneError::other(message())
+        );
+    }
+
+    #[test]
+    fn codes_name_the_iobluetooth_status() {
+        let error = StoneError::IoBluetooth {
+            code: 0xE00002E2u32 as i32,
+            name: ioreturn_name(0xE00002E2u32 as i32).to_string(),
+            context: String::new(),
+        };
+        assert_eq!(error.code(), "io_bluetooth:kIOReturnNotPermitted");
+        assert_eq!(StoneError::NotConnected.code(), "not_connected");
+    }
+
+    #[test]
+    fn serializes_kind_message_and_fields() {
+        let value = serde_json::to_value(StoneError::Timeout { timeout_ms: 500 }).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "kind": "timeout",
+                "message": "GAIA request timed out after 500 ms",
+                "timeout_ms": 500
+            })
+        );
+    }
+}
@@ -0,0 +1,596 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::{ErrorKind, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, SyncSender, TryRecvError, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::{AppHandle, Listener};
+use tungstenite::handshake::server::{Callback, ErrorResponse, Request, Response};
+use tungstenite::protocol::frame::coding::CloseCode;
+use tungstenite::protocol::CloseFrame;
+use tungstenite::{Message, WebSocket};
+
+use crate::error::StoneError;
+use crate::http_api::{bind_local, percent_decode, replace_listener};
+use crate::{back_log, persist};
+
+const CONFIG_FILE: &str = "event_stream.json";
+const CONFIG_VERSION: u32 = 1;
+const DEFAULT_PORT: u16 = 8788;
+const EVENTS_PATH: &str = "/events";
+const HANDSHAKE_TIMEOUT_MS: u64 = 5_000;
+// Each client gets a thread, so more than this are turned away as busy.
+const MAX_CLIENTS: usize = 16;
+// A client waits on its socket this long at a time before it looks for events to send.
+const POLL_MS: u64 = 100;
+// A client that falls this far behind is dropped instead of being buffered for without bound.
+const MAX_QUEUED: usize = 1024;
+
+/// The app events a client can subscribe to. `device_state_changed` carries the decoded PT
+/// state changes.
+const STREAMED_EVENTS: [&str; 4] = [
+    "gaia_packet",
+    "bt_device_event",
+    "bt_connect_result",
+    "device_state_changed",
+];
+
+/// The local event stream. Off until turned on; it only ever listens on 127.0.0.1 and takes
+/// the HTTP API token.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct EventStreamConfig {
+    pub enabled: bool,
+    pub port: u16,
+}
+
+impl Default for EventStreamConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: DEFAULT_PORT,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub(crate) struct EventStreamStatus {
+    #[serde(flatten)]
+    config: EventStreamConfig,
+    listening: bool,
+    clients: usize,
+    // Why the stream is not listening although it is enabled.
+    error: Option<String>,
+}
+
+/// What a client wants to see; an empty list lets everything through.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+struct Filter {
+    #[serde(default)]
+    addresses: Vec<String>,
+    #[serde(default)]
+    events: Vec<String>,
+}
+
+impl Filter {
+    fn matches(&self, published: &Published) -> bool {
+        (self.events.is_empty() || self.events.iter().any(|event| event == published.event))
+            && (self.addresses.is_empty()
+                || published.address.as_deref().is_some_and(|address| {
+                    self.addresses
+                        .iter()
+                        .any(|wanted| wanted.eq_ignore_ascii_case(address))
+                }))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    Subscribe(Filter),
+}
+
+struct Published {
+    event: &'static str,
+    address: Option<String>,
+    message: String,
+}
+
+struct Listening {
+    port: u16,
+    stop: Arc<AtomicBool>,
+}
+
+impl Listening {
+    fn shut_down(&self) {
+        self.stop.store(true, Ordering::SeqCst);
+        // Wakes the accept loop so it sees the flag and lets go of the port.
+        let _ = TcpStream::connect(("127.0.0.1", self.port));
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    path: Option<PathBuf>,
+    config: EventStreamConfig,
+    listening: Option<Listening>,
+    error: Option<String>,
+}
+
+/// Streams `gaia_packet`, `bt_device_event`, `bt_connect_result` and `device_state_changed`
+/// as JSON over a WebSocket for dashboards on this machine. Settings are kept in
+/// `event_stream.json`.
+#[derive(Default)]
+pub(crate) struct EventStream {
+    inner: Mutex<Inner>,
+    clients: Mutex<Vec<SyncSender<Arc<Published>>>>,
+    hooked: AtomicBool,
+}
+
+pub(crate) fn path_in(data_dir: &Path) -> PathBuf {
+    data_dir.join(CONFIG_FILE)
+}
+
+// Holds one of the `MAX_CLIENTS` places until the client's thread ends.
+struct Place(Arc<AtomicUsize>);
+
+impl Drop for Place {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+fn turn_away(mut stream: TcpStream) {
+    let body = serde_json::json!({ "kind": "busy" }).to_string();
+    let _ = write!(
+        stream,
+        "HTTP/1.1 503 Service Unavailable\r\nContent-Type: application/json\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    );
+}
+
+fn listen(port: u16) -> Result<Listening, StoneError> {
+    let listener = bind_local(port, |host, port| TcpListener::bind((host, port)))?;
+    let port = listener.local_addr().map_or(port, |bound| bound.port());
+    let stop = Arc::new(AtomicBool::new(false));
+    let accepting = stop.clone();
+    std::thread::spawn(move || {
+        let taken = Arc::new(AtomicUsize::new(0));
+        for stream in listener.incoming() {
+            if accepting.load(Ordering::SeqCst) {
+                break;
+            }
+            let Ok(stream) = stream else {
+                continue;
+            };
+            if taken.fetch_add(1, Ordering::SeqCst) >= MAX_CLIENTS {
+                taken.fetch_sub(1, Ordering::SeqCst);
+                turn_away(stream);
+                continue;
+            }
+            let place = Place(taken.clone());
+            let stop = accepting.clone();
+            std::thread::spawn(move || {
+                serve(stream, stop);
+                drop(place);
+            });
+        }
+    });
+    back_log(
+        "RUST",
+        format!(
+            "Event stream listening on ws://127.0.0.1:{}{}",
+            port, EVENTS_PATH
+        ),
+    );
+    Ok(Listening { port, stop })
+}
+
+impl EventStream {
+    /// Loads `path`, starts forwarding app events and listens if enabled.
+    pub(crate) fn load(&self, path: PathBuf, app: &AppHandle) {
+        let config: EventStreamConfig = persist::load(&path, CONFIG_VERSION);
+        if !self.hooked.swap(true, Ordering::SeqCst) {
+            for event in STREAMED_EVENTS {
+                app.listen_any(event, move |emitted| {
+                    crate::get_event_stream().publish(event, emitted.payload());
+                });
+            }
+        }
+        let (listening, error) = if config.enabled {
+            match listen(config.port) {
+                Ok(listening) => (Some(listening), None),
+                Err(err) => {
+                    back_log("RUST", err.to_string());
+                    (None, Some(err.to_string()))
+                }
+            }
+        } else {
+            (None, None)
+        };
+        if let Ok(mut inner) = self.inner.lock() {
+            inner.path = Some(path);
+            inner.config = config;
+            inner.listening = listening;
+            inner.error = error;
+        }
+    }
+
+    pub(crate) fn status(&self) -> EventStreamStatus {
+        let clients = self
+            .clients
+            .lock()
+            .map(|clients| clients.len())
+            .unwrap_or(0);
+        self.inner
+            .lock()
+            .map(|inner| EventStreamStatus {
+                config: inner.config.clone(),
+                listening: inner.listening.is_some(),
+                clients,
+                error: inner.error.clone(),
+            })
+            .unwrap_or_else(|_| EventStreamStatus {
+                config: EventStreamConfig::default(),
+                listening: false,
+                clients,
+                error: None,
+            })
+    }
+
+    /// Turns the stream on or off or moves it to another port. Connected clients are closed
+    /// either way. The change is only saved once the stream could listen with it.
+    pub(crate) fn configure(
+        &self,
+        enabled: bool,
+        port: u16,
+    ) -> Result<EventStreamStatus, StoneError> {
+        if port == 0 {
+            return Err(StoneError::InvalidParameter {
+                message: "port must not be 0".to_string(),
+            });
+        }
+        {
+            let mut inner = self
+                .inner
+                .lock()
+                .map_err(|_| StoneError::other("Event stream state poisoned"))?;
+            let config = EventStreamConfig { enabled, port };
+            let previous = inner.config.clone();
+            let path = inner.path.clone();
+            let listening = replace_listener(
+                &mut inner.listening,
+                |listening| listening.shut_down(),
+                || enabled.then(|| listen(port)).transpose(),
+                || match &path {
+                    Some(path) => persist::save(path, CONFIG_VERSION, &config),
+                    None => Ok(()),
+                },
+                || {
+                    previous
+                        .enabled
+                        .then(|| listen(previous.port).ok())
+                        .flatten()
+                },
+            )?;
+            if !enabled {
+                back_log("RUST", "Event stream stopped".to_string());
+            }
+            inner.config = config;
+            inner.listening = listening;
+            inner.error = None;
+        }
+        Ok(self.status())
+    }
+
+    fn publish(&self, event: &'static str, payload: &str) {
+        let Ok(mut clients) = self.clients.lock() else {
+            return;
+        };
+        if clients.is_empty() {
+            return;
+        }
+        let payload: Value = serde_json::from_str(payload).unwrap_or(Value::Null);
+        let address = payload
+            .get("address")
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        let message = serde_json::json!({ "type": "event", "event": event, "payload": payload });
+        let published = Arc::new(Published {
+            event,
+            address,
+            message: message.to_string(),
+        });
+        clients.retain(|client| match client.try_send(published.clone()) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                back_log(
+                    "RUST",
+                    "Event stream client fell behind, dropped".to_string(),
+                );
+                false
+            }
+            Err(TrySendError::Disconnected(_)) => false,
+        });
+    }
+
+    fn subscribe(&self) -> Option<mpsc::Receiver<Arc<Published>>> {
+        let (sender, receiver) = mpsc::sync_channel(MAX_QUEUED);
+        self.clients.lock().ok()?.push(sender);
+        Some(receiver)
+    }
+}
+
+fn reject(status: u16, kind: &str) -> ErrorResponse {
+    let mut response = ErrorResponse::new(Some(serde_json::json!({ "kind": kind }).to_string()));
+    if let Ok(status) = tungstenite::http::StatusCode::from_u16(status) {
+        *response.status_mut() = status;
+    }
+    response
+}
+
+fn query_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|item| percent_decode(item).trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// Checks the path and token of a connecting client and reads the filter it asked for with
+/// `?address=` and `?event=`. Browsers cannot set headers on a WebSocket, so the token may
+/// also come as `?token=`.
+fn admit(request: &Request, filter: &mut Filter) -> Result<(), (u16, &'static str)> {
+    if request.uri().path() != EVENTS_PATH {
+        return Err((404, "not_found"));
+    }
+    let mut token = request
+        .headers()
+        .get("Authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::to_string);
+    for pair in request.uri().query().unwrap_or_default().split('&') {
+        match pair.split_once('=') {
+            Some(("token", value)) => token = Some(percent_decode(value)),
+            Some(("address", value)) => filter.addresses.extend(query_list(value)),
+            Some(("event", value)) => filter.events.extend(query_list(value)),
+            _ => {}
+        }
+    }
+    if !token.is_some_and(|token| crate::get_http_api().accepts(&token)) {
+        return Err((401, "unauthorized"));
+    }
+    Ok(())
+}
+
+struct Admission<'a> {
+    filter: &'a mut Filter,
+}
+
+impl Callback for Admission<'_> {
+    fn on_request(self, request: &Request, response: Response) -> Result<Response, ErrorResponse> {
+        match admit(request, self.filter) {
+            Ok(()) => Ok(response),
+            Err((status, kind)) => Err(reject(status, kind)),
+        }
+    }
+}
+
+fn send_json(socket: &mut WebSocket<TcpStream>, value: Value) -> bool {
+    socket.send(Message::text(value.to_string())).is_ok()
+}
+
+fn close(socket: &mut WebSocket<TcpStream>, code: CloseCode, reason: &str) {
+    let _ = socket.close(Some(CloseFrame {
+        code,
+        reason: reason.to_string().into(),
+    }));
+    let _ = socket.flush();
+}
+
+/// Runs one client until it leaves, falls behind or the stream is turned off. Clients may
+/// send `{"type": "subscribe", "addresses": [...], "events": [...]}` to change their filter.
+fn serve(stream: TcpStream, stop: Arc<AtomicBool>) {
+    let _ = stream.set_read_timeout(Some(Duration::from_millis(HANDSHAKE_TIMEOUT_MS)));
+    let mut filter = Filter::default();
+    let admitted = tungstenite::accept_hdr(
+        stream,
+        Admission {
+            filter: &mut filter,
+        },
+    );
+    let Ok(mut socket) = admitted else {
+        return;
+    };
+    let _ = socket
+        .get_ref()
+        .set_read_timeout(Some(Duration::from_millis(POLL_MS)));
+    let Some(events) = crate::get_event_stream().subscribe() else {
+        return;
+    };
+    if !send_json(
+        &mut socket,
+        serde_json::json!({ "type": "subscribed", "filter": filter }),
+    ) {
+        return;
+    }
+    loop {
+        if stop.load(Ordering::SeqCst) {
+            close(&mut socket, CloseCode::Away, "Event stream stopped");
+            return;
+        }
+        loop {
+            match events.try_recv() {
+                Ok(published) if filter.matches(&published) => {
+                    if socket
+                        .write(Message::text(published.message.clone()))
+                        .is_err()
+                    {
+                        return;
+                    }
+                }
+                Ok(_) => {}
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    close(&mut socket, CloseCode::Again, "Fell behind");
+                    return;
+                }
+            }
+        }
+        if socket.flush().is_err() {
+            return;
+        }
+        match socket.read() {
+            Ok(Message::Text(text)) => {
+                let reply = match serde_json::from_str::<ClientMessage>(&text) {
+                    Ok(ClientMessage::Subscribe(next)) => {
+                        filter = next;
+                        serde_json::json!({ "type": "subscribed", "filter": filter })
+                    }
+                    Err(err) => serde_json::json!({ "type": "error", "message": err.to_string() }),
+                };
+                if !send_json(&mut socket, reply) {
+                    return;
+                }
+            }
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(err))
+                if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(_) => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::sync::OnceLock;
+
+    fn token() -> &'static str {
+        static TOKEN: OnceLock<String> = OnceLock::new();
+        TOKEN.get_or_init(|| {
+            let status = crate::get_http_api().configure(false, 8787, true).unwrap();
+            let status = serde_json::to_value(status).unwrap();
+            status["token"].as_str().unwrap().to_string()
+        })
+    }
+
+    fn request(uri: &str, authorization: Option<&str>) -> Request {
+        let mut request = Request::builder().uri(uri);
+        if let Some(authorization) = authorization {
+            request = request.header("Authorization", authorization);
+        }
+        request.body(()).unwrap()
+    }
+
+    fn published(event: &'static str, address: Option<&str>) -> Published {
+        Published {
+            event,
+            address: address.map(str::to_string),
+            message: String::new(),
+        }
+    }
+
+    #[test]
+    fn filters_match_on_event_and_address() {
+        let gaia = published("gaia_packet", Some("00:00:5E:00:53:D0"));
+        let other = published("gaia_packet", Some("00:00:5E:00:53:D1"));
+        let anywhere = published("bt_device_event", None);
+        assert!(Filter::default().matches(&gaia));
+        assert!(Filter::default().matches(&anywhere));
+
+        let events = Filter {
+            events: vec!["gaia_packet".to_string()],
+            ..Filter::default()
+        };
+        assert!(events.matches(&gaia));
+        assert!(!events.matches(&anywhere));
+
+        let addresses = Filter {
+            addresses: vec!["00:00:5e:00:53:d0".to_string()],
+            ..Filter::default()
+        };
+        assert!(addresses.matches(&gaia));
+        assert!(!addresses.matches(&other));
+        // An event without an address only gets through when no address is asked for.
+        assert!(!addresses.matches(&anywhere));
+    }
+
+    #[test]
+    fn admit_reads_the_token_and_filter_from_the_query() {
+        let token = token();
+        let mut filter = Filter::default();
+        let uri = format!(
+            "/events?token=%{:02X}{}&address=00:00:5E:00:53:D0,%20aa%3Abb,&event=gaia_packet,,bt_device_event&other=1",
+            token.as_bytes()[0],
+            &token[1..]
+        );
+        assert_eq!(admit(&request(&uri, None), &mut filter), Ok(()));
+        assert_eq!(filter.addresses, ["00:00:5E:00:53:D0", "aa:bb"]);
+        assert_eq!(filter.events, ["gaia_packet", "bt_device_event"]);
+
+        let mut filter = Filter::default();
+        let bearer = format!("Bearer {}", token);
+        assert_eq!(
+            admit(&request("/events", Some(&bearer)), &mut filter),
+            Ok(())
+        );
+        assert!(filter.addresses.is_empty() && filter.events.is_empty());
+    }
+
+    #[test]
+    fn admit_rejects_other_paths_and_wrong_tokens() {
+        let token = token();
+        let bearer = format!("Bearer {}", token);
+        let admitted = |uri: &str, authorization: Option<&str>| {
+            admit(&request(uri, authorization), &mut Filter::default())
+        };
+        assert_eq!(admitted("/other", Some(&bearer)), Err((404, "not_found")));
+        assert_eq!(admitted("/events/", Some(&bearer)), Err((404, "not_found")));
+        assert_eq!(admitted("/events", None), Err((401, "unauthorized")));
+        assert_eq!(admitted("/events?token=", None), Err((401, "unauthorized")));
+        assert_eq!(
+            admitted("/events", Some(&format!("Basic {}", token))),
+            Err((401, "unauthorized"))
+        );
+        assert_eq!(
+            admitted("/events?token=wrong", Some(&bearer)),
+            Err((401, "unauthorized"))
+        );
+    }
+
+    fn status_of(port: u16, path: &str) -> String {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let head = format!(
+            "GET {} HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Version: 13\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n",
+            path
+        );
+        stream.write_all(head.as_bytes()).unwrap();
+        let mut response = String::new();
+        let _ = stream.read_to_string(&mut response);
+        response.lines().next().unwrap_or_default().to_string()
+    }
+
+    #[test]
+    fn handshakes_are_rejected_and_extra_clients_turned_away() {
+        let listening = listen(0).unwrap();
+        let port = listening.port;
+        assert!(status_of(port, "/other").starts_with("HTTP/1.1 404"));
+        assert!(status_of(port, "/events").starts_with("HTTP/1.1 401"));
+
+        // Clients still in their handshake hold a place too.
+        let waiting: Vec<TcpStream> = (0..MAX_CLIENTS)
+            .map(|_| TcpStream::connect(("127.0.0.1", port)).unwrap())
+            .collect();
+        assert!(status_of(port, "/events").starts_with("HTTP/1.1 503"));
+        drop(waiting);
+        listening.shut_down();
+    }
+}
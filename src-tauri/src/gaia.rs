@@ -0,0 +1,636 @@
+//! GAIA frame codec shared by the app backend and headless tools.
+//!
+//! Only `core` and `alloc` are used so the module can be lifted into a
+//! `no_std` crate unchanged.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+pub const SOF: u8 = 0xFF;
+pub const FLAG_CHECKSUM: u8 = 0x01;
+pub const FLAG_LENGTH_EXTENSION: u8 = 0x02;
+pub const ACK_MASK: u16 = 0x8000;
+pub const COMMAND_MASK: u16 = 0x7FFF;
+pub const MAX_PAYLOAD_LEN: usize = 254;
+pub const MAX_EXTENDED_PAYLOAD_LEN: usize = 0xFFFF;
+pub const STATUS_SUCCESS: u8 = 0x00;
+
+const HEADER_LEN: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GaiaVersion {
+    #[default]
+    V1,
+    V2,
+    V3,
+}
+
+impl GaiaVersion {
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0x01 => Some(Self::V1),
+            0x02 => Some(Self::V2),
+            0x03 => Some(Self::V3),
+            _ => None,
+        }
+    }
+
+    pub fn as_byte(self) -> u8 {
+        match self {
+            Self::V1 => 0x01,
+            Self::V2 => 0x02,
+            Self::V3 => 0x03,
+        }
+    }
+
+    pub fn supports_length_extension(self) -> bool {
+        !matches!(self, Self::V1)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GaiaStatus {
+    Success,
+    NotSupported,
+    NotAuthenticated,
+    InsufficientResources,
+    Authenticating,
+    InvalidParameter,
+    IncorrectState,
+    InProgress,
+    Unknown(u8),
+}
+
+impl GaiaStatus {
+    pub fn from_byte(byte: u8) -> Self {
+        match byte {
+            0x00 => Self::Success,
+            0x01 => Self::NotSupported,
+            0x02 => Self::NotAuthenticated,
+            0x03 => Self::InsufficientResources,
+            0x04 => Self::Authenticating,
+            0x05 => Self::InvalidParameter,
+            0x06 => Self::IncorrectState,
+            0x07 => Self::InProgress,
+            other => Self::Unknown(other),
+        }
+    }
+
+    pub fn as_byte(self) -> u8 {
+        match self {
+            Self::Success => 0x00,
+            Self::NotSupported => 0x01,
+            Self::NotAuthenticated => 0x02,
+            Self::InsufficientResources => 0x03,
+            Self::Authenticating => 0x04,
+            Self::InvalidParameter => 0x05,
+            Self::IncorrectState => 0x06,
+            Self::InProgress => 0x07,
+            Self::Unknown(other) => other,
+        }
+    }
+
+    pub fn is_success(self) -> bool {
+        matches!(self, Self::Success)
+    }
+}
+
+impl fmt::Display for GaiaStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Success => f.write_str("success"),
+            Self::NotSupported => f.write_str("not supported"),
+            Self::NotAuthenticated => f.write_str("not authenticated"),
+            Self::InsufficientResources => f.write_str("insufficient resources"),
+            Self::Authenticating => f.write_str("authenticating"),
+            Self::InvalidParameter => f.write_str("invalid parameter"),
+            Self::IncorrectState => f.write_str("incorrect state"),
+            Self::InProgress => f.write_str("in progress"),
+            Self::Unknown(other) => write!(f, "unknown status 0x{:02X}", other),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum V3PacketType {
+    Command,
+    Notification,
+    Response,
+    Error,
+}
+
+/// GAIA v3 packs feature, packet type and command into the 15-bit command id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct V3CommandId {
+    pub feature: u8,
+    pub packet_type: V3PacketType,
+    pub command: u8,
+}
+
+impl V3CommandId {
+    pub fn from_command(command: u16) -> Self {
+        let packet_type = match (command >> 7) & 0x03 {
+            0 => V3PacketType::Command,
+            1 => V3PacketType::Notification,
+            2 => V3PacketType::Response,
+            _ => V3PacketType::Error,
+        };
+        Self {
+            feature: ((command >> 9) & 0x7F) as u8,
+            packet_type,
+            command: (command & 0x7F) as u8,
+        }
+    }
+
+    pub fn to_command(self) -> u16 {
+        let packet_type: u16 = match self.packet_type {
+            V3PacketType::Command => 0,
+            V3PacketType::Notification => 1,
+            V3PacketType::Response => 2,
+            V3PacketType::Error => 3,
+        };
+        ((self.feature as u16 & 0x7F) << 9) | (packet_type << 7) | (self.command as u16 & 0x7F)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EncodeError {
+    PayloadTooLong { len: usize, max: usize },
+    LengthExtensionUnsupported(GaiaVersion),
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PayloadTooLong { len, max } => {
+                write!(f, "Payload too long ({} bytes, max {})", len, max)
+            }
+            Self::LengthExtensionUnsupported(version) => {
+                write!(f, "Length extension is not supported by GAIA {:?}", version)
+            }
+        }
+    }
+}
+
+impl core::error::Error for EncodeError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    BadStartOfFrame(u8),
+    UnsupportedVersion(u8),
+    Truncated { needed: usize, available: usize },
+    ChecksumMismatch { expected: u8, actual: u8 },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadStartOfFrame(byte) => write!(f, "Bad start of frame 0x{:02X}", byte),
+            Self::UnsupportedVersion(byte) => write!(f, "Unsupported GAIA version 0x{:02X}", byte),
+            Self::Truncated { needed, available } => {
+                write!(f, "Truncated frame ({} of {} bytes)", available, needed)
+            }
+            Self::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "Checksum mismatch (expected 0x{:02X}, got 0x{:02X})",
+                expected, actual
+            ),
+        }
+    }
+}
+
+impl core::error::Error for DecodeError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GaiaFrame {
+    pub version: GaiaVersion,
+    pub flags: u8,
+    pub vendor_id: u16,
+    pub command: u16,
+    pub ack: bool,
+    pub status: Option<u8>,
+    pub payload: Vec<u8>,
+}
+
+impl GaiaFrame {
+    pub fn new(vendor_id: u16, command: u16, payload: Vec<u8>) -> Self {
+        Self {
+            version: GaiaVersion::V1,
+            flags: 0,
+            vendor_id,
+            command: command & COMMAND_MASK,
+            ack: false,
+            status: None,
+            payload,
+        }
+    }
+
+    pub fn from_command_id(vendor_id: u16, command_id: u16, payload: Vec<u8>) -> Self {
+        Self {
+            ack: (command_id & ACK_MASK) != 0,
+            ..Self::new(vendor_id, command_id, payload)
+        }
+    }
+
+    pub fn ack(vendor_id: u16, command: u16, status: u8, payload: Vec<u8>) -> Self {
+        Self {
+            ack: true,
+            status: Some(status),
+            ..Self::new(vendor_id, command, payload)
+        }
+    }
+
+    pub fn command_id(&self) -> u16 {
+        if self.ack {
+            self.command | ACK_MASK
+        } else {
+            self.command
+        }
+    }
+
+    pub fn gaia_status(&self) -> Option<GaiaStatus> {
+        self.status.map(GaiaStatus::from_byte)
+    }
+
+    pub fn has_checksum(&self) -> bool {
+        (self.flags & FLAG_CHECKSUM) != 0
+    }
+
+    pub fn has_length_extension(&self) -> bool {
+        (self.flags & FLAG_LENGTH_EXTENSION) != 0
+    }
+
+    pub fn v3_command(&self) -> V3CommandId {
+        V3CommandId::from_command(self.command)
+    }
+
+    /// Payload as it appears on the wire, i.e. with the ACK status byte prepended.
+    pub fn wire_payload_len(&self) -> usize {
+        self.payload.len() + usize::from(self.ack && self.status.is_some())
+    }
+
+    pub fn encoded_len(&self) -> usize {
+        HEADER_LEN
+            + usize::from(self.has_length_extension())
+            + self.wire_payload_len()
+            + usize::from(self.has_checksum())
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>, EncodeError> {
+        let mut out = Vec::with_capacity(self.encoded_len());
+        self.encode_into(&mut out)?;
+        Ok(out)
+    }
+
+    pub fn encode_into(&self, out: &mut Vec<u8>) -> Result<(), EncodeError> {
+        let extended = self.has_length_extension();
+        if extended && !self.version.supports_length_extension() {
+            return Err(EncodeError::LengthExtensionUnsupported(self.version));
+        }
+        let max = if extended {
+            MAX_EXTENDED_PAYLOAD_LEN
+        } else {
+            MAX_PAYLOAD_LEN
+        };
+        let len = self.wire_payload_len();
+        if len > max {
+            return Err(EncodeError::PayloadTooLong { len, max });
+        }
+
+        let start = out.len();
+        out.push(SOF);
+        out.push(self.version.as_byte());
+        out.push(self.flags);
+        if extended {
+            out.extend_from_slice(&(len as u16).to_be_bytes());
+        } else {
+            out.push(len as u8);
+        }
+        out.extend_from_slice(&self.vendor_id.to_be_bytes());
+        out.extend_from_slice(&self.command_id().to_be_bytes());
+        if self.ack {
+            if let Some(status) = self.status {
+                out.push(status);
+            }
+        }
+        out.extend_from_slice(&self.payload);
+
+        if self.has_checksum() {
+            let check = checksum(&out[start..]);
+            out.push(check);
+        }
+        Ok(())
+    }
+
+    /// Decodes one frame from the start of `data`, returning it with the number of bytes consumed.
+    pub fn decode(data: &[u8]) -> Result<(Self, usize), DecodeError> {
+        let header = parse_header(data)?;
+        if data.len() < header.frame_len {
+            return Err(DecodeError::Truncated {
+                needed: header.frame_len,
+                available: data.len(),
+            });
+        }
+        let frame = &data[..header.frame_len];
+
+        if (header.flags & FLAG_CHECKSUM) != 0 {
+            let expected = checksum(&frame[..frame.len() - 1]);
+            let actual = frame[frame.len() - 1];
+            if expected != actual {
+                return Err(DecodeError::ChecksumMismatch { expected, actual });
+            }
+        }
+
+        let ids = header.header_len - 4;
+        let vendor_id = u16::from_be_bytes([frame[ids], frame[ids + 1]]);
+        let command_id = u16::from_be_bytes([frame[ids + 2], frame[ids + 3]]);
+        let ack = (command_id & ACK_MASK) != 0;
+
+        let wire_payload = &frame[header.header_len..header.header_len + header.payload_len];
+        let (status, payload) = match wire_payload.split_first() {
+            Some((status, rest)) if ack => (Some(*status), rest.to_vec()),
+            _ => (None, wire_payload.to_vec()),
+        };
+
+        Ok((
+            Self {
+                version: header.version,
+                flags: header.flags,
+                vendor_id,
+                command: command_id & COMMAND_MASK,
+                ack,
+                status,
+                payload,
+            },
+            header.frame_len,
+        ))
+    }
+}
+
+struct Header {
+    version: GaiaVersion,
+    flags: u8,
+    header_len: usize,
+    payload_len: usize,
+    frame_len: usize,
+}
+
+/// Needs the bytes up to and including the length field; returns the full frame geometry.
+fn parse_header(data: &[u8]) -> Result<Header, DecodeError> {
+    let truncated = |needed| DecodeError::Truncated {
+        needed,
+        available: data.len(),
+    };
+    let sof = *data.first().ok_or(truncated(1))?;
+    if sof != SOF {
+        return Err(DecodeError::BadStartOfFrame(sof));
+    }
+    let version_byte = *data.get(1).ok_or(truncated(2))?;
+    let version = GaiaVersion::from_byte(version_byte)
+        .ok_or(DecodeError::UnsupportedVersion(version_byte))?;
+    let flags = *data.get(2).ok_or(truncated(3))?;
+    let extended = version.supports_length_extension() && (flags & FLAG_LENGTH_EXTENSION) != 0;
+    let (payload_len, header_len) = if extended {
+        let hi = *data.get(3).ok_or(truncated(5))?;
+        let lo = *data.get(4).ok_or(truncated(5))?;
+        (u16::from_be_bytes([hi, lo]) as usize, HEADER_LEN + 1)
+    } else {
+        (*data.get(3).ok_or(truncated(4))? as usize, HEADER_LEN)
+    };
+    let frame_len = header_len + payload_len + usize::from((flags & FLAG_CHECKSUM) != 0);
+    Ok(Header {
+        version,
+        flags,
+        header_len,
+        payload_len,
+        frame_len,
+    })
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |acc, b| acc ^ b)
+}
+
+/// Reassembles frames from an RFCOMM byte stream, resynchronising on the next SOF after garbage.
+pub struct GaiaParser {
+    buffer: Vec<u8>,
+    expected: Option<usize>,
+}
+
+impl Default for GaiaParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GaiaParser {
+    pub fn new() -> Self {
+        Self {
+            buffer: Vec::with_capacity(HEADER_LEN + MAX_PAYLOAD_LEN + 1),
+            expected: None,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.buffer.clear();
+        self.expected = None;
+    }
+
+    pub fn push_bytes(&mut self, data: &[u8]) -> Vec<Result<GaiaFrame, DecodeError>> {
+        let mut frames = Vec::new();
+        for &byte in data {
+            if let Some(result) = self.push_byte(byte) {
+                frames.push(result);
+            }
+        }
+        frames
+    }
+
+    pub fn push_byte(&mut self, byte: u8) -> Option<Result<GaiaFrame, DecodeError>> {
+        if self.buffer.is_empty() {
+            if byte == SOF {
+                self.buffer.push(byte);
+            }
+            return None;
+        }
+
+        self.buffer.push(byte);
+        if self.expected.is_none() {
+            match parse_header(&self.buffer) {
+                Ok(header) => self.expected = Some(header.frame_len),
+                Err(DecodeError::Truncated { .. }) => return None,
+                Err(err) => {
+                    self.reset();
+                    if byte == SOF {
+                        self.buffer.push(byte);
+                    }
+                    return Some(Err(err));
+                }
+            }
+        }
+
+        if self.expected == Some(self.buffer.len()) {
+            let result = GaiaFrame::decode(&self.buffer).map(|(frame, _)| frame);
+            self.reset();
+            return Some(result);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn round_trip(frame: &GaiaFrame) {
+        let bytes = frame.encode().unwrap();
+        assert_eq!(bytes.len(), frame.encoded_len());
+        assert_eq!(GaiaFrame::decode(&bytes), Ok((frame.clone(), bytes.len())));
+    }
+
+    #[test]
+    fn encodes_v1_command() {
+        let frame = GaiaFrame::new(0x5054, 0x0201, vec![10]);
+        assert_eq!(
+            frame.encode().unwrap(),
+            [0xFF, 0x01, 0x00, 0x01, 0x50, 0x54, 0x02, 0x01, 10]
+        );
+        round_trip(&frame);
+    }
+
+    #[test]
+    fn round_trips_v1_ack_with_checksum() {
+        let mut frame = GaiaFrame::ack(0x5054, 0x0455, STATUS_SUCCESS, vec![4, 80]);
+        frame.flags = FLAG_CHECKSUM;
+        round_trip(&frame);
+        assert_eq!(frame.gaia_status(), Some(GaiaStatus::Success));
+    }
+
+    #[test]
+    fn round_trips_v2_extended_length() {
+        let mut frame = GaiaFrame::new(0x000A, 0x0300, vec![0x5A; 300]);
+        frame.version = GaiaVersion::V2;
+        frame.flags = FLAG_LENGTH_EXTENSION;
+        round_trip(&frame);
+    }
+
+    #[test]
+    fn round_trips_v3_command_id() {
+        let id = V3CommandId {
+            feature: 0x1D,
+            packet_type: V3PacketType::Response,
+            command: 0x05,
+        };
+        assert_eq!(V3CommandId::from_command(id.to_command()), id);
+
+        let mut frame = GaiaFrame::new(0x001D, id.to_command(), vec![1; 300]);
+        frame.version = GaiaVersion::V3;
+        frame.flags = FLAG_LENGTH_EXTENSION | FLAG_CHECKSUM;
+        round_trip(&frame);
+        assert_eq!(frame.v3_command(), id);
+    }
+
+    #[test]
+    fn rejects_bad_checksum() {
+        let mut frame = GaiaFrame::new(0x5054, 0x0401, vec![1, 2, 3]);
+        frame.flags = FLAG_CHECKSUM;
+        let mut bytes = frame.encode().unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0x01;
+        assert!(matches!(
+            GaiaFrame::decode(&bytes),
+            Err(DecodeError::ChecksumMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_bad_lengths() {
+        assert_eq!(
+            GaiaFrame::new(0x5054, 0x0401, vec![0; MAX_PAYLOAD_LEN + 1]).encode(),
+            Err(EncodeError::PayloadTooLong {
+                len: MAX_PAYLOAD_LEN + 1,
+                max: MAX_PAYLOAD_LEN
+            })
+        );
+
+        let mut v1_extended = GaiaFrame::new(0x5054, 0x0401, vec![]);
+        v1_extended.flags = FLAG_LENGTH_EXTENSION;
+        assert_eq!(
+            v1_extended.encode(),
+            Err(EncodeError::LengthExtensionUnsupported(GaiaVersion::V1))
+        );
+
+        let bytes = GaiaFrame::new(0x5054, 0x0401, vec![1, 2, 3])
+            .encode()
+            .unwrap();
+        assert_eq!(
+            GaiaFrame::decode(&bytes[..bytes.len() - 1]),
+            Err(DecodeError::Truncated {
+                needed: bytes.len(),
+                available: bytes.len() - 1
+            })
+        );
+        assert_eq!(
+            GaiaFrame::decode(&bytes[..2]),
+            Err(DecodeError::Truncated {
+                needed: 3,
+                available: 2
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_bad_header() {
+        assert_eq!(
+            GaiaFrame::decode(&[0x00, 0x01]),
+            Err(DecodeError::BadStartOfFrame(0x00))
+        );
+        assert_eq!(
+            GaiaFrame::decode(&[SOF, 0x09, 0x00, 0x00]),
+            Err(DecodeError::UnsupportedVersion(0x09))
+        );
+    }
+
+    #[test]
+    fn parser_resyncs_after_garbage() {
+        let first = GaiaFrame::new(0x5054, 0x0201, vec![7]);
+        let second = GaiaFrame::ack(0x5054, 0x0401, STATUS_SUCCESS, vec![]);
+        let mut stream = vec![0x00, 0x13, SOF, 0x09];
+        stream.extend(first.encode().unwrap());
+        stream.extend([0x42, 0x42]);
+        stream.extend(second.encode().unwrap());
+
+        let results = GaiaParser::new().push_bytes(&stream);
+        assert_eq!(
+            results,
+            vec![
+                Err(DecodeError::UnsupportedVersion(0x09)),
+                Ok(first),
+                Ok(second)
+            ]
+        );
+    }
+
+    #[test]
+    fn parser_joins_split_reads() {
+        let mut frame = GaiaFrame::new(0x001D, 0x0100, vec![3; 40]);
+        frame.version = GaiaVersion::V3;
+        frame.flags = FLAG_LENGTH_EXTENSION | FLAG_CHECKSUM;
+        let bytes = frame.encode().unwrap();
+
+        let mut parser = GaiaParser::new();
+        let mut frames = Vec::new();
+        for chunk in bytes.chunks(3) {
+            frames.extend(parser.push_bytes(chunk));
+        }
+        assert_eq!(frames, vec![Ok(frame.clone())]);
+
+        // A read that ends one frame and starts the next.
+        let (head, tail) = bytes.split_at(4);
+        let mut joined = bytes.clone();
+        joined.extend_from_slice(head);
+        assert_eq!(parser.push_bytes(&joined), vec![Ok(frame.clone())]);
+        assert_eq!(parser.push_bytes(tail), vec![Ok(frame)]);
+    }
+}
@@ -0,0 +1,618 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt::Display;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::{AppHandle, Manager, Runtime};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::backend::BackendState;
+use crate::error::StoneError;
+use crate::pt;
+use crate::{back_log, persist, APP_HANDLE};
+
+const CONFIG_FILE: &str = "http_api.json";
+const CONFIG_VERSION: u32 = 1;
+const DEFAULT_PORT: u16 = 8787;
+const MAX_BODY_BYTES: u64 = 64 * 1024;
+// Device calls wait on Bluetooth, so a few workers keep one slow speaker from holding up the
+// rest; requests beyond the queue are turned away as busy.
+const WORKERS: usize = 4;
+const MAX_QUEUED_REQUESTS: usize = 32;
+const TOKEN_BYTES: usize = 24;
+const BIND_ATTEMPTS: u32 = 10;
+const BIND_RETRY_MS: u64 = 50;
+
+/// The local control API. Off until turned on; it only ever listens on 127.0.0.1.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct HttpApiConfig {
+    pub enabled: bool,
+    pub port: u16,
+    // Sent by clients as `Authorization: Bearer <token>`.
+    pub token: String,
+}
+
+impl Default for HttpApiConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: DEFAULT_PORT,
+            token: String::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub(crate) struct HttpApiStatus {
+    #[serde(flatten)]
+    config: HttpApiConfig,
+    listening: bool,
+    // Why the server is not listening although it is enabled.
+    error: Option<String>,
+}
+
+#[derive(Default)]
+struct Inner {
+    path: Option<PathBuf>,
+    config: HttpApiConfig,
+    server: Option<Arc<Server>>,
+    error: Option<String>,
+}
+
+/// Serves device, connection, battery, volume, lamp and raw GAIA calls as JSON over HTTP for
+/// other processes on this machine, through the same code as the matching commands. Settings
+/// are kept in `http_api.json`.
+#[derive(Default)]
+pub(crate) struct HttpApi {
+    inner: Mutex<Inner>,
+}
+
+pub(crate) fn path_in(data_dir: &Path) -> PathBuf {
+    data_dir.join(CONFIG_FILE)
+}
+
+fn new_token() -> Result<String, StoneError> {
+    let mut bytes = [0u8; TOKEN_BYTES];
+    getrandom::getrandom(&mut bytes).map_err(StoneError::other)?;
+    Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Binds `port` on 127.0.0.1 with `bind`. Shared with the event stream.
+pub(crate) fn bind_local<T, E: Display>(
+    port: u16,
+    bind: impl Fn(&str, u16) -> Result<T, E>,
+) -> Result<T, StoneError> {
+    // A listener that was just stopped lets go of its port a moment later.
+    let mut attempt = 1;
+    loop {
+        match bind("127.0.0.1", port) {
+            Ok(bound) => return Ok(bound),
+            Err(_) if attempt < BIND_ATTEMPTS => {
+                attempt += 1;
+                std::thread::sleep(Duration::from_millis(BIND_RETRY_MS));
+            }
+            Err(err) => {
+                return Err(StoneError::other(format!(
+                    "Listen on 127.0.0.1:{}: {}",
+                    port, err
+                )))
+            }
+        }
+    }
+}
+
+/// Stops `running` and starts its replacement with `start`, which gives `None` when the new
+/// settings are off. The replacement is only kept once `save` stored its settings; otherwise
+/// `restore` brings back what ran before. Shared with the event stream.
+pub(crate) fn replace_listener<L>(
+    running: &mut Option<L>,
+    stop: impl Fn(L),
+    start: impl FnOnce() -> Result<Option<L>, StoneError>,
+    save: impl FnOnce() -> Result<(), StoneError>,
+    restore: impl FnOnce() -> Option<L>,
+) -> Result<Option<L>, StoneError> {
+    // The old listener has to let go of the port before it can be bound again.
+    if let Some(old) = running.take() {
+        stop(old);
+    }
+    let started = start().and_then(|next| match save() {
+        Ok(()) => Ok(next),
+        Err(err) => {
+            if let Some(next) = next {
+                stop(next);
+            }
+            Err(err)
+        }
+    });
+    if started.is_err() {
+        *running = restore();
+    }
+    started
+}
+
+fn listen(config: &HttpApiConfig) -> Result<Arc<Server>, StoneError> {
+    if config.token.is_empty() {
+        return Err(StoneError::other("HTTP API has no token"));
+    }
+    let server = Arc::new(bind_local(config.port, |host, port| {
+        Server::http((host, port))
+    })?);
+    let (queue, requests) = mpsc::sync_channel::<Request>(MAX_QUEUED_REQUESTS);
+    let requests = Arc::new(Mutex::new(requests));
+    for _ in 0..WORKERS {
+        let requests = requests.clone();
+        let token = config.token.clone();
+        // Ends once the listener below stops and drops the queue.
+        std::thread::spawn(move || loop {
+            let Ok(Ok(request)) = requests.lock().map(|requests| requests.recv()) else {
+                return;
+            };
+            handle(request, &token);
+        });
+    }
+    let serving = server.clone();
+    std::thread::spawn(move || {
+        for request in serving.incoming_requests() {
+            if let Err(TrySendError::Full(request)) = queue.try_send(request) {
+                let _ = request.respond(reply(503, &serde_json::json!({ "kind": "busy" })));
+            }
+        }
+    });
+    back_log(
+        "RUST",
+        format!("HTTP API listening on 127.0.0.1:{}", config.port),
+    );
+    Ok(server)
+}
+
+impl HttpApi {
+    /// Loads `path`, making up a token the first time, and starts listening if enabled.
+    pub(crate) fn load(&self, path: PathBuf) {
+        let mut config: HttpApiConfig = persist::load(&path, CONFIG_VERSION);
+        if config.token.is_empty() {
+            match new_token() {
+                Ok(token) => {
+                    config.token = token;
+                    if let Err(err) = persist::save(&path, CONFIG_VERSION, &config) {
+                        back_log("RUST", format!("Save HTTP API config: {}", err));
+                    }
+                }
+                Err(err) => back_log("RUST", format!("HTTP API token: {}", err)),
+            }
+        }
+        let (server, error) = if config.enabled {
+            match listen(&config) {
+                Ok(server) => (Some(server), None),
+                Err(err) => {
+                    back_log("RUST", err.to_string());
+                    (None, Some(err.to_string()))
+                }
+            }
+        } else {
+            (None, None)
+        };
+        if let Ok(mut inner) = self.inner.lock() {
+            inner.path = Some(path);
+            inner.config = config;
+            inner.server = server;
+            inner.error = error;
+        }
+    }
+
+    pub(crate) fn status(&self) -> HttpApiStatus {
+        self.inner
+            .lock()
+            .map(|inner| HttpApiStatus {
+                config: inner.config.clone(),
+                listening: inner.server.is_some(),
+                error: inner.error.clone(),
+            })
+            .unwrap_or_else(|_| HttpApiStatus {
+                config: HttpApiConfig::default(),
+                listening: false,
+                error: None,
+            })
+    }
+
+    /// Whether `given` is the API token, which the event stream accepts too.
+    pub(crate) fn accepts(&self, given: &str) -> bool {
+        self.inner
+            .lock()
+            .is_ok_and(|inner| token_matches(given, &inner.config.token))
+    }
+
+    /// Applies a new port or on/off state, optionally with a fresh token. The change is only
+    /// saved once the server could listen with it.
+    pub(crate) fn configure(
+        &self,
+        enabled: bool,
+        port: u16,
+        reset_token: bool,
+    ) -> Result<HttpApiStatus, StoneError> {
+        if port == 0 {
+            return Err(StoneError::InvalidParameter {
+                message: "port must not be 0".to_string(),
+            });
+        }
+        {
+            let mut inner = self
+                .inner
+                .lock()
+                .map_err(|_| StoneError::other("HTTP API state poisoned"))?;
+            let mut config = HttpApiConfig {
+                enabled,
+                port,
+                token: inner.config.token.clone(),
+            };
+            if reset_token || config.token.is_empty() {
+                config.token = new_token()?;
+            }
+            let previous = inner.config.clone();
+            let path = inner.path.clone();
+            let server = replace_listener(
+                &mut inner.server,
+                |server| server.unblock(),
+                || enabled.then(|| listen(&config)).transpose(),
+                || match &path {
+                    Some(path) => persist::save(path, CONFIG_VERSION, &config),
+                    None => Ok(()),
+                },
+                || previous.enabled.then(|| listen(&previous).ok()).flatten(),
+            )?;
+            if !enabled {
+                back_log("RUST", "HTTP API stopped".to_string());
+            }
+            inner.config = config;
+            inner.server = server;
+            inner.error = None;
+        }
+        Ok(self.status())
+    }
+}
+
+fn json_header() -> Header {
+    Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
+        .expect("static header is valid")
+}
+
+fn reply(status: u16, body: &Value) -> Response<std::io::Cursor<Vec<u8>>> {
+    Response::from_string(body.to_string())
+        .with_status_code(status)
+        .with_header(json_header())
+}
+
+fn error_status(err: &StoneError) -> u16 {
+    match err {
+        StoneError::InvalidParameter { .. }
+        | StoneError::InvalidAddress
+        | StoneError::Decode { .. } => 400,
+        StoneError::PermissionDenied => 403,
+        StoneError::DeviceNotFound => 404,
+        StoneError::NotConnected | StoneError::Busy { .. } => 409,
+        StoneError::NotSupported { .. } => 501,
+        StoneError::Timeout { .. } => 504,
+        _ => 500,
+    }
+}
+
+fn authorized(request: &Request, token: &str) -> bool {
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("Authorization"))
+        .and_then(|header| header.value.as_str().strip_prefix("Bearer "))
+        .is_some_and(|given| token_matches(given, token))
+}
+
+// Compares every byte so the time taken does not give away how much of a guess was right.
+fn token_matches(given: &str, token: &str) -> bool {
+    let (given, token) = (given.trim().as_bytes(), token.as_bytes());
+    !token.is_empty()
+        && given.len() == token.len()
+        && given
+            .iter()
+            .zip(token)
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+pub(crate) fn percent_decode(segment: &str) -> String {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn parse<T: DeserializeOwned>(body: &[u8]) -> Result<T, StoneError> {
+    serde_json::from_slice(body).map_err(|err| StoneError::Decode {
+        message: err.to_string(),
+    })
+}
+
+fn to_json<T: Serialize>(result: Result<T, StoneError>) -> Result<Value, StoneError> {
+    serde_json::to_value(result?).map_err(StoneError::other)
+}
+
+#[derive(Deserialize)]
+struct VolumeBody {
+    volume: u8,
+}
+
+#[derive(Deserialize)]
+struct LampBody {
+    on: bool,
+    brightness: Option<u8>,
+    lamp_type: Option<u8>,
+    color: Option<pt::Rgb>,
+}
+
+#[derive(Deserialize)]
+struct GaiaBody {
+    vendor_id: u16,
+    command_id: u16,
+    #[serde(default)]
+    payload: Vec<u8>,
+}
+
+/// Runs one request. `None` means no endpoint matches the method and path.
+async fn route<R: Runtime>(
+    app: &AppHandle<R>,
+    method: &Method,
+    segments: &[String],
+    body: &[u8],
+) -> Option<Result<Value, StoneError>> {
+    let backend = || app.state::<BackendState>();
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+    let result = match (method, segments.as_slice()) {
+        (Method::Get, ["api", "devices"]) => to_json(crate::list_devices(backend()).await),
+        (Method::Get, ["api", "connections"]) => {
+            to_json(crate::get_connection_infos(backend()).await)
+        }
+        (Method::Get, ["api", "connections", "states"]) => {
+            to_json(Ok(crate::get_connection_states()))
+        }
+        (Method::Get, ["api", "devices", address, "battery"]) => {
+            to_json(crate::get_battery(backend(), address.to_string()).await)
+        }
+        (Method::Get, ["api", "devices", address, "volume"]) => {
+            let volume = crate::get_volume(backend(), address.to_string()).await;
+            to_json(volume.map(|volume| serde_json::json!({ "volume": volume })))
+        }
+        (Method::Put, ["api", "devices", target, "volume"]) => match parse::<VolumeBody>(body) {
+            Ok(body) => {
+                to_json(crate::set_volume(backend(), target.to_string(), body.volume).await)
+            }
+            Err(err) => Err(err),
+        },
+        (Method::Get, ["api", "devices", address, "lamp"]) => {
+            to_json(crate::get_lamp_state(backend(), address.to_string()).await)
+        }
+        (Method::Put, ["api", "devices", target, "lamp"]) => match parse::<LampBody>(body) {
+            Ok(body) => to_json(
+                crate::set_lamp(
+                    backend(),
+                    target.to_string(),
+                    body.on,
+                    body.brightness,
+                    body.lamp_type,
+                    body.color,
+                )
+                .await,
+            ),
+            Err(err) => Err(err),
+        },
+        (Method::Post, ["api", "devices", target, "gaia"]) => match parse::<GaiaBody>(body) {
+            Ok(body) => to_json(
+                crate::send_gaia_command(
+                    backend(),
+                    target.to_string(),
+                    body.vendor_id,
+                    body.command_id,
+                    body.payload,
+                )
+                .await,
+            ),
+            Err(err) => Err(err),
+        },
+        _ => return None,
+    };
+    Some(result)
+}
+
+fn too_large() -> Response<std::io::Cursor<Vec<u8>>> {
+    reply(413, &serde_json::json!({ "kind": "payload_too_large" }))
+}
+
+fn routed(result: Option<Result<Value, StoneError>>) -> Response<std::io::Cursor<Vec<u8>>> {
+    match result {
+        None => reply(404, &serde_json::json!({ "kind": "not_found" })),
+        Some(Ok(Value::Null)) => reply(200, &serde_json::json!({ "ok": true })),
+        Some(Ok(value)) => reply(200, &value),
+        Some(Err(err)) => reply(
+            error_status(&err),
+            &serde_json::to_value(&err).unwrap_or(Value::Null),
+        ),
+    }
+}
+
+fn handle(mut request: Request, token: &str) {
+    let response = if !authorized(&request, token) {
+        reply(401, &serde_json::json!({ "kind": "unauthorized" }))
+    } else if request
+        .body_length()
+        .is_some_and(|length| length as u64 > MAX_BODY_BYTES)
+    {
+        too_large()
+    } else if let Some(app) = APP_HANDLE.get() {
+        let mut body = Vec::new();
+        // One byte past the limit tells a chunked body that is too large from one that fits.
+        let read = request
+            .as_reader()
+            .take(MAX_BODY_BYTES + 1)
+            .read_to_end(&mut body);
+        let path = request.url().split('?').next().unwrap_or_default();
+        let segments: Vec<String> = path
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(percent_decode)
+            .collect();
+        match read {
+            Ok(_) if body.len() as u64 > MAX_BODY_BYTES => too_large(),
+            Ok(_) => routed(tauri::async_runtime::block_on(route(
+                app,
+                request.method(),
+                &segments,
+                &body,
+            ))),
+            Err(err) => routed(Some(Err(StoneError::other(err)))),
+        }
+    } else {
+        reply(503, &serde_json::json!({ "kind": "not_ready" }))
+    };
+    let _ = request.respond(response);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::net::TcpStream;
+
+    const TOKEN: &str = "secret";
+
+    fn serve() -> (Arc<Server>, u16) {
+        let server = listen(&HttpApiConfig {
+            enabled: true,
+            port: 0,
+            token: TOKEN.to_string(),
+        })
+        .unwrap();
+        let port = server.server_addr().to_ip().unwrap().port();
+        (server, port)
+    }
+
+    fn status_of(port: u16, head: &str) -> String {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        stream.write_all(head.as_bytes()).unwrap();
+        let mut response = String::new();
+        let _ = stream.read_to_string(&mut response);
+        response.lines().next().unwrap_or_default().to_string()
+    }
+
+    #[test]
+    fn oversized_bodies_get_413() {
+        let (server, port) = serve();
+        let head = format!(
+            "PUT /api/devices/00:00:5E:00:53:C0/volume HTTP/1.1\r\nHost: localhost\r\n\
+             Authorization: Bearer {}\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n",
+            TOKEN,
+            MAX_BODY_BYTES + 1
+        );
+        assert!(status_of(port, &head).starts_with("HTTP/1.1 413"));
+        server.unblock();
+    }
+
+    #[test]
+    fn workers_answer_every_request() {
+        let (server, port) = serve();
+        let unauthorized = "GET /devices HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n";
+        let requests: Vec<_> = (0..WORKERS * 2)
+            .map(|_| std::thread::spawn(move || status_of(port, unauthorized)))
+            .collect();
+        for request in requests {
+            assert!(request.join().unwrap().starts_with("HTTP/1.1 401"));
+        }
+        server.unblock();
+    }
+
+    #[test]
+    fn tokens_must_match_in_full() {
+        assert!(token_matches(TOKEN, TOKEN));
+        assert!(token_matches(" secret\t", TOKEN));
+        assert!(!token_matches("secreT", TOKEN));
+        assert!(!token_matches("secre", TOKEN));
+        assert!(!token_matches("secrets", TOKEN));
+        assert!(!token_matches("", TOKEN));
+        // An empty token never lets anyone in, not even with an empty guess.
+        assert!(!token_matches("", ""));
+        assert!(!token_matches(" ", ""));
+    }
+
+    #[test]
+    fn percent_decode_keeps_what_it_cannot_decode() {
+        assert_eq!(percent_decode("00%3A11%3a22"), "00:11:22");
+        assert_eq!(percent_decode("Living%20Room"), "Living Room");
+        assert_eq!(percent_decode("%EC%B9%A8%EC%8B%A4"), "침실");
+        assert_eq!(percent_decode("거실"), "거실");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%4"), "%4");
+        assert_eq!(percent_decode("%zz%"), "%zz%");
+        // Half a character comes out as a replacement rather than failing the request.
+        assert_eq!(percent_decode("%EC%B9"), "\u{FFFD}");
+    }
+
+    #[test]
+    fn errors_map_to_http_statuses() {
+        let invalid = StoneError::InvalidParameter {
+            message: String::new(),
+        };
+        let decode = StoneError::Decode {
+            message: String::new(),
+        };
+        assert_eq!(error_status(&invalid), 400);
+        assert_eq!(error_status(&StoneError::InvalidAddress), 400);
+        assert_eq!(error_status(&decode), 400);
+        assert_eq!(error_status(&StoneError::PermissionDenied), 403);
+        assert_eq!(error_status(&StoneError::DeviceNotFound), 404);
+        assert_eq!(error_status(&StoneError::NotConnected), 409);
+        let busy = StoneError::Busy {
+            message: String::new(),
+        };
+        assert_eq!(error_status(&busy), 409);
+        let unsupported = StoneError::NotSupported {
+            message: String::new(),
+        };
+        assert_eq!(error_status(&unsupported), 501);
+        assert_eq!(error_status(&StoneError::Timeout { timeout_ms: 1 }), 504);
+        assert_eq!(error_status(&StoneError::AdapterUnavailable), 500);
+        assert_eq!(error_status(&StoneError::other("boom")), 500);
+    }
+
+    #[test]
+    fn unknown_endpoints_are_not_routed() {
+        let app = tauri::test::mock_app();
+        let not_found = |method: Method, path: &str| {
+            let segments: Vec<String> = path.split('/').map(str::to_string).collect();
+            tauri::async_runtime::block_on(route(app.handle(), &method, &segments, b"")).is_none()
+        };
+        assert!(not_found(Method::Put, "volume"));
+        assert!(not_found(Method::Get, "api/devices/00:00:5E:00:53:C0"));
+        assert!(not_found(Method::Delete, "api/devices"));
+        assert!(not_found(
+            Method::Post,
+            "api/devices/00:00:5E:00:53:C0/volume"
+        ));
+        assert_eq!(routed(None).status_code(), tiny_http::StatusCode(404));
+    }
+}
@@ -0,0 +1,151 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use tokio::sync::Notify;
+
+use crate::error::StoneError;
+use crate::retry::unix_millis;
+use crate::{back_log, persist};
+
+// The runner looks at the clock at least this often, so suspend and clock changes are caught.
+const MAX_SLEEP_MS: u64 = 60_000;
+
+pub(crate) fn now() -> u64 {
+    unix_millis(SystemTime::now())
+}
+
+/// State kept by a `JobStore`: the part saved to its file, plus whatever the runner tracks
+/// beside it.
+pub(crate) trait JobList: Default {
+    type File: Serialize + DeserializeOwned + Default;
+    const VERSION: u32;
+
+    /// The part that is saved; also what a failed save rolls back to.
+    fn saved(&self) -> Self::File;
+    fn restore(&mut self, file: Self::File);
+}
+
+struct Inner<S> {
+    path: Option<PathBuf>,
+    list: S,
+}
+
+/// Timed jobs kept in a file, with one runner task that sleeps until the next one is due or
+/// the list changes. Shared by the scheduler and the alarm clock.
+pub(crate) struct JobStore<S> {
+    inner: Mutex<Inner<S>>,
+    wake: Notify,
+    started: AtomicBool,
+}
+
+impl<S: JobList> Default for JobStore<S> {
+    fn default() -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                path: None,
+                list: S::default(),
+            }),
+            wake: Notify::new(),
+            started: AtomicBool::new(false),
+        }
+    }
+}
+
+impl<S: JobList> JobStore<S> {
+    /// Loads `path` and saves there from now on. `prepare` works out the next run of every
+    /// job before anyone sees the list.
+    pub(crate) fn load(&self, path: PathBuf, prepare: impl FnOnce(&mut S)) {
+        let mut list = S::default();
+        list.restore(persist::load(&path, S::VERSION));
+        prepare(&mut list);
+        if let Ok(mut inner) = self.inner.lock() {
+            inner.path = Some(path);
+            inner.list = list;
+        }
+        self.wake.notify_one();
+    }
+
+    /// Spawns `runner` the first time it is called.
+    pub(crate) fn start(&self, runner: impl Future<Output = ()> + Send + 'static) {
+        if !self.started.swap(true, Ordering::SeqCst) {
+            tauri::async_runtime::spawn(runner);
+        }
+    }
+
+    /// Looks at or changes what the runner tracks; nothing is saved.
+    pub(crate) fn with<T>(&self, f: impl FnOnce(&mut S) -> T) -> Option<T> {
+        let mut inner = self.inner.lock().ok()?;
+        Some(f(&mut inner.list))
+    }
+
+    /// Applies `change` and saves the result. A change that fails, or cannot be saved, leaves
+    /// the saved part as it was.
+    pub(crate) fn update<T>(
+        &self,
+        change: impl FnOnce(&mut S) -> Result<T, StoneError>,
+    ) -> Result<T, StoneError> {
+        let result = {
+            let mut inner = self
+                .inner
+                .lock()
+                .map_err(|_| StoneError::other("Job state poisoned"))?;
+            let previous = inner.list.saved();
+            let result = change(&mut inner.list).and_then(|value| match &inner.path {
+                Some(path) => persist::save(path, S::VERSION, &inner.list.saved()).map(|()| value),
+                None => Ok(value),
+            });
+            if result.is_err() {
+                inner.list.restore(previous);
+            }
+            result
+        }?;
+        self.wake.notify_one();
+        Ok(result)
+    }
+
+    /// Hands `take` the list when `any_due` finds something, and saves what it moved on.
+    /// Returns `None` when nothing was due, so an idle tick neither saves nor announces. A
+    /// failed save does not roll back here, or the same runs would be handed out again.
+    pub(crate) fn take_due<T>(
+        &self,
+        any_due: impl FnOnce(&S) -> bool,
+        take: impl FnOnce(&mut S) -> Vec<T>,
+    ) -> Option<Vec<T>> {
+        let mut inner = self.inner.lock().ok()?;
+        if !any_due(&inner.list) {
+            return None;
+        }
+        let due = take(&mut inner.list);
+        if let Some(path) = &inner.path {
+            if let Err(err) = persist::save(path, S::VERSION, &inner.list.saved()) {
+                back_log("RUST", format!("Save {}: {}", path.display(), err));
+            }
+        }
+        Some(due)
+    }
+
+    /// Wakes the runner early, say after a change that moves its next wake.
+    pub(crate) fn wake(&self) {
+        self.wake.notify_one();
+    }
+
+    /// Calls `tick` with the time, then sleeps until `next_due` or a change.
+    pub(crate) async fn run(
+        &self,
+        mut tick: impl FnMut(u64),
+        next_due: impl Fn(&S) -> Option<u64>,
+    ) {
+        loop {
+            tick(now());
+            let now = now();
+            let next = self.with(|list| next_due(list)).flatten();
+            let wait = next.map_or(MAX_SLEEP_MS, |at| at.saturating_sub(now));
+            let wait = Duration::from_millis(wait.min(MAX_SLEEP_MS));
+            let _ = tokio::time::timeout(wait, self.wake.notified()).await;
+        }
+    }
+}
@@ -0,0 +1,445 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+use tauri::Emitter;
+
+use crate::backend::BackendState;
+use crate::cancel::CancelToken;
+use crate::error::StoneError;
+use crate::retry::unix_millis;
+use crate::{back_log, pt, APP_HANDLE};
+
+/// Heartbeat settings. A beat is one RSSI query; `degraded_after` and `disconnect_after`
+/// count consecutive beats that went unanswered.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub(crate) struct KeepaliveConfig {
+    pub enabled: bool,
+    pub interval_ms: u64,
+    pub timeout_ms: u64,
+    pub degraded_after: u32,
+    pub disconnect_after: u32,
+}
+
+impl Default for KeepaliveConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_ms: 15_000,
+            timeout_ms: 3_000,
+            degraded_after: 1,
+            disconnect_after: 3,
+        }
+    }
+}
+
+impl KeepaliveConfig {
+    fn validate(&self) -> Result<(), StoneError> {
+        let invalid = |message: &str| {
+            Err(StoneError::InvalidParameter {
+                message: message.to_string(),
+            })
+        };
+        if self.interval_ms < 1_000 {
+            return invalid("interval_ms must be at least 1000");
+        }
+        if self.timeout_ms == 0 || self.timeout_ms > self.interval_ms {
+            return invalid("timeout_ms must be between 1 and interval_ms");
+        }
+        if self.degraded_after == 0 {
+            return invalid("degraded_after must be at least 1");
+        }
+        if self.disconnect_after < self.degraded_after {
+            return invalid("disconnect_after must not be below degraded_after");
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum LinkHealth {
+    Healthy,
+    Degraded,
+    Lost,
+}
+
+/// Emitted as `bt_link_health` after every beat.
+#[derive(Serialize, Clone, Debug)]
+pub(crate) struct LinkStatus {
+    address: String,
+    health: LinkHealth,
+    latency_ms: Option<u64>,
+    average_latency_ms: Option<u64>,
+    missed: u32,
+    last_seen_at: Option<u64>,
+    last_error: Option<StoneError>,
+}
+
+struct Session {
+    status: LinkStatus,
+    stop: CancelToken,
+}
+
+struct Inner {
+    config: KeepaliveConfig,
+    sessions: HashMap<String, Session>,
+}
+
+/// Per-address heartbeat for connected speakers, so a channel that stopped answering is
+/// noticed before the next write fails.
+pub(crate) struct Keepalive {
+    inner: Mutex<Inner>,
+}
+
+fn key(address: &str) -> String {
+    address.to_ascii_lowercase()
+}
+
+impl Keepalive {
+    pub(crate) fn new() -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                config: KeepaliveConfig::default(),
+                sessions: HashMap::new(),
+            }),
+        }
+    }
+
+    pub(crate) fn config(&self) -> KeepaliveConfig {
+        self.inner
+            .lock()
+            .map(|inner| inner.config.clone())
+            .unwrap_or_default()
+    }
+
+    /// Applies `config` and restarts the heartbeat of every connected address under it.
+    pub(crate) fn set_config(
+        &'static self,
+        config: KeepaliveConfig,
+        backend: &BackendState,
+    ) -> Result<(), StoneError> {
+        config.validate()?;
+        {
+            let mut inner = self
+                .inner
+                .lock()
+                .map_err(|_| StoneError::other("Keepalive state poisoned"))?;
+            inner.config = config;
+            for (_, session) in inner.sessions.drain() {
+                session.stop.cancel();
+            }
+        }
+        for address in crate::get_connections().connected_addresses() {
+            self.start(&address, backend);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn snapshot(&self) -> Vec<LinkStatus> {
+        let mut statuses: Vec<LinkStatus> = self
+            .inner
+            .lock()
+            .map(|inner| {
+                inner
+                    .sessions
+                    .values()
+                    .map(|session| session.status.clone())
+                    .collect()
+            })
+            .unwrap_or_default();
+        statuses.sort_by(|a, b| a.address.cmp(&b.address));
+        statuses
+    }
+
+    /// Starts beating for a freshly connected address, replacing any earlier heartbeat.
+    pub(crate) fn start(&'static self, address: &str, backend: &BackendState) {
+        let stop = CancelToken::new();
+        {
+            let Ok(mut inner) = self.inner.lock() else {
+                return;
+            };
+            if !inner.config.enabled {
+                return;
+            }
+            let session = Session {
+                status: LinkStatus {
+                    address: address.to_string(),
+                    health: LinkHealth::Healthy,
+                    latency_ms: None,
+                    average_latency_ms: None,
+                    missed: 0,
+                    last_seen_at: None,
+                    last_error: None,
+                },
+                stop: stop.clone(),
+            };
+            if let Some(previous) = inner.sessions.insert(key(address), session) {
+                previous.stop.cancel();
+            }
+        }
+        let address = address.to_string();
+        let backend = backend.clone();
+        tauri::async_runtime::spawn(async move {
+            beat(self, address, backend, stop).await;
+        });
+    }
+
+    pub(crate) fn stop(&self, address: &str) {
+        let session = self
+            .inner
+            .lock()
+            .ok()
+            .and_then(|mut inner| inner.sessions.remove(&key(address)));
+        if let Some(session) = session {
+            session.stop.cancel();
+        }
+    }
+
+    /// Books one beat and returns the status to publish, or `None` once the session is gone.
+    fn record(
+        &self,
+        address: &str,
+        stop: &CancelToken,
+        outcome: Result<Duration, StoneError>,
+    ) -> Option<(LinkHealth, LinkStatus)> {
+        let mut inner = self.inner.lock().ok()?;
+        let config = inner.config.clone();
+        let session = inner.sessions.get_mut(&key(address))?;
+        if stop.is_cancelled() {
+            return None;
+        }
+        let previous = session.status.health;
+        let status = &mut session.status;
+        match outcome {
+            Ok(latency) => {
+                let latency_ms = latency.as_millis() as u64;
+                status.latency_ms = Some(latency_ms);
+                status.average_latency_ms = Some(match status.average_latency_ms {
+                    Some(average) => (average * 3 + latency_ms) / 4,
+                    None => latency_ms,
+                });
+                status.missed = 0;
+                status.last_seen_at = Some(unix_millis(SystemTime::now()));
+                status.last_error = None;
+                status.health = LinkHealth::Healthy;
+            }
+            Err(err) => {
+                status.missed += 1;
+                status.last_error = Some(err);
+                if status.missed >= config.disconnect_after {
+                    status.health = LinkHealth::Lost;
+                } else if status.missed >= config.degraded_after {
+                    status.health = LinkHealth::Degraded;
+                }
+            }
+        }
+        let status = status.clone();
+        if status.health == LinkHealth::Lost {
+            inner.sessions.remove(&key(address));
+        }
+        Some((previous, status))
+    }
+}
+
+fn emit_status(status: &LinkStatus) {
+    if let Some(app) = APP_HANDLE.get() {
+        let _ = app.emit("bt_link_health", status.clone());
+    }
+}
+
+async fn beat(
+    keepalive: &'static Keepalive,
+    address: String,
+    backend: BackendState,
+    stop: CancelToken,
+) {
+    loop {
+        let config = keepalive.config();
+        if stop
+            .run(tokio::time::sleep(Duration::from_millis(
+                config.interval_ms,
+            )))
+            .await
+            .is_err()
+        {
+            return;
+        }
+        let started = Instant::now();
+        let Ok(result) = stop
+            .run(pt::query::<pt::Rssi>(
+                &*backend,
+                &address,
+                config.timeout_ms,
+            ))
+            .await
+        else {
+            return;
+        };
+        let outcome = result.map(|_| started.elapsed());
+        let Some((previous, status)) = keepalive.record(&address, &stop, outcome) else {
+            return;
+        };
+        emit_status(&status);
+        match status.health {
+            LinkHealth::Lost => {
+                back_log(
+                    "RUST",
+                    format!(
+                        "No keepalive answer from {} after {} beats, dropping the link",
+                        address, status.missed
+                    ),
+                );
+                if let Err(err) = backend.disconnect_device(&address).await {
+                    back_log(
+                        "RUST",
+                        format!("Close stale channel to {}: {}", address, err),
+                    );
+                }
+                crate::emit_backend_device_event(address, false);
+                return;
+            }
+            health if health != previous => {
+                back_log("RUST", format!("Link to {} is {:?}", address, health));
+                crate::emit_link_health_event(&address, health);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::BluetoothBackend;
+    use crate::connection::ConnectionPhase;
+    use crate::gaia::GaiaFrame;
+    use crate::{BluetoothDeviceInfo, ConnectionInfo};
+    use async_trait::async_trait;
+    use std::collections::HashSet;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    /// Takes every write and never answers, like a speaker that stopped listening.
+    #[derive(Default)]
+    struct SilentBackend {
+        connected: Mutex<HashSet<String>>,
+        writes: AtomicU32,
+    }
+
+    #[async_trait]
+    impl BluetoothBackend for SilentBackend {
+        fn name(&self) -> &'static str {
+            "silent"
+        }
+
+        async fn list_devices(&self) -> Result<Vec<BluetoothDeviceInfo>, StoneError> {
+            Ok(Vec::new())
+        }
+
+        async fn scan_unpaired_stone_devices(
+            &self,
+        ) -> Result<Vec<BluetoothDeviceInfo>, StoneError> {
+            Ok(Vec::new())
+        }
+
+        async fn get_connection_infos(&self) -> Result<Vec<ConnectionInfo>, StoneError> {
+            Ok(self
+                .connected
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|address| ConnectionInfo {
+                    address: address.clone(),
+                    link: true,
+                    rfcomm: true,
+                })
+                .collect())
+        }
+
+        async fn connect_device(&self, address: &str) -> Result<(), StoneError> {
+            self.connected.lock().unwrap().insert(key(address));
+            Ok(())
+        }
+
+        async fn disconnect_device(&self, address: &str) -> Result<(), StoneError> {
+            self.connected.lock().unwrap().remove(&key(address));
+            Ok(())
+        }
+
+        async fn write_frame(&self, _address: &str, _frame: &[u8]) -> Result<(), StoneError> {
+            self.writes.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn stopped_keepalive_leaves_nothing_behind() {
+        let address = "00:00:5E:00:53:40";
+        let stub = Arc::new(SilentBackend::default());
+        let backend = BackendState(stub.clone());
+        let keepalive: &'static Keepalive = Box::leak(Box::new(Keepalive::new()));
+        keepalive.inner.lock().unwrap().config = KeepaliveConfig {
+            interval_ms: 20,
+            timeout_ms: 5_000,
+            ..KeepaliveConfig::default()
+        };
+        tauri::async_runtime::block_on(async {
+            keepalive.start(address, &backend);
+            assert_eq!(keepalive.snapshot().len(), 1);
+            // Stop while the first beat waits on its RSSI answer.
+            while stub.writes.load(Ordering::SeqCst) == 0 {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+            keepalive.stop(address);
+            assert!(keepalive.snapshot().is_empty());
+
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            assert_eq!(stub.writes.load(Ordering::SeqCst), 1);
+            // The late answer finds no waiter, and the query never staged anything.
+            let command = pt::PtCommand::GetRssi.id();
+            let ack = GaiaFrame::ack(pt::PT_VENDOR_ID, command, 0, vec![0xC4]);
+            assert!(!crate::get_pending_requests().resolve(address, &ack));
+            assert!(crate::get_device_states()
+                .acknowledge(address, command, true)
+                .is_none());
+        });
+    }
+
+    #[test]
+    fn lost_link_reaches_the_supervisor() {
+        let address = "00:00:5E:00:53:41";
+        let stub = Arc::new(SilentBackend::default());
+        let backend = BackendState(stub.clone());
+        let keepalive: &'static Keepalive = Box::leak(Box::new(Keepalive::new()));
+        keepalive.inner.lock().unwrap().config = KeepaliveConfig {
+            interval_ms: 20,
+            timeout_ms: 50,
+            degraded_after: 1,
+            disconnect_after: 2,
+            ..KeepaliveConfig::default()
+        };
+        crate::get_supervisor().set_devices(vec![address.to_string()]);
+        tauri::async_runtime::block_on(async {
+            let connections = crate::get_connections();
+            connections.enqueue(address, &backend);
+            while connections.phase(address) != ConnectionPhase::Connected {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            keepalive.start(address, &backend);
+            for _ in 0..100 {
+                if connections.phase(address) == ConnectionPhase::Idle {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+            assert_eq!(connections.phase(address), ConnectionPhase::Idle);
+            assert!(stub.connected.lock().unwrap().is_empty());
+            assert!(keepalive.snapshot().is_empty());
+
+            let supervised = serde_json::to_value(crate::get_supervisor().snapshot()).unwrap();
+            assert_ne!(supervised[0]["phase"], "idle");
+        });
+    }
+}
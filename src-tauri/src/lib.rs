@@ -1,26 +1,69 @@
+extern crate alloc;
+
 use once_cell::sync::OnceCell;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-#[cfg(target_os = "android")]
-use std::collections::HashSet;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Mutex;
-#[cfg(target_os = "android")]
-use std::time::Duration;
-use tauri::{AppHandle, Emitter};
+use tauri::{AppHandle, Emitter, Manager, State};
 use tauri_plugin_opener::OpenerExt;
 
+mod alarm;
 #[cfg(target_os = "android")]
 mod android_backend;
+mod backend;
+mod broadcast;
+mod cancel;
+mod capture;
+mod cli;
+mod connection;
+mod cron;
+mod device_state;
+mod effects;
+mod error;
+mod event_stream;
+pub mod gaia;
+mod http_api;
+mod jobs;
+mod keepalive;
+#[cfg(target_os = "linux")]
+mod linux_backend;
 #[cfg(target_os = "macos")]
-use std::ffi::{CStr, CString};
+mod macos_backend;
+mod persist;
+mod pt;
+mod registry;
+mod retry;
+mod scenes;
+mod scheduler;
+mod simulator;
+mod sleep;
+mod supervisor;
+mod transaction;
+
+use alarm::{Alarm, AlarmClock, AlarmSpec, AlarmStatus};
+use backend::{BackendState, BluetoothBackend};
+use connection::{ConnectionEntry, ConnectionManager};
+use device_state::{DeviceState, DeviceStateChanged, DeviceStateStore};
+use effects::{EffectSpec, EffectStatus, LampEffects};
+use error::StoneError;
+use event_stream::{EventStream, EventStreamStatus};
+use gaia::{GaiaFrame, GaiaParser, GaiaStatus};
+use http_api::{HttpApi, HttpApiStatus};
+use keepalive::{Keepalive, KeepaliveConfig, LinkHealth, LinkStatus};
+use registry::{DeviceGroup, DeviceProfile, DeviceRegistry, RegisteredDevice};
+use scenes::{Scene, SceneReport, SceneStore};
+use scheduler::{Job, JobSpec, Scheduler};
+use sleep::{SleepStatus, SleepTimer};
+use supervisor::{ReconnectPolicy, Supervisor, SupervisorStatus};
+use transaction::{GaiaResponse, PendingRequests, DEFAULT_REQUEST_TIMEOUT_MS};
 
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
 use tauri::menu::{Menu, MenuItem, PredefinedMenuItem};
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
 use tauri::tray::{TrayIcon, TrayIconBuilder};
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
-use tauri::{Manager, WindowEvent, Wry};
+use tauri::{WindowEvent, Wry};
 
 #[derive(Serialize, Deserialize, Clone)]
 pub(crate) struct BluetoothDeviceInfo {
@@ -30,6 +73,11 @@ pub(crate) struct BluetoothDeviceInfo {
     has_gaia: bool,
     #[serde(default = "default_true")]
     paired: bool,
+    // The advertised name, set when an alias took its place in `name`.
+    #[serde(default)]
+    raw_name: Option<String>,
+    #[serde(flatten, default)]
+    profile: DeviceProfile,
 }
 
 fn default_true() -> bool {
@@ -59,240 +107,203 @@ pub(crate) struct ConnectionInfo {
 pub(crate) struct DeviceStateEvent {
     address: String,
     connected: bool,
+    // Only set on keepalive verdicts for a link that is still up.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    health: Option<LinkHealth>,
 }
 
 #[derive(Serialize, Clone)]
 struct ConnectResult {
     address: String,
     ok: bool,
-    error: Option<String>,
-}
-
-struct GaiaParser {
-    packet: [u8; 270],
-    packet_length: usize,
-    expected: usize,
-    flags: u8,
+    error: Option<StoneError>,
 }
 
-impl GaiaParser {
-    fn new() -> Self {
-        Self {
-            packet: [0u8; 270],
-            packet_length: 0,
-            expected: 254,
-            flags: 0,
+impl GaiaPacketEvent {
+    fn from_frame(address: &str, frame: &GaiaFrame) -> Self {
+        let mut payload = Vec::with_capacity(frame.wire_payload_len());
+        if frame.ack {
+            payload.extend(frame.status);
         }
-    }
-
-    fn push_bytes(&mut self, data: &[u8], address: &str) -> Vec<GaiaPacketEvent> {
-        let mut packets = Vec::new();
-        for &byte in data {
-            if self.packet_length > 0 && self.packet_length < self.packet.len() {
-                self.packet[self.packet_length] = byte;
-                if self.packet_length == 2 {
-                    self.flags = byte;
-                } else if self.packet_length == 3 {
-                    let payload_length = byte as usize;
-                    let check_len = if (self.flags & 1) != 0 { 1 } else { 0 };
-                    self.expected = payload_length + 8 + check_len;
-                    if self.expected > self.packet.len() {
-                        self.packet_length = 0;
-                        self.expected = 254;
-                        continue;
-                    }
-                }
-
-                self.packet_length += 1;
-                if self.packet_length == self.expected {
-                    if let Some(packet) =
-                        parse_gaia_packet(&self.packet[..self.packet_length], address)
-                    {
-                        packets.push(packet);
-                    }
-                    self.packet_length = 0;
-                    self.expected = 254;
-                }
-            } else if byte == 0xFF {
-                self.packet[0] = 0xFF;
-                self.packet_length = 1;
-            }
+        payload.extend_from_slice(&frame.payload);
+        Self {
+            address: address.to_string(),
+            vendor_id: frame.vendor_id,
+            command_id: frame.command_id(),
+            command: frame.command,
+            ack: frame.ack,
+            flags: frame.flags,
+            payload,
+            status: frame.status,
         }
-        packets
     }
 }
 
-fn parse_gaia_packet(data: &[u8], address: &str) -> Option<GaiaPacketEvent> {
-    if data.len() < 8 {
-        return None;
-    }
-    let flags = data[2];
-    let payload_length = data[3] as usize;
-    let check_len = if (flags & 1) != 0 { 1 } else { 0 };
-    if data.len() < payload_length + 8 + check_len {
-        return None;
-    }
+fn gaia_frame(vendor_id: u16, command_id: u16, payload: &[u8]) -> Result<Vec<u8>, StoneError> {
+    GaiaFrame::from_command_id(vendor_id, command_id, payload.to_vec())
+        .encode()
+        .map_err(|err| StoneError::InvalidParameter {
+            message: err.to_string(),
+        })
+}
 
-    if (flags & 1) != 0 {
-        let mut check: u8 = 0;
-        for b in &data[..data.len() - 1] {
-            check ^= *b;
-        }
-        if check != data[data.len() - 1] {
-            return None;
-        }
+fn back_log(source: &str, message: String) {
+    if LOG_TO_STDERR.load(Ordering::Relaxed) {
+        eprintln!("[STONE][BACK][{}] {}", source, message);
+    } else {
+        println!("[STONE][BACK][{}] {}", source, message);
     }
+}
 
-    let vendor_id = u16::from_be_bytes([data[4], data[5]]);
-    let command_id = u16::from_be_bytes([data[6], data[7]]);
-    let ack = (command_id & 0x8000) != 0;
-    let command = command_id & 0x7FFF;
+static APP_HANDLE: OnceCell<AppHandle> = OnceCell::new();
+static PARSERS: OnceCell<Mutex<HashMap<String, GaiaParser>>> = OnceCell::new();
+static PENDING_REQUESTS: OnceCell<PendingRequests> = OnceCell::new();
+static LOG_TO_STDERR: AtomicBool = AtomicBool::new(false);
+static DEVICE_STATES: OnceCell<DeviceStateStore> = OnceCell::new();
+static CONNECTIONS: OnceCell<ConnectionManager> = OnceCell::new();
+static SUPERVISOR: OnceCell<Supervisor> = OnceCell::new();
+static KEEPALIVE: OnceCell<Keepalive> = OnceCell::new();
+static REGISTRY: OnceCell<DeviceRegistry> = OnceCell::new();
+static LAMP_EFFECTS: OnceCell<LampEffects> = OnceCell::new();
+static SCENES: OnceCell<SceneStore> = OnceCell::new();
+static SCHEDULER: OnceCell<Scheduler> = OnceCell::new();
+static SLEEP_TIMER: OnceCell<SleepTimer> = OnceCell::new();
+static ALARM_CLOCK: OnceCell<AlarmClock> = OnceCell::new();
+static HTTP_API: OnceCell<HttpApi> = OnceCell::new();
+static EVENT_STREAM: OnceCell<EventStream> = OnceCell::new();
+static TRAY_SOURCE: Mutex<Option<(String, bool)>> = Mutex::new(None);
 
-    let payload_end = 8 + payload_length;
-    let payload = if payload_length > 0 && data.len() >= payload_end {
-        data[8..payload_end].to_vec()
-    } else {
-        Vec::new()
-    };
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+static TRAY: OnceCell<TrayIcon<Wry>> = OnceCell::new();
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+static TRAY_BATTERY_ITEM: OnceCell<MenuItem<Wry>> = OnceCell::new();
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+static TRAY_SLEEP_ITEM: OnceCell<MenuItem<Wry>> = OnceCell::new();
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+static TRAY_SLEEP_CANCEL_ITEM: OnceCell<MenuItem<Wry>> = OnceCell::new();
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+static TRAY_ALARM_ITEM: OnceCell<MenuItem<Wry>> = OnceCell::new();
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+static TRAY_ALARM_SNOOZE_ITEM: OnceCell<MenuItem<Wry>> = OnceCell::new();
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+static TRAY_ALARM_DISMISS_ITEM: OnceCell<MenuItem<Wry>> = OnceCell::new();
 
-    let status = if ack && !payload.is_empty() {
-        Some(payload[0])
-    } else {
-        None
-    };
+fn get_parsers() -> &'static Mutex<HashMap<String, GaiaParser>> {
+    PARSERS.get_or_init(|| Mutex::new(HashMap::new()))
+}
 
-    Some(GaiaPacketEvent {
-        address: address.to_string(),
-        vendor_id,
-        command_id,
-        command,
-        ack,
-        flags,
-        payload,
-        status,
-    })
+fn get_pending_requests() -> &'static PendingRequests {
+    PENDING_REQUESTS.get_or_init(PendingRequests::default)
 }
 
-fn gaia_frame(
-    vendor_id: u16,
-    command_id: u16,
-    payload: &[u8],
-    flags: u8,
-) -> Result<Vec<u8>, String> {
-    if payload.len() > 254 {
-        return Err("Payload too long".to_string());
-    }
+fn get_device_states() -> &'static DeviceStateStore {
+    DEVICE_STATES.get_or_init(DeviceStateStore::default)
+}
 
-    let check_len = if (flags & 1) != 0 { 1 } else { 0 };
-    let mut frame = Vec::with_capacity(8 + payload.len() + check_len);
-    frame.push(0xFF);
-    frame.push(0x01);
-    frame.push(flags);
-    frame.push(payload.len() as u8);
-    frame.extend_from_slice(&vendor_id.to_be_bytes());
-    frame.extend_from_slice(&command_id.to_be_bytes());
-    frame.extend_from_slice(payload);
-
-    if check_len == 1 {
-        let mut check: u8 = 0;
-        for b in &frame {
-            check ^= *b;
-        }
-        frame.push(check);
-    }
+fn get_connections() -> &'static ConnectionManager {
+    CONNECTIONS.get_or_init(ConnectionManager::new)
+}
 
-    Ok(frame)
+fn get_supervisor() -> &'static Supervisor {
+    SUPERVISOR.get_or_init(Supervisor::new)
 }
 
-#[cfg(target_os = "macos")]
-fn ioreturn_name(code: i32) -> &'static str {
-    match code as u32 {
-        0x00000000 => "kIOReturnSuccess",
-        0xE0020002 => "kIOBluetoothConnectionAlreadyExists",
-        0xE00002BC => "kIOReturnError",
-        0xE00002C0 => "kIOReturnNoDevice",
-        0xE00002C5 => "kIOReturnExclusiveAccess",
-        0xE00002CD => "kIOReturnNotOpen",
-        0xE00002D6 => "kIOReturnTimeout",
-        0xE00002E2 => "kIOReturnNotPermitted",
-        0xE00002F0 => "kIOReturnNotFound",
-        _ => "kIOReturnUnknown",
-    }
+fn get_keepalive() -> &'static Keepalive {
+    KEEPALIVE.get_or_init(Keepalive::new)
 }
 
-fn back_log(source: &str, message: String) {
-    println!("[STONE][BACK][{}] {}", source, message);
+fn get_registry() -> &'static DeviceRegistry {
+    REGISTRY.get_or_init(DeviceRegistry::default)
 }
 
-static APP_HANDLE: OnceCell<AppHandle> = OnceCell::new();
-static PARSERS: OnceCell<Mutex<HashMap<String, GaiaParser>>> = OnceCell::new();
-static CONNECT_IN_FLIGHT: AtomicBool = AtomicBool::new(false);
-#[cfg(target_os = "android")]
-static CONNECT_CANCELLED: OnceCell<Mutex<HashSet<String>>> = OnceCell::new();
+fn get_lamp_effects() -> &'static LampEffects {
+    LAMP_EFFECTS.get_or_init(LampEffects::default)
+}
 
-#[cfg(not(any(target_os = "android", target_os = "ios")))]
-static TRAY: OnceCell<TrayIcon<Wry>> = OnceCell::new();
-#[cfg(not(any(target_os = "android", target_os = "ios")))]
-static TRAY_BATTERY_ITEM: OnceCell<MenuItem<Wry>> = OnceCell::new();
+fn get_scenes() -> &'static SceneStore {
+    SCENES.get_or_init(SceneStore::default)
+}
 
-#[cfg(target_os = "android")]
-const ANDROID_CONNECT_RETRY_DELAY_MS: u64 = 6_000;
-#[cfg(target_os = "android")]
-const ANDROID_CONNECT_MAX_RETRIES: u32 = 30;
+fn get_scheduler() -> &'static Scheduler {
+    SCHEDULER.get_or_init(Scheduler::default)
+}
 
-fn get_parsers() -> &'static Mutex<HashMap<String, GaiaParser>> {
-    PARSERS.get_or_init(|| Mutex::new(HashMap::new()))
+fn get_sleep_timer() -> &'static SleepTimer {
+    SLEEP_TIMER.get_or_init(SleepTimer::default)
 }
 
-#[cfg(target_os = "android")]
-fn get_connect_cancelled() -> &'static Mutex<HashSet<String>> {
-    CONNECT_CANCELLED.get_or_init(|| Mutex::new(HashSet::new()))
+fn get_alarm_clock() -> &'static AlarmClock {
+    ALARM_CLOCK.get_or_init(AlarmClock::default)
 }
 
-#[cfg(target_os = "android")]
-fn clear_connect_cancel(address: &str) {
-    if let Ok(mut cancelled) = get_connect_cancelled().lock() {
-        cancelled.remove(address);
-    }
+fn get_http_api() -> &'static HttpApi {
+    HTTP_API.get_or_init(HttpApi::default)
 }
 
-#[cfg(target_os = "android")]
-fn request_connect_cancel(address: &str) {
-    if let Ok(mut cancelled) = get_connect_cancelled().lock() {
-        cancelled.insert(address.to_string());
-    }
+fn get_event_stream() -> &'static EventStream {
+    EVENT_STREAM.get_or_init(EventStream::default)
 }
 
-#[cfg(target_os = "android")]
-fn is_connect_cancelled(address: &str) -> bool {
-    get_connect_cancelled()
+fn publish_device_state(change: Option<DeviceStateChanged>) {
+    let Some(change) = change else {
+        return;
+    };
+    let is_tray_device = TRAY_SOURCE
         .lock()
-        .map(|cancelled| cancelled.contains(address))
-        .unwrap_or(false)
+        .ok()
+        .and_then(|source| {
+            source
+                .as_ref()
+                .map(|(address, _)| address.eq_ignore_ascii_case(&change.address))
+        })
+        .unwrap_or(false);
+    if is_tray_device {
+        refresh_tray_battery();
+    }
+    if let Some(app) = APP_HANDLE.get() {
+        let _ = app.emit("device_state_changed", change);
+    }
 }
 
-#[cfg(target_os = "android")]
-fn should_retry_android_connect(error: &str) -> bool {
-    let normalized = error.to_ascii_lowercase();
-    if normalized.contains("permission denied")
-        || normalized.contains("pairing was cancelled")
-        || normalized.contains("pairing timed out")
-        || normalized.contains("failed to start pairing")
-        || normalized.contains("invalid bluetooth address")
-        || normalized.contains("connect cancelled")
-    {
-        return false;
+/// Feeds a PT frame from `address` into `store` and returns the changes it made.
+fn apply_frame(
+    store: &DeviceStateStore,
+    address: &str,
+    frame: &GaiaFrame,
+) -> Vec<DeviceStateChanged> {
+    if frame.vendor_id != pt::PT_VENDOR_ID {
+        return Vec::new();
     }
-
-    normalized.contains("rfcomm connection failed")
-        || normalized.contains("socket might closed")
-        || normalized.contains("read failed")
+    let success = frame.gaia_status().is_none_or(GaiaStatus::is_success);
+    let mut changes = Vec::new();
+    if frame.ack {
+        changes.extend(store.acknowledge(address, frame.command, success));
+    }
+    if !success {
+        return changes;
+    }
+    // Unsolicited PT packets lead with a byte the ACK status would otherwise occupy.
+    let data = if frame.ack {
+        frame.payload.as_slice()
+    } else {
+        frame.payload.get(1..).unwrap_or_default()
+    };
+    match pt::PtReport::decode(frame.command, data) {
+        Some(Ok(report)) => changes.extend(store.apply_report(address, &report)),
+        Some(Err(err)) => back_log(
+            "RUST",
+            format!("Ignored PT report from {}: {}", address, err),
+        ),
+        None => {}
+    }
+    changes
 }
 
 pub(crate) fn handle_backend_data(address: &str, data: &[u8]) {
     if address.is_empty() || data.is_empty() {
         return;
     }
+    capture::record(address, capture::Direction::Inbound, data);
     let mut parsers = match get_parsers().lock() {
         Ok(guard) => guard,
         Err(_) => return,
@@ -300,409 +311,675 @@ pub(crate) fn handle_backend_data(address: &str, data: &[u8]) {
     let parser = parsers
         .entry(address.to_string())
         .or_insert_with(GaiaParser::new);
-    let packets = parser.push_bytes(data, address);
-    if let Some(app) = APP_HANDLE.get() {
-        for packet in packets {
-            let _ = app.emit("gaia_packet", packet);
+    let frames = parser.push_bytes(data);
+    drop(parsers);
+    for frame in frames {
+        match frame {
+            Ok(frame) => {
+                get_pending_requests().resolve(address, &frame);
+                for change in apply_frame(get_device_states(), address, &frame) {
+                    publish_device_state(Some(change));
+                }
+                if let Some(app) = APP_HANDLE.get() {
+                    let _ = app.emit("gaia_packet", GaiaPacketEvent::from_frame(address, &frame));
+                }
+            }
+            Err(err) => back_log(
+                "RUST",
+                format!("Dropped GAIA frame from {}: {}", address, err),
+            ),
         }
     }
 }
 
 pub(crate) fn emit_backend_device_event(address: String, connected: bool) {
+    if !connected {
+        get_keepalive().stop(&address);
+        get_pending_requests().cancel_address(&address);
+    }
+    if get_connections().link_changed(&address, connected) {
+        get_supervisor().link_lost(&address);
+    } else if connected {
+        get_supervisor().link_restored(&address);
+    }
+    publish_device_state(get_device_states().set_connected(&address, connected));
     if let Some(app) = APP_HANDLE.get() {
-        let _ = app.emit("bt_device_event", DeviceStateEvent { address, connected });
+        let _ = app.emit(
+            "bt_device_event",
+            DeviceStateEvent {
+                address,
+                connected,
+                health: None,
+            },
+        );
     }
 }
 
-#[cfg(target_os = "macos")]
-extern "C" {
-    fn macos_bt_list_paired_devices() -> *mut std::os::raw::c_char;
-    fn macos_bt_scan_unpaired_stone_devices() -> *mut std::os::raw::c_char;
-    fn macos_bt_connect(address: *const std::os::raw::c_char) -> i32;
-    fn macos_bt_disconnect(address: *const std::os::raw::c_char) -> i32;
-    fn macos_bt_write(address: *const std::os::raw::c_char, data: *const u8, len: usize) -> i32;
-    fn macos_bt_last_error_context() -> *mut std::os::raw::c_char;
-    fn macos_bt_get_connection_infos() -> *mut std::os::raw::c_char;
+pub(crate) fn emit_link_health_event(address: &str, health: LinkHealth) {
+    if let Some(app) = APP_HANDLE.get() {
+        let _ = app.emit(
+            "bt_device_event",
+            DeviceStateEvent {
+                address: address.to_string(),
+                connected: true,
+                health: Some(health),
+            },
+        );
+    }
 }
 
-#[cfg(target_os = "macos")]
-#[no_mangle]
-pub extern "C" fn macos_bt_on_data(
-    address: *const std::os::raw::c_char,
-    data: *const u8,
-    len: usize,
-) {
-    if address.is_null() || data.is_null() || len == 0 {
-        return;
+/// Fills in what the user registered for the device, with the alias shown as its name.
+fn with_profile(mut device: BluetoothDeviceInfo) -> BluetoothDeviceInfo {
+    if let Some(profile) = get_registry().profile(&device.address) {
+        if let Some(alias) = &profile.alias {
+            device.raw_name = Some(std::mem::replace(&mut device.name, alias.clone()));
+        }
+        device.profile = profile;
     }
-    let address = unsafe { CStr::from_ptr(address) }
-        .to_string_lossy()
-        .to_string();
-    let bytes = unsafe { std::slice::from_raw_parts(data, len) };
-    handle_backend_data(&address, bytes);
+    device
 }
 
-#[cfg(target_os = "macos")]
-#[no_mangle]
-pub extern "C" fn macos_bt_on_device_event(address: *const std::os::raw::c_char, connected: i32) {
-    if address.is_null() {
-        return;
+/// Runs `action` for every address `target` stands for (see `DeviceRegistry::resolve`),
+/// reporting the first failure once all of them ran.
+async fn for_each_target<F, Fut>(target: &str, action: F) -> Result<(), StoneError>
+where
+    F: Fn(String) -> Fut,
+    Fut: std::future::Future<Output = Result<(), StoneError>>,
+{
+    let mut first_error = None;
+    for address in get_registry().resolve(target) {
+        if let Err(err) = action(address).await {
+            first_error.get_or_insert(err);
+        }
     }
-    let addr = unsafe { CStr::from_ptr(address) }
-        .to_string_lossy()
-        .to_string();
-    emit_backend_device_event(addr, connected != 0);
+    first_error.map_or(Ok(()), Err)
 }
 
 #[tauri::command]
-async fn list_devices(_app: AppHandle) -> Result<Vec<BluetoothDeviceInfo>, String> {
-    #[cfg(target_os = "macos")]
-    {
-        back_log("RUST", "List devices".to_string());
-        let ptr = unsafe { macos_bt_list_paired_devices() };
-        if ptr.is_null() {
-            return Ok(Vec::new());
-        }
-        let json = unsafe { CString::from_raw(ptr) }
-            .into_string()
-            .map_err(|_| "Invalid device list encoding".to_string())?;
-        let list: Vec<BluetoothDeviceInfo> =
-            serde_json::from_str(&json).map_err(|e| e.to_string())?;
-        Ok(list)
-    }
+async fn list_devices(
+    backend: State<'_, BackendState>,
+) -> Result<Vec<BluetoothDeviceInfo>, StoneError> {
+    Ok(backend
+        .list_devices()
+        .await?
+        .into_iter()
+        .map(with_profile)
+        .collect())
+}
 
-    #[cfg(target_os = "android")]
-    {
-        android_backend::list_devices(&_app).await
-    }
+#[tauri::command]
+async fn scan_unpaired_stone_devices(
+    backend: State<'_, BackendState>,
+) -> Result<Vec<BluetoothDeviceInfo>, StoneError> {
+    get_connections()
+        .with_scan_turn(
+            async {
+                back_log("RUST", "Scan unpaired STONE devices".to_string());
+                backend.scan_unpaired_stone_devices().await
+            },
+            backend.inner(),
+        )
+        .await
+}
 
-    #[cfg(not(any(target_os = "macos", target_os = "android")))]
-    {
-        Err("Not supported on this platform".to_string())
-    }
+#[tauri::command]
+async fn get_connection_infos(
+    backend: State<'_, BackendState>,
+) -> Result<Vec<ConnectionInfo>, StoneError> {
+    backend.get_connection_infos().await
 }
 
 #[tauri::command]
-async fn scan_unpaired_stone_devices(_app: AppHandle) -> Result<Vec<BluetoothDeviceInfo>, String> {
-    #[cfg(target_os = "macos")]
-    {
-        if CONNECT_IN_FLIGHT.load(Ordering::SeqCst) {
-            back_log("RUST", "Skip scan while connect is in progress".to_string());
-            return Ok(Vec::new());
-        }
-        back_log("RUST", "Scan unpaired STONE devices".to_string());
-        let json = tauri::async_runtime::spawn_blocking(move || {
-            let ptr = unsafe { macos_bt_scan_unpaired_stone_devices() };
-            if ptr.is_null() {
-                return Ok::<String, String>("[]".to_string());
-            }
-            unsafe { CString::from_raw(ptr) }
-                .into_string()
-                .map_err(|_| "Invalid scan list encoding".to_string())
-        })
-        .await
-        .map_err(|_| "Join error".to_string())??;
-        let list: Vec<BluetoothDeviceInfo> =
-            serde_json::from_str(&json).map_err(|e| e.to_string())?;
-        Ok(list)
+async fn connect_device_async(
+    backend: State<'_, BackendState>,
+    address: String,
+) -> Result<(), StoneError> {
+    if APP_HANDLE.get().is_none() {
+        return Err(StoneError::other("App not ready"));
     }
+    get_connections().enqueue(&address, backend.inner());
+    Ok(())
+}
 
-    #[cfg(target_os = "android")]
-    {
-        if CONNECT_IN_FLIGHT.load(Ordering::SeqCst) {
-            back_log("RUST", "Skip scan while connect is in progress".to_string());
-            return Ok(Vec::new());
-        }
-        android_backend::scan_unpaired_stone_devices(&_app).await
-    }
+#[tauri::command]
+fn get_connection_states() -> Vec<ConnectionEntry> {
+    get_connections().snapshot()
+}
 
-    #[cfg(not(any(target_os = "macos", target_os = "android")))]
-    {
-        Err("Not supported on this platform".to_string())
-    }
+#[tauri::command]
+fn set_connect_concurrency(backend: State<'_, BackendState>, limit: usize) {
+    get_connections().set_limit(limit, backend.inner());
 }
 
 #[tauri::command]
-async fn get_connection_infos(_app: AppHandle) -> Result<Vec<ConnectionInfo>, String> {
-    #[cfg(target_os = "macos")]
-    {
-        let ptr = unsafe { macos_bt_get_connection_infos() };
-        if ptr.is_null() {
-            return Ok(Vec::new());
-        }
-        let json = unsafe { CString::from_raw(ptr) }
-            .into_string()
-            .map_err(|_| "Invalid connection info encoding".to_string())?;
-        let infos: Vec<ConnectionInfo> = serde_json::from_str(&json).map_err(|e| e.to_string())?;
-        Ok(infos)
-    }
+fn cancel_connect(address: String) -> bool {
+    let stood_down = get_supervisor().stand_down(&address);
+    get_connections().cancel(&address) || stood_down
+}
 
-    #[cfg(target_os = "android")]
-    {
-        android_backend::get_connection_infos(&_app).await
-    }
+#[tauri::command]
+fn get_connect_retry_policy(address: Option<String>) -> retry::RetryPolicy {
+    get_connections().policy(address.as_deref())
+}
 
-    #[cfg(not(any(target_os = "macos", target_os = "android")))]
-    {
-        Err("Not supported on this platform".to_string())
-    }
+#[tauri::command]
+fn set_connect_retry_policy(
+    address: Option<String>,
+    policy: Option<retry::RetryPolicy>,
+) -> Result<(), StoneError> {
+    get_connections().set_policy(address.as_deref(), policy)
 }
 
 #[tauri::command]
-async fn connect_device_async(_app: AppHandle, address: String) -> Result<(), String> {
-    #[cfg(any(target_os = "macos", target_os = "android"))]
-    {
-        if CONNECT_IN_FLIGHT.swap(true, Ordering::SeqCst) {
-            return Err("Connect already in progress (frontend queue should retry)".to_string());
-        }
-        let app = APP_HANDLE
-            .get()
-            .cloned()
-            .ok_or_else(|| "App not ready".to_string())?;
-        #[cfg(target_os = "android")]
-        let runtime_app = _app.clone();
-        tauri::async_runtime::spawn(async move {
-            #[cfg(target_os = "macos")]
-            let result = connect_device_inner(address.clone()).await;
-            #[cfg(target_os = "android")]
-            let result = connect_device_inner(runtime_app, address.clone()).await;
-            CONNECT_IN_FLIGHT.store(false, Ordering::SeqCst);
-            let payload = match result {
-                Ok(()) => ConnectResult {
-                    address,
-                    ok: true,
-                    error: None,
-                },
-                Err(err) => ConnectResult {
-                    address,
-                    ok: false,
-                    error: Some(err),
-                },
-            };
-            let _ = app.emit("bt_connect_result", payload);
-        });
-        Ok(())
-    }
+fn get_registered_devices() -> Vec<RegisteredDevice> {
+    get_registry().list()
+}
 
-    #[cfg(not(any(target_os = "macos", target_os = "android")))]
-    {
-        let _ = address;
-        Err("Not supported on this platform".to_string())
-    }
+#[tauri::command]
+fn register_device(address: String, name: String) -> Result<Vec<RegisteredDevice>, StoneError> {
+    get_registry().upsert(&address, &name)
 }
 
-#[cfg(target_os = "macos")]
-async fn connect_device_inner(address: String) -> Result<(), String> {
-    back_log("RUST", format!("Connect request: {}", address));
-    let status = tauri::async_runtime::spawn_blocking(move || -> Result<i32, String> {
-        let cstr = CString::new(address).map_err(|_| "Invalid address".to_string())?;
-        Ok(unsafe { macos_bt_connect(cstr.as_ptr()) })
-    })
-    .await
-    .map_err(|_| "Join error".to_string())??;
-    if status == 0 {
-        Ok(())
-    } else {
-        let context = unsafe {
-            let ptr = macos_bt_last_error_context();
-            if ptr.is_null() {
-                "".to_string()
-            } else {
-                CString::from_raw(ptr)
-                    .into_string()
-                    .unwrap_or_else(|_| "".to_string())
-            }
-        };
-        Err(format!(
-            "IOBluetooth error {} ({}){}",
-            status,
-            ioreturn_name(status),
-            if context.is_empty() {
-                "".to_string()
-            } else {
-                format!(" ({})", context)
-            }
-        ))
-    }
+#[tauri::command]
+fn unregister_device(address: String) -> Result<Vec<RegisteredDevice>, StoneError> {
+    get_registry().remove(&address)
 }
 
-#[cfg(target_os = "android")]
-async fn connect_device_inner(app: AppHandle, address: String) -> Result<(), String> {
-    back_log("RUST", format!("Connect request: {}", address));
-    clear_connect_cancel(&address);
-
-    let mut retry_count = 0u32;
-    loop {
-        if is_connect_cancelled(&address) {
-            clear_connect_cancel(&address);
-            return Err("Connect cancelled".to_string());
-        }
+#[tauri::command]
+fn rename_device(address: String, name: String) -> Result<Vec<RegisteredDevice>, StoneError> {
+    get_registry().rename(&address, &name)
+}
 
-        match android_backend::connect_device(&app, &address).await {
-            Ok(()) => {
-                if is_connect_cancelled(&address) {
-                    let _ = android_backend::disconnect_device(&app, &address).await;
-                    clear_connect_cancel(&address);
-                    return Err("Connect cancelled".to_string());
-                }
-                clear_connect_cancel(&address);
-                return Ok(());
-            }
-            Err(err) => {
-                if is_connect_cancelled(&address) {
-                    clear_connect_cancel(&address);
-                    return Err("Connect cancelled".to_string());
-                }
+#[tauri::command]
+fn reorder_devices(addresses: Vec<String>) -> Result<Vec<RegisteredDevice>, StoneError> {
+    get_registry().reorder(&addresses)
+}
 
-                if retry_count < ANDROID_CONNECT_MAX_RETRIES && should_retry_android_connect(&err) {
-                    retry_count += 1;
-                    back_log(
-                        "RUST",
-                        format!(
-                            "Retrying connect for {} in {} ms ({}/{})",
-                            address,
-                            ANDROID_CONNECT_RETRY_DELAY_MS,
-                            retry_count,
-                            ANDROID_CONNECT_MAX_RETRIES
-                        ),
-                    );
-                    tauri::async_runtime::spawn_blocking(|| {
-                        std::thread::sleep(Duration::from_millis(ANDROID_CONNECT_RETRY_DELAY_MS));
-                    })
-                    .await
-                    .map_err(|_| "Join error".to_string())?;
-                    continue;
-                }
+#[tauri::command]
+fn import_registered_devices(
+    devices: Vec<RegisteredDevice>,
+) -> Result<Vec<RegisteredDevice>, StoneError> {
+    get_registry().import_legacy(devices)
+}
 
-                clear_connect_cancel(&address);
-                return Err(err);
-            }
-        }
-    }
+#[tauri::command]
+fn set_device_alias(
+    address: String,
+    alias: Option<String>,
+) -> Result<Vec<RegisteredDevice>, StoneError> {
+    get_registry().set_alias(&address, alias)
 }
 
 #[tauri::command]
-async fn disconnect_device(_app: AppHandle, address: String) -> Result<(), String> {
-    #[cfg(target_os = "macos")]
-    {
-        back_log("RUST", format!("Disconnect request: {}", address));
-        let status = tauri::async_runtime::spawn_blocking(move || -> Result<i32, String> {
-            let cstr = CString::new(address).map_err(|_| "Invalid address".to_string())?;
-            Ok(unsafe { macos_bt_disconnect(cstr.as_ptr()) })
-        })
+fn set_device_icon(
+    address: String,
+    icon: Option<String>,
+) -> Result<Vec<RegisteredDevice>, StoneError> {
+    get_registry().set_icon(&address, icon)
+}
+
+#[tauri::command]
+fn set_device_groups(
+    address: String,
+    groups: Vec<String>,
+) -> Result<Vec<RegisteredDevice>, StoneError> {
+    get_registry().set_groups(&address, groups)
+}
+
+#[tauri::command]
+fn get_device_groups() -> Vec<DeviceGroup> {
+    get_registry().groups()
+}
+
+#[tauri::command]
+fn get_reconnect_policy() -> ReconnectPolicy {
+    get_supervisor().policy()
+}
+
+#[tauri::command]
+fn set_reconnect_policy(policy: ReconnectPolicy) -> Result<(), StoneError> {
+    get_supervisor().set_policy(policy)
+}
+
+#[tauri::command]
+fn get_supervisor_states() -> Vec<SupervisorStatus> {
+    get_supervisor().snapshot()
+}
+
+#[tauri::command]
+fn set_app_visible(visible: bool) {
+    get_supervisor().set_visible(visible);
+}
+
+#[tauri::command]
+fn get_keepalive_config() -> KeepaliveConfig {
+    get_keepalive().config()
+}
+
+#[tauri::command]
+fn set_keepalive_config(
+    backend: State<'_, BackendState>,
+    config: KeepaliveConfig,
+) -> Result<(), StoneError> {
+    get_keepalive().set_config(config, backend.inner())
+}
+
+#[tauri::command]
+fn get_link_health() -> Vec<LinkStatus> {
+    get_keepalive().snapshot()
+}
+
+#[tauri::command]
+async fn disconnect_device(
+    backend: State<'_, BackendState>,
+    address: String,
+) -> Result<(), StoneError> {
+    get_supervisor().stand_down(&address);
+    get_connections()
+        .disconnect(&address, backend.inner())
         .await
-        .map_err(|_| "Join error".to_string())??;
-        if status == 0 {
-            Ok(())
-        } else {
-            let context = unsafe {
-                let ptr = macos_bt_last_error_context();
-                if ptr.is_null() {
-                    "".to_string()
-                } else {
-                    CString::from_raw(ptr)
-                        .into_string()
-                        .unwrap_or_else(|_| "".to_string())
-                }
-            };
-            Err(format!(
-                "IOBluetooth error {} ({}){}",
-                status,
-                ioreturn_name(status),
-                if context.is_empty() {
-                    "".to_string()
-                } else {
-                    format!(" ({})", context)
-                }
-            ))
+}
+
+async fn send_gaia_frame(
+    backend: &dyn BluetoothBackend,
+    address: &str,
+    vendor_id: u16,
+    command_id: u16,
+    payload: &[u8],
+) -> Result<(), StoneError> {
+    write_gaia_frame(
+        backend,
+        address,
+        vendor_id,
+        command_id,
+        payload,
+        DEFAULT_REQUEST_TIMEOUT_MS,
+    )
+    .await
+    .map(|_| ())
+}
+
+/// Writes one frame; a PT setter is staged until its ACK, and the staging token comes back.
+async fn write_gaia_frame(
+    backend: &dyn BluetoothBackend,
+    address: &str,
+    vendor_id: u16,
+    command_id: u16,
+    payload: &[u8],
+    ack_timeout_ms: u64,
+) -> Result<Option<u64>, StoneError> {
+    back_log(
+        "RUST",
+        format!(
+            "Send GAIA command: addr={} vendor=0x{:04X} cmd=0x{:04X} len={}",
+            address,
+            vendor_id,
+            command_id,
+            payload.len()
+        ),
+    );
+    let frame = gaia_frame(vendor_id, command_id, payload)?;
+    let staged = if vendor_id == pt::PT_VENDOR_ID {
+        pt::PtCommand::parse(command_id, payload)
+            .ok()
+            .and_then(|command| get_device_states().stage_command(address, command, ack_timeout_ms))
+    } else {
+        None
+    };
+    match backend.write_frame(address, &frame).await {
+        Ok(()) => {
+            capture::record(address, capture::Direction::Outbound, &frame);
+            Ok(staged)
+        }
+        Err(err) => {
+            if let Some(token) = staged {
+                get_device_states().unstage(address, command_id, token);
+            }
+            Err(err)
         }
     }
+}
 
-    #[cfg(target_os = "android")]
-    {
-        back_log("RUST", format!("Disconnect request: {}", address));
-        request_connect_cancel(&address);
-        android_backend::disconnect_device(&_app, &address).await
+async fn request_gaia(
+    backend: &dyn BluetoothBackend,
+    address: &str,
+    vendor_id: u16,
+    command_id: u16,
+    payload: &[u8],
+    timeout_ms: u64,
+) -> Result<GaiaResponse, StoneError> {
+    let receiver = get_pending_requests().register(address, vendor_id, command_id);
+    let mut staged = StagedRequest {
+        address,
+        command_id,
+        token: write_gaia_frame(backend, address, vendor_id, command_id, payload, timeout_ms)
+            .await?,
+    };
+    let result = transaction::await_response(address, receiver, timeout_ms).await;
+    if !matches!(
+        result,
+        Err(StoneError::Timeout { .. } | StoneError::RequestDropped)
+    ) {
+        // The ACK settled the staged setter.
+        staged.token = None;
     }
+    result
+}
 
-    #[cfg(not(any(target_os = "macos", target_os = "android")))]
-    {
-        let _ = address;
-        Err("Not supported on this platform".to_string())
+/// Unstages a request's setter that got no ACK, also when the request is dropped midway.
+struct StagedRequest<'a> {
+    address: &'a str,
+    command_id: u16,
+    token: Option<u64>,
+}
+
+impl Drop for StagedRequest<'_> {
+    fn drop(&mut self) {
+        if let Some(token) = self.token {
+            get_device_states().unstage(self.address, self.command_id, token);
+        }
     }
 }
 
 #[tauri::command]
 async fn send_gaia_command(
-    _app: AppHandle,
+    backend: State<'_, BackendState>,
     address: String,
     vendor_id: u16,
     command_id: u16,
     payload: Vec<u8>,
-) -> Result<(), String> {
-    #[cfg(target_os = "macos")]
-    {
-        back_log(
-            "RUST",
-            format!(
-                "Send GAIA command: addr={} vendor=0x{:04X} cmd=0x{:04X} len={}",
-                address,
-                vendor_id,
-                command_id,
-                payload.len()
-            ),
-        );
-        let status = tauri::async_runtime::spawn_blocking(move || -> Result<i32, String> {
-            let ptr = unsafe { macos_bt_get_connection_infos() };
-            if ptr.is_null() {
-                return Err("Target device is not connected".to_string());
-            }
-            let json = unsafe { CString::from_raw(ptr) }
-                .into_string()
-                .map_err(|_| "Invalid connection info encoding".to_string())?;
-            let infos: Vec<ConnectionInfo> =
-                serde_json::from_str(&json).map_err(|e| e.to_string())?;
-            let connected = infos
-                .iter()
-                .any(|info| info.rfcomm && info.address.eq_ignore_ascii_case(&address));
-            if !connected {
-                return Err("Target device is not connected".to_string());
-            }
+) -> Result<(), StoneError> {
+    let backend: &dyn BluetoothBackend = &**backend;
+    let payload = &payload;
+    for_each_target(&address, move |address| async move {
+        send_gaia_frame(backend, &address, vendor_id, command_id, payload).await
+    })
+    .await
+}
 
-            let frame = gaia_frame(vendor_id, command_id, &payload, 0)?;
-            let cstr = CString::new(address).map_err(|_| "Invalid address".to_string())?;
-            Ok(unsafe { macos_bt_write(cstr.as_ptr(), frame.as_ptr(), frame.len()) })
-        })
+#[tauri::command]
+async fn send_gaia_request(
+    backend: State<'_, BackendState>,
+    address: String,
+    vendor_id: u16,
+    command_id: u16,
+    payload: Vec<u8>,
+    timeout_ms: Option<u64>,
+) -> Result<GaiaResponse, StoneError> {
+    request_gaia(
+        &**backend,
+        &address,
+        vendor_id,
+        command_id,
+        &payload,
+        timeout_ms.unwrap_or(DEFAULT_REQUEST_TIMEOUT_MS),
+    )
+    .await
+}
+
+/// Sends one command to every connected speaker `target` stands for at once and reports
+/// each device's ACK, so a group change lands or fails as a whole from the UI's side.
+#[tauri::command]
+async fn send_gaia_broadcast(
+    backend: State<'_, BackendState>,
+    target: broadcast::BroadcastTarget,
+    vendor_id: u16,
+    command_id: u16,
+    payload: Vec<u8>,
+    timeout_ms: Option<u64>,
+) -> Result<broadcast::BroadcastReport, StoneError> {
+    broadcast::send(
+        &backend,
+        &target,
+        vendor_id,
+        command_id,
+        &payload,
+        timeout_ms.unwrap_or(DEFAULT_REQUEST_TIMEOUT_MS),
+    )
+    .await
+}
+
+#[tauri::command]
+async fn get_battery(
+    backend: State<'_, BackendState>,
+    address: String,
+) -> Result<pt::BatteryReport, StoneError> {
+    pt::battery(&**backend, &address, DEFAULT_REQUEST_TIMEOUT_MS).await
+}
+
+#[tauri::command]
+async fn get_volume(backend: State<'_, BackendState>, address: String) -> Result<u8, StoneError> {
+    let volume: pt::Volume = pt::query(&**backend, &address, DEFAULT_REQUEST_TIMEOUT_MS).await?;
+    Ok(volume.volume)
+}
+
+#[tauri::command]
+async fn set_volume(
+    backend: State<'_, BackendState>,
+    address: String,
+    volume: u8,
+) -> Result<(), StoneError> {
+    let backend: &dyn BluetoothBackend = &**backend;
+    for_each_target(&address, move |address| async move {
+        pt::send(
+            backend,
+            &address,
+            pt::PtCommand::SetVolume(volume),
+            DEFAULT_REQUEST_TIMEOUT_MS,
+        )
         .await
-        .map_err(|_| "Join error".to_string())??;
-        if status == 0 {
-            Ok(())
-        } else {
-            Err(format!("IOBluetooth error {}", status))
-        }
-    }
+        .map(drop)
+    })
+    .await
+}
 
-    #[cfg(target_os = "android")]
-    {
-        back_log(
-            "RUST",
-            format!(
-                "Send GAIA command: addr={} vendor=0x{:04X} cmd=0x{:04X} len={}",
-                address,
-                vendor_id,
-                command_id,
-                payload.len()
-            ),
-        );
-        let frame = gaia_frame(vendor_id, command_id, &payload, 0)?;
-        android_backend::send_gaia_command(&_app, &address, &frame).await
-    }
+#[tauri::command]
+async fn get_lamp_state(
+    backend: State<'_, BackendState>,
+    address: String,
+) -> Result<pt::LampState, StoneError> {
+    pt::query(&**backend, &address, DEFAULT_REQUEST_TIMEOUT_MS).await
+}
 
-    #[cfg(not(any(target_os = "macos", target_os = "android")))]
-    {
-        let _ = (address, vendor_id, command_id, payload);
-        Err("Not supported on this platform".to_string())
-    }
+#[tauri::command]
+async fn set_lamp(
+    backend: State<'_, BackendState>,
+    address: String,
+    on: bool,
+    brightness: Option<u8>,
+    lamp_type: Option<u8>,
+    color: Option<pt::Rgb>,
+) -> Result<(), StoneError> {
+    let command = if on {
+        pt::PtCommand::RunLamp(pt::LampSettings {
+            brightness: brightness.unwrap_or(pt::MAX_LAMP_BRIGHTNESS),
+            lamp_type: lamp_type.unwrap_or(1),
+            color: color.unwrap_or(pt::Rgb::WHITE),
+        })
+    } else {
+        pt::PtCommand::StopLamp
+    };
+    // A running effect would paint over the change on its next frame.
+    get_lamp_effects().release(&get_registry().resolve(&address));
+    let backend: &dyn BluetoothBackend = &**backend;
+    for_each_target(&address, move |address| async move {
+        pt::send(backend, &address, command, DEFAULT_REQUEST_TIMEOUT_MS)
+            .await
+            .map(drop)
+    })
+    .await
+}
+
+#[tauri::command]
+fn start_lamp_effect(
+    backend: State<'_, BackendState>,
+    target: broadcast::BroadcastTarget,
+    spec: EffectSpec,
+) -> Result<EffectStatus, StoneError> {
+    get_lamp_effects().start(&target, spec, backend.inner())
+}
+
+#[tauri::command]
+fn stop_lamp_effect(id: Option<u64>) -> usize {
+    get_lamp_effects().stop(id)
+}
+
+#[tauri::command]
+fn get_lamp_effects_state() -> Vec<EffectStatus> {
+    get_lamp_effects().snapshot()
+}
+
+#[tauri::command]
+fn list_scenes() -> Vec<Scene> {
+    get_scenes().list()
+}
+
+#[tauri::command]
+async fn save_scene(
+    backend: State<'_, BackendState>,
+    name: String,
+    target: broadcast::BroadcastTarget,
+) -> Result<Scene, StoneError> {
+    get_scenes().capture(&name, &target, &backend).await
+}
+
+#[tauri::command]
+async fn apply_scene(
+    backend: State<'_, BackendState>,
+    name: String,
+) -> Result<SceneReport, StoneError> {
+    get_scenes().apply(&name, &backend).await
+}
+
+#[tauri::command]
+fn delete_scene(name: String) -> Result<Vec<Scene>, StoneError> {
+    get_scenes().remove(&name)
+}
+
+#[tauri::command]
+fn list_scheduled_jobs() -> Vec<Job> {
+    get_scheduler().list()
+}
+
+#[tauri::command]
+fn add_scheduled_job(job: JobSpec) -> Result<Job, StoneError> {
+    get_scheduler().add(job)
+}
+
+#[tauri::command]
+fn set_scheduled_job_paused(id: u64, paused: bool) -> Result<Job, StoneError> {
+    get_scheduler().set_paused(id, paused)
+}
+
+#[tauri::command]
+fn delete_scheduled_job(id: u64) -> Result<Vec<Job>, StoneError> {
+    get_scheduler().remove(id)
+}
+
+#[tauri::command]
+fn start_sleep_timer(
+    backend: State<'_, BackendState>,
+    target: broadcast::BroadcastTarget,
+    duration_ms: u64,
+) -> Result<SleepStatus, StoneError> {
+    get_sleep_timer().start(&target, duration_ms, backend.inner())
+}
+
+#[tauri::command]
+fn cancel_sleep_timer() -> bool {
+    get_sleep_timer().cancel()
+}
+
+#[tauri::command]
+fn get_sleep_timer_status() -> Option<SleepStatus> {
+    get_sleep_timer().status()
+}
+
+#[tauri::command]
+fn list_alarms() -> Vec<Alarm> {
+    get_alarm_clock().list()
+}
+
+#[tauri::command]
+fn add_alarm(alarm: AlarmSpec) -> Result<Alarm, StoneError> {
+    get_alarm_clock().add(alarm)
+}
+
+#[tauri::command]
+fn set_alarm_enabled(id: u64, enabled: bool) -> Result<Alarm, StoneError> {
+    get_alarm_clock().set_enabled(id, enabled)
+}
+
+#[tauri::command]
+fn delete_alarm(id: u64) -> Result<Vec<Alarm>, StoneError> {
+    get_alarm_clock().remove(id)
+}
+
+#[tauri::command]
+fn snooze_alarm() -> Result<AlarmStatus, StoneError> {
+    get_alarm_clock().snooze()
+}
+
+#[tauri::command]
+fn dismiss_alarm() -> bool {
+    get_alarm_clock().dismiss()
+}
+
+#[tauri::command]
+fn get_alarm_status() -> Option<AlarmStatus> {
+    get_alarm_clock().status()
+}
+
+#[tauri::command]
+fn get_http_api_config() -> HttpApiStatus {
+    get_http_api().status()
+}
+
+#[tauri::command]
+fn set_http_api_config(
+    enabled: bool,
+    port: u16,
+    reset_token: Option<bool>,
+) -> Result<HttpApiStatus, StoneError> {
+    get_http_api().configure(enabled, port, reset_token.unwrap_or(false))
+}
+
+#[tauri::command]
+fn get_event_stream_config() -> EventStreamStatus {
+    get_event_stream().status()
+}
+
+#[tauri::command]
+fn set_event_stream_config(enabled: bool, port: u16) -> Result<EventStreamStatus, StoneError> {
+    get_event_stream().configure(enabled, port)
+}
+
+#[tauri::command]
+async fn get_device_info(
+    backend: State<'_, BackendState>,
+    address: String,
+) -> Result<pt::DeviceInfo, StoneError> {
+    pt::device_info(&**backend, &address, DEFAULT_REQUEST_TIMEOUT_MS).await
+}
+
+#[tauri::command]
+fn start_capture(path: String) -> Result<(), StoneError> {
+    capture::start(std::path::Path::new(&path))
+}
+
+#[tauri::command]
+fn stop_capture() -> Option<String> {
+    capture::stop().map(|path| path.display().to_string())
+}
+
+#[tauri::command]
+fn replay_capture(path: String) -> Result<capture::Replay, StoneError> {
+    let chunks = capture::read(std::path::Path::new(&path))?;
+    back_log(
+        "RUST",
+        format!("Replaying {} captured chunks from {}", chunks.len(), path),
+    );
+    Ok(capture::decode(&chunks))
 }
 
 #[tauri::command]
@@ -711,14 +988,39 @@ fn log_line(line: String, tone: String, _ts: String) {
 }
 
 #[tauri::command]
-fn open_url(app: AppHandle, url: String) -> Result<(), String> {
+fn open_url(app: AppHandle, url: String) -> Result<(), StoneError> {
     app.opener()
         .open_url(url, None::<&str>)
-        .map_err(|err| err.to_string())
+        .map_err(StoneError::other)
 }
 
 #[tauri::command]
-fn set_tray_battery(percent: Option<u8>, charging: bool, full: bool) {
+fn get_device_state(address: String) -> DeviceState {
+    get_device_states().get(&address)
+}
+
+#[tauri::command]
+fn set_tray_device(address: Option<String>, use_level: bool) {
+    if let Ok(mut source) = TRAY_SOURCE.lock() {
+        *source = address.map(|address| (address, use_level));
+    }
+    refresh_tray_battery();
+}
+
+fn refresh_tray_battery() {
+    let source = TRAY_SOURCE.lock().ok().and_then(|source| source.clone());
+    let Some((address, use_level)) = source else {
+        update_tray_battery(None, false, false);
+        return;
+    };
+    let state = get_device_states().get(&address);
+    let percent = state.battery_percent(use_level);
+    let charging = state.dc_state == Some(pt::DC_CHARGING);
+    let full = state.dc_state == Some(pt::DC_FULL) && percent.is_some_and(|p| p >= 100);
+    update_tray_battery(percent, charging, full);
+}
+
+fn update_tray_battery(percent: Option<u8>, charging: bool, full: bool) {
     #[cfg(not(any(target_os = "android", target_os = "ios")))]
     {
         let Some(tray) = TRAY.get() else {
@@ -762,6 +1064,91 @@ fn set_tray_battery(percent: Option<u8>, charging: bool, full: bool) {
     }
 }
 
+fn update_tray_sleep(status: Option<SleepStatus>) {
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    {
+        if let Some(item) = TRAY_SLEEP_ITEM.get() {
+            let label = match &status {
+                Some(status) => format!(
+                    "잠자기 타이머: {}분 남음",
+                    status.remaining_ms.div_ceil(60_000)
+                ),
+                None => "잠자기 타이머: 꺼짐".to_string(),
+            };
+            let _ = item.set_text(label);
+        }
+        if let Some(item) = TRAY_SLEEP_CANCEL_ITEM.get() {
+            let _ = item.set_enabled(status.is_some());
+        }
+    }
+
+    #[cfg(any(target_os = "android", target_os = "ios"))]
+    {
+        let _ = status;
+    }
+}
+
+fn update_tray_alarm(label: String, ringing: bool) {
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    {
+        if let Some(item) = TRAY_ALARM_ITEM.get() {
+            let _ = item.set_text(label);
+        }
+        for item in [TRAY_ALARM_SNOOZE_ITEM.get(), TRAY_ALARM_DISMISS_ITEM.get()]
+            .into_iter()
+            .flatten()
+        {
+            let _ = item.set_enabled(ringing);
+        }
+    }
+
+    #[cfg(any(target_os = "android", target_os = "ios"))]
+    {
+        let _ = (label, ringing);
+    }
+}
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+fn build_tray_menu(app: &tauri::App<Wry>) -> tauri::Result<Menu<Wry>> {
+    let battery = MenuItem::with_id(app, "battery", "배터리: --", false, None::<&str>)?;
+    let sleep = MenuItem::with_id(app, "sleep", "잠자기 타이머: 꺼짐", false, None::<&str>)?;
+    let sleep_cancel = MenuItem::with_id(
+        app,
+        "sleep_cancel",
+        "잠자기 타이머 취소",
+        false,
+        None::<&str>,
+    )?;
+    let alarm = MenuItem::with_id(app, "alarm", "알람: 없음", false, None::<&str>)?;
+    let alarm_snooze =
+        MenuItem::with_id(app, "alarm_snooze", "알람 다시 알림", false, None::<&str>)?;
+    let alarm_dismiss = MenuItem::with_id(app, "alarm_dismiss", "알람 끄기", false, None::<&str>)?;
+    let sep = PredefinedMenuItem::separator(app)?;
+    let show = MenuItem::with_id(app, "show", "STONE 매니저 열기", true, None::<&str>)?;
+    let quit = MenuItem::with_id(app, "quit", "종료", true, None::<&str>)?;
+    let menu = Menu::with_items(
+        app,
+        &[
+            &battery,
+            &sleep,
+            &sleep_cancel,
+            &alarm,
+            &alarm_snooze,
+            &alarm_dismiss,
+            &sep,
+            &show,
+            &quit,
+        ],
+    )?;
+    let _ = TRAY_BATTERY_ITEM.set(battery);
+    let _ = TRAY_SLEEP_ITEM.set(sleep);
+    let _ = TRAY_SLEEP_CANCEL_ITEM.set(sleep_cancel);
+    let _ = TRAY_ALARM_ITEM.set(alarm);
+    let _ = TRAY_ALARM_SNOOZE_ITEM.set(alarm_snooze);
+    let _ = TRAY_ALARM_DISMISS_ITEM.set(alarm_dismiss);
+    Ok(menu)
+}
+
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
 fn setup_desktop_app(app: &mut tauri::App<Wry>) {
     #[cfg(target_os = "macos")]
@@ -773,27 +1160,13 @@ fn setup_desktop_app(app: &mut tauri::App<Wry>) {
             if let WindowEvent::CloseRequested { api, .. } = event {
                 api.prevent_close();
                 let _ = window_handle.hide();
+                get_supervisor().set_visible(false);
             }
         });
     }
 
     if let Some(icon) = app.default_window_icon().cloned() {
-        let battery_item =
-            MenuItem::with_id(app, "battery", "배터리: --", false, None::<&str>).ok();
-        let separator = PredefinedMenuItem::separator(app).ok();
-        let show_item =
-            MenuItem::with_id(app, "show", "STONE 매니저 열기", true, None::<&str>).ok();
-        let quit_item = MenuItem::with_id(app, "quit", "종료", true, None::<&str>).ok();
-        let menu = if let (Some(battery), Some(sep), Some(show), Some(quit)) =
-            (battery_item, separator, show_item, quit_item)
-        {
-            let menu = Menu::with_items(app, &[&battery, &sep, &show, &quit]).ok();
-            let _ = TRAY_BATTERY_ITEM.set(battery);
-            menu
-        } else {
-            None
-        };
-        if let Some(menu) = menu {
+        if let Ok(menu) = build_tray_menu(app) {
             if let Ok(tray) = TrayIconBuilder::new()
                 .icon(icon)
                 .tooltip("STONE 매니저")
@@ -804,8 +1177,18 @@ fn setup_desktop_app(app: &mut tauri::App<Wry>) {
                         if let Some(win) = app.get_webview_window("main") {
                             let _ = win.show();
                             let _ = win.set_focus();
+                            get_supervisor().set_visible(true);
                         }
                     }
+                    "sleep_cancel" => {
+                        get_sleep_timer().cancel();
+                    }
+                    "alarm_snooze" => {
+                        let _ = get_alarm_clock().snooze();
+                    }
+                    "alarm_dismiss" => {
+                        get_alarm_clock().dismiss();
+                    }
                     "quit" => {
                         app.exit(0);
                     }
@@ -819,6 +1202,10 @@ fn setup_desktop_app(app: &mut tauri::App<Wry>) {
     }
 }
 
+pub fn run_cli() -> i32 {
+    cli::run(std::env::args().skip(1))
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     let builder = tauri::Builder::default();
@@ -830,6 +1217,31 @@ pub fn run() {
         .plugin(tauri_plugin_opener::init())
         .setup(|app| {
             let _ = APP_HANDLE.set(app.handle().clone());
+            let backend = backend::select(app.handle());
+            back_log("RUST", format!("Bluetooth backend: {}", backend.name()));
+            app.manage(backend);
+            capture::start_from_env();
+            match app.path().app_data_dir() {
+                Ok(dir) => {
+                    get_registry().load(registry::path_in(&dir));
+                    get_scenes().load(scenes::path_in(&dir));
+                    get_scheduler().load(
+                        scheduler::path_in(&dir),
+                        app.state::<BackendState>().inner(),
+                    );
+                    get_alarm_clock()
+                        .load(alarm::path_in(&dir), app.state::<BackendState>().inner());
+                    get_http_api().load(http_api::path_in(&dir));
+                    get_event_stream().load(event_stream::path_in(&dir), app.handle());
+                }
+                Err(err) => back_log(
+                    "RUST",
+                    format!(
+                        "No app data dir for the registry, scenes, schedule, alarms and local APIs: {}",
+                        err
+                    ),
+                ),
+            }
 
             #[cfg(not(any(target_os = "android", target_os = "ios")))]
             setup_desktop_app(app);
@@ -841,10 +1253,69 @@ pub fn run() {
             scan_unpaired_stone_devices,
             get_connection_infos,
             connect_device_async,
+            cancel_connect,
+            get_connection_states,
+            set_connect_concurrency,
+            get_connect_retry_policy,
+            set_connect_retry_policy,
+            get_registered_devices,
+            register_device,
+            unregister_device,
+            rename_device,
+            reorder_devices,
+            import_registered_devices,
+            set_device_alias,
+            set_device_icon,
+            set_device_groups,
+            get_device_groups,
+            get_reconnect_policy,
+            set_reconnect_policy,
+            get_supervisor_states,
+            set_app_visible,
+            get_keepalive_config,
+            set_keepalive_config,
+            get_link_health,
             disconnect_device,
             send_gaia_command,
+            send_gaia_request,
+            send_gaia_broadcast,
+            get_battery,
+            get_volume,
+            set_volume,
+            get_lamp_state,
+            set_lamp,
+            start_lamp_effect,
+            stop_lamp_effect,
+            get_lamp_effects_state,
+            list_scenes,
+            save_scene,
+            apply_scene,
+            delete_scene,
+            list_scheduled_jobs,
+            add_scheduled_job,
+            set_scheduled_job_paused,
+            delete_scheduled_job,
+            start_sleep_timer,
+            cancel_sleep_timer,
+            get_sleep_timer_status,
+            list_alarms,
+            add_alarm,
+            set_alarm_enabled,
+            delete_alarm,
+            snooze_alarm,
+            dismiss_alarm,
+            get_alarm_status,
+            get_http_api_config,
+            set_http_api_config,
+            get_event_stream_config,
+            set_event_stream_config,
+            get_device_info,
+            get_device_state,
+            start_capture,
+            stop_capture,
+            replay_capture,
             log_line,
-            set_tray_battery,
+            set_tray_device,
             open_url
         ])
         .run(tauri::generate_context!())
@@ -0,0 +1,1122 @@
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use once_cell::sync::OnceCell;
+use socket2::Socket;
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::oneshot;
+use zbus::fdo::{ObjectManagerProxy, PropertiesChanged};
+use zbus::message::Type as MessageType;
+use zbus::zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Value};
+use zbus::{interface, proxy, Connection, MatchRule, MessageStream};
+
+use crate::backend::BluetoothBackend;
+use crate::error::StoneError;
+use crate::registry::DeviceProfile;
+use crate::{
+    back_log, emit_backend_device_event, handle_backend_data, BluetoothDeviceInfo, ConnectionInfo,
+};
+
+const BLUEZ_SERVICE: &str = "org.bluez";
+const ADAPTER_INTERFACE: &str = "org.bluez.Adapter1";
+const DEVICE_INTERFACE: &str = "org.bluez.Device1";
+const GAIA_UUID: &str = "00001107-d102-11e1-9b23-00025b00a5a5";
+const PROFILE_PATH: &str = "/com/stone/manager/gaia";
+const STONE_NAME_PREFIX: &str = "STONE";
+const SCAN_WINDOW_MS: u64 = 5_000;
+const PROFILE_CONNECT_TIMEOUT_MS: u64 = 10_000;
+// Set to "session" to talk to a mock org.bluez service instead of the system daemon.
+const BUS_ENV: &str = "STONE_BLUEZ_BUS";
+
+#[proxy(interface = "org.bluez.Adapter1", default_service = "org.bluez")]
+trait Adapter1 {
+    fn set_discovery_filter(&self, filter: HashMap<&str, Value<'_>>) -> zbus::Result<()>;
+    fn start_discovery(&self) -> zbus::Result<()>;
+    fn stop_discovery(&self) -> zbus::Result<()>;
+}
+
+#[proxy(interface = "org.bluez.Device1", default_service = "org.bluez")]
+trait Device1 {
+    fn pair(&self) -> zbus::Result<()>;
+    fn connect_profile(&self, uuid: &str) -> zbus::Result<()>;
+    fn disconnect_profile(&self, uuid: &str) -> zbus::Result<()>;
+    #[zbus(property)]
+    fn set_trusted(&self, value: bool) -> zbus::Result<()>;
+}
+
+#[proxy(
+    interface = "org.bluez.ProfileManager1",
+    default_service = "org.bluez",
+    default_path = "/org/bluez"
+)]
+trait ProfileManager1 {
+    fn register_profile(
+        &self,
+        profile: &ObjectPath<'_>,
+        uuid: &str,
+        options: HashMap<&str, Value<'_>>,
+    ) -> zbus::Result<()>;
+}
+
+struct Session {
+    socket: Arc<Socket>,
+    generation: u64,
+}
+
+struct DeviceEntry {
+    path: OwnedObjectPath,
+    address: String,
+    name: String,
+    paired: bool,
+    connected: bool,
+    has_gaia: bool,
+}
+
+struct GaiaProfile;
+
+#[interface(name = "org.bluez.Profile1")]
+impl GaiaProfile {
+    fn release(&self) {
+        back_log("BLUEZ", "Profile released".to_string());
+    }
+
+    fn new_connection(
+        &self,
+        device: ObjectPath<'_>,
+        fd: zbus::zvariant::OwnedFd,
+        _properties: HashMap<String, OwnedValue>,
+    ) -> zbus::fdo::Result<()> {
+        let address = address_from_path(device.as_str())
+            .ok_or_else(|| zbus::fdo::Error::InvalidArgs(format!("Unknown device {}", device)))?;
+        let fd: std::os::fd::OwnedFd = fd.into();
+        attach_session(address, Socket::from(fd));
+        Ok(())
+    }
+
+    fn request_disconnection(&self, device: ObjectPath<'_>) {
+        if let Some(address) = address_from_path(device.as_str()) {
+            close_session(&address, true);
+        }
+    }
+}
+
+static CONNECTION: tokio::sync::OnceCell<Connection> = tokio::sync::OnceCell::const_new();
+static SESSIONS: OnceCell<Mutex<HashMap<String, Session>>> = OnceCell::new();
+static CONNECT_WAITERS: OnceCell<Mutex<HashMap<String, oneshot::Sender<()>>>> = OnceCell::new();
+static PENDING_CONNECTS: OnceCell<Mutex<HashSet<String>>> = OnceCell::new();
+static SESSION_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+fn get_sessions() -> &'static Mutex<HashMap<String, Session>> {
+    SESSIONS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn get_connect_waiters() -> &'static Mutex<HashMap<String, oneshot::Sender<()>>> {
+    CONNECT_WAITERS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn get_pending_connects() -> &'static Mutex<HashSet<String>> {
+    PENDING_CONNECTS.get_or_init(|| Mutex::new(HashSet::new()))
+}
+
+fn normalized_address(address: &str) -> String {
+    address.trim().replace('-', ":").to_ascii_uppercase()
+}
+
+fn is_valid_address(address: &str) -> bool {
+    let octets: Vec<&str> = address.split(':').collect();
+    octets.len() == 6
+        && octets
+            .iter()
+            .all(|octet| octet.len() == 2 && octet.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+fn address_from_path(path: &str) -> Option<String> {
+    let (_, device) = path.rsplit_once("/dev_")?;
+    let address = device.replace('_', ":");
+    if address.len() == 17 {
+        Some(address)
+    } else {
+        None
+    }
+}
+
+fn has_session(address: &str) -> bool {
+    get_sessions()
+        .lock()
+        .map(|sessions| sessions.contains_key(address))
+        .unwrap_or(false)
+}
+
+fn is_pending_connect(address: &str) -> bool {
+    get_pending_connects()
+        .lock()
+        .map(|pending| pending.contains(address))
+        .unwrap_or(false)
+}
+
+fn is_stone_candidate(name: &str) -> bool {
+    name.trim()
+        .to_ascii_uppercase()
+        .starts_with(STONE_NAME_PREFIX)
+}
+
+/// Maps BlueZ method errors to their kind; anything else goes through `fallback`.
+fn bluez_error(err: zbus::Error, fallback: impl FnOnce(String) -> StoneError) -> StoneError {
+    if let zbus::Error::MethodError(name, _, _) = &err {
+        match name.as_str() {
+            "org.bluez.Error.AuthenticationCanceled" => return StoneError::PairingCancelled,
+            "org.bluez.Error.AuthenticationTimeout" => return StoneError::PairingTimeout,
+            "org.bluez.Error.NotReady" => return StoneError::BluetoothDisabled,
+            "org.bluez.Error.NotConnected" => return StoneError::NotConnected,
+            "org.bluez.Error.DoesNotExist" => return StoneError::DeviceNotFound,
+            "org.bluez.Error.NotSupported" => {
+                return StoneError::NotSupported {
+                    message: err.to_string(),
+                }
+            }
+            "org.bluez.Error.InProgress" => {
+                return StoneError::Busy {
+                    message: err.to_string(),
+                }
+            }
+            "org.freedesktop.DBus.Error.AccessDenied" => return StoneError::PermissionDenied,
+            _ => {}
+        }
+    }
+    fallback(err.to_string())
+}
+
+fn attach_session(address: String, socket: Socket) {
+    let reader = match socket.try_clone() {
+        Ok(reader) => reader,
+        Err(err) => {
+            back_log(
+                "BLUEZ",
+                format!("Failed to clone RFCOMM socket for {}: {}", address, err),
+            );
+            return;
+        }
+    };
+    let generation = SESSION_GENERATION.fetch_add(1, Ordering::SeqCst);
+    let previous = get_sessions().lock().ok().and_then(|mut sessions| {
+        sessions.insert(
+            address.clone(),
+            Session {
+                socket: Arc::new(socket),
+                generation,
+            },
+        )
+    });
+    if let Some(previous) = previous {
+        let _ = previous.socket.shutdown(std::net::Shutdown::Both);
+    }
+    back_log("BLUEZ", format!("RFCOMM session opened: {}", address));
+    if let Some(waiter) = get_connect_waiters()
+        .lock()
+        .ok()
+        .and_then(|mut waiters| waiters.remove(&address))
+    {
+        let _ = waiter.send(());
+    }
+
+    let thread_address = address.clone();
+    let spawned = std::thread::Builder::new()
+        .name(format!("stone-gaia-reader-{}", address))
+        .spawn(move || read_session(thread_address, reader, generation));
+    if let Err(err) = spawned {
+        back_log(
+            "BLUEZ",
+            format!("Failed to start reader for {}: {}", address, err),
+        );
+        close_session(&address, false);
+    }
+}
+
+fn read_session(address: String, mut socket: Socket, generation: u64) {
+    let mut buffer = [0u8; 1024];
+    loop {
+        match socket.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => handle_backend_data(&address, &buffer[..read]),
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(err) => {
+                back_log("BLUEZ", format!("Reader stopped for {}: {}", address, err));
+                break;
+            }
+        }
+    }
+
+    let owned = get_sessions()
+        .lock()
+        .map(|mut sessions| {
+            if sessions.get(&address).map(|s| s.generation) == Some(generation) {
+                sessions.remove(&address);
+                true
+            } else {
+                false
+            }
+        })
+        .unwrap_or(false);
+    if owned {
+        back_log("BLUEZ", format!("RFCOMM session closed: {}", address));
+        emit_backend_device_event(address, false);
+    }
+}
+
+fn close_session(address: &str, emit_event: bool) {
+    let session = get_sessions()
+        .lock()
+        .ok()
+        .and_then(|mut sessions| sessions.remove(address));
+    if let Some(session) = session {
+        let _ = session.socket.shutdown(std::net::Shutdown::Both);
+        if emit_event {
+            emit_backend_device_event(address.to_string(), false);
+        }
+    }
+}
+
+async fn connection() -> Result<&'static Connection, StoneError> {
+    CONNECTION
+        .get_or_try_init(|| async {
+            let use_session_bus = std::env::var(BUS_ENV)
+                .map(|value| value.eq_ignore_ascii_case("session"))
+                .unwrap_or(false);
+            let conn = if use_session_bus {
+                Connection::session().await
+            } else {
+                Connection::system().await
+            }
+            .map_err(|err| StoneError::other(format!("D-Bus connection failed: {}", err)))?;
+
+            conn.object_server()
+                .at(PROFILE_PATH, GaiaProfile)
+                .await
+                .map_err(StoneError::other)?;
+            let manager = ProfileManager1Proxy::new(&conn)
+                .await
+                .map_err(StoneError::other)?;
+            let mut options = HashMap::new();
+            options.insert("Name", Value::from("STONE GAIA"));
+            options.insert("Role", Value::from("client"));
+            options.insert("AutoConnect", Value::from(false));
+            let profile_path = ObjectPath::try_from(PROFILE_PATH).map_err(StoneError::other)?;
+            manager
+                .register_profile(&profile_path, GAIA_UUID, options)
+                .await
+                .map_err(|err| {
+                    StoneError::other(format!("Failed to register GAIA profile: {}", err))
+                })?;
+
+            tauri::async_runtime::spawn(watch_device_events(conn.clone()));
+            back_log("BLUEZ", "BlueZ backend ready".to_string());
+            Ok(conn)
+        })
+        .await
+}
+
+async fn watch_device_events(conn: Connection) {
+    let rule = MatchRule::builder()
+        .msg_type(MessageType::Signal)
+        .sender(BLUEZ_SERVICE)
+        .and_then(|builder| builder.interface("org.freedesktop.DBus.Properties"))
+        .and_then(|builder| builder.member("PropertiesChanged"))
+        .and_then(|builder| builder.arg(0, DEVICE_INTERFACE))
+        .map(|builder| builder.build());
+    let mut stream = match rule {
+        Ok(rule) => match MessageStream::for_match_rule(rule, &conn, None).await {
+            Ok(stream) => stream,
+            Err(err) => {
+                back_log("BLUEZ", format!("Device event watch failed: {}", err));
+                return;
+            }
+        },
+        Err(err) => {
+            back_log("BLUEZ", format!("Invalid match rule: {}", err));
+            return;
+        }
+    };
+
+    while let Some(message) = stream.next().await {
+        let Ok(message) = message else {
+            continue;
+        };
+        let Some(signal) = PropertiesChanged::from_message(message) else {
+            continue;
+        };
+        let Some(address) = signal
+            .message()
+            .header()
+            .path()
+            .and_then(|path| address_from_path(path.as_str()))
+        else {
+            continue;
+        };
+        let Ok(args) = signal.args() else {
+            continue;
+        };
+        let Some(connected) = args
+            .changed_properties()
+            .get("Connected")
+            .and_then(|value| value.downcast_ref::<bool>().ok())
+        else {
+            continue;
+        };
+
+        if connected {
+            if !is_pending_connect(&address) {
+                emit_backend_device_event(address, true);
+            }
+        } else {
+            close_session(&address, false);
+            emit_backend_device_event(address, false);
+        }
+    }
+}
+
+fn string_property(props: &HashMap<String, OwnedValue>, key: &str) -> Option<String> {
+    props
+        .get(key)
+        .and_then(|value| value.downcast_ref::<&str>().ok())
+        .map(str::to_string)
+}
+
+fn bool_property(props: &HashMap<String, OwnedValue>, key: &str) -> bool {
+    props
+        .get(key)
+        .and_then(|value| value.downcast_ref::<bool>().ok())
+        .unwrap_or(false)
+}
+
+fn uuids_property(props: &HashMap<String, OwnedValue>) -> Vec<String> {
+    props
+        .get("UUIDs")
+        .and_then(|value| value.try_clone().ok())
+        .and_then(|value| Vec::<String>::try_from(value).ok())
+        .unwrap_or_default()
+}
+
+async fn managed_objects(
+    conn: &Connection,
+) -> Result<(Option<OwnedObjectPath>, Vec<DeviceEntry>), StoneError> {
+    let manager = ObjectManagerProxy::builder(conn)
+        .destination(BLUEZ_SERVICE)
+        .and_then(|builder| builder.path("/"))
+        .map_err(StoneError::other)?
+        .build()
+        .await
+        .map_err(StoneError::other)?;
+    let objects = manager
+        .get_managed_objects()
+        .await
+        .map_err(|err| StoneError::other(format!("Failed to query BlueZ objects: {}", err)))?;
+
+    let mut adapter = None;
+    let mut devices = Vec::new();
+    for (path, interfaces) in objects {
+        if adapter.is_none()
+            && interfaces
+                .keys()
+                .any(|name| name.as_str() == ADAPTER_INTERFACE)
+        {
+            adapter = Some(path.clone());
+        }
+        let Some(props) = interfaces
+            .iter()
+            .find(|(name, _)| name.as_str() == DEVICE_INTERFACE)
+            .map(|(_, props)| props)
+        else {
+            continue;
+        };
+        let Some(address) = string_property(props, "Address") else {
+            continue;
+        };
+        let address = normalized_address(&address);
+        let name = string_property(props, "Alias")
+            .or_else(|| string_property(props, "Name"))
+            .unwrap_or_default();
+        let has_gaia = uuids_property(props)
+            .iter()
+            .any(|uuid| uuid.eq_ignore_ascii_case(GAIA_UUID));
+        devices.push(DeviceEntry {
+            path,
+            paired: bool_property(props, "Paired"),
+            connected: bool_property(props, "Connected"),
+            has_gaia,
+            name,
+            address,
+        });
+    }
+    devices.sort_by(|a, b| {
+        a.name
+            .to_lowercase()
+            .cmp(&b.name.to_lowercase())
+            .then_with(|| a.address.cmp(&b.address))
+    });
+    Ok((adapter, devices))
+}
+
+async fn find_device(conn: &Connection, address: &str) -> Result<DeviceEntry, StoneError> {
+    if !is_valid_address(address) {
+        return Err(StoneError::InvalidAddress);
+    }
+    let (_, devices) = managed_objects(conn).await?;
+    devices
+        .into_iter()
+        .find(|device| device.address == address)
+        .ok_or(StoneError::DeviceNotFound)
+}
+
+async fn device_proxy(
+    conn: &Connection,
+    path: &OwnedObjectPath,
+) -> Result<Device1Proxy<'static>, StoneError> {
+    Device1Proxy::builder(conn)
+        .path(path.clone())
+        .map_err(StoneError::other)?
+        .build()
+        .await
+        .map_err(StoneError::other)
+}
+
+async fn list_devices() -> Result<Vec<BluetoothDeviceInfo>, StoneError> {
+    let conn = connection().await?;
+    let (_, devices) = managed_objects(conn).await?;
+    Ok(devices
+        .into_iter()
+        .filter(|device| device.paired)
+        .map(|device| BluetoothDeviceInfo {
+            connected: device.connected || has_session(&device.address),
+            has_gaia: device.has_gaia || is_stone_candidate(&device.name),
+            name: if device.name.is_empty() {
+                device.address.clone()
+            } else {
+                device.name
+            },
+            address: device.address,
+            paired: true,
+            raw_name: None,
+            profile: DeviceProfile::default(),
+        })
+        .collect())
+}
+
+async fn scan_unpaired_stone_devices() -> Result<Vec<BluetoothDeviceInfo>, StoneError> {
+    let conn = connection().await?;
+    let (adapter, _) = managed_objects(conn).await?;
+    let adapter = adapter.ok_or(StoneError::AdapterUnavailable)?;
+    let adapter = Adapter1Proxy::builder(conn)
+        .path(adapter)
+        .map_err(StoneError::other)?
+        .build()
+        .await
+        .map_err(StoneError::other)?;
+
+    let mut filter = HashMap::new();
+    filter.insert("Transport", Value::from("bredr"));
+    let _ = adapter.set_discovery_filter(filter).await;
+    adapter.start_discovery().await.map_err(|err| {
+        bluez_error(err, |message| {
+            StoneError::other(format!("Failed to start device discovery: {}", message))
+        })
+    })?;
+    tokio::time::sleep(Duration::from_millis(SCAN_WINDOW_MS)).await;
+    let _ = adapter.stop_discovery().await;
+
+    let (_, devices) = managed_objects(conn).await?;
+    let list: Vec<BluetoothDeviceInfo> = devices
+        .into_iter()
+        .filter(|device| !device.paired && is_stone_candidate(&device.name))
+        .map(|device| BluetoothDeviceInfo {
+            name: device.name,
+            address: device.address,
+            connected: device.connected,
+            has_gaia: false,
+            paired: false,
+            raw_name: None,
+            profile: DeviceProfile::default(),
+        })
+        .collect();
+    back_log(
+        "BLUEZ",
+        format!("Discovery result: {} unpaired STONE", list.len()),
+    );
+    Ok(list)
+}
+
+async fn get_connection_infos() -> Result<Vec<ConnectionInfo>, StoneError> {
+    let conn = connection().await?;
+    let (_, devices) = managed_objects(conn).await?;
+    let mut infos: Vec<ConnectionInfo> = devices
+        .into_iter()
+        .filter_map(|device| {
+            let rfcomm = has_session(&device.address);
+            (device.connected || rfcomm).then_some(ConnectionInfo {
+                address: device.address,
+                link: device.connected || rfcomm,
+                rfcomm,
+            })
+        })
+        .collect();
+    infos.sort_by(|a, b| a.address.cmp(&b.address));
+    Ok(infos)
+}
+
+async fn connect_device(address: &str) -> Result<(), StoneError> {
+    let address = normalized_address(address);
+    if has_session(&address) {
+        return Ok(());
+    }
+    let conn = connection().await?;
+    let device = find_device(conn, &address).await?;
+    let proxy = device_proxy(conn, &device.path).await?;
+
+    let _pending = PendingConnect::start(&address);
+    connect_profile(&proxy, &device, &address).await
+}
+
+/// Marks a connect in flight; cleared on drop so a cancelled connect leaves nothing behind.
+struct PendingConnect(String);
+
+impl PendingConnect {
+    fn start(address: &str) -> Self {
+        if let Ok(mut pending) = get_pending_connects().lock() {
+            pending.insert(address.to_string());
+        }
+        Self(address.to_string())
+    }
+}
+
+impl Drop for PendingConnect {
+    fn drop(&mut self) {
+        if let Ok(mut pending) = get_pending_connects().lock() {
+            pending.remove(&self.0);
+        }
+        if let Ok(mut waiters) = get_connect_waiters().lock() {
+            waiters.remove(&self.0);
+        }
+    }
+}
+
+async fn connect_profile(
+    proxy: &Device1Proxy<'static>,
+    device: &DeviceEntry,
+    address: &str,
+) -> Result<(), StoneError> {
+    if !device.paired {
+        back_log("BLUEZ", format!("Pairing {}", address));
+        proxy.pair().await.map_err(|err| {
+            bluez_error(err, |message| StoneError::PairingFailed {
+                message: format!("Failed to start pairing: {}", message),
+            })
+        })?;
+    }
+    let _ = proxy.set_trusted(true).await;
+
+    let (tx, rx) = oneshot::channel();
+    if let Ok(mut waiters) = get_connect_waiters().lock() {
+        waiters.insert(address.to_string(), tx);
+    }
+    proxy.connect_profile(GAIA_UUID).await.map_err(|err| {
+        bluez_error(err, |message| StoneError::ConnectFailed {
+            message: format!("RFCOMM connection failed: {}", message),
+        })
+    })?;
+    if has_session(address) {
+        return Ok(());
+    }
+    match tokio::time::timeout(Duration::from_millis(PROFILE_CONNECT_TIMEOUT_MS), rx).await {
+        Ok(Ok(())) => Ok(()),
+        _ if has_session(address) => Ok(()),
+        _ => Err(StoneError::ConnectFailed {
+            message: "RFCOMM connection failed: no GAIA channel from BlueZ".to_string(),
+        }),
+    }
+}
+
+async fn disconnect_device(address: &str) -> Result<(), StoneError> {
+    let address = normalized_address(address);
+    close_session(&address, false);
+    let conn = connection().await?;
+    let device = find_device(conn, &address).await?;
+    let proxy = device_proxy(conn, &device.path).await?;
+    match proxy.disconnect_profile(GAIA_UUID).await {
+        Ok(()) => Ok(()),
+        Err(zbus::Error::MethodError(name, _, _))
+            if name.as_str() == "org.bluez.Error.NotConnected" =>
+        {
+            Ok(())
+        }
+        Err(err) => Err(bluez_error(err, |message| {
+            StoneError::other(format!("Disconnect failed: {}", message))
+        })),
+    }
+}
+
+async fn write_frame(address: &str, frame: &[u8]) -> Result<(), StoneError> {
+    let address = normalized_address(address);
+    let socket = get_sessions()
+        .lock()
+        .ok()
+        .and_then(|sessions| sessions.get(&address).map(|session| session.socket.clone()))
+        .ok_or(StoneError::NotConnected)?;
+    let frame = frame.to_vec();
+    tauri::async_runtime::spawn_blocking(move || (&*socket).write_all(&frame))
+        .await
+        .map_err(|_| StoneError::other("Join error"))?
+        .map_err(|err| StoneError::other(format!("RFCOMM write failed: {}", err)))
+}
+
+pub(crate) struct LinuxBackend;
+
+impl LinuxBackend {
+    pub(crate) fn new() -> Self {
+        tauri::async_runtime::spawn(async {
+            if let Err(err) = connection().await {
+                back_log("BLUEZ", err.to_string());
+            }
+        });
+        Self
+    }
+}
+
+#[async_trait]
+impl BluetoothBackend for LinuxBackend {
+    fn name(&self) -> &'static str {
+        "bluez"
+    }
+
+    async fn list_devices(&self) -> Result<Vec<BluetoothDeviceInfo>, StoneError> {
+        list_devices().await
+    }
+
+    async fn scan_unpaired_stone_devices(&self) -> Result<Vec<BluetoothDeviceInfo>, StoneError> {
+        scan_unpaired_stone_devices().await
+    }
+
+    async fn get_connection_infos(&self) -> Result<Vec<ConnectionInfo>, StoneError> {
+        get_connection_infos().await
+    }
+
+    async fn connect_device(&self, address: &str) -> Result<(), StoneError> {
+        connect_device(address).await
+    }
+
+    async fn disconnect_device(&self, address: &str) -> Result<(), StoneError> {
+        disconnect_device(address).await
+    }
+
+    async fn write_frame(&self, address: &str, frame: &[u8]) -> Result<(), StoneError> {
+        write_frame(address, frame).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    //! Runs the backend against a mock `org.bluez` on a private session bus.
+
+    use super::*;
+    use crate::gaia::GaiaFrame;
+    use std::io::{BufRead, BufReader};
+    use std::os::unix::net::UnixStream;
+    use std::process::{Child, Command, Stdio};
+    use std::sync::OnceLock;
+    use zbus::fdo::ObjectManager;
+    use zbus::message::Header;
+    use zbus::zvariant::Fd;
+
+    const PAIRED: &str = "00:11:22:33:44:55";
+    const PLAIN: &str = "00:11:22:33:44:66";
+    const UNPAIRED: &str = "00:11:22:33:44:77";
+    const INCOMING: &str = "00:11:22:33:44:88";
+    const INCOMING_PATH: &str = "/org/bluez/hci0/dev_00_11_22_33_44_88";
+    const DROPPED: &str = "00:11:22:33:44:99";
+
+    /// What the mock saw, and the far ends of the RFCOMM sockets it handed out.
+    #[derive(Default)]
+    struct MockState {
+        profile: Mutex<Option<(String, String)>>,
+        connected: Mutex<HashSet<String>>,
+        disconnects: Mutex<Vec<String>>,
+        sockets: Mutex<HashMap<String, UnixStream>>,
+    }
+
+    struct MockProfileManager(Arc<MockState>);
+
+    #[interface(name = "org.bluez.ProfileManager1")]
+    impl MockProfileManager {
+        fn register_profile(
+            &self,
+            profile: ObjectPath<'_>,
+            uuid: String,
+            _options: HashMap<String, OwnedValue>,
+            #[zbus(header)] header: Header<'_>,
+        ) -> zbus::fdo::Result<()> {
+            assert_eq!(uuid, GAIA_UUID);
+            let sender = header.sender().map(|sender| sender.to_string());
+            *self.0.profile.lock().unwrap() =
+                Some((sender.unwrap_or_default(), profile.to_string()));
+            Ok(())
+        }
+    }
+
+    struct MockAdapter;
+
+    #[interface(name = "org.bluez.Adapter1")]
+    impl MockAdapter {
+        fn set_discovery_filter(&self, _filter: HashMap<String, OwnedValue>) {}
+        fn start_discovery(&self) {}
+        fn stop_discovery(&self) {}
+    }
+
+    struct MockDevice {
+        state: Arc<MockState>,
+        path: String,
+        address: String,
+        alias: String,
+        paired: bool,
+        uuids: Vec<String>,
+        trusted: bool,
+    }
+
+    #[interface(name = "org.bluez.Device1")]
+    impl MockDevice {
+        fn pair(&mut self) {
+            self.paired = true;
+        }
+
+        async fn connect_profile(
+            &self,
+            uuid: String,
+            #[zbus(connection)] conn: &Connection,
+        ) -> zbus::fdo::Result<()> {
+            if uuid != GAIA_UUID {
+                return Err(zbus::fdo::Error::InvalidArgs(uuid));
+            }
+            offer_connection(&self.state, conn, &self.path, &self.address).await
+        }
+
+        fn disconnect_profile(&self, _uuid: String) {
+            self.state.connected.lock().unwrap().remove(&self.address);
+            self.state
+                .disconnects
+                .lock()
+                .unwrap()
+                .push(self.address.clone());
+        }
+
+        #[zbus(property)]
+        fn address(&self) -> String {
+            self.address.clone()
+        }
+
+        #[zbus(property)]
+        fn alias(&self) -> String {
+            self.alias.clone()
+        }
+
+        #[zbus(property)]
+        fn paired(&self) -> bool {
+            self.paired
+        }
+
+        #[zbus(property)]
+        fn connected(&self) -> bool {
+            self.state.connected.lock().unwrap().contains(&self.address)
+        }
+
+        #[zbus(property, name = "UUIDs")]
+        fn uuids(&self) -> Vec<String> {
+            self.uuids.clone()
+        }
+
+        #[zbus(property)]
+        fn trusted(&self) -> bool {
+            self.trusted
+        }
+
+        #[zbus(property)]
+        fn set_trusted(&mut self, value: bool) {
+            self.trusted = value;
+        }
+    }
+
+    /// Hands the registered profile one end of a socket pair, as BlueZ does for RFCOMM.
+    async fn offer_connection(
+        state: &MockState,
+        conn: &Connection,
+        path: &str,
+        address: &str,
+    ) -> zbus::fdo::Result<()> {
+        let (sender, profile) = state
+            .profile
+            .lock()
+            .unwrap()
+            .clone()
+            .ok_or_else(|| zbus::fdo::Error::Failed("No profile registered".to_string()))?;
+        let (ours, theirs) =
+            UnixStream::pair().map_err(|err| zbus::fdo::Error::IOError(err.to_string()))?;
+        let theirs: std::os::fd::OwnedFd = theirs.into();
+        let properties: HashMap<&str, Value<'_>> = HashMap::new();
+        conn.call_method(
+            Some(sender.as_str()),
+            profile.as_str(),
+            Some("org.bluez.Profile1"),
+            "NewConnection",
+            &(
+                ObjectPath::try_from(path).map_err(zbus::Error::from)?,
+                Fd::from(theirs),
+                properties,
+            ),
+        )
+        .await?;
+        state.connected.lock().unwrap().insert(address.to_string());
+        state
+            .sockets
+            .lock()
+            .unwrap()
+            .insert(address.to_string(), ours);
+        Ok(())
+    }
+
+    struct MockBluez {
+        state: Arc<MockState>,
+        conn: Connection,
+        // Its stdin closes when the test process exits, which stops the bus daemon.
+        _daemon: Child,
+    }
+
+    fn device(state: &Arc<MockState>, address: &str, alias: &str, paired: bool) -> MockDevice {
+        MockDevice {
+            state: state.clone(),
+            path: format!("/org/bluez/hci0/dev_{}", address.replace(':', "_")),
+            address: address.to_string(),
+            alias: alias.to_string(),
+            paired,
+            uuids: if alias.starts_with(STONE_NAME_PREFIX) {
+                vec![GAIA_UUID.to_string()]
+            } else {
+                Vec::new()
+            },
+            trusted: false,
+        }
+    }
+
+    async fn start_mock() -> Option<MockBluez> {
+        let available = Command::new("dbus-daemon")
+            .arg("--version")
+            .stdout(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success());
+        if !available {
+            eprintln!("dbus-daemon not found, skipping the BlueZ mock tests");
+            return None;
+        }
+        let mut daemon = Command::new("sh")
+            .args([
+                "-c",
+                "dbus-daemon --session --nofork --print-address=1 2>/dev/null & pid=$!; read _; kill $pid",
+            ])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .ok()?;
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.take().unwrap())
+            .read_line(&mut address)
+            .unwrap();
+        let address = address.trim().to_string();
+        std::env::set_var("DBUS_SESSION_BUS_ADDRESS", &address);
+        std::env::set_var(BUS_ENV, "session");
+
+        let state = Arc::new(MockState::default());
+        let mut builder = zbus::connection::Builder::address(address.as_str())
+            .unwrap()
+            .name(BLUEZ_SERVICE)
+            .unwrap()
+            .serve_at("/", ObjectManager)
+            .unwrap()
+            .serve_at("/org/bluez", MockProfileManager(state.clone()))
+            .unwrap()
+            .serve_at("/org/bluez/hci0", MockAdapter)
+            .unwrap();
+        for (address, alias, paired) in [
+            (PAIRED, "STONE Kitchen", true),
+            (PLAIN, "Headphones", true),
+            (UNPAIRED, "STONE Patio", false),
+            (INCOMING, "STONE Bedroom", true),
+            (DROPPED, "STONE Office", true),
+        ] {
+            let device = device(&state, address, alias, paired);
+            builder = builder.serve_at(device.path.clone(), device).unwrap();
+        }
+        let conn = builder.build().await.unwrap();
+        Some(MockBluez {
+            state,
+            conn,
+            _daemon: daemon,
+        })
+    }
+
+    /// The mock bus, or `None` where there is no dbus-daemon to run it on.
+    fn mock() -> Option<&'static MockBluez> {
+        static MOCK: OnceLock<Option<MockBluez>> = OnceLock::new();
+        MOCK.get_or_init(|| tauri::async_runtime::block_on(start_mock()))
+            .as_ref()
+    }
+
+    fn far_end(address: &str) -> UnixStream {
+        let sockets = mock().unwrap().state.sockets.lock().unwrap();
+        sockets[address].try_clone().unwrap()
+    }
+
+    #[test]
+    fn lists_paired_devices() {
+        if mock().is_none() {
+            return;
+        }
+        let devices = tauri::async_runtime::block_on(list_devices()).unwrap();
+        let listed: Vec<(&str, &str, bool)> = devices
+            .iter()
+            .map(|device| {
+                (
+                    device.address.as_str(),
+                    device.name.as_str(),
+                    device.has_gaia,
+                )
+            })
+            .collect();
+        assert!(listed.contains(&(PLAIN, "Headphones", false)));
+        assert!(listed.contains(&(PAIRED, "STONE Kitchen", true)));
+        assert!(!listed.iter().any(|(address, _, _)| *address == UNPAIRED));
+        assert!(devices.iter().all(|device| device.paired));
+    }
+
+    #[test]
+    fn connects_writes_and_disconnects() {
+        let Some(mock) = mock() else {
+            return;
+        };
+        tauri::async_runtime::block_on(async {
+            connect_device(&PAIRED.to_ascii_lowercase()).await.unwrap();
+            assert!(has_session(PAIRED));
+            let infos = get_connection_infos().await.unwrap();
+            assert!(infos
+                .iter()
+                .any(|info| info.address == PAIRED && info.link && info.rfcomm));
+
+            write_frame(PAIRED, &[0xFF, 0x01, 0x00, 0x00, 0x50, 0x54, 0x04, 0x01])
+                .await
+                .unwrap();
+            let mut far = far_end(PAIRED);
+            let mut frame = [0u8; 8];
+            far.read_exact(&mut frame).unwrap();
+            assert_eq!(frame, [0xFF, 0x01, 0x00, 0x00, 0x50, 0x54, 0x04, 0x01]);
+
+            disconnect_device(PAIRED).await.unwrap();
+            assert!(!has_session(PAIRED));
+            assert!(mock
+                .state
+                .disconnects
+                .lock()
+                .unwrap()
+                .contains(&PAIRED.to_string()));
+            assert_eq!(far.read(&mut frame).unwrap(), 0);
+            assert!(matches!(
+                write_frame(PAIRED, &frame).await,
+                Err(StoneError::NotConnected)
+            ));
+        });
+    }
+
+    #[test]
+    fn rejects_unknown_devices() {
+        if mock().is_none() {
+            return;
+        }
+        tauri::async_runtime::block_on(async {
+            assert!(matches!(
+                connect_device("AA:BB:CC:DD:EE:FF").await,
+                Err(StoneError::DeviceNotFound)
+            ));
+            assert!(matches!(
+                connect_device("not-an-address").await,
+                Err(StoneError::InvalidAddress)
+            ));
+        });
+    }
+
+    #[test]
+    fn takes_connections_the_device_opens() {
+        let Some(mock) = mock() else {
+            return;
+        };
+        tauri::async_runtime::block_on(async {
+            // Registers the profile before the device reaches out.
+            connection().await.unwrap();
+            offer_connection(&mock.state, &mock.conn, INCOMING_PATH, INCOMING)
+                .await
+                .unwrap();
+            assert!(has_session(INCOMING));
+
+            // An ACK from the speaker reaches whoever is waiting on it.
+            let receiver = crate::get_pending_requests().register(INCOMING, 0x000A, 0x0201);
+            let ack = GaiaFrame::ack(0x000A, 0x0201, 0x00, vec![7]);
+            far_end(INCOMING).write_all(&ack.encode().unwrap()).unwrap();
+            let response = crate::transaction::await_response(INCOMING, receiver, 1_000)
+                .await
+                .unwrap();
+            assert_eq!(response.payload, vec![7]);
+
+            // BlueZ asking for the channel back closes the session.
+            let (sender, profile) = mock.state.profile.lock().unwrap().clone().unwrap();
+            mock.conn
+                .call_method(
+                    Some(sender.as_str()),
+                    profile.as_str(),
+                    Some("org.bluez.Profile1"),
+                    "RequestDisconnection",
+                    &(ObjectPath::try_from(INCOMING_PATH).unwrap(),),
+                )
+                .await
+                .unwrap();
+            assert!(!has_session(INCOMING));
+        });
+    }
+
+    #[test]
+    fn refuses_connections_for_odd_paths() {
+        let Some(mock) = mock() else {
+            return;
+        };
+        tauri::async_runtime::block_on(async {
+            connection().await.unwrap();
+            let err = offer_connection(&mock.state, &mock.conn, "/org/bluez/hci0", "hci0")
+                .await
+                .unwrap_err();
+            assert!(matches!(err, zbus::fdo::Error::InvalidArgs(_)));
+        });
+    }
+
+    #[test]
+    fn session_ends_when_the_channel_drops() {
+        let Some(mock) = mock() else {
+            return;
+        };
+        tauri::async_runtime::block_on(async {
+            connect_device(DROPPED).await.unwrap();
+        });
+        let far = mock.state.sockets.lock().unwrap().remove(DROPPED).unwrap();
+        drop(far);
+        for _ in 0..50 {
+            if !has_session(DROPPED) {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        panic!("session outlived its channel");
+    }
+}
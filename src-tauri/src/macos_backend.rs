@@ -0,0 +1,157 @@
+use async_trait::async_trait;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+
+use crate::backend::BluetoothBackend;
+use crate::error::{ioreturn_name, StoneError};
+use crate::{
+    back_log, emit_backend_device_event, handle_backend_data, BluetoothDeviceInfo, ConnectionInfo,
+};
+
+extern "C" {
+    fn macos_bt_list_paired_devices() -> *mut c_char;
+    fn macos_bt_scan_unpaired_stone_devices() -> *mut c_char;
+    fn macos_bt_connect(address: *const c_char) -> i32;
+    fn macos_bt_disconnect(address: *const c_char) -> i32;
+    fn macos_bt_write(address: *const c_char, data: *const u8, len: usize) -> i32;
+    fn macos_bt_last_error_context() -> *mut c_char;
+    fn macos_bt_get_connection_infos() -> *mut c_char;
+}
+
+#[no_mangle]
+pub extern "C" fn macos_bt_on_data(address: *const c_char, data: *const u8, len: usize) {
+    if address.is_null() || data.is_null() || len == 0 {
+        return;
+    }
+    let address = unsafe { CStr::from_ptr(address) }
+        .to_string_lossy()
+        .to_string();
+    let bytes = unsafe { std::slice::from_raw_parts(data, len) };
+    handle_backend_data(&address, bytes);
+}
+
+#[no_mangle]
+pub extern "C" fn macos_bt_on_device_event(address: *const c_char, connected: i32) {
+    if address.is_null() {
+        return;
+    }
+    let addr = unsafe { CStr::from_ptr(address) }
+        .to_string_lossy()
+        .to_string();
+    emit_backend_device_event(addr, connected != 0);
+}
+
+fn take_c_string(ptr: *mut c_char) -> Option<Result<String, ()>> {
+    if ptr.is_null() {
+        return None;
+    }
+    Some(
+        unsafe { CString::from_raw(ptr) }
+            .into_string()
+            .map_err(|_| ()),
+    )
+}
+
+fn last_error_context() -> String {
+    take_c_string(unsafe { macos_bt_last_error_context() })
+        .and_then(Result::ok)
+        .unwrap_or_default()
+}
+
+fn iobluetooth_error(status: i32, context: String) -> StoneError {
+    StoneError::IoBluetooth {
+        code: status,
+        name: ioreturn_name(status).to_string(),
+        context,
+    }
+}
+
+fn connection_infos() -> Result<Vec<ConnectionInfo>, StoneError> {
+    let Some(json) = take_c_string(unsafe { macos_bt_get_connection_infos() }) else {
+        return Ok(Vec::new());
+    };
+    let json = json.map_err(|_| StoneError::other("Invalid connection info encoding"))?;
+    serde_json::from_str(&json).map_err(StoneError::other)
+}
+
+async fn run_with_address(
+    address: &str,
+    call: unsafe extern "C" fn(*const c_char) -> i32,
+) -> Result<(), StoneError> {
+    let address = address.to_string();
+    let status = tauri::async_runtime::spawn_blocking(move || -> Result<i32, StoneError> {
+        let cstr = CString::new(address).map_err(|_| StoneError::InvalidAddress)?;
+        Ok(unsafe { call(cstr.as_ptr()) })
+    })
+    .await
+    .map_err(|_| StoneError::other("Join error"))??;
+    if status == 0 {
+        Ok(())
+    } else {
+        Err(iobluetooth_error(status, last_error_context()))
+    }
+}
+
+pub(crate) struct MacosBackend;
+
+#[async_trait]
+impl BluetoothBackend for MacosBackend {
+    fn name(&self) -> &'static str {
+        "macos"
+    }
+
+    async fn list_devices(&self) -> Result<Vec<BluetoothDeviceInfo>, StoneError> {
+        back_log("RUST", "List devices".to_string());
+        let Some(json) = take_c_string(unsafe { macos_bt_list_paired_devices() }) else {
+            return Ok(Vec::new());
+        };
+        let json = json.map_err(|_| StoneError::other("Invalid device list encoding"))?;
+        serde_json::from_str(&json).map_err(StoneError::other)
+    }
+
+    async fn scan_unpaired_stone_devices(&self) -> Result<Vec<BluetoothDeviceInfo>, StoneError> {
+        let json = tauri::async_runtime::spawn_blocking(move || {
+            match take_c_string(unsafe { macos_bt_scan_unpaired_stone_devices() }) {
+                None => Ok::<String, StoneError>("[]".to_string()),
+                Some(json) => json.map_err(|_| StoneError::other("Invalid scan list encoding")),
+            }
+        })
+        .await
+        .map_err(|_| StoneError::other("Join error"))??;
+        serde_json::from_str(&json).map_err(StoneError::other)
+    }
+
+    async fn get_connection_infos(&self) -> Result<Vec<ConnectionInfo>, StoneError> {
+        connection_infos()
+    }
+
+    async fn connect_device(&self, address: &str) -> Result<(), StoneError> {
+        run_with_address(address, macos_bt_connect).await
+    }
+
+    async fn disconnect_device(&self, address: &str) -> Result<(), StoneError> {
+        run_with_address(address, macos_bt_disconnect).await
+    }
+
+    async fn write_frame(&self, address: &str, frame: &[u8]) -> Result<(), StoneError> {
+        let address = address.to_string();
+        let frame = frame.to_vec();
+        let status = tauri::async_runtime::spawn_blocking(move || -> Result<i32, StoneError> {
+            let connected = connection_infos()?
+                .iter()
+                .any(|info| info.rfcomm && info.address.eq_ignore_ascii_case(&address));
+            if !connected {
+                return Err(StoneError::NotConnected);
+            }
+            let cstr = CString::new(address).map_err(|_| StoneError::InvalidAddress)?;
+            Ok(unsafe { macos_bt_write(cstr.as_ptr(), frame.as_ptr(), frame.len()) })
+        })
+        .await
+        .map_err(|_| StoneError::other("Join error"))??;
+        if status == 0 {
+            Ok(())
+        } else {
+            Err(iobluetooth_error(status, String::new()))
+        }
+    }
+}
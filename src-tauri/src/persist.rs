@@ -0,0 +1,135 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::back_log;
+use crate::error::StoneError;
+
+#[derive(Deserialize)]
+struct Versioned<T> {
+    version: u32,
+    #[serde(flatten)]
+    data: T,
+}
+
+#[derive(Serialize)]
+struct VersionedRef<'a, T> {
+    version: u32,
+    #[serde(flatten)]
+    data: &'a T,
+}
+
+fn io(err: std::io::Error) -> StoneError {
+    StoneError::Io {
+        message: err.to_string(),
+    }
+}
+
+fn read<T: DeserializeOwned + Default>(path: &Path, version: u32) -> Result<T, StoneError> {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(T::default()),
+        Err(err) => return Err(io(err)),
+    };
+    let file: Versioned<T> = serde_json::from_slice(&bytes).map_err(|err| StoneError::Decode {
+        message: err.to_string(),
+    })?;
+    if file.version > version {
+        return Err(StoneError::Decode {
+            message: format!("Unsupported file version {}", file.version),
+        });
+    }
+    Ok(file.data)
+}
+
+/// Reads a `{"version": .., ...}` file written by `save`, or the default when there is none.
+/// A file that cannot be read, or comes from a newer build, is moved to `.json.bad` rather
+/// than overwritten by the next save.
+pub(crate) fn load<T: DeserializeOwned + Default>(path: &Path, version: u32) -> T {
+    match read(path, version) {
+        Ok(data) => data,
+        Err(err) => {
+            let aside = path.with_extension("json.bad");
+            back_log(
+                "RUST",
+                format!(
+                    "Unreadable {} ({}), moved to {}",
+                    path.display(),
+                    err,
+                    aside.display()
+                ),
+            );
+            let _ = std::fs::rename(path, &aside);
+            T::default()
+        }
+    }
+}
+
+pub(crate) fn save<T: Serialize>(path: &Path, version: u32, data: &T) -> Result<(), StoneError> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(io)?;
+    }
+    let json =
+        serde_json::to_vec_pretty(&VersionedRef { version, data }).map_err(StoneError::other)?;
+    // Write beside the file and swap it in, so a crash never leaves half a file.
+    let staging = path.with_extension("json.tmp");
+    std::fs::write(&staging, json).map_err(io)?;
+    std::fs::rename(&staging, path).map_err(io)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[derive(Serialize, Deserialize, Default, Debug, PartialEq)]
+    struct Notes {
+        notes: Vec<String>,
+    }
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("stone-persist-{}", std::process::id()));
+        let path = dir.join(name).join("notes.json");
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+        path
+    }
+
+    #[test]
+    fn saves_and_loads_with_a_version() {
+        let path = scratch("round-trip");
+        let notes = Notes {
+            notes: vec!["one".to_string()],
+        };
+        save(&path, 2, &notes).unwrap();
+
+        let json: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(json["version"], 2);
+        assert_eq!(load::<Notes>(&path, 2), notes);
+        assert!(!path.with_extension("json.tmp").exists());
+    }
+
+    #[test]
+    fn missing_file_loads_the_default() {
+        let path = scratch("missing");
+        assert_eq!(load::<Notes>(&path, 1), Notes::default());
+        assert!(!path.with_extension("json.bad").exists());
+    }
+
+    #[test]
+    fn unreadable_and_newer_files_are_moved_aside() {
+        let path = scratch("bad");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, b"{ not json").unwrap();
+        assert_eq!(load::<Notes>(&path, 1), Notes::default());
+        assert!(!path.exists());
+        assert_eq!(
+            std::fs::read(path.with_extension("json.bad")).unwrap(),
+            b"{ not json"
+        );
+
+        save(&path, 3, &Notes::default()).unwrap();
+        assert_eq!(load::<Notes>(&path, 2), Notes::default());
+        assert!(!path.exists());
+    }
+}
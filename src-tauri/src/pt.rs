@@ -0,0 +1,782 @@
+//! Typed requests and responses for the STONE vendor (PT, 0x5054) command set.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::backend::BluetoothBackend;
+use crate::error::StoneError;
+use crate::request_gaia;
+use crate::transaction::GaiaResponse;
+
+pub(crate) const PT_VENDOR_ID: u16 = 0x5054;
+
+pub(crate) const MAX_VOLUME: u8 = 30;
+pub(crate) const MAX_LAMP_BRIGHTNESS: u8 = 100;
+pub(crate) const LAMP_TYPES: std::ops::RangeInclusive<u8> = 1..=5;
+// The lamp type that shows the color set with 0x0204.
+pub(crate) const SOLID_LAMP_TYPE: u8 = 1;
+
+pub(crate) const DC_FULL: u8 = 1;
+pub(crate) const DC_CHARGING: u8 = 3;
+
+pub(crate) const SET_VOLUME: u16 = 0x0201;
+pub(crate) const SET_LAMP_BRIGHTNESS: u16 = 0x0202;
+pub(crate) const SET_LAMP_TYPE: u16 = 0x0203;
+pub(crate) const SET_LAMP_COLOR: u16 = 0x0204;
+pub(crate) const RUN_LAMP: u16 = 0x0212;
+pub(crate) const STOP_LAMP: u16 = 0x0213;
+pub(crate) const GET_VOLUME: u16 = 0x0401;
+pub(crate) const GET_LAMP_STATE: u16 = 0x0411;
+pub(crate) const GET_DEVICE_NAME: u16 = 0x0451;
+pub(crate) const GET_FIRMWARE: u16 = 0x0452;
+pub(crate) const GET_MAC: u16 = 0x0453;
+pub(crate) const GET_RSSI: u16 = 0x0454;
+pub(crate) const GET_BATTERY_STEP: u16 = 0x0455;
+pub(crate) const GET_DC_STATE: u16 = 0x0456;
+pub(crate) const GET_WHEEL_COUNT: u16 = 0x0457;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum PtError {
+    UnknownCommand(u16),
+    Truncated {
+        command: u16,
+        needed: usize,
+        available: usize,
+    },
+    InvalidParameter(String),
+}
+
+impl fmt::Display for PtError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownCommand(command) => write!(f, "unknown PT command 0x{:04X}", command),
+            Self::Truncated {
+                command,
+                needed,
+                available,
+            } => write!(
+                f,
+                "PT 0x{:04X} payload too short: need {} bytes, got {}",
+                command, needed, available
+            ),
+            Self::InvalidParameter(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for PtError {}
+
+impl From<PtError> for StoneError {
+    fn from(err: PtError) -> Self {
+        match err {
+            PtError::InvalidParameter(message) => Self::InvalidParameter { message },
+            other => Self::Decode {
+                message: other.to_string(),
+            },
+        }
+    }
+}
+
+fn expect_len(command: u16, data: &[u8], needed: usize) -> Result<(), PtError> {
+    if data.len() < needed {
+        return Err(PtError::Truncated {
+            command,
+            needed,
+            available: data.len(),
+        });
+    }
+    Ok(())
+}
+
+fn decode_text(data: &[u8]) -> String {
+    String::from_utf8_lossy(data)
+        .trim_matches(|c: char| c == '\0' || c.is_whitespace())
+        .to_string()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub(crate) const WHITE: Self = Self::new(255, 255, 255);
+
+    pub(crate) const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    pub(crate) fn to_hex(self) -> String {
+        format!("{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct LampSettings {
+    pub brightness: u8,
+    pub lamp_type: u8,
+    pub color: Rgb,
+}
+
+fn check_volume(volume: u8) -> Result<(), PtError> {
+    if volume > MAX_VOLUME {
+        return Err(PtError::InvalidParameter(format!(
+            "Volume {} out of range (0-{})",
+            volume, MAX_VOLUME
+        )));
+    }
+    Ok(())
+}
+
+fn check_brightness(brightness: u8) -> Result<(), PtError> {
+    if brightness > MAX_LAMP_BRIGHTNESS {
+        return Err(PtError::InvalidParameter(format!(
+            "Lamp brightness {} out of range (0-{})",
+            brightness, MAX_LAMP_BRIGHTNESS
+        )));
+    }
+    Ok(())
+}
+
+fn check_lamp_type(lamp_type: u8) -> Result<(), PtError> {
+    if !LAMP_TYPES.contains(&lamp_type) {
+        return Err(PtError::InvalidParameter(format!(
+            "Lamp type {} out of range ({}-{})",
+            lamp_type,
+            LAMP_TYPES.start(),
+            LAMP_TYPES.end()
+        )));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PtCommand {
+    SetVolume(u8),
+    SetLampBrightness(u8),
+    SetLampType(u8),
+    SetLampColor(Rgb),
+    RunLamp(LampSettings),
+    StopLamp,
+    GetVolume,
+    GetLampState,
+    GetDeviceName,
+    GetFirmware,
+    GetMac,
+    GetRssi,
+    GetBatteryStep,
+    GetDcState,
+    GetWheelCount,
+}
+
+impl PtCommand {
+    pub(crate) fn id(&self) -> u16 {
+        match self {
+            Self::SetVolume(_) => SET_VOLUME,
+            Self::SetLampBrightness(_) => SET_LAMP_BRIGHTNESS,
+            Self::SetLampType(_) => SET_LAMP_TYPE,
+            Self::SetLampColor(_) => SET_LAMP_COLOR,
+            Self::RunLamp(_) => RUN_LAMP,
+            Self::StopLamp => STOP_LAMP,
+            Self::GetVolume => GET_VOLUME,
+            Self::GetLampState => GET_LAMP_STATE,
+            Self::GetDeviceName => GET_DEVICE_NAME,
+            Self::GetFirmware => GET_FIRMWARE,
+            Self::GetMac => GET_MAC,
+            Self::GetRssi => GET_RSSI,
+            Self::GetBatteryStep => GET_BATTERY_STEP,
+            Self::GetDcState => GET_DC_STATE,
+            Self::GetWheelCount => GET_WHEEL_COUNT,
+        }
+    }
+
+    pub(crate) fn payload(&self) -> Vec<u8> {
+        match *self {
+            Self::SetVolume(volume) => vec![volume],
+            Self::SetLampBrightness(brightness) => vec![brightness],
+            Self::SetLampType(lamp_type) => vec![lamp_type],
+            Self::SetLampColor(Rgb { r, g, b }) => vec![r, g, b],
+            Self::RunLamp(LampSettings {
+                brightness,
+                lamp_type,
+                color: Rgb { r, g, b },
+            }) => vec![brightness, lamp_type, r, g, b],
+            _ => Vec::new(),
+        }
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), PtError> {
+        match *self {
+            Self::SetVolume(volume) => check_volume(volume),
+            Self::SetLampBrightness(brightness) => check_brightness(brightness),
+            Self::SetLampType(lamp_type) => check_lamp_type(lamp_type),
+            Self::RunLamp(settings) => {
+                check_brightness(settings.brightness)?;
+                check_lamp_type(settings.lamp_type)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Parses a request as the speaker receives it.
+    pub(crate) fn parse(command: u16, data: &[u8]) -> Result<Self, PtError> {
+        let parsed = match command {
+            SET_VOLUME => {
+                expect_len(command, data, 1)?;
+                Self::SetVolume(data[0])
+            }
+            SET_LAMP_BRIGHTNESS => {
+                expect_len(command, data, 1)?;
+                Self::SetLampBrightness(data[0])
+            }
+            SET_LAMP_TYPE => {
+                expect_len(command, data, 1)?;
+                Self::SetLampType(data[0])
+            }
+            SET_LAMP_COLOR => {
+                expect_len(command, data, 3)?;
+                Self::SetLampColor(Rgb {
+                    r: data[0],
+                    g: data[1],
+                    b: data[2],
+                })
+            }
+            RUN_LAMP => {
+                expect_len(command, data, 5)?;
+                Self::RunLamp(LampSettings {
+                    brightness: data[0],
+                    lamp_type: data[1],
+                    color: Rgb {
+                        r: data[2],
+                        g: data[3],
+                        b: data[4],
+                    },
+                })
+            }
+            STOP_LAMP => Self::StopLamp,
+            GET_VOLUME => Self::GetVolume,
+            GET_LAMP_STATE => Self::GetLampState,
+            GET_DEVICE_NAME => Self::GetDeviceName,
+            GET_FIRMWARE => Self::GetFirmware,
+            GET_MAC => Self::GetMac,
+            GET_RSSI => Self::GetRssi,
+            GET_BATTERY_STEP => Self::GetBatteryStep,
+            GET_DC_STATE => Self::GetDcState,
+            GET_WHEEL_COUNT => Self::GetWheelCount,
+            other => return Err(PtError::UnknownCommand(other)),
+        };
+        parsed.validate()?;
+        Ok(parsed)
+    }
+}
+
+/// A PT response body (the ACK payload after the status byte).
+pub(crate) trait PtResponse: Sized {
+    const QUERY: PtCommand;
+
+    fn decode(data: &[u8]) -> Result<Self, PtError>;
+
+    fn encode(&self) -> Vec<u8>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub(crate) struct BatteryStep {
+    pub step: u8,
+    pub level: Option<u8>,
+}
+
+impl BatteryStep {
+    pub(crate) fn from_level(level: u8) -> Self {
+        let step = match level {
+            0..=20 => 1,
+            21..=40 => 2,
+            41..=60 => 3,
+            61..=80 => 4,
+            _ => 5,
+        };
+        Self {
+            step,
+            level: Some(level),
+        }
+    }
+
+    pub(crate) fn step_percent(&self) -> u8 {
+        match self.step {
+            0 | 1 => 20,
+            2 => 40,
+            3 => 60,
+            4 => 80,
+            _ => 100,
+        }
+    }
+
+    pub(crate) fn percent(&self) -> u8 {
+        self.level
+            .map(|level| level.min(100))
+            .unwrap_or_else(|| self.step_percent())
+    }
+}
+
+impl PtResponse for BatteryStep {
+    const QUERY: PtCommand = PtCommand::GetBatteryStep;
+
+    fn decode(data: &[u8]) -> Result<Self, PtError> {
+        expect_len(GET_BATTERY_STEP, data, 1)?;
+        Ok(Self {
+            step: data[0],
+            level: data.get(1).copied(),
+        })
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut data = vec![self.step];
+        data.extend(self.level);
+        data
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub(crate) struct DcState {
+    pub state: u8,
+}
+
+impl DcState {
+    pub(crate) fn is_charging(&self) -> bool {
+        self.state == DC_CHARGING
+    }
+
+    pub(crate) fn is_full(&self) -> bool {
+        self.state == DC_FULL
+    }
+}
+
+impl PtResponse for DcState {
+    const QUERY: PtCommand = PtCommand::GetDcState;
+
+    fn decode(data: &[u8]) -> Result<Self, PtError> {
+        expect_len(GET_DC_STATE, data, 1)?;
+        Ok(Self { state: data[0] })
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        vec![self.state]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub(crate) struct Volume {
+    pub volume: u8,
+}
+
+impl PtResponse for Volume {
+    const QUERY: PtCommand = PtCommand::GetVolume;
+
+    fn decode(data: &[u8]) -> Result<Self, PtError> {
+        expect_len(GET_VOLUME, data, 1)?;
+        Ok(Self { volume: data[0] })
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        vec![self.volume]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct LampState {
+    pub on: bool,
+    pub brightness: u8,
+    pub lamp_type: u8,
+    pub color: Rgb,
+}
+
+impl PtResponse for LampState {
+    const QUERY: PtCommand = PtCommand::GetLampState;
+
+    fn decode(data: &[u8]) -> Result<Self, PtError> {
+        expect_len(GET_LAMP_STATE, data, 6)?;
+        Ok(Self {
+            on: data[0] == 1,
+            brightness: data[1],
+            lamp_type: if LAMP_TYPES.contains(&data[2]) {
+                data[2]
+            } else {
+                1
+            },
+            color: Rgb {
+                r: data[3],
+                g: data[4],
+                b: data[5],
+            },
+        })
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        vec![
+            u8::from(self.on),
+            self.brightness,
+            self.lamp_type,
+            self.color.r,
+            self.color.g,
+            self.color.b,
+        ]
+    }
+}
+
+macro_rules! text_response {
+    ($name:ident, $query:ident, $command:ident) => {
+        #[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+        pub(crate) struct $name(pub String);
+
+        impl PtResponse for $name {
+            const QUERY: PtCommand = PtCommand::$query;
+
+            fn decode(data: &[u8]) -> Result<Self, PtError> {
+                expect_len($command, data, 1)?;
+                Ok(Self(decode_text(data)))
+            }
+
+            fn encode(&self) -> Vec<u8> {
+                self.0.as_bytes().to_vec()
+            }
+        }
+    };
+}
+
+text_response!(DeviceName, GetDeviceName, GET_DEVICE_NAME);
+text_response!(Firmware, GetFirmware, GET_FIRMWARE);
+text_response!(Mac, GetMac, GET_MAC);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub(crate) struct Rssi(pub i8);
+
+impl PtResponse for Rssi {
+    const QUERY: PtCommand = PtCommand::GetRssi;
+
+    fn decode(data: &[u8]) -> Result<Self, PtError> {
+        expect_len(GET_RSSI, data, 1)?;
+        Ok(Self(data[0] as i8))
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        vec![self.0 as u8]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub(crate) struct WheelCount(pub i32);
+
+impl PtResponse for WheelCount {
+    const QUERY: PtCommand = PtCommand::GetWheelCount;
+
+    fn decode(data: &[u8]) -> Result<Self, PtError> {
+        expect_len(GET_WHEEL_COUNT, data, 4)?;
+        Ok(Self(i32::from_be_bytes([
+            data[0], data[1], data[2], data[3],
+        ])))
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        self.0.to_be_bytes().to_vec()
+    }
+}
+
+/// Any decoded PT report, whether it arrived as an ACK or unsolicited.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum PtReport {
+    BatteryStep(BatteryStep),
+    DcState(DcState),
+    Volume(Volume),
+    LampState(LampState),
+    DeviceName(DeviceName),
+    Firmware(Firmware),
+    Mac(Mac),
+    Rssi(Rssi),
+    WheelCount(WheelCount),
+}
+
+impl PtReport {
+    /// Returns `None` for commands that carry no report, such as setters.
+    pub(crate) fn decode(command: u16, data: &[u8]) -> Option<Result<Self, PtError>> {
+        let report = match command {
+            GET_BATTERY_STEP => BatteryStep::decode(data).map(Self::BatteryStep),
+            GET_DC_STATE => DcState::decode(data).map(Self::DcState),
+            GET_VOLUME => Volume::decode(data).map(Self::Volume),
+            GET_LAMP_STATE => LampState::decode(data).map(Self::LampState),
+            GET_DEVICE_NAME => DeviceName::decode(data).map(Self::DeviceName),
+            GET_FIRMWARE => Firmware::decode(data).map(Self::Firmware),
+            GET_MAC => Mac::decode(data).map(Self::Mac),
+            GET_RSSI => Rssi::decode(data).map(Self::Rssi),
+            GET_WHEEL_COUNT => WheelCount::decode(data).map(Self::WheelCount),
+            _ => return None,
+        };
+        Some(report)
+    }
+}
+
+pub(crate) async fn send(
+    backend: &dyn BluetoothBackend,
+    address: &str,
+    command: PtCommand,
+    timeout_ms: u64,
+) -> Result<GaiaResponse, StoneError> {
+    command.validate()?;
+    request_gaia(
+        backend,
+        address,
+        PT_VENDOR_ID,
+        command.id(),
+        &command.payload(),
+        timeout_ms,
+    )
+    .await
+}
+
+pub(crate) async fn query<T: PtResponse>(
+    backend: &dyn BluetoothBackend,
+    address: &str,
+    timeout_ms: u64,
+) -> Result<T, StoneError> {
+    let response = send(backend, address, T::QUERY, timeout_ms).await?;
+    Ok(T::decode(&response.payload)?)
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub(crate) struct BatteryReport {
+    pub step: u8,
+    pub level: Option<u8>,
+    pub percent: u8,
+    pub dc_state: u8,
+    pub charging: bool,
+    pub full: bool,
+}
+
+impl BatteryReport {
+    pub(crate) fn new(battery: BatteryStep, dc: DcState) -> Self {
+        Self {
+            step: battery.step,
+            level: battery.level,
+            percent: battery.percent(),
+            dc_state: dc.state,
+            charging: dc.is_charging(),
+            full: dc.is_full(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct DeviceInfo {
+    pub name: String,
+    pub firmware: String,
+    pub mac: String,
+    pub rssi: i8,
+    pub wheel_count: i32,
+}
+
+pub(crate) async fn battery(
+    backend: &dyn BluetoothBackend,
+    address: &str,
+    timeout_ms: u64,
+) -> Result<BatteryReport, StoneError> {
+    let battery = query::<BatteryStep>(backend, address, timeout_ms).await?;
+    let dc = query::<DcState>(backend, address, timeout_ms).await?;
+    Ok(BatteryReport::new(battery, dc))
+}
+
+pub(crate) async fn device_info(
+    backend: &dyn BluetoothBackend,
+    address: &str,
+    timeout_ms: u64,
+) -> Result<DeviceInfo, StoneError> {
+    Ok(DeviceInfo {
+        name: query::<DeviceName>(backend, address, timeout_ms).await?.0,
+        firmware: query::<Firmware>(backend, address, timeout_ms).await?.0,
+        mac: query::<Mac>(backend, address, timeout_ms).await?.0,
+        rssi: query::<Rssi>(backend, address, timeout_ms).await?.0,
+        wheel_count: query::<WheelCount>(backend, address, timeout_ms).await?.0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: Rgb = Rgb::new(0xFF, 0x10, 0x00);
+
+    fn all_commands() -> Vec<PtCommand> {
+        vec![
+            PtCommand::SetVolume(MAX_VOLUME),
+            PtCommand::SetLampBrightness(MAX_LAMP_BRIGHTNESS),
+            PtCommand::SetLampType(*LAMP_TYPES.end()),
+            PtCommand::SetLampColor(RED),
+            PtCommand::RunLamp(LampSettings {
+                brightness: 0,
+                lamp_type: SOLID_LAMP_TYPE,
+                color: RED,
+            }),
+            PtCommand::StopLamp,
+            PtCommand::GetVolume,
+            PtCommand::GetLampState,
+            PtCommand::GetDeviceName,
+            PtCommand::GetFirmware,
+            PtCommand::GetMac,
+            PtCommand::GetRssi,
+            PtCommand::GetBatteryStep,
+            PtCommand::GetDcState,
+            PtCommand::GetWheelCount,
+        ]
+    }
+
+    #[test]
+    fn every_command_round_trips() {
+        for command in all_commands() {
+            assert_eq!(
+                PtCommand::parse(command.id(), &command.payload()),
+                Ok(command)
+            );
+        }
+        assert_eq!(
+            PtCommand::RunLamp(LampSettings {
+                brightness: 40,
+                lamp_type: 2,
+                color: RED,
+            })
+            .payload(),
+            vec![40, 2, 0xFF, 0x10, 0x00]
+        );
+    }
+
+    #[test]
+    fn out_of_range_parameters_are_rejected() {
+        let invalid = [
+            PtCommand::SetVolume(MAX_VOLUME + 1),
+            PtCommand::SetLampBrightness(MAX_LAMP_BRIGHTNESS + 1),
+            PtCommand::SetLampType(0),
+            PtCommand::SetLampType(*LAMP_TYPES.end() + 1),
+            PtCommand::RunLamp(LampSettings {
+                brightness: MAX_LAMP_BRIGHTNESS + 1,
+                lamp_type: SOLID_LAMP_TYPE,
+                color: RED,
+            }),
+            PtCommand::RunLamp(LampSettings {
+                brightness: 0,
+                lamp_type: 9,
+                color: RED,
+            }),
+        ];
+        for command in invalid {
+            assert!(matches!(
+                command.validate(),
+                Err(PtError::InvalidParameter(_))
+            ));
+            assert!(matches!(
+                PtCommand::parse(command.id(), &command.payload()),
+                Err(PtError::InvalidParameter(_))
+            ));
+        }
+        assert_eq!(
+            PtCommand::parse(0x0999, &[]),
+            Err(PtError::UnknownCommand(0x0999))
+        );
+    }
+
+    #[test]
+    fn short_requests_are_truncated() {
+        for command in all_commands() {
+            let payload = command.payload();
+            if payload.is_empty() {
+                continue;
+            }
+            assert_eq!(
+                PtCommand::parse(command.id(), &payload[..payload.len() - 1]),
+                Err(PtError::Truncated {
+                    command: command.id(),
+                    needed: payload.len(),
+                    available: payload.len() - 1,
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn decodes_the_lamp_state() {
+        let lamp = LampState::decode(&[1, 80, 3, 0x01, 0x02, 0x03]).unwrap();
+        assert_eq!(
+            lamp,
+            LampState {
+                on: true,
+                brightness: 80,
+                lamp_type: 3,
+                color: Rgb::new(1, 2, 3),
+            }
+        );
+        assert_eq!(LampState::decode(&lamp.encode()), Ok(lamp));
+        // A lamp type the app does not know reads as solid.
+        assert_eq!(
+            LampState::decode(&[0, 0, 0xEE, 0, 0, 0]).unwrap().lamp_type,
+            SOLID_LAMP_TYPE
+        );
+        assert!(matches!(
+            LampState::decode(&[1, 80, 3, 0x01, 0x02]),
+            Err(PtError::Truncated { needed: 6, .. })
+        ));
+    }
+
+    #[test]
+    fn decodes_big_endian_wheel_counts() {
+        assert_eq!(
+            WheelCount::decode(&[0x00, 0x00, 0x01, 0x02]),
+            Ok(WheelCount(0x0102))
+        );
+        assert_eq!(WheelCount::decode(&[0xFF; 4]), Ok(WheelCount(-1)));
+        assert_eq!(WheelCount(-300).encode(), vec![0xFF, 0xFF, 0xFE, 0xD4]);
+        assert!(WheelCount::decode(&[0x00, 0x01, 0x02]).is_err());
+    }
+
+    #[test]
+    fn decodes_battery_steps_with_and_without_a_level() {
+        let bare = BatteryStep::decode(&[3]).unwrap();
+        assert_eq!(bare.level, None);
+        assert_eq!(bare.percent(), 60);
+        assert_eq!(bare.encode(), vec![3]);
+
+        let exact = BatteryStep::decode(&[4, 77]).unwrap();
+        assert_eq!(exact.level, Some(77));
+        assert_eq!(exact.percent(), 77);
+        assert_eq!(exact.encode(), vec![4, 77]);
+        assert_eq!(BatteryStep::from_level(77), exact);
+    }
+
+    #[test]
+    fn short_or_garbage_reports_do_not_panic() {
+        for command in [
+            GET_BATTERY_STEP,
+            GET_DC_STATE,
+            GET_VOLUME,
+            GET_LAMP_STATE,
+            GET_DEVICE_NAME,
+            GET_FIRMWARE,
+            GET_MAC,
+            GET_RSSI,
+            GET_WHEEL_COUNT,
+        ] {
+            assert!(matches!(
+                PtReport::decode(command, &[]),
+                Some(Err(PtError::Truncated { .. }))
+            ));
+            assert!(
+                PtReport::decode(command, &[0xFF, 0xFE, 0x00, 0xC3, 0x28, 0x9F, 0x80])
+                    .unwrap()
+                    .is_ok()
+            );
+        }
+        assert_eq!(
+            DeviceName::decode(b"STONE\0\0 "),
+            Ok(DeviceName("STONE".to_string()))
+        );
+        assert_eq!(Rssi::decode(&[0xC4]), Ok(Rssi(-60)));
+        assert!(PtReport::decode(SET_VOLUME, &[1]).is_none());
+    }
+}
@@ -0,0 +1,519 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::Emitter;
+
+use crate::error::StoneError;
+use crate::{back_log, persist, APP_HANDLE};
+
+// Overrides where the registry file lives, for the app and the CLI alike.
+pub(crate) const REGISTRY_ENV: &str = "STONE_REGISTRY";
+const REGISTRY_FILE: &str = "registry.json";
+// Matches `identifier` in tauri.conf.json, so the CLI finds the app's file.
+const APP_IDENTIFIER: &str = "com.stone.manager";
+// 2 added the profile fields; older builds would drop them on their next save.
+const REGISTRY_VERSION: u32 = 2;
+
+/// What the user chose for a speaker, as opposed to what it advertises.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub(crate) struct DeviceProfile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct RegisteredDevice {
+    pub address: String,
+    pub name: String,
+    #[serde(flatten)]
+    pub profile: DeviceProfile,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub(crate) struct DeviceGroup {
+    name: String,
+    addresses: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct RegistryFile {
+    devices: Vec<RegisteredDevice>,
+}
+
+#[derive(Default)]
+struct Inner {
+    path: Option<PathBuf>,
+    devices: Vec<RegisteredDevice>,
+}
+
+/// Registered speakers in the order the user keeps them, saved to `registry.json` on every
+/// change and announced as `registry_changed`.
+#[derive(Default)]
+pub(crate) struct DeviceRegistry {
+    inner: Mutex<Inner>,
+}
+
+/// The registry file outside the app: `STONE_REGISTRY`, else the app's data dir.
+pub(crate) fn default_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os(REGISTRY_ENV) {
+        return Some(PathBuf::from(path));
+    }
+    dirs::data_dir().map(|dir| dir.join(APP_IDENTIFIER).join(REGISTRY_FILE))
+}
+
+pub(crate) fn path_in(data_dir: &Path) -> PathBuf {
+    match std::env::var_os(REGISTRY_ENV) {
+        Some(path) => PathBuf::from(path),
+        None => data_dir.join(REGISTRY_FILE),
+    }
+}
+
+fn position(devices: &[RegisteredDevice], address: &str) -> Option<usize> {
+    devices
+        .iter()
+        .position(|device| device.address.eq_ignore_ascii_case(address))
+}
+
+// Blank text clears the field.
+fn non_empty(value: Option<String>) -> Option<String> {
+    value
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+fn normalize_groups(groups: Vec<String>) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::with_capacity(groups.len());
+    for group in groups
+        .into_iter()
+        .filter_map(|group| non_empty(Some(group)))
+    {
+        if !normalized
+            .iter()
+            .any(|existing| existing.eq_ignore_ascii_case(&group))
+        {
+            normalized.push(group);
+        }
+    }
+    normalized
+}
+
+impl DeviceRegistry {
+    /// Loads `path` and saves there from now on. An unreadable file is moved aside rather
+    /// than overwritten.
+    pub(crate) fn load(&self, path: PathBuf) {
+        let RegistryFile { devices } = persist::load(&path, REGISTRY_VERSION);
+        back_log(
+            "RUST",
+            format!(
+                "Loaded {} registered devices from {}",
+                devices.len(),
+                path.display()
+            ),
+        );
+        if let Ok(mut inner) = self.inner.lock() {
+            inner.path = Some(path);
+            inner.devices = devices;
+        }
+        self.announce();
+    }
+
+    pub(crate) fn list(&self) -> Vec<RegisteredDevice> {
+        self.inner
+            .lock()
+            .map(|inner| inner.devices.clone())
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn profile(&self, address: &str) -> Option<DeviceProfile> {
+        self.inner.lock().ok().and_then(|inner| {
+            position(&inner.devices, address).map(|index| inner.devices[index].profile.clone())
+        })
+    }
+
+    /// Groups in the order they first appear in the registry, each with its members in order.
+    pub(crate) fn groups(&self) -> Vec<DeviceGroup> {
+        let mut groups: Vec<DeviceGroup> = Vec::new();
+        for device in self.list() {
+            for name in device.profile.groups {
+                match groups
+                    .iter_mut()
+                    .find(|group| group.name.eq_ignore_ascii_case(&name))
+                {
+                    Some(group) => group.addresses.push(device.address.clone()),
+                    None => groups.push(DeviceGroup {
+                        name,
+                        addresses: vec![device.address.clone()],
+                    }),
+                }
+            }
+        }
+        groups
+    }
+
+    /// Expands `target` into addresses: a group name stands for its members, anything else
+    /// is taken as a single address.
+    pub(crate) fn resolve(&self, target: &str) -> Vec<String> {
+        let target = target.trim();
+        self.groups()
+            .into_iter()
+            .find(|group| group.name.eq_ignore_ascii_case(target))
+            .map(|group| group.addresses)
+            .unwrap_or_else(|| vec![target.to_string()])
+    }
+
+    /// Registers a device at the top of the list, or renames it in place when already known.
+    pub(crate) fn upsert(
+        &self,
+        address: &str,
+        name: &str,
+    ) -> Result<Vec<RegisteredDevice>, StoneError> {
+        if address.trim().is_empty() {
+            return Err(StoneError::InvalidAddress);
+        }
+        self.update(|devices| {
+            match position(devices, address) {
+                Some(index) => devices[index].name = name.to_string(),
+                None => devices.insert(
+                    0,
+                    RegisteredDevice {
+                        address: address.to_string(),
+                        name: name.to_string(),
+                        profile: DeviceProfile::default(),
+                    },
+                ),
+            }
+            Ok(())
+        })
+    }
+
+    pub(crate) fn remove(&self, address: &str) -> Result<Vec<RegisteredDevice>, StoneError> {
+        self.update(|devices| {
+            let index = position(devices, address).ok_or(StoneError::DeviceNotFound)?;
+            devices.remove(index);
+            Ok(())
+        })
+    }
+
+    pub(crate) fn rename(
+        &self,
+        address: &str,
+        name: &str,
+    ) -> Result<Vec<RegisteredDevice>, StoneError> {
+        self.update(|devices| {
+            let index = position(devices, address).ok_or(StoneError::DeviceNotFound)?;
+            devices[index].name = name.to_string();
+            Ok(())
+        })
+    }
+
+    pub(crate) fn set_alias(
+        &self,
+        address: &str,
+        alias: Option<String>,
+    ) -> Result<Vec<RegisteredDevice>, StoneError> {
+        self.update_profile(address, |profile| profile.alias = non_empty(alias))
+    }
+
+    pub(crate) fn set_icon(
+        &self,
+        address: &str,
+        icon: Option<String>,
+    ) -> Result<Vec<RegisteredDevice>, StoneError> {
+        self.update_profile(address, |profile| profile.icon = non_empty(icon))
+    }
+
+    pub(crate) fn set_groups(
+        &self,
+        address: &str,
+        groups: Vec<String>,
+    ) -> Result<Vec<RegisteredDevice>, StoneError> {
+        self.update_profile(address, |profile| profile.groups = normalize_groups(groups))
+    }
+
+    fn update_profile(
+        &self,
+        address: &str,
+        change: impl FnOnce(&mut DeviceProfile),
+    ) -> Result<Vec<RegisteredDevice>, StoneError> {
+        self.update(|devices| {
+            let index = position(devices, address).ok_or(StoneError::DeviceNotFound)?;
+            change(&mut devices[index].profile);
+            Ok(())
+        })
+    }
+
+    /// Moves `addresses` to the front in the given order; the rest keep their relative order.
+    pub(crate) fn reorder(
+        &self,
+        addresses: &[String],
+    ) -> Result<Vec<RegisteredDevice>, StoneError> {
+        self.update(|devices| {
+            let mut ordered = Vec::with_capacity(devices.len());
+            for address in addresses {
+                let index = position(devices, address).ok_or(StoneError::DeviceNotFound)?;
+                ordered.push(devices.remove(index));
+            }
+            ordered.append(devices);
+            *devices = ordered;
+            Ok(())
+        })
+    }
+
+    /// Takes over the list the webview used to keep in `localStorage`. Devices already
+    /// registered win; the rest are appended in their old order.
+    pub(crate) fn import_legacy(
+        &self,
+        legacy: Vec<RegisteredDevice>,
+    ) -> Result<Vec<RegisteredDevice>, StoneError> {
+        self.update(|devices| {
+            for device in legacy {
+                if device.address.trim().is_empty() || position(devices, &device.address).is_some()
+                {
+                    continue;
+                }
+                devices.push(device);
+            }
+            Ok(())
+        })
+    }
+
+    fn update(
+        &self,
+        change: impl FnOnce(&mut Vec<RegisteredDevice>) -> Result<(), StoneError>,
+    ) -> Result<Vec<RegisteredDevice>, StoneError> {
+        let devices = {
+            let mut inner = self
+                .inner
+                .lock()
+                .map_err(|_| StoneError::other("Registry state poisoned"))?;
+            let mut devices = inner.devices.clone();
+            change(&mut devices)?;
+            if devices == inner.devices {
+                return Ok(devices);
+            }
+            if let Some(path) = &inner.path {
+                persist::save(
+                    path,
+                    REGISTRY_VERSION,
+                    &RegistryFile {
+                        devices: devices.clone(),
+                    },
+                )?;
+            }
+            inner.devices = devices.clone();
+            devices
+        };
+        self.announce();
+        Ok(devices)
+    }
+
+    fn announce(&self) {
+        let devices = self.list();
+        // Only the app's registry decides what gets reconnected; a scratch one in a test
+        // must not take over the supervisor.
+        if std::ptr::eq(self, crate::get_registry()) {
+            crate::get_supervisor().set_devices(
+                devices
+                    .iter()
+                    .map(|device| device.address.clone())
+                    .collect(),
+            );
+        }
+        if let Some(app) = APP_HANDLE.get() {
+            let _ = app.emit("registry_changed", devices);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("stone-registry-{}", std::process::id()));
+        let path = dir.join(name).join(REGISTRY_FILE);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+        path
+    }
+
+    fn device(address: &str, name: &str) -> RegisteredDevice {
+        RegisteredDevice {
+            address: address.to_string(),
+            name: name.to_string(),
+            profile: DeviceProfile::default(),
+        }
+    }
+
+    fn addresses(devices: &[RegisteredDevice]) -> Vec<&str> {
+        devices
+            .iter()
+            .map(|device| device.address.as_str())
+            .collect()
+    }
+
+    // Newest first, as `upsert` registers them.
+    fn registry(list: &[&str]) -> DeviceRegistry {
+        let registry = DeviceRegistry::default();
+        for address in list.iter().rev() {
+            registry.upsert(address, "Stone").unwrap();
+        }
+        registry
+    }
+
+    #[test]
+    fn legacy_list_is_appended_without_duplicates() {
+        let path = scratch("legacy");
+        let registry = DeviceRegistry::default();
+        registry.load(path.clone());
+        registry.upsert("AA:00:00:00:00:01", "Kitchen").unwrap();
+
+        let mut legacy = vec![
+            device("aa:00:00:00:00:01", "Old kitchen"),
+            device("AA:00:00:00:00:02", "Porch"),
+            device("  ", "Blank"),
+            device("AA:00:00:00:00:03", "Attic"),
+        ];
+        legacy[1].profile.alias = Some("Front".to_string());
+        let devices = registry.import_legacy(legacy.clone()).unwrap();
+        assert_eq!(
+            addresses(&devices),
+            [
+                "AA:00:00:00:00:01",
+                "AA:00:00:00:00:02",
+                "AA:00:00:00:00:03"
+            ]
+        );
+        assert_eq!(devices[0].name, "Kitchen");
+        assert_eq!(devices[1].profile.alias.as_deref(), Some("Front"));
+
+        // Importing again changes nothing, and what was imported is on disk.
+        assert_eq!(registry.import_legacy(legacy).unwrap(), devices);
+        let reloaded = DeviceRegistry::default();
+        reloaded.load(path);
+        assert_eq!(reloaded.list(), devices);
+    }
+
+    #[test]
+    fn version_1_file_is_upgraded_on_the_next_save() {
+        let path = scratch("v1");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let v1 = serde_json::json!({
+            "version": 1,
+            "devices": [
+                { "address": "AA:00:00:00:00:01", "name": "Kitchen" },
+                { "address": "AA:00:00:00:00:02", "name": "Porch" },
+            ],
+        });
+        std::fs::write(&path, v1.to_string()).unwrap();
+
+        let registry = DeviceRegistry::default();
+        registry.load(path.clone());
+        assert_eq!(
+            registry.list(),
+            [
+                device("AA:00:00:00:00:01", "Kitchen"),
+                device("AA:00:00:00:00:02", "Porch"),
+            ]
+        );
+        registry
+            .set_groups("AA:00:00:00:00:02", vec!["Outside".to_string()])
+            .unwrap();
+
+        let saved: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(saved["version"], REGISTRY_VERSION);
+        assert_eq!(saved["devices"][0]["name"], "Kitchen");
+        assert_eq!(saved["devices"][1]["groups"][0], "Outside");
+    }
+
+    #[test]
+    fn reorder_moves_the_given_addresses_to_the_front() {
+        let registry = registry(&["A", "B", "C", "D"]);
+        let devices = registry
+            .reorder(&["c".to_string(), "A".to_string()])
+            .unwrap();
+        assert_eq!(addresses(&devices), ["C", "A", "B", "D"]);
+        assert_eq!(
+            addresses(&registry.reorder(&[]).unwrap()),
+            ["C", "A", "B", "D"]
+        );
+
+        // An unknown or repeated address leaves the order as it was.
+        for bad in [vec!["B", "E"], vec!["B", "B"]] {
+            let bad: Vec<String> = bad.into_iter().map(String::from).collect();
+            assert!(matches!(
+                registry.reorder(&bad),
+                Err(StoneError::DeviceNotFound)
+            ));
+            assert_eq!(addresses(&registry.list()), ["C", "A", "B", "D"]);
+        }
+    }
+
+    #[test]
+    fn remove_and_rename_find_devices_by_address() {
+        let registry = registry(&["AA:00:00:00:00:01", "AA:00:00:00:00:02"]);
+        let devices = registry.rename("aa:00:00:00:00:02", "Porch").unwrap();
+        assert_eq!(devices[1].name, "Porch");
+        assert!(matches!(
+            registry.rename("AA:00:00:00:00:09", "Attic"),
+            Err(StoneError::DeviceNotFound)
+        ));
+
+        let devices = registry.remove("AA:00:00:00:00:01").unwrap();
+        assert_eq!(addresses(&devices), ["AA:00:00:00:00:02"]);
+        assert!(matches!(
+            registry.remove("AA:00:00:00:00:01"),
+            Err(StoneError::DeviceNotFound)
+        ));
+        assert!(registry.profile("AA:00:00:00:00:01").is_none());
+    }
+
+    #[test]
+    fn resolve_expands_groups_and_passes_addresses_through() {
+        let registry = registry(&["A", "B", "C"]);
+        registry
+            .set_groups("A", vec!["Kitchen".to_string(), " kitchen ".to_string()])
+            .unwrap();
+        registry
+            .set_groups("C", vec!["KITCHEN".to_string()])
+            .unwrap();
+        assert_eq!(registry.profile("A").unwrap().groups, ["Kitchen"]);
+
+        assert_eq!(registry.resolve(" kItChEn "), ["A", "C"]);
+        assert_eq!(registry.resolve("B"), ["B"]);
+        assert_eq!(
+            registry.resolve(" 00:00:5E:00:53:99 "),
+            ["00:00:5E:00:53:99"]
+        );
+        // A name no group uses is taken as an address, which then fails to connect.
+        assert_eq!(registry.resolve("Attic"), ["Attic"]);
+    }
+
+    #[test]
+    fn alias_edits_leave_group_membership_alone() {
+        let registry = registry(&["A", "B"]);
+        registry
+            .set_groups("B", vec!["Outside".to_string()])
+            .unwrap();
+        registry
+            .set_alias("B", Some("Kitchen".to_string()))
+            .unwrap();
+        registry.set_icon("B", Some("speaker".to_string())).unwrap();
+
+        let profile = registry.profile("B").unwrap();
+        assert_eq!(profile.alias.as_deref(), Some("Kitchen"));
+        assert_eq!(profile.groups, ["Outside"]);
+        // The alias is not a group name, so it resolves as an address.
+        assert_eq!(registry.resolve("Kitchen"), ["Kitchen"]);
+        assert_eq!(registry.resolve("outside"), ["B"]);
+
+        registry.set_alias("B", Some("  ".to_string())).unwrap();
+        let profile = registry.profile("B").unwrap();
+        assert_eq!(profile.alias, None);
+        assert_eq!(profile.groups, ["Outside"]);
+    }
+}
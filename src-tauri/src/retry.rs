@@ -0,0 +1,260 @@
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::back_log;
+use crate::backend::BluetoothBackend;
+use crate::cancel::CancelToken;
+use crate::error::{StoneError, IORETURN_NAMES, KINDS};
+
+// Errors worth another connect attempt unless a policy says otherwise. A bare kind covers
+// every error of that kind; `io_bluetooth:<name>` only that IOReturn status, so permanent
+// ones such as kIOReturnNotPermitted fail at once.
+const DEFAULT_RETRY_ON: &[&str] = &[
+    "connect_failed",
+    "busy",
+    "timeout",
+    "io_bluetooth:kIOReturnError",
+    "io_bluetooth:kIOReturnNoDevice",
+    "io_bluetooth:kIOReturnExclusiveAccess",
+    "io_bluetooth:kIOReturnNotOpen",
+    "io_bluetooth:kIOReturnTimeout",
+];
+
+/// How a connect is retried. Delays grow by `multiplier` from `initial_delay_ms` up to
+/// `max_delay_ms`, each spread by +/- `jitter`, and no retry starts past `deadline_ms`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub(crate) struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_delay_ms: u64,
+    pub max_delay_ms: u64,
+    pub multiplier: f64,
+    pub jitter: f64,
+    pub deadline_ms: Option<u64>,
+    pub retry_on: Vec<String>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 8,
+            initial_delay_ms: 1_000,
+            max_delay_ms: 15_000,
+            multiplier: 2.0,
+            jitter: 0.2,
+            deadline_ms: Some(180_000),
+            retry_on: DEFAULT_RETRY_ON
+                .iter()
+                .map(|kind| kind.to_string())
+                .collect(),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub(crate) fn validate(&self) -> Result<(), StoneError> {
+        let invalid = |message: &str| {
+            Err(StoneError::InvalidParameter {
+                message: message.to_string(),
+            })
+        };
+        if self.max_attempts == 0 {
+            return invalid("max_attempts must be at least 1");
+        }
+        if !self.multiplier.is_finite() || self.multiplier < 1.0 {
+            return invalid("multiplier must be at least 1");
+        }
+        if !(0.0..=1.0).contains(&self.jitter) {
+            return invalid("jitter must be between 0 and 1");
+        }
+        if self.max_delay_ms < self.initial_delay_ms {
+            return invalid("max_delay_ms must not be below initial_delay_ms");
+        }
+        if let Some(unknown) = self.retry_on.iter().find(|code| !is_known_code(code)) {
+            return Err(StoneError::InvalidParameter {
+                message: format!("retry_on has unknown error code {:?}", unknown),
+            });
+        }
+        Ok(())
+    }
+
+    pub(crate) fn should_retry(&self, error: &StoneError) -> bool {
+        let code = error.code();
+        self.retry_on
+            .iter()
+            .any(|entry| entry == error.kind() || *entry == code)
+    }
+
+    /// Delay before the attempt following `attempt` (1-based).
+    pub(crate) fn delay(&self, attempt: u32) -> Duration {
+        backoff(
+            self.initial_delay_ms,
+            self.max_delay_ms,
+            self.multiplier,
+            self.jitter,
+            attempt,
+        )
+    }
+}
+
+fn is_known_code(code: &str) -> bool {
+    match code.split_once(':') {
+        Some(("io_bluetooth", name)) => IORETURN_NAMES.iter().any(|(_, known)| *known == name),
+        Some(_) => false,
+        None => KINDS.contains(&code),
+    }
+}
+
+/// Exponential backoff for the `step`th (1-based) wait, capped at `max_ms` and spread by
+/// +/- `jitter`.
+pub(crate) fn backoff(
+    initial_ms: u64,
+    max_ms: u64,
+    multiplier: f64,
+    jitter: f64,
+    step: u32,
+) -> Duration {
+    let exponent = step.saturating_sub(1).min(32) as i32;
+    let base = (initial_ms as f64 * multiplier.powi(exponent)).min(max_ms as f64);
+    let spread = base * jitter * (unit_random() * 2.0 - 1.0);
+    Duration::from_millis((base + spread).max(0.0) as u64)
+}
+
+/// Emitted as `bt_connect_progress` when an attempt starts (`next_retry_at` unset) and when
+/// one failed and another is scheduled.
+#[derive(Serialize, Clone, Debug)]
+pub(crate) struct ConnectProgress {
+    pub address: String,
+    pub attempt: u32,
+    pub max_attempts: u32,
+    pub next_retry_at: Option<u64>,
+    pub error: Option<StoneError>,
+}
+
+/// Runs `connect_device` under `policy` until it succeeds, gives up, or `cancel` fires. A
+/// cancel ends the wait between attempts at once and the running attempt once it settles.
+pub(crate) async fn connect_with_retry(
+    backend: &dyn BluetoothBackend,
+    address: &str,
+    policy: &RetryPolicy,
+    mut report: impl FnMut(ConnectProgress),
+    cancel: &CancelToken,
+) -> Result<(), StoneError> {
+    let deadline = policy
+        .deadline_ms
+        .map(|deadline_ms| Instant::now() + Duration::from_millis(deadline_ms));
+    let mut attempt = 1;
+    loop {
+        report(ConnectProgress {
+            address: address.to_string(),
+            attempt,
+            max_attempts: policy.max_attempts,
+            next_retry_at: None,
+            error: None,
+        });
+        // A running attempt is let finish: dropping it does not stop a platform connect that
+        // runs on its own thread, which could then connect behind the cleanup disconnect.
+        let error = match backend.connect_device(address).await {
+            Ok(()) if cancel.is_cancelled() => return Err(StoneError::ConnectCancelled),
+            Ok(()) => return Ok(()),
+            Err(error) => error,
+        };
+        if cancel.is_cancelled() {
+            return Err(StoneError::ConnectCancelled);
+        }
+        if attempt >= policy.max_attempts || !policy.should_retry(&error) {
+            return Err(error);
+        }
+        let delay = policy.delay(attempt);
+        if deadline.is_some_and(|deadline| Instant::now() + delay > deadline) {
+            return Err(error);
+        }
+        back_log(
+            "RUST",
+            format!(
+                "Retrying connect for {} in {} ms ({}/{}): {}",
+                address,
+                delay.as_millis(),
+                attempt,
+                policy.max_attempts,
+                error
+            ),
+        );
+        report(ConnectProgress {
+            address: address.to_string(),
+            attempt,
+            max_attempts: policy.max_attempts,
+            next_retry_at: Some(unix_millis(SystemTime::now() + delay)),
+            error: Some(error),
+        });
+        cancel.run(tokio::time::sleep(delay)).await?;
+        attempt += 1;
+    }
+}
+
+fn unit_random() -> f64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos(),
+    );
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+pub(crate) fn unix_millis(at: SystemTime) -> u64 {
+    at.duration_since(UNIX_EPOCH)
+        .map(|since| since.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn iobluetooth(name: &str) -> StoneError {
+        StoneError::IoBluetooth {
+            code: 0,
+            name: name.to_string(),
+            context: String::new(),
+        }
+    }
+
+    #[test]
+    fn retries_transient_statuses_only() {
+        let policy = RetryPolicy::default();
+        assert!(policy.should_retry(&iobluetooth("kIOReturnTimeout")));
+        assert!(!policy.should_retry(&iobluetooth("kIOReturnNotPermitted")));
+        assert!(policy.should_retry(&StoneError::ConnectFailed {
+            message: "read failed, socket might closed".to_string()
+        }));
+        assert!(!policy.should_retry(&StoneError::PermissionDenied));
+    }
+
+    #[test]
+    fn bare_kind_covers_every_status() {
+        let policy = RetryPolicy {
+            retry_on: vec!["io_bluetooth".to_string()],
+            ..RetryPolicy::default()
+        };
+        assert!(policy.should_retry(&iobluetooth("kIOReturnNotPermitted")));
+    }
+
+    #[test]
+    fn rejects_unknown_codes() {
+        assert!(RetryPolicy::default().validate().is_ok());
+        for code in ["conect_failed", "io_bluetooth:kIOReturnTimout", "busy:soon"] {
+            let policy = RetryPolicy {
+                retry_on: vec![code.to_string()],
+                ..RetryPolicy::default()
+            };
+            assert!(matches!(
+                policy.validate(),
+                Err(StoneError::InvalidParameter { .. })
+            ));
+        }
+    }
+}
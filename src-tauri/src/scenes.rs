@@ -0,0 +1,614 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+use tauri::Emitter;
+
+use crate::backend::BackendState;
+use crate::broadcast::BroadcastTarget;
+use crate::error::StoneError;
+use crate::pt::{self, LampSettings, LampState, PtCommand};
+use crate::retry::unix_millis;
+use crate::transaction::DEFAULT_REQUEST_TIMEOUT_MS;
+use crate::{back_log, persist, APP_HANDLE};
+
+const SCENES_FILE: &str = "scenes.json";
+const SCENES_VERSION: u32 = 1;
+// A speaker that still reads back differently after this many applies is reported as such.
+const APPLY_ATTEMPTS: u32 = 2;
+
+/// What one speaker was doing when the scene was saved.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub(crate) struct SpeakerState {
+    pub volume: u8,
+    pub lamp: LampState,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct SceneDevice {
+    pub address: String,
+    #[serde(flatten)]
+    pub state: SpeakerState,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct Scene {
+    pub name: String,
+    pub devices: Vec<SceneDevice>,
+    pub saved_at: u64,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub(crate) struct SceneOutcome {
+    address: String,
+    // The speaker read back the scene's state after it was applied.
+    verified: bool,
+    attempts: u32,
+    actual: Option<SpeakerState>,
+    error: Option<StoneError>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub(crate) struct SceneReport {
+    name: String,
+    verified: usize,
+    failed: usize,
+    results: Vec<SceneOutcome>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct ScenesFile {
+    scenes: Vec<Scene>,
+}
+
+#[derive(Default)]
+struct Inner {
+    path: Option<PathBuf>,
+    scenes: Vec<Scene>,
+}
+
+/// Named snapshots of volume and lamp state, saved to `scenes.json` beside the registry and
+/// announced as `scenes_changed`.
+#[derive(Default)]
+pub(crate) struct SceneStore {
+    inner: Mutex<Inner>,
+}
+
+pub(crate) fn path_in(data_dir: &Path) -> PathBuf {
+    data_dir.join(SCENES_FILE)
+}
+
+fn position(scenes: &[Scene], name: &str) -> Option<usize> {
+    scenes
+        .iter()
+        .position(|scene| scene.name.eq_ignore_ascii_case(name.trim()))
+}
+
+async fn read_state(backend: &BackendState, address: &str) -> Result<SpeakerState, StoneError> {
+    let volume: pt::Volume = pt::query(&**backend, address, DEFAULT_REQUEST_TIMEOUT_MS).await?;
+    let lamp: LampState = pt::query(&**backend, address, DEFAULT_REQUEST_TIMEOUT_MS).await?;
+    Ok(SpeakerState {
+        volume: volume.volume,
+        lamp,
+    })
+}
+
+// The color only shows, and so only has to match, on the solid color lamp type.
+fn matches(expected: &SpeakerState, actual: &SpeakerState) -> bool {
+    let (want, got) = (&expected.lamp, &actual.lamp);
+    expected.volume == actual.volume
+        && want.on == got.on
+        && (!want.on
+            || (want.brightness == got.brightness
+                && want.lamp_type == got.lamp_type
+                && (want.lamp_type != pt::SOLID_LAMP_TYPE || want.color == got.color)))
+}
+
+async fn write_state(
+    backend: &BackendState,
+    address: &str,
+    state: &SpeakerState,
+) -> Result<(), StoneError> {
+    let lamp = &state.lamp;
+    let mut commands = vec![PtCommand::SetVolume(state.volume)];
+    if lamp.on {
+        commands.push(PtCommand::RunLamp(LampSettings {
+            brightness: lamp.brightness,
+            lamp_type: lamp.lamp_type,
+            color: lamp.color,
+        }));
+        if lamp.lamp_type == pt::SOLID_LAMP_TYPE {
+            commands.push(PtCommand::SetLampColor(lamp.color));
+        }
+    } else {
+        commands.push(PtCommand::StopLamp);
+    }
+    for command in commands {
+        pt::send(&**backend, address, command, DEFAULT_REQUEST_TIMEOUT_MS).await?;
+    }
+    Ok(())
+}
+
+async fn apply_one(backend: BackendState, device: SceneDevice) -> SceneOutcome {
+    let mut outcome = SceneOutcome {
+        address: device.address.clone(),
+        verified: false,
+        attempts: 0,
+        actual: None,
+        error: None,
+    };
+    while outcome.attempts < APPLY_ATTEMPTS && !outcome.verified {
+        outcome.attempts += 1;
+        let result = match write_state(&backend, &device.address, &device.state).await {
+            Ok(()) => read_state(&backend, &device.address).await,
+            Err(err) => Err(err),
+        };
+        match result {
+            Ok(actual) => {
+                outcome.verified = matches(&device.state, &actual);
+                outcome.actual = Some(actual);
+                outcome.error = None;
+            }
+            Err(err) => outcome.error = Some(err),
+        }
+    }
+    outcome
+}
+
+impl SceneStore {
+    pub(crate) fn load(&self, path: PathBuf) {
+        let ScenesFile { scenes } = persist::load(&path, SCENES_VERSION);
+        if let Ok(mut inner) = self.inner.lock() {
+            inner.path = Some(path);
+            inner.scenes = scenes;
+        }
+        self.announce();
+    }
+
+    pub(crate) fn list(&self) -> Vec<Scene> {
+        self.inner
+            .lock()
+            .map(|inner| inner.scenes.clone())
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<Scene> {
+        let inner = self.inner.lock().ok()?;
+        position(&inner.scenes, name).map(|index| inner.scenes[index].clone())
+    }
+
+    /// Reads the current state of every speaker `target` stands for and saves it as `name`,
+    /// replacing a scene of the same name.
+    pub(crate) async fn capture(
+        &self,
+        name: &str,
+        target: &BroadcastTarget,
+        backend: &BackendState,
+    ) -> Result<Scene, StoneError> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(StoneError::InvalidParameter {
+                message: "Scene name must not be empty".to_string(),
+            });
+        }
+        let addresses = target.addresses();
+        if addresses.is_empty() {
+            return Err(StoneError::InvalidParameter {
+                message: "Scene has no devices".to_string(),
+            });
+        }
+        let tasks: Vec<_> = addresses
+            .into_iter()
+            .map(|address| {
+                let backend = backend.clone();
+                tauri::async_runtime::spawn(async move {
+                    let state = read_state(&backend, &address).await;
+                    (address, state)
+                })
+            })
+            .collect();
+        let mut devices = Vec::with_capacity(tasks.len());
+        for task in tasks {
+            let (address, state) = task.await.map_err(StoneError::other)?;
+            devices.push(SceneDevice {
+                state: state.map_err(|err| {
+                    StoneError::other(format!("Read state of {}: {}", address, err))
+                })?,
+                address,
+            });
+        }
+        let scene = Scene {
+            name: name.to_string(),
+            devices,
+            saved_at: unix_millis(SystemTime::now()),
+        };
+        back_log(
+            "RUST",
+            format!(
+                "Saved scene {} with {} devices",
+                scene.name,
+                scene.devices.len()
+            ),
+        );
+        let saved = scene.clone();
+        self.update(move |scenes| {
+            match position(scenes, &saved.name) {
+                Some(index) => scenes[index] = saved,
+                None => scenes.push(saved),
+            }
+            Ok(())
+        })?;
+        Ok(scene)
+    }
+
+    pub(crate) fn remove(&self, name: &str) -> Result<Vec<Scene>, StoneError> {
+        self.update(|scenes| {
+            let index = position(scenes, name).ok_or_else(|| StoneError::InvalidParameter {
+                message: format!("No scene named {}", name),
+            })?;
+            scenes.remove(index);
+            Ok(())
+        })
+    }
+
+    /// Puts every speaker of scene `name` back the way it was saved, all at once, and reads
+    /// each one back to confirm it took.
+    pub(crate) async fn apply(
+        &self,
+        name: &str,
+        backend: &BackendState,
+    ) -> Result<SceneReport, StoneError> {
+        let scene = self.get(name).ok_or_else(|| StoneError::InvalidParameter {
+            message: format!("No scene named {}", name),
+        })?;
+        back_log(
+            "RUST",
+            format!(
+                "Apply scene {} to {} devices",
+                scene.name,
+                scene.devices.len()
+            ),
+        );
+        // A running effect would paint over the scene's lamps on its next frame.
+        let addresses: Vec<String> = scene
+            .devices
+            .iter()
+            .map(|device| device.address.clone())
+            .collect();
+        crate::get_lamp_effects().release(&addresses);
+        let tasks: Vec<_> = scene
+            .devices
+            .into_iter()
+            .map(|device| {
+                let address = device.address.clone();
+                let task = tauri::async_runtime::spawn(apply_one(backend.clone(), device));
+                (address, task)
+            })
+            .collect();
+        let mut results = Vec::with_capacity(tasks.len());
+        for (address, task) in tasks {
+            results.push(task.await.unwrap_or_else(|err| SceneOutcome {
+                address,
+                verified: false,
+                attempts: 0,
+                actual: None,
+                error: Some(StoneError::other(err)),
+            }));
+        }
+        let verified = results.iter().filter(|outcome| outcome.verified).count();
+        Ok(SceneReport {
+            name: scene.name,
+            verified,
+            failed: results.len() - verified,
+            results,
+        })
+    }
+
+    fn update(
+        &self,
+        change: impl FnOnce(&mut Vec<Scene>) -> Result<(), StoneError>,
+    ) -> Result<Vec<Scene>, StoneError> {
+        let scenes = {
+            let mut inner = self
+                .inner
+                .lock()
+                .map_err(|_| StoneError::other("Scene state poisoned"))?;
+            let mut scenes = inner.scenes.clone();
+            change(&mut scenes)?;
+            if let Some(path) = &inner.path {
+                persist::save(
+                    path,
+                    SCENES_VERSION,
+                    &ScenesFile {
+                        scenes: scenes.clone(),
+                    },
+                )?;
+            }
+            inner.scenes = scenes.clone();
+            scenes
+        };
+        self.announce();
+        Ok(scenes)
+    }
+
+    fn announce(&self) {
+        if let Some(app) = APP_HANDLE.get() {
+            let _ = app.emit("scenes_changed", self.list());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::BluetoothBackend;
+    use crate::gaia::{GaiaFrame, GaiaStatus};
+    use crate::pt::{PtResponse, Rgb, Volume};
+    use crate::{BluetoothDeviceInfo, ConnectionInfo};
+    use async_trait::async_trait;
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    /// Answers PT commands from a state per address. Volume writes are acknowledged but
+    /// dropped while `stubborn` lasts, like a speaker that is busy with something else.
+    #[derive(Default)]
+    struct SceneBackend {
+        speakers: Mutex<HashMap<String, SpeakerState>>,
+        stubborn: AtomicU32,
+        writes: AtomicU32,
+    }
+
+    impl SceneBackend {
+        fn state(&self, address: &str) -> SpeakerState {
+            self.speakers.lock().unwrap()[address]
+        }
+    }
+
+    #[async_trait]
+    impl BluetoothBackend for SceneBackend {
+        fn name(&self) -> &'static str {
+            "scenes"
+        }
+
+        async fn list_devices(&self) -> Result<Vec<BluetoothDeviceInfo>, StoneError> {
+            Ok(Vec::new())
+        }
+
+        async fn scan_unpaired_stone_devices(
+            &self,
+        ) -> Result<Vec<BluetoothDeviceInfo>, StoneError> {
+            Ok(Vec::new())
+        }
+
+        async fn get_connection_infos(&self) -> Result<Vec<ConnectionInfo>, StoneError> {
+            Ok(Vec::new())
+        }
+
+        async fn connect_device(&self, _address: &str) -> Result<(), StoneError> {
+            Ok(())
+        }
+
+        async fn disconnect_device(&self, _address: &str) -> Result<(), StoneError> {
+            Ok(())
+        }
+
+        async fn write_frame(&self, address: &str, frame: &[u8]) -> Result<(), StoneError> {
+            self.writes.fetch_add(1, Ordering::SeqCst);
+            let (request, _) = GaiaFrame::decode(frame).map_err(StoneError::other)?;
+            let command = PtCommand::parse(request.command, &request.payload).unwrap();
+            let payload = {
+                let mut speakers = self.speakers.lock().unwrap();
+                let state = speakers.get_mut(address).ok_or(StoneError::NotConnected)?;
+                match command {
+                    PtCommand::SetVolume(volume) => {
+                        let dropped = self
+                            .stubborn
+                            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |left| {
+                                left.checked_sub(1)
+                            })
+                            .is_ok();
+                        if !dropped {
+                            state.volume = volume;
+                        }
+                        Vec::new()
+                    }
+                    PtCommand::RunLamp(settings) => {
+                        state.lamp = LampState {
+                            on: true,
+                            brightness: settings.brightness,
+                            lamp_type: settings.lamp_type,
+                            color: settings.color,
+                        };
+                        Vec::new()
+                    }
+                    PtCommand::SetLampColor(color) => {
+                        state.lamp.color = color;
+                        Vec::new()
+                    }
+                    PtCommand::StopLamp => {
+                        state.lamp.on = false;
+                        Vec::new()
+                    }
+                    PtCommand::GetVolume => Volume {
+                        volume: state.volume,
+                    }
+                    .encode(),
+                    PtCommand::GetLampState => state.lamp.encode(),
+                    _ => Vec::new(),
+                }
+            };
+            let ack = GaiaFrame::ack(
+                request.vendor_id,
+                request.command,
+                GaiaStatus::Success.as_byte(),
+                payload,
+            );
+            let (address, bytes) = (address.to_string(), ack.encode().unwrap());
+            tauri::async_runtime::spawn(async move {
+                crate::handle_backend_data(&address, &bytes);
+            });
+            Ok(())
+        }
+    }
+
+    fn speaker(volume: u8, on: bool, lamp_type: u8, color: Rgb) -> SpeakerState {
+        SpeakerState {
+            volume,
+            lamp: LampState {
+                on,
+                brightness: 60,
+                lamp_type,
+                color,
+            },
+        }
+    }
+
+    fn with_speakers(speakers: &[(&str, SpeakerState)]) -> (Arc<SceneBackend>, BackendState) {
+        let stub = Arc::new(SceneBackend::default());
+        stub.speakers.lock().unwrap().extend(
+            speakers
+                .iter()
+                .map(|(address, state)| (address.to_string(), *state)),
+        );
+        let backend = BackendState(stub.clone());
+        (stub, backend)
+    }
+
+    #[test]
+    fn matches_ignores_what_the_lamp_does_not_show() {
+        let red = Rgb::new(255, 0, 0);
+        let blue = Rgb::new(0, 0, 255);
+        let solid = speaker(10, true, pt::SOLID_LAMP_TYPE, red);
+        assert!(matches(&solid, &solid));
+        assert!(!matches(
+            &solid,
+            &speaker(10, true, pt::SOLID_LAMP_TYPE, blue)
+        ));
+        assert!(!matches(
+            &solid,
+            &speaker(11, true, pt::SOLID_LAMP_TYPE, red)
+        ));
+        assert!(!matches(
+            &solid,
+            &speaker(10, false, pt::SOLID_LAMP_TYPE, red)
+        ));
+        assert!(!matches(&solid, &speaker(10, true, 3, red)));
+
+        // Other lamp types do not show the color.
+        assert!(matches(
+            &speaker(10, true, 3, red),
+            &speaker(10, true, 3, blue)
+        ));
+
+        // Nothing but the volume shows while the lamp is off.
+        let mut dimmed = speaker(10, false, 3, blue);
+        dimmed.lamp.brightness = 5;
+        assert!(matches(
+            &speaker(10, false, pt::SOLID_LAMP_TYPE, red),
+            &dimmed
+        ));
+        assert!(!matches(
+            &speaker(10, false, 1, red),
+            &speaker(9, false, 1, red)
+        ));
+    }
+
+    #[test]
+    fn apply_retries_until_the_speaker_reads_back_the_scene() {
+        let address = "00:00:5E:00:53:80";
+        let wanted = SceneDevice {
+            address: address.to_string(),
+            state: speaker(20, true, pt::SOLID_LAMP_TYPE, Rgb::new(0, 255, 0)),
+        };
+        let start = speaker(5, false, 1, Rgb::WHITE);
+        tauri::async_runtime::block_on(async {
+            // Dropped once: the second apply takes.
+            let (stub, backend) = with_speakers(&[(address, start)]);
+            stub.stubborn.store(1, Ordering::SeqCst);
+            let outcome = apply_one(backend, wanted.clone()).await;
+            assert!(outcome.verified);
+            assert_eq!(outcome.attempts, 2);
+            assert_eq!(outcome.actual, Some(wanted.state));
+            assert!(outcome.error.is_none());
+            assert_eq!(stub.state(address), wanted.state);
+
+            // Dropped every time: reported with what the speaker reads back instead.
+            let (stub, backend) = with_speakers(&[(address, start)]);
+            stub.stubborn.store(u32::MAX, Ordering::SeqCst);
+            let outcome = apply_one(backend, wanted.clone()).await;
+            assert!(!outcome.verified);
+            assert_eq!(outcome.attempts, APPLY_ATTEMPTS);
+            assert_eq!(outcome.actual.unwrap().volume, 5);
+            assert!(outcome.error.is_none());
+
+            // Gone: every attempt fails before it is read back.
+            let (stub, backend) = with_speakers(&[]);
+            let outcome = apply_one(backend, wanted.clone()).await;
+            assert!(!outcome.verified);
+            assert_eq!(outcome.attempts, APPLY_ATTEMPTS);
+            assert!(outcome.actual.is_none());
+            assert!(matches!(outcome.error, Some(StoneError::NotConnected)));
+            assert_eq!(stub.writes.load(Ordering::SeqCst), APPLY_ATTEMPTS);
+        });
+    }
+
+    #[test]
+    fn capture_replaces_a_scene_whatever_its_case() {
+        let address = "00:00:5E:00:53:81";
+        let (stub, backend) = with_speakers(&[(address, speaker(7, false, 1, Rgb::WHITE))]);
+        let store = SceneStore::default();
+        let target = BroadcastTarget::Addresses(vec![address.to_string()]);
+        tauri::async_runtime::block_on(async {
+            store.capture("Evening", &target, &backend).await.unwrap();
+            stub.speakers
+                .lock()
+                .unwrap()
+                .get_mut(address)
+                .unwrap()
+                .volume = 3;
+            let scene = store.capture(" evening ", &target, &backend).await.unwrap();
+            assert_eq!(scene.name, "evening");
+
+            let scenes = store.list();
+            assert_eq!(scenes.len(), 1);
+            assert_eq!(scenes[0].devices[0].state.volume, 3);
+            assert_eq!(store.get("EVENING"), Some(scene));
+            assert!(matches!(
+                store.capture("  ", &target, &backend).await,
+                Err(StoneError::InvalidParameter { .. })
+            ));
+        });
+    }
+
+    #[test]
+    fn failed_save_keeps_the_scenes_as_they_were() {
+        let dir = std::env::temp_dir().join(format!("stone-scenes-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // A file where the directory should be, so every save fails.
+        let blocker = dir.join("blocker");
+        std::fs::write(&blocker, b"").unwrap();
+
+        let address = "00:00:5E:00:53:82";
+        let (_, backend) = with_speakers(&[(address, speaker(7, false, 1, Rgb::WHITE))]);
+        let store = SceneStore::default();
+        let kept = Scene {
+            name: "Morning".to_string(),
+            devices: Vec::new(),
+            saved_at: 1,
+        };
+        {
+            let mut inner = store.inner.lock().unwrap();
+            inner.path = Some(path_in(&blocker));
+            inner.scenes = vec![kept.clone()];
+        }
+        assert!(matches!(
+            store.remove("morning"),
+            Err(StoneError::Io { .. })
+        ));
+        let target = BroadcastTarget::Addresses(vec![address.to_string()]);
+        let captured = tauri::async_runtime::block_on(store.capture("Night", &target, &backend));
+        assert!(matches!(captured, Err(StoneError::Io { .. })));
+        assert_eq!(store.list(), [kept]);
+    }
+}
@@ -0,0 +1,640 @@
+use chrono::{Local, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tauri::Emitter;
+
+use crate::backend::BackendState;
+use crate::broadcast;
+use crate::cancel::CancelToken;
+use crate::cron::CronExpr;
+use crate::error::StoneError;
+use crate::jobs::{now, JobList, JobStore};
+use crate::pt::{self, LampSettings, PtCommand};
+use crate::transaction::DEFAULT_REQUEST_TIMEOUT_MS;
+use crate::{back_log, APP_HANDLE};
+
+const SCHEDULE_FILE: &str = "schedule.json";
+const SCHEDULE_VERSION: u32 = 1;
+// One-shot jobs that came due while the app was closed still run if they are this fresh.
+const MISSED_GRACE_MS: u64 = 10 * 60 * 1000;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum Schedule {
+    Once { at: u64 },
+    Cron { expression: String },
+}
+
+impl Schedule {
+    fn validate(&self, now: u64) -> Result<(), StoneError> {
+        match self {
+            Self::Once { at } if *at <= now => Err(StoneError::InvalidParameter {
+                message: "Scheduled time is in the past".to_string(),
+            }),
+            Self::Once { .. } => Ok(()),
+            Self::Cron { expression } => {
+                CronExpr::parse(expression)?;
+                // `0 0 30 2 *` parses, but no date ever matches it.
+                match self.next_after(now) {
+                    Some(_) => Ok(()),
+                    None => Err(StoneError::InvalidParameter {
+                        message: format!("Cron expression \"{}\" never runs", expression),
+                    }),
+                }
+            }
+        }
+    }
+
+    fn next_after(&self, after: u64) -> Option<u64> {
+        match self {
+            Self::Once { at } => (*at > after).then_some(*at),
+            Self::Cron { expression } => {
+                let after = Local.timestamp_millis_opt(after as i64).single()?;
+                let next = CronExpr::parse(expression).ok()?.next_after(after)?;
+                Some(next.timestamp_millis() as u64)
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub(crate) enum JobAction {
+    StopLamp,
+    RunLamp(LampSettings),
+    SetVolume { volume: u8 },
+    // Steps the volume one unit at a time from wherever each speaker is.
+    FadeVolume { volume: u8, duration_ms: u64 },
+}
+
+impl JobAction {
+    fn validate(&self) -> Result<(), StoneError> {
+        let command = match *self {
+            Self::StopLamp => PtCommand::StopLamp,
+            Self::RunLamp(settings) => PtCommand::RunLamp(settings),
+            Self::SetVolume { volume } | Self::FadeVolume { volume, .. } => {
+                PtCommand::SetVolume(volume)
+            }
+        };
+        Ok(command.validate()?)
+    }
+}
+
+/// What to run, where and when. `target` is an address or a group name, resolved when the
+/// job fires.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct JobSpec {
+    #[serde(default)]
+    pub name: Option<String>,
+    pub target: String,
+    pub schedule: Schedule,
+    pub action: JobAction,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct JobRun {
+    at: u64,
+    succeeded: usize,
+    failed: usize,
+    // Only the message is kept, so the file stays readable by older and newer builds.
+    error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct Job {
+    id: u64,
+    #[serde(flatten)]
+    spec: JobSpec,
+    paused: bool,
+    next_run_at: Option<u64>,
+    last_run: Option<JobRun>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct ScheduleFile {
+    next_id: u64,
+    jobs: Vec<Job>,
+}
+
+#[derive(Default)]
+struct Jobs {
+    next_id: u64,
+    jobs: Vec<Job>,
+    // Runs in progress by job id, with the time each one started.
+    running: HashMap<u64, (u64, CancelToken)>,
+}
+
+impl JobList for Jobs {
+    type File = ScheduleFile;
+    const VERSION: u32 = SCHEDULE_VERSION;
+
+    fn saved(&self) -> ScheduleFile {
+        ScheduleFile {
+            next_id: self.next_id,
+            jobs: self.jobs.clone(),
+        }
+    }
+
+    fn restore(&mut self, file: ScheduleFile) {
+        self.next_id = file.next_id;
+        self.jobs = file.jobs;
+    }
+}
+
+/// Timed PT actions, kept in `schedule.json` and announced as `schedule_changed`. One runner
+/// task sleeps until the earliest job is due.
+#[derive(Default)]
+pub(crate) struct Scheduler {
+    jobs: JobStore<Jobs>,
+}
+
+pub(crate) fn path_in(data_dir: &Path) -> PathBuf {
+    data_dir.join(SCHEDULE_FILE)
+}
+
+fn job_not_found(id: u64) -> StoneError {
+    StoneError::InvalidParameter {
+        message: format!("No scheduled job {}", id),
+    }
+}
+
+impl Scheduler {
+    /// Loads `path`, works out when every job runs next and starts the runner.
+    pub(crate) fn load(&self, path: PathBuf, backend: &BackendState) {
+        let now = now();
+        let source = path.display().to_string();
+        self.jobs.load(path, |list| {
+            for job in &mut list.jobs {
+                // Missed cron runs are skipped; a one-shot job still runs if it is only just late.
+                job.next_run_at = match &job.spec.schedule {
+                    _ if job.paused => None,
+                    Schedule::Once { .. } => {
+                        job.next_run_at.filter(|at| at + MISSED_GRACE_MS > now)
+                    }
+                    schedule => schedule.next_after(now),
+                };
+            }
+        });
+        back_log(
+            "RUST",
+            format!(
+                "Loaded {} scheduled jobs from {}",
+                self.list().len(),
+                source
+            ),
+        );
+        self.announce();
+        let backend = backend.clone();
+        self.jobs
+            .start(async move { crate::get_scheduler().run(backend).await });
+    }
+
+    pub(crate) fn list(&self) -> Vec<Job> {
+        self.jobs.with(|list| list.jobs.clone()).unwrap_or_default()
+    }
+
+    pub(crate) fn add(&self, spec: JobSpec) -> Result<Job, StoneError> {
+        if spec.target.trim().is_empty() {
+            return Err(StoneError::InvalidAddress);
+        }
+        let now = now();
+        spec.schedule.validate(now)?;
+        spec.action.validate()?;
+        let job = self.update(|list| {
+            list.next_id += 1;
+            let job = Job {
+                id: list.next_id,
+                next_run_at: spec.schedule.next_after(now),
+                spec,
+                paused: false,
+                last_run: None,
+            };
+            list.jobs.push(job.clone());
+            Ok(job)
+        })?;
+        back_log(
+            "RUST",
+            format!("Scheduled job {} ({:?})", job.id, job.spec.action),
+        );
+        Ok(job)
+    }
+
+    /// Pausing also stops a run in progress; resuming schedules from now on.
+    pub(crate) fn set_paused(&self, id: u64, paused: bool) -> Result<Job, StoneError> {
+        self.update(|list| {
+            let job = list
+                .jobs
+                .iter_mut()
+                .find(|job| job.id == id)
+                .ok_or_else(|| job_not_found(id))?;
+            job.paused = paused;
+            job.next_run_at = if paused {
+                None
+            } else {
+                job.spec.schedule.next_after(now())
+            };
+            let job = job.clone();
+            if paused {
+                if let Some((_, stop)) = list.running.remove(&id) {
+                    stop.cancel();
+                }
+            }
+            Ok(job)
+        })
+    }
+
+    pub(crate) fn remove(&self, id: u64) -> Result<Vec<Job>, StoneError> {
+        self.update(|list| {
+            let index = list
+                .jobs
+                .iter()
+                .position(|job| job.id == id)
+                .ok_or_else(|| job_not_found(id))?;
+            list.jobs.remove(index);
+            if let Some((_, stop)) = list.running.remove(&id) {
+                stop.cancel();
+            }
+            Ok(list.jobs.clone())
+        })
+    }
+
+    fn update<T>(
+        &self,
+        change: impl FnOnce(&mut Jobs) -> Result<T, StoneError>,
+    ) -> Result<T, StoneError> {
+        let result = self.jobs.update(change)?;
+        self.announce();
+        Ok(result)
+    }
+
+    fn announce(&self) {
+        if let Some(app) = APP_HANDLE.get() {
+            let _ = app.emit("schedule_changed", self.list());
+        }
+    }
+
+    /// Marks every job due at `now` as started, moves it to its next time and hands it out
+    /// with the token that stops it.
+    fn take_due(&self, now: u64) -> Vec<(Job, CancelToken)> {
+        let is_due = |job: &Job| !job.paused && job.next_run_at.is_some_and(|at| at <= now);
+        let due = self.jobs.take_due(
+            |list| list.jobs.iter().any(is_due),
+            |list| {
+                let Jobs { jobs, running, .. } = list;
+                let mut due = Vec::new();
+                for job in jobs.iter_mut().filter(|job| is_due(job)) {
+                    job.next_run_at = job.spec.schedule.next_after(now);
+                    let stop = CancelToken::new();
+                    if let Some((_, previous)) = running.insert(job.id, (now, stop.clone())) {
+                        previous.cancel();
+                    }
+                    due.push((job.clone(), stop));
+                }
+                due
+            },
+        );
+        let Some(due) = due else {
+            return Vec::new();
+        };
+        self.announce();
+        due
+    }
+
+    fn next_due(list: &Jobs) -> Option<u64> {
+        list.jobs.iter().filter_map(|job| job.next_run_at).min()
+    }
+
+    fn finished(&self, id: u64, run: JobRun) {
+        let _ = self.update(|list| {
+            if list
+                .running
+                .get(&id)
+                .is_some_and(|(started, _)| *started == run.at)
+            {
+                list.running.remove(&id);
+            }
+            if let Some(job) = list.jobs.iter_mut().find(|job| job.id == id) {
+                job.last_run = Some(run);
+            }
+            Ok(())
+        });
+    }
+
+    async fn run(&self, backend: BackendState) {
+        let tick = |now| {
+            for (job, stop) in self.take_due(now) {
+                let backend = backend.clone();
+                tauri::async_runtime::spawn(async move {
+                    let run = execute(&job, now, &backend, &stop).await;
+                    crate::get_scheduler().finished(job.id, run);
+                });
+            }
+        };
+        self.jobs.run(tick, Self::next_due).await;
+    }
+}
+
+/// The volumes a fade passes through, one unit apart, and the wait before each.
+fn fade_steps(from: u8, to: u8, duration_ms: u64) -> (Duration, Vec<u8>) {
+    let steps: Vec<u8> = if to < from {
+        (to..from).rev().collect()
+    } else {
+        (from..to).map(|volume| volume + 1).collect()
+    };
+    let interval = duration_ms / steps.len().max(1) as u64;
+    (Duration::from_millis(interval), steps)
+}
+
+async fn fade_volume(
+    backend: BackendState,
+    address: String,
+    volume: u8,
+    duration_ms: u64,
+    stop: CancelToken,
+) -> Result<(), StoneError> {
+    let current: pt::Volume = pt::query(&*backend, &address, DEFAULT_REQUEST_TIMEOUT_MS).await?;
+    let (interval, steps) = fade_steps(current.volume, volume, duration_ms);
+    for next in steps {
+        stop.run(tokio::time::sleep(interval)).await?;
+        pt::send(
+            &*backend,
+            &address,
+            PtCommand::SetVolume(next),
+            DEFAULT_REQUEST_TIMEOUT_MS,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+async fn execute(job: &Job, at: u64, backend: &BackendState, stop: &CancelToken) -> JobRun {
+    let addresses = crate::get_registry().resolve(&job.spec.target);
+    back_log(
+        "RUST",
+        format!(
+            "Run scheduled job {} ({:?}) on {} devices",
+            job.id,
+            job.spec.action,
+            addresses.len()
+        ),
+    );
+    let command = match job.spec.action {
+        JobAction::StopLamp => PtCommand::StopLamp,
+        JobAction::RunLamp(settings) => PtCommand::RunLamp(settings),
+        JobAction::SetVolume { volume } => PtCommand::SetVolume(volume),
+        JobAction::FadeVolume {
+            volume,
+            duration_ms,
+        } => {
+            let tasks: Vec<_> = addresses
+                .into_iter()
+                .map(|address| {
+                    tauri::async_runtime::spawn(fade_volume(
+                        backend.clone(),
+                        address,
+                        volume,
+                        duration_ms,
+                        stop.clone(),
+                    ))
+                })
+                .collect();
+            let mut run = JobRun {
+                at,
+                succeeded: 0,
+                failed: 0,
+                error: None,
+            };
+            for task in tasks {
+                match task
+                    .await
+                    .map_err(StoneError::other)
+                    .and_then(|result| result)
+                {
+                    Ok(()) => run.succeeded += 1,
+                    Err(err) => {
+                        run.failed += 1;
+                        run.error.get_or_insert(err.to_string());
+                    }
+                }
+            }
+            return run;
+        }
+    };
+    if matches!(command, PtCommand::StopLamp | PtCommand::RunLamp(_)) {
+        crate::get_lamp_effects().release(&addresses);
+    }
+    let report = broadcast::send_to(
+        backend,
+        &addresses,
+        pt::PT_VENDOR_ID,
+        command.id(),
+        &command.payload(),
+        DEFAULT_REQUEST_TIMEOUT_MS,
+    )
+    .await;
+    JobRun {
+        at,
+        succeeded: report.succeeded,
+        failed: report.failed,
+        error: report
+            .results
+            .into_iter()
+            .find_map(|outcome| outcome.error)
+            .map(|err| err.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::tests::{backend, StubBackend};
+    use crate::persist;
+    use std::sync::Arc;
+
+    const MINUTE_MS: u64 = 60_000;
+
+    fn job(id: u64, schedule: Schedule, next_run_at: Option<u64>) -> Job {
+        Job {
+            id,
+            spec: JobSpec {
+                name: None,
+                target: "00:00:5E:00:53:90".to_string(),
+                schedule,
+                action: JobAction::SetVolume { volume: 10 },
+            },
+            paused: false,
+            next_run_at,
+            last_run: None,
+        }
+    }
+
+    fn hourly() -> Schedule {
+        Schedule::Cron {
+            expression: "0 * * * *".to_string(),
+        }
+    }
+
+    fn yearly() -> Schedule {
+        Schedule::Cron {
+            expression: "0 0 1 1 *".to_string(),
+        }
+    }
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("stone-schedule-{}", std::process::id()));
+        let dir = dir.join(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        path_in(&dir)
+    }
+
+    fn with_jobs(jobs: Vec<Job>) -> Scheduler {
+        let scheduler = Scheduler::default();
+        scheduler.jobs.with(|list| {
+            list.next_id = jobs.len() as u64;
+            list.jobs = jobs;
+        });
+        scheduler
+    }
+
+    #[test]
+    fn impossible_and_past_schedules_are_rejected() {
+        let now = now();
+        let cron = |expression: &str| Schedule::Cron {
+            expression: expression.to_string(),
+        };
+        assert!(cron("0 0 30 2 *").validate(now).is_err());
+        assert!(cron("0 0 31 4,6,9,11 *").validate(now).is_err());
+        assert!(cron("0 0 31 4 1").validate(now).is_ok());
+        assert!(cron("0 0 29 2 *").validate(now).is_ok());
+        assert!(cron("0 0 * *").validate(now).is_err());
+        assert!(Schedule::Once { at: now }.validate(now).is_err());
+        assert!(Schedule::Once { at: now + 1 }.validate(now).is_ok());
+    }
+
+    #[test]
+    fn take_due_hands_out_each_due_job_once() {
+        let now = now();
+        let mut paused = job(3, hourly(), None);
+        paused.paused = true;
+        let scheduler = with_jobs(vec![
+            job(1, Schedule::Once { at: now - 1_000 }, Some(now - 1_000)),
+            job(2, yearly(), Some(now)),
+            paused,
+            job(
+                4,
+                Schedule::Once {
+                    at: now + MINUTE_MS,
+                },
+                Some(now + MINUTE_MS),
+            ),
+        ]);
+
+        let due = scheduler.take_due(now);
+        let ids: Vec<u64> = due.iter().map(|(job, _)| job.id).collect();
+        assert_eq!(ids, [1, 2]);
+        let jobs = scheduler.list();
+        assert_eq!(jobs[0].next_run_at, None);
+        assert!(jobs[1].next_run_at.is_some_and(|at| at > now));
+        assert_eq!(jobs[3].next_run_at, Some(now + MINUTE_MS));
+        assert!(scheduler.take_due(now).is_empty());
+        assert_eq!(
+            scheduler.jobs.with(|list| Scheduler::next_due(list)),
+            Some(Some(now + MINUTE_MS))
+        );
+
+        // A job that comes due again while its last run goes on stops that run.
+        let later = now + MINUTE_MS;
+        let again = scheduler.take_due(later);
+        assert_eq!(again.len(), 1);
+        assert!(!due[1].1.is_cancelled());
+        scheduler
+            .jobs
+            .with(|list| list.jobs[1].next_run_at = Some(later));
+        scheduler.take_due(later);
+        assert!(due[1].1.is_cancelled());
+    }
+
+    #[test]
+    fn load_keeps_one_shot_jobs_only_within_the_grace() {
+        let path = scratch("load");
+        let now = now();
+        let mut paused = job(5, hourly(), Some(now));
+        paused.paused = true;
+        let file = ScheduleFile {
+            next_id: 5,
+            jobs: vec![
+                job(
+                    1,
+                    Schedule::Once {
+                        at: now - MINUTE_MS,
+                    },
+                    Some(now - MINUTE_MS),
+                ),
+                job(
+                    2,
+                    Schedule::Once { at: now },
+                    Some(now - MISSED_GRACE_MS - 1),
+                ),
+                job(3, Schedule::Once { at: now }, None),
+                job(4, hourly(), Some(now - 3 * MINUTE_MS * 60)),
+                paused,
+            ],
+        };
+        persist::save(&path, SCHEDULE_VERSION, &file).unwrap();
+
+        let scheduler = Scheduler::default();
+        // Keeps the runner from starting.
+        scheduler.jobs.start(async {});
+        scheduler.load(path, &backend(&Arc::new(StubBackend::default())));
+        let next: Vec<Option<u64>> = scheduler.list().iter().map(|job| job.next_run_at).collect();
+        assert_eq!(next[0], Some(now - MINUTE_MS));
+        assert_eq!(next[1..3], [None, None]);
+        assert!(next[3].is_some_and(|at| at > now && at <= now + 60 * MINUTE_MS));
+        assert_eq!(next[4], None);
+
+        let added = scheduler.add(job(0, hourly(), None).spec).unwrap();
+        assert_eq!(added.id, 6);
+    }
+
+    #[test]
+    fn failed_save_rolls_the_change_back() {
+        let dir = std::env::temp_dir().join(format!("stone-schedule-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // A file where the directory should be, so every save fails.
+        let blocker = dir.join("blocker");
+        std::fs::write(&blocker, b"").unwrap();
+
+        let now = now();
+        let scheduler = Scheduler::default();
+        scheduler.jobs.load(path_in(&blocker), |list| {
+            list.next_id = 1;
+            list.jobs = vec![job(1, hourly(), Some(now + MINUTE_MS))];
+        });
+        let before = scheduler.list();
+
+        assert!(matches!(
+            scheduler.add(job(0, hourly(), None).spec),
+            Err(StoneError::Io { .. })
+        ));
+        assert!(scheduler.set_paused(1, true).is_err());
+        assert!(scheduler.remove(1).is_err());
+        assert_eq!(scheduler.list(), before);
+        assert_eq!(scheduler.jobs.with(|list| list.next_id), Some(1));
+    }
+
+    #[test]
+    fn fades_step_one_unit_at_a_time() {
+        assert_eq!(
+            fade_steps(10, 14, 2_000),
+            (Duration::from_millis(500), vec![11, 12, 13, 14])
+        );
+        assert_eq!(
+            fade_steps(14, 11, 3_000),
+            (Duration::from_millis(1_000), vec![13, 12, 11])
+        );
+        assert_eq!(fade_steps(7, 7, 1_000).1, Vec::<u8>::new());
+        assert_eq!(fade_steps(u8::MAX - 1, u8::MAX, 0).1, [u8::MAX]);
+    }
+}
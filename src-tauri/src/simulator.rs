@@ -0,0 +1,503 @@
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::backend::BluetoothBackend;
+use crate::error::StoneError;
+use crate::gaia::{GaiaFrame, GaiaStatus};
+use crate::pt::{
+    self, BatteryStep, DcState, DeviceName, Firmware, LampState, Mac, PtCommand, PtError,
+    PtResponse, Rgb, Rssi, Volume, WheelCount,
+};
+use crate::registry::DeviceProfile;
+use crate::{
+    back_log, emit_backend_device_event, handle_backend_data, BluetoothDeviceInfo, ConnectionInfo,
+};
+
+// Set to "simulator" to run the app against in-process STONE speakers.
+pub(crate) const BACKEND_ENV: &str = "STONE_BACKEND";
+
+const ACK_LATENCY_MS: u64 = 30;
+const CONNECT_LATENCY_MS: u64 = 400;
+const SCAN_LATENCY_MS: u64 = 1_500;
+const TICK_INTERVAL_MS: u64 = 10_000;
+const BATTERY_DRAIN_PER_TICK: u8 = 1;
+const BATTERY_CHARGE_PER_TICK: u8 = 3;
+const BATTERY_LOW_LEVEL: u8 = 5;
+
+const DC_UNPLUGGED: u8 = 0;
+
+pub(crate) fn is_requested() -> bool {
+    std::env::var(BACKEND_ENV)
+        .map(|value| value.eq_ignore_ascii_case("simulator"))
+        .unwrap_or(false)
+}
+
+struct SimulatedSpeaker {
+    name: String,
+    address: String,
+    firmware: String,
+    paired: bool,
+    connected: bool,
+    volume: u8,
+    lamp: LampState,
+    battery_level: u8,
+    dc_state: u8,
+    rssi: i8,
+    wheel_count: i32,
+}
+
+impl SimulatedSpeaker {
+    fn new(name: &str, address: &str, paired: bool) -> Self {
+        Self {
+            name: name.to_string(),
+            address: address.to_string(),
+            firmware: "1.0.0-sim".to_string(),
+            paired,
+            connected: false,
+            volume: 12,
+            lamp: LampState {
+                on: false,
+                brightness: 50,
+                lamp_type: 1,
+                color: Rgb::WHITE,
+            },
+            battery_level: 80,
+            dc_state: DC_UNPLUGGED,
+            rssi: -48,
+            wheel_count: 0,
+        }
+    }
+
+    fn battery(&self) -> BatteryStep {
+        BatteryStep::from_level(self.battery_level)
+    }
+
+    fn apply(&mut self, command: PtCommand) -> Vec<u8> {
+        match command {
+            PtCommand::SetVolume(volume) => self.volume = volume,
+            PtCommand::SetLampBrightness(brightness) => self.lamp.brightness = brightness,
+            PtCommand::SetLampType(lamp_type) => self.lamp.lamp_type = lamp_type,
+            PtCommand::SetLampColor(color) => self.lamp.color = color,
+            PtCommand::RunLamp(settings) => {
+                self.lamp = LampState {
+                    on: true,
+                    brightness: settings.brightness,
+                    lamp_type: settings.lamp_type,
+                    color: settings.color,
+                }
+            }
+            PtCommand::StopLamp => self.lamp.on = false,
+            PtCommand::GetVolume => {
+                return Volume {
+                    volume: self.volume,
+                }
+                .encode()
+            }
+            PtCommand::GetLampState => return self.lamp.encode(),
+            PtCommand::GetDeviceName => return DeviceName(self.name.clone()).encode(),
+            PtCommand::GetFirmware => return Firmware(self.firmware.clone()).encode(),
+            PtCommand::GetMac => return Mac(self.address.clone()).encode(),
+            PtCommand::GetRssi => return Rssi(self.rssi).encode(),
+            PtCommand::GetBatteryStep => return self.battery().encode(),
+            PtCommand::GetDcState => {
+                return DcState {
+                    state: self.dc_state,
+                }
+                .encode()
+            }
+            PtCommand::GetWheelCount => return WheelCount(self.wheel_count).encode(),
+        }
+        Vec::new()
+    }
+
+    fn handle(&mut self, request: &GaiaFrame) -> GaiaFrame {
+        let (status, payload) = if request.vendor_id != pt::PT_VENDOR_ID {
+            (GaiaStatus::NotSupported, Vec::new())
+        } else {
+            match PtCommand::parse(request.command, &request.payload) {
+                Ok(command) => (GaiaStatus::Success, self.apply(command)),
+                Err(PtError::UnknownCommand(_)) => (GaiaStatus::NotSupported, Vec::new()),
+                Err(_) => (GaiaStatus::InvalidParameter, Vec::new()),
+            }
+        };
+        GaiaFrame::ack(
+            request.vendor_id,
+            request.command,
+            status.as_byte(),
+            payload,
+        )
+    }
+
+    // The frontend drops the first payload byte of every PT packet, so notifications carry a
+    // leading zero just like ACKs carry their status.
+    fn notification<R: PtResponse>(report: &R) -> GaiaFrame {
+        let mut payload = vec![0];
+        payload.extend(report.encode());
+        GaiaFrame::new(pt::PT_VENDOR_ID, R::QUERY.id(), payload)
+    }
+
+    fn tick(&mut self) -> Vec<GaiaFrame> {
+        let previous_step = self.battery().step;
+        let previous_dc = self.dc_state;
+
+        match self.dc_state {
+            pt::DC_CHARGING => {
+                self.battery_level = self
+                    .battery_level
+                    .saturating_add(BATTERY_CHARGE_PER_TICK)
+                    .min(100);
+                if self.battery_level == 100 {
+                    self.dc_state = pt::DC_FULL;
+                }
+            }
+            pt::DC_FULL => self.dc_state = DC_UNPLUGGED,
+            _ => {
+                self.battery_level = self.battery_level.saturating_sub(BATTERY_DRAIN_PER_TICK);
+                if self.battery_level <= BATTERY_LOW_LEVEL {
+                    self.dc_state = pt::DC_CHARGING;
+                }
+            }
+        }
+        self.rssi = (self.rssi + if self.wheel_count % 2 == 0 { 1 } else { -1 }).clamp(-70, -40);
+        self.wheel_count = self.wheel_count.wrapping_add(1);
+
+        let mut notifications = Vec::new();
+        if self.battery().step != previous_step {
+            notifications.push(Self::notification(&self.battery()));
+        }
+        if self.dc_state != previous_dc {
+            notifications.push(Self::notification(&DcState {
+                state: self.dc_state,
+            }));
+        }
+        notifications
+    }
+
+    fn device_info(&self) -> BluetoothDeviceInfo {
+        BluetoothDeviceInfo {
+            name: self.name.clone(),
+            address: self.address.clone(),
+            connected: self.connected,
+            has_gaia: self.paired,
+            paired: self.paired,
+            raw_name: None,
+            profile: DeviceProfile::default(),
+        }
+    }
+}
+
+fn deliver(address: String, frame: GaiaFrame) {
+    match frame.encode() {
+        Ok(bytes) => handle_backend_data(&address, &bytes),
+        Err(err) => back_log("SIM", format!("Failed to encode frame: {}", err)),
+    }
+}
+
+pub(crate) struct SimulatorBackend {
+    speakers: Arc<Mutex<HashMap<String, SimulatedSpeaker>>>,
+}
+
+impl SimulatorBackend {
+    pub(crate) fn new() -> Self {
+        let speakers: HashMap<String, SimulatedSpeaker> = [
+            SimulatedSpeaker::new("STONE Simulator", "00:00:5E:00:53:01", true),
+            SimulatedSpeaker::new("STONE Simulator 2", "00:00:5E:00:53:02", false),
+        ]
+        .into_iter()
+        .map(|speaker| (speaker.address.to_ascii_lowercase(), speaker))
+        .collect();
+        let speakers = Arc::new(Mutex::new(speakers));
+
+        let ticker = speakers.clone();
+        tauri::async_runtime::spawn(async move {
+            loop {
+                tokio::time::sleep(Duration::from_millis(TICK_INTERVAL_MS)).await;
+                let notifications: Vec<(String, GaiaFrame)> = match ticker.lock() {
+                    Ok(mut speakers) => speakers
+                        .values_mut()
+                        .filter(|speaker| speaker.connected)
+                        .flat_map(|speaker| {
+                            let address = speaker.address.clone();
+                            speaker
+                                .tick()
+                                .into_iter()
+                                .map(move |frame| (address.clone(), frame))
+                        })
+                        .collect(),
+                    Err(_) => return,
+                };
+                for (address, frame) in notifications {
+                    deliver(address, frame);
+                }
+            }
+        });
+
+        Self { speakers }
+    }
+
+    fn with_speaker<T>(
+        &self,
+        address: &str,
+        f: impl FnOnce(&mut SimulatedSpeaker) -> T,
+    ) -> Result<T, StoneError> {
+        let mut speakers = self
+            .speakers
+            .lock()
+            .map_err(|_| StoneError::other("Simulator state poisoned"))?;
+        speakers
+            .get_mut(&address.to_ascii_lowercase())
+            .map(f)
+            .ok_or(StoneError::DeviceNotFound)
+    }
+}
+
+#[async_trait]
+impl BluetoothBackend for SimulatorBackend {
+    fn name(&self) -> &'static str {
+        "simulator"
+    }
+
+    async fn list_devices(&self) -> Result<Vec<BluetoothDeviceInfo>, StoneError> {
+        let speakers = self
+            .speakers
+            .lock()
+            .map_err(|_| StoneError::other("Simulator state poisoned"))?;
+        let mut list: Vec<BluetoothDeviceInfo> = speakers
+            .values()
+            .filter(|speaker| speaker.paired)
+            .map(SimulatedSpeaker::device_info)
+            .collect();
+        list.sort_by(|a, b| a.address.cmp(&b.address));
+        Ok(list)
+    }
+
+    async fn scan_unpaired_stone_devices(&self) -> Result<Vec<BluetoothDeviceInfo>, StoneError> {
+        tokio::time::sleep(Duration::from_millis(SCAN_LATENCY_MS)).await;
+        let speakers = self
+            .speakers
+            .lock()
+            .map_err(|_| StoneError::other("Simulator state poisoned"))?;
+        let mut list: Vec<BluetoothDeviceInfo> = speakers
+            .values()
+            .filter(|speaker| !speaker.paired)
+            .map(SimulatedSpeaker::device_info)
+            .collect();
+        list.sort_by(|a, b| a.address.cmp(&b.address));
+        Ok(list)
+    }
+
+    async fn get_connection_infos(&self) -> Result<Vec<ConnectionInfo>, StoneError> {
+        let speakers = self
+            .speakers
+            .lock()
+            .map_err(|_| StoneError::other("Simulator state poisoned"))?;
+        let mut infos: Vec<ConnectionInfo> = speakers
+            .values()
+            .filter(|speaker| speaker.connected)
+            .map(|speaker| ConnectionInfo {
+                address: speaker.address.clone(),
+                link: true,
+                rfcomm: true,
+            })
+            .collect();
+        infos.sort_by(|a, b| a.address.cmp(&b.address));
+        Ok(infos)
+    }
+
+    async fn connect_device(&self, address: &str) -> Result<(), StoneError> {
+        self.with_speaker(address, |_| ())?;
+        tokio::time::sleep(Duration::from_millis(CONNECT_LATENCY_MS)).await;
+        self.with_speaker(address, |speaker| {
+            speaker.paired = true;
+            speaker.connected = true;
+        })
+    }
+
+    async fn disconnect_device(&self, address: &str) -> Result<(), StoneError> {
+        let was_connected = self.with_speaker(address, |speaker| {
+            std::mem::replace(&mut speaker.connected, false)
+        })?;
+        if was_connected {
+            emit_backend_device_event(address.to_string(), false);
+        }
+        Ok(())
+    }
+
+    async fn write_frame(&self, address: &str, frame: &[u8]) -> Result<(), StoneError> {
+        let (request, _) = GaiaFrame::decode(frame).map_err(StoneError::other)?;
+        let response = self.with_speaker(address, |speaker| {
+            if speaker.connected {
+                Some(speaker.handle(&request))
+            } else {
+                None
+            }
+        })?;
+        let response = response.ok_or(StoneError::NotConnected)?;
+        let address = address.to_string();
+        tauri::async_runtime::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(ACK_LATENCY_MS)).await;
+            deliver(address, response);
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pt::{BatteryStep, PtReport};
+    use crate::{get_device_states, request_gaia};
+
+    const TIMEOUT_MS: u64 = 1_000;
+
+    #[test]
+    fn requests_get_their_own_ack() {
+        tauri::async_runtime::block_on(async {
+            let backend = Arc::new(SimulatorBackend::new());
+            let address = "00:00:5E:00:53:01";
+            backend.connect_device(address).await.unwrap();
+
+            pt::send(&*backend, address, PtCommand::SetVolume(21), TIMEOUT_MS)
+                .await
+                .unwrap();
+            let volume: Volume = pt::query(&*backend, address, TIMEOUT_MS).await.unwrap();
+            assert_eq!(volume.volume, 21);
+            assert_eq!(get_device_states().get(address).volume, Some(21));
+
+            // Requests in flight together are answered in order, each with its own payload.
+            let queries: Vec<_> = [PtCommand::GetMac, PtCommand::GetDeviceName]
+                .into_iter()
+                .map(|command| {
+                    let backend = backend.clone();
+                    tauri::async_runtime::spawn(async move {
+                        pt::send(&*backend, address, command, TIMEOUT_MS).await
+                    })
+                })
+                .collect();
+            let mut payloads = Vec::new();
+            for query in queries {
+                payloads.push(query.await.unwrap().unwrap().payload);
+            }
+            assert_eq!(
+                payloads,
+                vec![
+                    Mac(address.to_string()).encode(),
+                    DeviceName("STONE Simulator".to_string()).encode()
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn unknown_commands_are_not_supported() {
+        tauri::async_runtime::block_on(async {
+            let backend = SimulatorBackend::new();
+            let address = "00:00:5E:00:53:02";
+            backend.connect_device(address).await.unwrap();
+
+            let unknown =
+                request_gaia(&backend, address, pt::PT_VENDOR_ID, 0x0999, &[], TIMEOUT_MS)
+                    .await
+                    .unwrap_err();
+            assert!(matches!(
+                unknown,
+                StoneError::Protocol { status, .. } if status == GaiaStatus::NotSupported.as_byte()
+            ));
+
+            let other_vendor = request_gaia(&backend, address, 0x000A, 0x0201, &[], TIMEOUT_MS)
+                .await
+                .unwrap_err();
+            assert!(matches!(
+                other_vendor,
+                StoneError::Protocol { status, .. } if status == GaiaStatus::NotSupported.as_byte()
+            ));
+
+            let out_of_range = request_gaia(
+                &backend,
+                address,
+                pt::PT_VENDOR_ID,
+                PtCommand::SetVolume(0).id(),
+                &[200],
+                TIMEOUT_MS,
+            )
+            .await
+            .unwrap_err();
+            assert!(matches!(
+                out_of_range,
+                StoneError::Protocol { status, .. } if status == GaiaStatus::InvalidParameter.as_byte()
+            ));
+        });
+    }
+
+    #[test]
+    fn requests_need_a_connection() {
+        tauri::async_runtime::block_on(async {
+            let backend = SimulatorBackend::new();
+            let frame = GaiaFrame::new(pt::PT_VENDOR_ID, PtCommand::GetVolume.id(), Vec::new())
+                .encode()
+                .unwrap();
+            assert!(matches!(
+                backend.write_frame("00:00:5E:00:53:02", &frame).await,
+                Err(StoneError::NotConnected)
+            ));
+            assert!(matches!(
+                backend.write_frame("00:00:5E:00:53:FF", &frame).await,
+                Err(StoneError::DeviceNotFound)
+            ));
+        });
+    }
+
+    fn report(frame: &GaiaFrame) -> PtReport {
+        assert!(!frame.ack);
+        assert_eq!(frame.payload[0], 0);
+        PtReport::decode(frame.command, &frame.payload[1..])
+            .unwrap()
+            .unwrap()
+    }
+
+    #[test]
+    fn ticks_notify_battery_and_charger_changes() {
+        let address = "00:00:5E:00:53:10";
+        let mut speaker = SimulatedSpeaker::new("STONE Tick", address, true);
+
+        // Draining across a step boundary reports the new step only.
+        speaker.battery_level = 61;
+        let frames = speaker.tick();
+        assert_eq!(frames.len(), 1);
+        assert!(matches!(
+            report(&frames[0]),
+            PtReport::BatteryStep(BatteryStep {
+                step: 3,
+                level: Some(60)
+            })
+        ));
+        assert!(speaker.tick().is_empty());
+
+        // Running low plugs the charger in.
+        speaker.battery_level = BATTERY_LOW_LEVEL + 1;
+        let frames = speaker.tick();
+        assert_eq!(frames.len(), 1);
+        assert!(matches!(
+            report(&frames[0]),
+            PtReport::DcState(DcState {
+                state: pt::DC_CHARGING
+            })
+        ));
+
+        // Notifications feed the device state like any inbound packet.
+        speaker.battery_level = 79;
+        let mut frames = speaker.tick();
+        speaker.battery_level = 98;
+        frames.extend(speaker.tick());
+        assert_eq!(frames.len(), 2);
+        for frame in frames {
+            deliver(address.to_string(), frame);
+        }
+        let state = get_device_states().get(address);
+        assert_eq!(state.battery_step, Some(5));
+        assert_eq!(state.battery_level, Some(82));
+        assert_eq!(state.dc_state, Some(pt::DC_FULL));
+    }
+}
@@ -0,0 +1,401 @@
+use serde::Serialize;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use tauri::Emitter;
+use tokio::time::Instant;
+
+use crate::backend::BackendState;
+use crate::broadcast::BroadcastTarget;
+use crate::cancel::CancelToken;
+use crate::connection::ConnectionPhase;
+use crate::error::StoneError;
+use crate::pt::{self, LampState, PtCommand};
+use crate::retry::unix_millis;
+use crate::transaction::DEFAULT_REQUEST_TIMEOUT_MS;
+use crate::{back_log, APP_HANDLE};
+
+// How often each speaker is moved down to where the fade should be by now.
+const TICK_MS: u64 = 1_000;
+const MIN_DURATION_MS: u64 = 60_000;
+// A speaker that drops out near the end still gets switched off if it is back within this.
+const RESUME_GRACE_MS: u64 = 5 * 60 * 1000;
+const TRAY_REFRESH_MS: u64 = 15_000;
+
+/// Emitted as `sleep_timer_changed`, or `null` once the timer is over.
+#[derive(Serialize, Clone, Debug)]
+pub(crate) struct SleepStatus {
+    addresses: Vec<String>,
+    started_at: u64,
+    ends_at: u64,
+    pub remaining_ms: u64,
+}
+
+struct Session {
+    status: SleepStatus,
+    stop: CancelToken,
+}
+
+/// Fades volume and lamp brightness of a set of speakers to zero over a chosen time, then
+/// stops their lamps. Only one timer runs at a time.
+#[derive(Default)]
+pub(crate) struct SleepTimer {
+    inner: Mutex<Option<Session>>,
+}
+
+impl SleepTimer {
+    pub(crate) fn status(&self) -> Option<SleepStatus> {
+        let inner = self.inner.lock().ok()?;
+        let mut status = inner.as_ref()?.status.clone();
+        status.remaining_ms = status
+            .ends_at
+            .saturating_sub(unix_millis(SystemTime::now()));
+        Some(status)
+    }
+
+    /// Starts fading `target` over `duration_ms`, replacing a timer that is already running.
+    pub(crate) fn start(
+        &self,
+        target: &BroadcastTarget,
+        duration_ms: u64,
+        backend: &BackendState,
+    ) -> Result<SleepStatus, StoneError> {
+        if duration_ms < MIN_DURATION_MS {
+            return Err(StoneError::InvalidParameter {
+                message: format!("duration_ms must be at least {}", MIN_DURATION_MS),
+            });
+        }
+        let addresses = target.addresses();
+        if addresses.is_empty() {
+            return Err(StoneError::InvalidParameter {
+                message: "Sleep timer has no targets".to_string(),
+            });
+        }
+        let now = unix_millis(SystemTime::now());
+        let status = SleepStatus {
+            addresses,
+            started_at: now,
+            ends_at: now + duration_ms,
+            remaining_ms: duration_ms,
+        };
+        let stop = CancelToken::new();
+        {
+            let mut inner = self
+                .inner
+                .lock()
+                .map_err(|_| StoneError::other("Sleep timer state poisoned"))?;
+            let previous = inner.replace(Session {
+                status: status.clone(),
+                stop: stop.clone(),
+            });
+            if let Some(previous) = previous {
+                previous.stop.cancel();
+            }
+        }
+        crate::get_lamp_effects().release(&status.addresses);
+        back_log(
+            "RUST",
+            format!(
+                "Sleep timer for {} devices over {} s",
+                status.addresses.len(),
+                duration_ms / 1000
+            ),
+        );
+        self.announce();
+
+        let started = Instant::now();
+        let duration = Duration::from_millis(duration_ms);
+        let devices: Vec<_> = status
+            .addresses
+            .iter()
+            .map(|address| {
+                tauri::async_runtime::spawn(fade_device(
+                    backend.clone(),
+                    address.clone(),
+                    started,
+                    duration,
+                    stop.clone(),
+                ))
+            })
+            .collect();
+        let done = stop.clone();
+        tauri::async_runtime::spawn(async move {
+            for device in devices {
+                let _ = device.await;
+            }
+            crate::get_sleep_timer().finished(&done);
+        });
+        let ticking = stop.clone();
+        tauri::async_runtime::spawn(async move {
+            while !ticking.is_cancelled() {
+                crate::update_tray_sleep(crate::get_sleep_timer().status());
+                let _ = ticking
+                    .run(tokio::time::sleep(Duration::from_millis(TRAY_REFRESH_MS)))
+                    .await;
+            }
+        });
+        Ok(status)
+    }
+
+    /// Stops the timer where it is; volume and lamps keep their current level.
+    pub(crate) fn cancel(&self) -> bool {
+        let session = self.inner.lock().ok().and_then(|mut inner| inner.take());
+        let Some(session) = session else {
+            return false;
+        };
+        session.stop.cancel();
+        back_log("RUST", "Sleep timer cancelled".to_string());
+        self.announce();
+        true
+    }
+
+    fn finished(&self, stop: &CancelToken) {
+        // A timer that was cancelled or replaced meanwhile is no longer ours to clear.
+        let cleared = self
+            .inner
+            .lock()
+            .map(|mut inner| {
+                let current = inner
+                    .as_ref()
+                    .is_some_and(|session| session.stop.is_same(stop));
+                if current {
+                    *inner = None;
+                }
+                current
+            })
+            .unwrap_or(false);
+        stop.cancel();
+        if cleared {
+            back_log("RUST", "Sleep timer finished".to_string());
+            self.announce();
+        }
+    }
+
+    fn announce(&self) {
+        let status = self.status();
+        crate::update_tray_sleep(status.clone());
+        if let Some(app) = APP_HANDLE.get() {
+            let _ = app.emit("sleep_timer_changed", status);
+        }
+    }
+}
+
+fn scaled(start: u8, left: f64) -> u8 {
+    (start as f64 * left).round() as u8
+}
+
+/// What a speaker was last told. Reset when its link drops, so it is brought up to date
+/// once it is back.
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+struct Sent {
+    volume: Option<u8>,
+    brightness: Option<u8>,
+}
+
+/// The writes that take a speaker from `sent` to where the fade is with `left` of it to go.
+/// `from` is its volume, and lamp brightness when the lamp was on, as the fade found it.
+fn fade_steps(from: (u8, Option<u8>), left: f64, sent: Sent, done: bool) -> Vec<PtCommand> {
+    let (volume, brightness) = from;
+    let mut steps = vec![];
+    let next_volume = scaled(volume, left);
+    if sent.volume != Some(next_volume) {
+        steps.push(PtCommand::SetVolume(next_volume));
+    }
+    let next_brightness = brightness.map(|brightness| scaled(brightness, left));
+    if let Some(next) = next_brightness.filter(|next| sent.brightness != Some(*next)) {
+        steps.push(PtCommand::SetLampBrightness(next));
+    }
+    if done && brightness.is_some() {
+        steps.push(PtCommand::StopLamp);
+    }
+    steps
+}
+
+/// Walks one speaker down from the levels it had when the timer first reached it. While the
+/// link is down nothing is sent; once it is back the speaker jumps to where the fade is now.
+async fn fade_device(
+    backend: BackendState,
+    address: String,
+    started: Instant,
+    duration: Duration,
+    stop: CancelToken,
+) {
+    // Volume, and lamp brightness when the lamp was on.
+    let mut from: Option<(u8, Option<u8>)> = None;
+    let mut sent = Sent::default();
+    let tick = Duration::from_millis(TICK_MS);
+    let give_up = duration + Duration::from_millis(RESUME_GRACE_MS);
+    loop {
+        let elapsed = started.elapsed();
+        let connected = crate::get_connections().phase(&address) == ConnectionPhase::Connected;
+        if !connected {
+            sent = Sent::default();
+        } else if from.is_none() {
+            let volume = pt::query::<pt::Volume>(&*backend, &address, DEFAULT_REQUEST_TIMEOUT_MS);
+            if let Ok(volume) = stop.run(volume).await.and_then(|result| result) {
+                let lamp = pt::query::<LampState>(&*backend, &address, DEFAULT_REQUEST_TIMEOUT_MS);
+                if let Ok(lamp) = stop.run(lamp).await.and_then(|result| result) {
+                    from = Some((volume.volume, lamp.on.then_some(lamp.brightness)));
+                }
+            }
+        }
+        if let (true, Some(from)) = (connected, from) {
+            let left = 1.0 - (elapsed.as_secs_f64() / duration.as_secs_f64()).min(1.0);
+            let mut all_sent = true;
+            for command in fade_steps(from, left, sent, elapsed >= duration) {
+                let send = pt::send(&*backend, &address, command, DEFAULT_REQUEST_TIMEOUT_MS);
+                let Ok(result) = stop.run(send).await else {
+                    return;
+                };
+                match (result, command) {
+                    (Ok(_), PtCommand::SetVolume(volume)) => sent.volume = Some(volume),
+                    (Ok(_), PtCommand::SetLampBrightness(level)) => sent.brightness = Some(level),
+                    (Ok(_), _) => {}
+                    (Err(_), _) => all_sent = false,
+                }
+            }
+            if elapsed >= duration && all_sent {
+                return;
+            }
+        }
+        if elapsed >= give_up {
+            back_log(
+                "RUST",
+                format!("Sleep timer could not reach {} before it ended", address),
+            );
+            return;
+        }
+        let ticks = started.elapsed().as_millis() / tick.as_millis() + 1;
+        if stop
+            .run(tokio::time::sleep_until(started + tick * ticks as u32))
+            .await
+            .is_err()
+        {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::tests::{backend, StubBackend};
+    use std::sync::Arc;
+
+    #[test]
+    fn levels_scale_with_the_time_left() {
+        assert_eq!(scaled(15, 1.0), 15);
+        assert_eq!(scaled(15, 0.5), 8);
+        assert_eq!(scaled(15, 0.1), 2);
+        assert_eq!(scaled(15, 0.0), 0);
+        assert_eq!(scaled(u8::MAX, 1.0), u8::MAX);
+    }
+
+    #[test]
+    fn steps_send_only_what_changed() {
+        let lamp_on = (20, Some(80));
+        assert_eq!(
+            fade_steps(lamp_on, 1.0, Sent::default(), false),
+            [PtCommand::SetVolume(20), PtCommand::SetLampBrightness(80)]
+        );
+        let halfway = Sent {
+            volume: Some(10),
+            brightness: Some(40),
+        };
+        assert!(fade_steps(lamp_on, 0.5, halfway, false).is_empty());
+        assert_eq!(
+            fade_steps(lamp_on, 0.45, halfway, false),
+            [PtCommand::SetVolume(9), PtCommand::SetLampBrightness(36)]
+        );
+
+        // A lamp that was off is left alone, also at the end.
+        assert_eq!(
+            fade_steps((20, None), 0.0, Sent::default(), true),
+            [PtCommand::SetVolume(0)]
+        );
+        let down = Sent {
+            volume: Some(0),
+            brightness: Some(0),
+        };
+        assert_eq!(fade_steps(lamp_on, 0.0, down, true), [PtCommand::StopLamp]);
+    }
+
+    #[test]
+    fn speaker_back_from_a_blip_jumps_to_the_current_level() {
+        let lamp_on = (20, Some(80));
+        let mut sent = Sent::default();
+        for command in fade_steps(lamp_on, 0.9, sent, false) {
+            match command {
+                PtCommand::SetVolume(volume) => sent.volume = Some(volume),
+                PtCommand::SetLampBrightness(level) => sent.brightness = Some(level),
+                _ => {}
+            }
+        }
+        assert_eq!(sent.volume, Some(18));
+        // The link dropped while the fade went on to a quarter.
+        sent = Sent::default();
+        assert_eq!(
+            fade_steps(lamp_on, 0.25, sent, false),
+            [PtCommand::SetVolume(5), PtCommand::SetLampBrightness(20)]
+        );
+    }
+
+    #[test]
+    fn unreachable_speaker_is_given_up_after_the_grace() {
+        let stub = Arc::new(StubBackend::default());
+        let duration = Duration::from_millis(MIN_DURATION_MS);
+        let grace = Duration::from_millis(RESUME_GRACE_MS);
+        tauri::async_runtime::block_on(async {
+            // Never connected, and the fade plus its grace are over.
+            let started = Instant::now().checked_sub(duration + grace).unwrap();
+            let fade = fade_device(
+                backend(&stub),
+                "00:00:5E:00:53:A0".to_string(),
+                started,
+                duration,
+                CancelToken::new(),
+            );
+            assert!(tokio::time::timeout(Duration::from_millis(100), fade)
+                .await
+                .is_ok());
+
+            // Within the grace it keeps waiting for the speaker.
+            let started = Instant::now().checked_sub(duration).unwrap();
+            let fade = fade_device(
+                backend(&stub),
+                "00:00:5E:00:53:A0".to_string(),
+                started,
+                duration,
+                CancelToken::new(),
+            );
+            assert!(tokio::time::timeout(Duration::from_millis(1_500), fade)
+                .await
+                .is_err());
+        });
+    }
+
+    #[test]
+    fn finished_leaves_a_replacing_timer_alone() {
+        let timer = SleepTimer::default();
+        let session = |stop: &CancelToken| Session {
+            status: SleepStatus {
+                addresses: vec!["00:00:5E:00:53:A1".to_string()],
+                started_at: 0,
+                ends_at: 0,
+                remaining_ms: 0,
+            },
+            stop: stop.clone(),
+        };
+        let (replaced, current) = (CancelToken::new(), CancelToken::new());
+        *timer.inner.lock().unwrap() = Some(session(&current));
+
+        timer.finished(&replaced);
+        assert!(replaced.is_cancelled());
+        assert!(!current.is_cancelled());
+        assert!(timer.status().is_some());
+
+        timer.finished(&current);
+        assert!(current.is_cancelled());
+        assert!(timer.status().is_none());
+        assert!(!timer.cancel());
+    }
+}
@@ -0,0 +1,650 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use tauri::{Emitter, Manager};
+
+use crate::backend::BackendState;
+use crate::cancel::CancelToken;
+use crate::connection::ConnectionPhase;
+use crate::error::StoneError;
+use crate::retry::{self, unix_millis};
+use crate::{back_log, APP_HANDLE};
+
+/// When registered speakers are brought back after their link drops.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ReconnectMode {
+    #[default]
+    Always,
+    WhileVisible,
+    Never,
+}
+
+/// Backoff between reconnect rounds; each round is one full connect under the retry policy.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub(crate) struct ReconnectPolicy {
+    pub mode: ReconnectMode,
+    pub initial_delay_ms: u64,
+    pub max_delay_ms: u64,
+    pub multiplier: f64,
+    pub jitter: f64,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            mode: ReconnectMode::Always,
+            initial_delay_ms: 2_000,
+            max_delay_ms: 300_000,
+            multiplier: 2.0,
+            jitter: 0.2,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    fn validate(&self) -> Result<(), StoneError> {
+        let invalid = |message: &str| {
+            Err(StoneError::InvalidParameter {
+                message: message.to_string(),
+            })
+        };
+        if !self.multiplier.is_finite() || self.multiplier < 1.0 {
+            return invalid("multiplier must be at least 1");
+        }
+        if !(0.0..=1.0).contains(&self.jitter) {
+            return invalid("jitter must be between 0 and 1");
+        }
+        if self.max_delay_ms < self.initial_delay_ms {
+            return invalid("max_delay_ms must not be below initial_delay_ms");
+        }
+        Ok(())
+    }
+
+    fn delay(&self, round: u32) -> Duration {
+        retry::backoff(
+            self.initial_delay_ms,
+            self.max_delay_ms,
+            self.multiplier,
+            self.jitter,
+            round,
+        )
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum SupervisorPhase {
+    Idle,
+    Waiting,
+    Reconnecting,
+    Paused,
+}
+
+/// Emitted as `bt_supervisor_state` whenever a supervised device changes phase.
+#[derive(Serialize, Clone, Debug)]
+pub(crate) struct SupervisorStatus {
+    address: String,
+    phase: SupervisorPhase,
+    round: u32,
+    next_attempt_at: Option<u64>,
+    last_error: Option<StoneError>,
+}
+
+struct Supervised {
+    status: SupervisorStatus,
+    // Set while `Waiting`; any phase change cancels it.
+    timer: Option<CancelToken>,
+}
+
+impl Supervised {
+    fn set(
+        &mut self,
+        phase: SupervisorPhase,
+        round: u32,
+        next_attempt_at: Option<u64>,
+        last_error: Option<StoneError>,
+    ) -> SupervisorStatus {
+        if let Some(timer) = self.timer.take() {
+            timer.cancel();
+        }
+        self.status.phase = phase;
+        self.status.round = round;
+        self.status.next_attempt_at = next_attempt_at;
+        self.status.last_error = last_error;
+        self.status.clone()
+    }
+}
+
+struct Inner {
+    policy: ReconnectPolicy,
+    visible: bool,
+    devices: HashMap<String, Supervised>,
+}
+
+impl Inner {
+    fn allowed(&self) -> bool {
+        match self.policy.mode {
+            ReconnectMode::Always => true,
+            ReconnectMode::WhileVisible => self.visible,
+            ReconnectMode::Never => false,
+        }
+    }
+}
+
+/// Reconnects registered speakers whose RFCOMM link dropped while connected.
+///
+/// A lost device waits out a backoff delay, then gets a regular connect through the
+/// connection manager; a failed round schedules the next one with a longer delay until the
+/// device is back, the user disconnects or cancels, or the mode stops allowing it.
+pub(crate) struct Supervisor {
+    inner: Mutex<Inner>,
+}
+
+fn key(address: &str) -> String {
+    address.to_ascii_lowercase()
+}
+
+fn emit_status(status: Option<SupervisorStatus>) {
+    let Some(status) = status else {
+        return;
+    };
+    if let Some(app) = APP_HANDLE.get() {
+        let _ = app.emit("bt_supervisor_state", status);
+    }
+}
+
+impl Supervisor {
+    pub(crate) fn new() -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                policy: ReconnectPolicy::default(),
+                visible: true,
+                devices: HashMap::new(),
+            }),
+        }
+    }
+
+    pub(crate) fn policy(&self) -> ReconnectPolicy {
+        self.inner
+            .lock()
+            .map(|inner| inner.policy.clone())
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn set_policy(&self, policy: ReconnectPolicy) -> Result<(), StoneError> {
+        policy.validate()?;
+        if let Ok(mut inner) = self.inner.lock() {
+            inner.policy = policy;
+        }
+        self.reevaluate();
+        Ok(())
+    }
+
+    pub(crate) fn set_visible(&self, visible: bool) {
+        if let Ok(mut inner) = self.inner.lock() {
+            if inner.visible == visible {
+                return;
+            }
+            inner.visible = visible;
+        }
+        self.reevaluate();
+    }
+
+    pub(crate) fn snapshot(&self) -> Vec<SupervisorStatus> {
+        let mut statuses: Vec<SupervisorStatus> = self
+            .inner
+            .lock()
+            .map(|inner| {
+                inner
+                    .devices
+                    .values()
+                    .map(|device| device.status.clone())
+                    .collect()
+            })
+            .unwrap_or_default();
+        statuses.sort_by(|a, b| a.address.cmp(&b.address));
+        statuses
+    }
+
+    /// Replaces the set of supervised addresses; dropped ones stop any pending reconnect.
+    pub(crate) fn set_devices(&self, addresses: Vec<String>) {
+        let Ok(mut inner) = self.inner.lock() else {
+            return;
+        };
+        let keep: HashSet<String> = addresses.iter().map(|address| key(address)).collect();
+        inner.devices.retain(|address, device| {
+            let kept = keep.contains(address);
+            if !kept {
+                if let Some(timer) = device.timer.take() {
+                    timer.cancel();
+                }
+            }
+            kept
+        });
+        for address in addresses {
+            inner
+                .devices
+                .entry(key(&address))
+                .or_insert_with(|| Supervised {
+                    status: SupervisorStatus {
+                        address,
+                        phase: SupervisorPhase::Idle,
+                        round: 0,
+                        next_attempt_at: None,
+                        last_error: None,
+                    },
+                    timer: None,
+                });
+        }
+    }
+
+    /// Starts reconnecting a supervised device whose established connection just dropped.
+    pub(crate) fn link_lost(&self, address: &str) {
+        let status = {
+            let Ok(mut inner) = self.inner.lock() else {
+                return;
+            };
+            match inner.devices.get(&key(address)) {
+                Some(device) if device.status.phase == SupervisorPhase::Idle => {}
+                _ => return,
+            }
+            back_log("RUST", format!("Link lost, supervising: {}", address));
+            let delay = inner.policy.delay(1);
+            Self::schedule(&mut inner, &key(address), 1, None, delay)
+        };
+        emit_status(status);
+    }
+
+    /// Skips the rest of the wait when the device shows up again on its own.
+    pub(crate) fn link_restored(&self, address: &str) {
+        let timer = self.inner.lock().ok().and_then(|inner| {
+            inner
+                .devices
+                .get(&key(address))
+                .filter(|device| device.status.phase == SupervisorPhase::Waiting)
+                .and_then(|device| device.timer.clone())
+        });
+        if let Some(timer) = timer {
+            self.fire(address, &timer);
+        }
+    }
+
+    /// Follows up on a finished connect, whoever asked for it.
+    pub(crate) fn connect_finished(&self, address: &str, result: &Result<(), StoneError>) {
+        let status = {
+            let Ok(mut inner) = self.inner.lock() else {
+                return;
+            };
+            let address = key(address);
+            let Some(device) = inner.devices.get_mut(&address) else {
+                return;
+            };
+            let (phase, round) = (device.status.phase, device.status.round);
+            match (phase, result) {
+                (SupervisorPhase::Idle, Ok(())) => return,
+                (_, Ok(())) => Some(device.set(SupervisorPhase::Idle, 0, None, None)),
+                (SupervisorPhase::Reconnecting, Err(StoneError::ConnectCancelled)) => {
+                    Some(device.set(SupervisorPhase::Idle, 0, None, None))
+                }
+                (SupervisorPhase::Reconnecting, Err(err)) => {
+                    let delay = inner.policy.delay(round + 1);
+                    Self::schedule(&mut inner, &address, round + 1, Some(err.clone()), delay)
+                }
+                _ => return,
+            }
+        };
+        emit_status(status);
+    }
+
+    /// Calls off supervision after a user disconnect or cancel; returns true when a reconnect
+    /// was waiting to run.
+    pub(crate) fn stand_down(&self, address: &str) -> bool {
+        let (waiting, status) = {
+            let Ok(mut inner) = self.inner.lock() else {
+                return false;
+            };
+            let Some(device) = inner.devices.get_mut(&key(address)) else {
+                return false;
+            };
+            if device.status.phase == SupervisorPhase::Idle {
+                return false;
+            }
+            let waiting = matches!(
+                device.status.phase,
+                SupervisorPhase::Waiting | SupervisorPhase::Paused
+            );
+            (waiting, device.set(SupervisorPhase::Idle, 0, None, None))
+        };
+        back_log("RUST", format!("Supervisor stands down: {}", address));
+        emit_status(Some(status));
+        waiting
+    }
+
+    /// Pauses, resumes or drops pending reconnects after the mode or visibility changed.
+    fn reevaluate(&self) {
+        let statuses = {
+            let Ok(mut inner) = self.inner.lock() else {
+                return;
+            };
+            let pending: Vec<(String, SupervisorPhase, u32, Option<StoneError>)> = inner
+                .devices
+                .iter()
+                .filter(|(_, device)| {
+                    matches!(
+                        device.status.phase,
+                        SupervisorPhase::Waiting | SupervisorPhase::Paused
+                    )
+                })
+                .map(|(address, device)| {
+                    (
+                        address.clone(),
+                        device.status.phase,
+                        device.status.round,
+                        device.status.last_error.clone(),
+                    )
+                })
+                .collect();
+            let allowed = inner.allowed();
+            let never = inner.policy.mode == ReconnectMode::Never;
+            pending
+                .into_iter()
+                .filter(|(_, phase, _, _)| match phase {
+                    SupervisorPhase::Paused => allowed || never,
+                    _ => !allowed,
+                })
+                .filter_map(|(address, _, round, last_error)| {
+                    // A resumed device goes right away instead of serving its old delay.
+                    Self::schedule(&mut inner, &address, round, last_error, Duration::ZERO)
+                })
+                .collect::<Vec<_>>()
+        };
+        for status in statuses {
+            emit_status(Some(status));
+        }
+    }
+
+    fn schedule(
+        inner: &mut Inner,
+        address: &str,
+        round: u32,
+        last_error: Option<StoneError>,
+        delay: Duration,
+    ) -> Option<SupervisorStatus> {
+        let allowed = inner.allowed();
+        let mode = inner.policy.mode;
+        let device = inner.devices.get_mut(address)?;
+        if mode == ReconnectMode::Never {
+            return Some(device.set(SupervisorPhase::Idle, 0, None, last_error));
+        }
+        if !allowed {
+            return Some(device.set(SupervisorPhase::Paused, round, None, last_error));
+        }
+        let next_attempt_at = unix_millis(SystemTime::now() + delay);
+        let status = device.set(
+            SupervisorPhase::Waiting,
+            round,
+            Some(next_attempt_at),
+            last_error,
+        );
+        let timer = CancelToken::new();
+        device.timer = Some(timer.clone());
+        let address = status.address.clone();
+        tauri::async_runtime::spawn(async move {
+            if timer.run(tokio::time::sleep(delay)).await.is_ok() {
+                crate::get_supervisor().fire(&address, &timer);
+            }
+        });
+        Some(status)
+    }
+
+    fn fire(&self, address: &str, timer: &CancelToken) {
+        let backend = APP_HANDLE
+            .get()
+            .and_then(|app| app.try_state::<BackendState>())
+            .map(|backend| backend.inner().clone());
+        self.reconnect(address, timer, backend);
+    }
+
+    fn reconnect(&self, address: &str, timer: &CancelToken, backend: Option<BackendState>) {
+        let (status, backend) = {
+            let Ok(mut inner) = self.inner.lock() else {
+                return;
+            };
+            let Some(device) = inner.devices.get_mut(&key(address)) else {
+                return;
+            };
+            // Every phase change cancels the timer, so a cancelled one is stale.
+            if timer.is_cancelled() {
+                return;
+            }
+            let (round, last_error) = (device.status.round, device.status.last_error.clone());
+            match backend {
+                Some(backend) => (
+                    device.set(SupervisorPhase::Reconnecting, round, None, last_error),
+                    backend,
+                ),
+                None => {
+                    // Nothing to connect through yet; going to `Reconnecting` would leave the
+                    // device there with no connect that could ever finish it.
+                    back_log("RUST", format!("No backend to reconnect: {}", address));
+                    let delay = inner.policy.delay(round + 1);
+                    let status =
+                        Self::schedule(&mut inner, &key(address), round + 1, last_error, delay);
+                    drop(inner);
+                    emit_status(status);
+                    return;
+                }
+            }
+        };
+        emit_status(Some(status.clone()));
+
+        let connections = crate::get_connections();
+        if connections.phase(address) == ConnectionPhase::Connected {
+            self.connect_finished(address, &Ok(()));
+            return;
+        }
+        back_log(
+            "RUST",
+            format!("Reconnect round {}: {}", status.round, address),
+        );
+        connections.enqueue(address, &backend);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::tests::{backend, StubBackend};
+    use crate::retry::RetryPolicy;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+
+    fn supervising(address: &str, policy: ReconnectPolicy) -> Supervisor {
+        let supervisor = Supervisor::new();
+        supervisor.set_policy(policy).unwrap();
+        supervisor.set_devices(vec![address.to_string()]);
+        supervisor
+    }
+
+    fn steady() -> ReconnectPolicy {
+        ReconnectPolicy {
+            initial_delay_ms: 1_000,
+            max_delay_ms: 3_000,
+            jitter: 0.0,
+            ..ReconnectPolicy::default()
+        }
+    }
+
+    fn status(supervisor: &Supervisor, address: &str) -> SupervisorStatus {
+        supervisor
+            .snapshot()
+            .into_iter()
+            .find(|status| status.address == address)
+            .unwrap()
+    }
+
+    fn timer(supervisor: &Supervisor, address: &str) -> Option<CancelToken> {
+        let inner = supervisor.inner.lock().unwrap();
+        inner.devices[&key(address)].timer.clone()
+    }
+
+    // How far off the next attempt is, rounded to whole seconds.
+    fn wait_s(status: &SupervisorStatus) -> u64 {
+        let next = status.next_attempt_at.unwrap();
+        (next.saturating_sub(unix_millis(SystemTime::now())) + 500) / 1_000
+    }
+
+    /// Fails every connect once, so a round ends without retries behind it.
+    fn failing(address: &str) -> Arc<StubBackend> {
+        let policy = RetryPolicy {
+            max_attempts: 1,
+            ..RetryPolicy::default()
+        };
+        crate::get_connections()
+            .set_policy(Some(address), Some(policy))
+            .unwrap();
+        Arc::new(StubBackend {
+            fail: true,
+            ..StubBackend::default()
+        })
+    }
+
+    #[test]
+    fn failed_rounds_back_off_until_the_device_is_back() {
+        let address = "00:00:5E:00:53:70";
+        let stub = failing(address);
+        let supervisor = supervising(address, steady());
+        tauri::async_runtime::block_on(async {
+            supervisor.link_lost(address);
+            let waiting = status(&supervisor, address);
+            assert_eq!(
+                (waiting.phase, waiting.round),
+                (SupervisorPhase::Waiting, 1)
+            );
+            assert_eq!(wait_s(&waiting), 1);
+
+            for (round, wait) in [(2, 2), (3, 3), (4, 3)] {
+                let timer = timer(&supervisor, address).unwrap();
+                supervisor.reconnect(address, &timer, Some(backend(&stub)));
+                assert!(timer.is_cancelled());
+                assert_eq!(
+                    status(&supervisor, address).phase,
+                    SupervisorPhase::Reconnecting
+                );
+                let failed = Err(StoneError::ConnectFailed {
+                    message: "gone".to_string(),
+                });
+                supervisor.connect_finished(address, &failed);
+                let waiting = status(&supervisor, address);
+                assert_eq!(waiting.phase, SupervisorPhase::Waiting);
+                assert_eq!(waiting.round, round);
+                assert_eq!(wait_s(&waiting), wait);
+                assert!(waiting.last_error.is_some());
+                tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            }
+            assert!(stub.attempts.load(Ordering::SeqCst) >= 1);
+
+            supervisor.connect_finished(address, &Ok(()));
+            let back = status(&supervisor, address);
+            assert_eq!((back.phase, back.round), (SupervisorPhase::Idle, 0));
+            assert!(timer(&supervisor, address).is_none());
+        });
+    }
+
+    #[test]
+    fn missing_backend_waits_another_round() {
+        let address = "00:00:5E:00:53:71";
+        let supervisor = supervising(address, steady());
+        supervisor.link_lost(address);
+        let timer = timer(&supervisor, address).unwrap();
+        supervisor.reconnect(address, &timer, None);
+
+        let waiting = status(&supervisor, address);
+        assert_eq!(
+            (waiting.phase, waiting.round),
+            (SupervisorPhase::Waiting, 2)
+        );
+        assert_eq!(wait_s(&waiting), 2);
+        assert!(timer.is_cancelled());
+    }
+
+    #[test]
+    fn stand_down_cancels_the_pending_reconnect() {
+        let address = "00:00:5E:00:53:72";
+        let supervisor = supervising(address, steady());
+        assert!(!supervisor.stand_down(address));
+
+        supervisor.link_lost(address);
+        let timer = timer(&supervisor, address).unwrap();
+        assert!(supervisor.stand_down(address));
+        assert!(timer.is_cancelled());
+        assert_eq!(status(&supervisor, address).phase, SupervisorPhase::Idle);
+        assert!(!supervisor.stand_down(address));
+        assert!(!supervisor.stand_down("00:00:5E:00:53:7F"));
+    }
+
+    #[test]
+    fn mode_changes_pause_resume_and_drop_reconnects() {
+        let address = "00:00:5E:00:53:73";
+        let supervisor = supervising(
+            address,
+            ReconnectPolicy {
+                mode: ReconnectMode::WhileVisible,
+                ..steady()
+            },
+        );
+        supervisor.link_lost(address);
+        let first = timer(&supervisor, address).unwrap();
+
+        supervisor.set_visible(false);
+        let paused = status(&supervisor, address);
+        assert_eq!((paused.phase, paused.round), (SupervisorPhase::Paused, 1));
+        assert!(paused.next_attempt_at.is_none());
+        assert!(first.is_cancelled());
+
+        supervisor.set_visible(true);
+        let resumed = status(&supervisor, address);
+        assert_eq!(
+            (resumed.phase, resumed.round),
+            (SupervisorPhase::Waiting, 1)
+        );
+        assert_eq!(wait_s(&resumed), 0);
+
+        supervisor
+            .set_policy(ReconnectPolicy {
+                mode: ReconnectMode::Never,
+                ..steady()
+            })
+            .unwrap();
+        assert_eq!(status(&supervisor, address).phase, SupervisorPhase::Idle);
+        assert!(timer(&supervisor, address).is_none());
+    }
+
+    #[test]
+    fn stale_timer_is_ignored() {
+        let address = "00:00:5E:00:53:74";
+        let stub = failing(address);
+        let supervisor = supervising(address, steady());
+        tauri::async_runtime::block_on(async {
+            supervisor.link_lost(address);
+            let stale = timer(&supervisor, address).unwrap();
+            supervisor.stand_down(address);
+            supervisor.reconnect(address, &stale, Some(backend(&stub)));
+            assert_eq!(status(&supervisor, address).phase, SupervisorPhase::Idle);
+
+            supervisor.link_lost(address);
+            supervisor.reconnect(address, &stale, Some(backend(&stub)));
+            let waiting = status(&supervisor, address);
+            assert_eq!(
+                (waiting.phase, waiting.round),
+                (SupervisorPhase::Waiting, 1)
+            );
+
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            assert_eq!(stub.attempts.load(Ordering::SeqCst), 0);
+        });
+    }
+}
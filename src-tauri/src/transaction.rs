@@ -0,0 +1,147 @@
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use tokio::sync::oneshot;
+
+use crate::error::StoneError;
+use crate::gaia::{GaiaFrame, GaiaStatus};
+
+pub(crate) const DEFAULT_REQUEST_TIMEOUT_MS: u64 = 3_000;
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct RequestKey {
+    address: String,
+    vendor_id: u16,
+    command: u16,
+}
+
+impl RequestKey {
+    fn new(address: &str, vendor_id: u16, command: u16) -> Self {
+        Self {
+            address: address.to_ascii_lowercase(),
+            vendor_id,
+            command: command & crate::gaia::COMMAND_MASK,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub(crate) struct GaiaResponse {
+    pub(crate) address: String,
+    pub(crate) vendor_id: u16,
+    pub(crate) command: u16,
+    pub(crate) status: u8,
+    pub(crate) payload: Vec<u8>,
+}
+
+/// Outstanding requests waiting for their ACK, matched FIFO per (address, vendor, command).
+#[derive(Default)]
+pub(crate) struct PendingRequests {
+    waiters: Mutex<HashMap<RequestKey, VecDeque<oneshot::Sender<GaiaFrame>>>>,
+}
+
+impl PendingRequests {
+    pub(crate) fn register(
+        &self,
+        address: &str,
+        vendor_id: u16,
+        command: u16,
+    ) -> oneshot::Receiver<GaiaFrame> {
+        let (tx, rx) = oneshot::channel();
+        if let Ok(mut waiters) = self.waiters.lock() {
+            let queue = waiters
+                .entry(RequestKey::new(address, vendor_id, command))
+                .or_default();
+            queue.retain(|waiter| !waiter.is_closed());
+            queue.push_back(tx);
+        }
+        rx
+    }
+
+    /// Hands an ACK frame to the oldest live waiter; returns false when nobody was waiting.
+    pub(crate) fn resolve(&self, address: &str, frame: &GaiaFrame) -> bool {
+        if !frame.ack {
+            return false;
+        }
+        let Ok(mut waiters) = self.waiters.lock() else {
+            return false;
+        };
+        let key = RequestKey::new(address, frame.vendor_id, frame.command);
+        let Some(queue) = waiters.get_mut(&key) else {
+            return false;
+        };
+        let mut delivered = false;
+        while let Some(waiter) = queue.pop_front() {
+            if waiter.send(frame.clone()).is_ok() {
+                delivered = true;
+                break;
+            }
+        }
+        if queue.is_empty() {
+            waiters.remove(&key);
+        }
+        delivered
+    }
+
+    pub(crate) fn cancel_address(&self, address: &str) {
+        if let Ok(mut waiters) = self.waiters.lock() {
+            waiters.retain(|key, _| !key.address.eq_ignore_ascii_case(address));
+        }
+    }
+}
+
+pub(crate) async fn await_response(
+    address: &str,
+    receiver: oneshot::Receiver<GaiaFrame>,
+    timeout_ms: u64,
+) -> Result<GaiaResponse, StoneError> {
+    let frame =
+        match tokio::time::timeout(std::time::Duration::from_millis(timeout_ms), receiver).await {
+            Ok(Ok(frame)) => frame,
+            Ok(Err(_)) => return Err(StoneError::RequestDropped),
+            Err(_) => return Err(StoneError::Timeout { timeout_ms }),
+        };
+
+    let status = frame.gaia_status().unwrap_or(GaiaStatus::Success);
+    if !status.is_success() {
+        return Err(StoneError::protocol(status));
+    }
+    Ok(GaiaResponse {
+        address: address.to_string(),
+        vendor_id: frame.vendor_id,
+        command: frame.command,
+        status: status.as_byte(),
+        payload: frame.payload,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDRESS: &str = "00:00:5E:00:53:50";
+
+    #[test]
+    fn dropped_waiter_does_not_take_the_ack() {
+        let pending = PendingRequests::default();
+        let dropped = pending.register(ADDRESS, 0x001D, 0x0201);
+        let mut waiting = pending.register(ADDRESS, 0x001D, 0x0201);
+        drop(dropped);
+
+        let ack = GaiaFrame::ack(0x001D, 0x0201, 0, vec![7]);
+        assert!(pending.resolve(ADDRESS, &ack));
+        assert_eq!(waiting.try_recv().unwrap().payload, vec![7]);
+        assert!(!pending.resolve(ADDRESS, &ack));
+    }
+
+    #[test]
+    fn nobody_waits_after_a_disconnect() {
+        let pending = PendingRequests::default();
+        let mut waiting = pending.register(ADDRESS, 0x001D, 0x0201);
+        pending.cancel_address(&ADDRESS.to_ascii_lowercase());
+
+        let ack = GaiaFrame::ack(0x001D, 0x0201, 0, Vec::new());
+        assert!(!pending.resolve(ADDRESS, &ack));
+        assert!(waiting.try_recv().is_err());
+    }
+}
@@ -9,10 +9,16 @@ import { bindOnboardingPage, renderOnboardingPage } from "./pages/onboarding";
 import {
   initConnectController,
   type DeviceInfo,
+  type ConnectProgressEvent,
   type ConnectResultEvent,
   type DeviceStateEvent,
 } from "./services/bluetooth";
 import { initAddDevicePage, renderAddDevicePage } from "./pages/add-device";
+import {
+  handleSupervisorState,
+  initReconnect,
+  type SupervisorStateEvent,
+} from "./services/reconnect";
 import { renderLicensesPage } from "./pages/licenses";
 import { renderSelect, bindSelect } from "./components/select";
 import {
@@ -22,6 +28,7 @@ import {
 } from "./state/connection";
 import {
   getSelectedSingleDeviceAddress,
+  getRegisteredDeviceLabel,
   getRegisteredDevices,
   removeRegisteredDevice,
   MULTI_CONTROL_SELECT_VALUE,
@@ -70,6 +77,11 @@ import {
   requestDynamicDeviceInfo,
   updateDeviceInfoUI,
 } from "./services/device-info";
+import { restoreDeviceState } from "./services/device-state";
+import { initScenes } from "./services/scenes";
+import { initSleepTimer } from "./services/sleep-timer";
+import { initHttpApi } from "./services/http-api";
+import { errorMessage } from "./services/errors";
 
 const ONBOARDING_SEEN_KEY = "stone.onboarding_seen_v1";
 
@@ -192,7 +204,8 @@ export function initApp() {
     const multiControlMenuEnabled = isMultiControlMenuEnabled();
     const options: Array<{ value: string; label: string; icon?: string }> = devices.map((device) => ({
       value: device.address,
-      label: device.name ?? device.address,
+      label: getRegisteredDeviceLabel(device),
+      icon: device.icon,
     }));
     if (multiControlMenuEnabled) {
       options.push({
@@ -239,9 +252,16 @@ export function initApp() {
         batteryPollingAddress = selectedAddress;
       }
       if (primedAddress !== selectedAddress) {
-        requestBattery().catch((err) => logLine(String(err), "SYS"));
-        requestVolume().catch((err) => logLine(String(err), "SYS"));
-        requestLampState().catch((err) => logLine(String(err), "SYS"));
+        restoreDeviceState(selectedAddress)
+          .then(() => {
+            updateBatteryLabel();
+            updateVolumeUI();
+            updateLampUI();
+          })
+          .catch((err) => logLine(errorMessage(err), "SYS"));
+        requestBattery().catch((err) => logLine(errorMessage(err), "SYS"));
+        requestVolume().catch((err) => logLine(errorMessage(err), "SYS"));
+        requestLampState().catch((err) => logLine(errorMessage(err), "SYS"));
         requestStaticDeviceInfo();
         primedAddress = selectedAddress;
       }
@@ -271,7 +291,10 @@ export function initApp() {
     const state = activeConnection?.state ?? "idle";
     switch (state) {
       case "connecting":
-        status.textContent = "연결 중...";
+        status.textContent = activeConnection?.attempt &&
+          (activeConnection.attempt.attempt > 1 || activeConnection.attempt.nextRetryAt)
+          ? `연결 중... (${activeConnection.attempt.attempt}/${activeConnection.attempt.maxAttempts})`
+          : "연결 중...";
         status.classList.remove("connected");
         break;
       case "disconnecting":
@@ -282,13 +305,15 @@ export function initApp() {
         const label = selectedAddress
           ? connectController?.getDeviceLabel(selectedAddress) ?? selectedAddress
           : "Unknown";
-        status.textContent = label;
+        status.textContent = activeConnection?.degraded ? `${label} (응답 없음)` : label;
         status.classList.add("connected");
         break;
       }
       case "idle":
       default:
-        status.textContent = `${getSelectedDeviceLabel() ?? "STONE"}이 연결되지 않음`;
+        status.textContent = activeConnection?.reconnect?.phase === "waiting"
+          ? "재연결 대기 중..."
+          : `${getSelectedDeviceLabel() ?? "STONE"}이 연결되지 않음`;
         status.classList.remove("connected");
         break;
     }
@@ -322,7 +347,11 @@ export function initApp() {
 
   initBattery();
   initVolume();
+  initSleepTimer();
+  initReconnect();
+  initHttpApi();
   initLamp();
+  initScenes();
   initDeviceInfo();
 
   connectController = initConnectController({
@@ -344,7 +373,10 @@ export function initApp() {
     },
     onCancelPairing: async (address) => {
       if (!connectController) return;
-      void connectController.disconnectAddress(address);
+      // The connect may already have finished; drop the link in that case.
+      if (!(await connectController.cancelConnect(address))) {
+        await connectController.disconnectAddress(address);
+      }
     },
     onConfirmSuccess: () => {
       goBack();
@@ -386,7 +418,7 @@ export function initApp() {
       .then(() => {
         return connectController?.autoConnectRegisteredDevices();
       })
-      .catch((err) => logLine(String(err), "SYS"));
+      .catch((err) => logLine(errorMessage(err), "SYS"));
   }
 
   bindDevPage({
@@ -407,7 +439,7 @@ export function initApp() {
       try {
         await invoke("send_gaia_command", { address, vendorId, commandId, payload });
         logLine(`${toHex(vendorId, 4)} ${toHex(commandId, 4)} ${payload.length ? payload.map(b => toHex(b, 2)).join(" ") : "<empty>"}`, "OUT");
-      } catch (err) { logLine(String(err), "SYS"); }
+      } catch (err) { logLine(errorMessage(err), "SYS"); }
     },
     onPairingDebugMockSuccess: () => {
       runPairingDebugAction(() => {
@@ -492,7 +524,7 @@ export function initApp() {
     const target = e.target as HTMLElement;
     const item = target.closest("[data-url]") as HTMLElement;
     if (item && item.dataset.url) {
-      invoke("open_url", { url: item.dataset.url }).catch((err) => logLine(String(err), "SYS"));
+      invoke("open_url", { url: item.dataset.url }).catch((err) => logLine(errorMessage(err), "SYS"));
     }
   });
 
@@ -556,6 +588,12 @@ export function initApp() {
     addDevicePage?.handleConnectResult(event.payload);
     addDevicePage?.render();
   });
+  listen<ConnectProgressEvent>("bt_connect_progress", (event) => {
+    connectController?.handleConnectProgress(event.payload);
+  });
+  listen<SupervisorStateEvent>("bt_supervisor_state", (event) => {
+    handleSupervisorState(event.payload);
+  });
   listen<DeviceStateEvent>("bt_device_event", (event) => {
     connectController?.handleDeviceEvent(event.payload);
     addDevicePage?.render();
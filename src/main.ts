@@ -1,4 +1,5 @@
 import "./style.scss";
 import { initApp } from "./app";
+import { loadRegistry } from "./state/registry";
 
-initApp();
+void loadRegistry().finally(() => initApp());
@@ -3,6 +3,7 @@ import stoneImg from "../assets/stone.png";
 import { renderButton } from "../components/button";
 import { renderHeader } from "../components/header";
 import type { ConnectResultEvent, DeviceInfo } from "../services/bluetooth";
+import { errorMessage, toStoneError, type StoneError } from "../services/errors";
 
 type PairFlowStage = "select" | "connecting" | "success" | "fail";
 type DebugSyntheticOutcome = "success" | "fail";
@@ -78,11 +79,18 @@ function normalizeAddress(address: string) {
   return address.trim().toLowerCase();
 }
 
-function summarizeError(message: string | null | undefined) {
-  if (!message) {
+function summarizeError(error: StoneError | null | undefined) {
+  if (!error) {
     return "연결에 실패했습니다.";
   }
-  const compact = message.replace(/\s+/g, " ").trim();
+  switch (error.kind) {
+    case "pairing_cancelled": return "페어링이 취소되었습니다.";
+    case "pairing_timeout": return "페어링 시간이 초과되었습니다.";
+    case "permission_denied": return "블루투스 권한이 필요합니다.";
+    case "bluetooth_disabled": return "블루투스를 켜 주세요.";
+    case "adapter_unavailable": return "블루투스 어댑터를 찾을 수 없습니다.";
+  }
+  const compact = error.message.replace(/\s+/g, " ").trim();
   if (!compact) return "연결에 실패했습니다.";
   return compact.length > 90 ? `${compact.slice(0, 90)}...` : compact;
 }
@@ -115,7 +123,7 @@ export function initAddDevicePage(handlers: AddDeviceHandlers) {
   let flowStage: PairFlowStage = "select";
   let pendingAddress: string | null = null;
   let pendingName: string | null = null;
-  let lastError: string | null = null;
+  let lastError: StoneError | null = null;
   let cancelRequested = false;
   let pendingSynthetic = false;
   let debugSyntheticOutcome: DebugSyntheticOutcome | null = null;
@@ -446,7 +454,7 @@ export function initAddDevicePage(handlers: AddDeviceHandlers) {
           if (outcome === "success") {
             setFlowStage("success");
           } else {
-            lastError = "Debug simulated failure";
+            lastError = { kind: "other", message: "Debug simulated failure" };
             setFlowStage("fail");
           }
         }, 380);
@@ -455,7 +463,7 @@ export function initAddDevicePage(handlers: AddDeviceHandlers) {
     }
 
     void Promise.resolve(handlers.onPair(candidate.address)).catch((err) => {
-      lastError = String(err);
+      lastError = toStoneError(err);
       setFlowStage("fail");
     });
   }
@@ -480,7 +488,7 @@ export function initAddDevicePage(handlers: AddDeviceHandlers) {
       render();
     } catch (err) {
       if (token !== refreshToken || flowStage !== "select") return;
-      handlers.logLine(String(err), "SYS");
+      handlers.logLine(errorMessage(err), "SYS");
     } finally {
       if (token !== refreshToken) return;
       refreshInFlight = false;
@@ -501,10 +509,10 @@ export function initAddDevicePage(handlers: AddDeviceHandlers) {
       return;
     }
     stopAutoScan();
-    void refresh().catch((err) => handlers.logLine(String(err), "SYS"));
+    void refresh().catch((err) => handlers.logLine(errorMessage(err), "SYS"));
     scanTimer = window.setInterval(() => {
       if (flowStage !== "select") return;
-      void refresh().catch((err) => handlers.logLine(String(err), "SYS"));
+      void refresh().catch((err) => handlers.logLine(errorMessage(err), "SYS"));
     }, 10000);
   }
 
@@ -524,7 +532,7 @@ export function initAddDevicePage(handlers: AddDeviceHandlers) {
 
     setFlowStage("select");
     if (options?.shouldRefresh !== false) {
-      void refresh().catch((err) => handlers.logLine(String(err), "SYS"));
+      void refresh().catch((err) => handlers.logLine(errorMessage(err), "SYS"));
     } else {
       render();
     }
@@ -560,7 +568,7 @@ export function initAddDevicePage(handlers: AddDeviceHandlers) {
     if (result.ok) {
       setFlowStage("success");
     } else {
-      lastError = result.error ?? "Connect failed";
+      lastError = result.error ?? null;
       setFlowStage("fail");
     }
   }
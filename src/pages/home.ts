@@ -70,6 +70,23 @@ export function renderHomePage() {
                   ${renderRange({ id: "volumeSlider", min: 0, max: 30, step: 0.1, value: 0, icon: "volume_up" })}
                 </div>
               </div>
+              ${renderList([
+                renderListItem({
+                  label: "잠자기 타이머",
+                  valueId: "sleepTimerRemaining",
+                  right: renderSelect({
+                    id: "sleepTimer",
+                    value: "off",
+                    options: [
+                      { value: "off", label: "사용 안 함" },
+                      { value: "15", label: "15분" },
+                      { value: "30", label: "30분" },
+                      { value: "60", label: "1시간" },
+                      { value: "90", label: "1시간 30분" },
+                    ],
+                  }),
+                }),
+              ])}
             `,
           })}
           ${renderSection({
@@ -110,10 +127,50 @@ export function renderHomePage() {
                       </div>
                     `,
                 }),
+                renderListItem({
+                  label: "동기화 효과",
+                  right: renderSelect({
+                    id: "lampEffect",
+                    value: "none",
+                    direction: "up",
+                    options: [
+                      { value: "none", label: "사용 안 함", icon: "block" },
+                      { value: "breathing", label: "숨쉬기", icon: "air" },
+                      { value: "rainbow", label: "무지개", icon: "looks" },
+                      { value: "strobe", label: "깜빡임", icon: "flash_on" },
+                      { value: "sunset_fade", label: "노을", icon: "wb_twilight" },
+                    ],
+                  }),
+                }),
                 ])}
               </div>
             `,
           })}
+          ${renderSection({
+            title: "장면",
+            id: "sectionScenes",
+            body: renderList([
+              renderListItem({
+                label: "저장된 장면",
+                right: renderSelect({
+                  id: "sceneSelect",
+                  value: "none",
+                  direction: "up",
+                  options: [{ value: "none", label: "저장된 장면 없음" }],
+                }),
+              }),
+              renderListItem({ label: "장면 적용", id: "sceneApply" }),
+              renderListItem({ label: "장면 삭제", id: "sceneDelete" }),
+              renderListItem({
+                label: "현재 상태를 장면으로 저장",
+                col: true,
+                body: `
+                  <input id="sceneName" placeholder="장면 이름"/>
+                  ${renderButton({ id: "sceneSave", text: "저장", tone: "primary" })}
+                `,
+              }),
+            ]),
+          })}
         </main>
       </div>
     </div>
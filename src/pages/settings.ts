@@ -27,6 +27,30 @@ export function renderSettingsPage() {
           value: "30",
         }),
       }),
+      renderListItem({
+        label: "자동 재연결",
+        right: renderSelect({
+          id: "settingsReconnectMode",
+          options: [
+            { value: "always", label: "항상" },
+            { value: "while_visible", label: "앱 사용 중에만" },
+            { value: "never", label: "사용 안 함" },
+          ],
+          value: "always",
+        }),
+      }),
+      renderListItem({
+        label: "로컬 HTTP API",
+        valueId: "settingsHttpApiInfo",
+        right: renderToggle({ id: "settingsHttpApiToggle" }),
+        valueVisibleWhenChecked: true,
+      }),
+      renderListItem({
+        label: "이벤트 스트림 (WebSocket)",
+        valueId: "settingsEventStreamInfo",
+        right: renderToggle({ id: "settingsEventStreamToggle" }),
+        valueVisibleWhenChecked: true,
+      }),
     ]),
   });
 
@@ -4,6 +4,7 @@ import { getSelectedSingleDeviceAddress } from "../state/registry";
 import { updateDeviceData } from "../state/telemetry";
 import { getSelectionAnchorDeviceData, isSelectedDeviceConnected } from "../state/active";
 import { logLine } from "../utils/formatter";
+import { errorMessage } from "./errors";
 
 let batteryEl: HTMLElement | null = null;
 let batteryIconEl: HTMLSpanElement | null = null;
@@ -117,10 +118,14 @@ export function startBatteryPolling() {
   batteryTimer = setInterval(requestBattery, intervalMs);
 }
 
+function syncTrayDevice(address: string | null) {
+  void invoke("set_tray_device", { address, useLevel: useBatteryLevelDisplay });
+}
+
 export function resetBatteryState() {
   if (batteryEl) batteryEl.textContent = "--";
   if (batteryIconEl) batteryIconEl.textContent = "battery_android_question";
-  void invoke("set_tray_battery", { percent: null, charging: false, full: false });
+  syncTrayDevice(null);
 }
 
 export async function requestBattery() {
@@ -131,7 +136,7 @@ export async function requestBattery() {
     await invoke("send_gaia_command", { address, vendorId: 0x5054, commandId: 0x0456, payload: [] });
     logLine("Battery request (5054 0455)", "OUT");
   } catch (err) {
-    logLine(String(err), "SYS");
+    logLine(errorMessage(err), "SYS");
   }
 }
 
@@ -140,9 +145,10 @@ export function updateBatteryLabel() {
   if (!isSelectedDeviceConnected()) {
     batteryEl.textContent = "--";
     batteryIconEl.textContent = "battery_android_question";
-    void invoke("set_tray_battery", { percent: null, charging: false, full: false });
+    syncTrayDevice(null);
     return;
   }
+  syncTrayDevice(getSelectedSingleDeviceAddress());
   const { batteryStep, batteryLevel, dcState } = getSelectionAnchorDeviceData();
   const percent = useBatteryLevelDisplay
     ? (
@@ -161,7 +167,6 @@ export function updateBatteryLabel() {
   if (percent === null) {
     batteryEl.textContent = "--";
     batteryIconEl.textContent = "battery_android_question";
-    void invoke("set_tray_battery", { percent: null, charging: false, full: false });
     return;
   }
 
@@ -187,7 +192,6 @@ export function updateBatteryLabel() {
 
   batteryEl.textContent = `${percent}%${suffix}`;
   batteryIconEl.textContent = icon;
-  void invoke("set_tray_battery", { percent, charging: isCharging, full: isFull });
 }
 
 export function handleBatteryStepPacket(connectedAddress: string, dataPayload: number[]) {
@@ -2,18 +2,22 @@ import { invoke } from "@tauri-apps/api/core";
 import {
   getDeviceConnection,
   replaceConnectionInfos,
+  setDeviceConnectAttempt,
   setDeviceConnected,
   setDeviceConnectionState,
+  setDeviceDegraded,
   setDeviceDisconnected,
   setDeviceLinkState,
 } from "../state/connection";
 import {
+  getRegisteredDeviceLabel,
   getRegisteredDevices,
   getSelectedSingleDeviceAddress,
   isSelectedTargetMulti,
   setSelectedSingleDeviceAddress,
   upsertRegisteredDevice as upsertRegisteredDeviceFromStore,
 } from "../state/registry";
+import { errorMessage, type StoneError } from "./errors";
 
 export type DeviceInfo = {
   name: string;
@@ -23,6 +27,8 @@ export type DeviceInfo = {
   paired: boolean;
   alias?: string | null;
   raw_name?: string | null;
+  icon?: string | null;
+  groups?: string[];
 };
 
 export type { ConnectionState } from "../state/connection";
@@ -30,12 +36,22 @@ export type { ConnectionState } from "../state/connection";
 export type ConnectResultEvent = {
   address: string;
   ok: boolean;
-  error?: string | null;
+  error?: StoneError | null;
+};
+
+export type ConnectProgressEvent = {
+  address: string;
+  attempt: number;
+  max_attempts: number;
+  next_retry_at?: number | null;
+  error?: StoneError | null;
 };
 
 export type DeviceStateEvent = {
   address: string;
   connected: boolean;
+  // Keepalive verdict for a link that is still up; absent on plain link changes.
+  health?: "healthy" | "degraded" | "lost";
 };
 
 type ConnectionInfo = {
@@ -44,11 +60,11 @@ type ConnectionInfo = {
   rfcomm: boolean;
 };
 
-type ConnectQueueReason = "manual" | "startup" | "pair";
+type ConnectReason = "manual" | "startup" | "pair";
 
-type ConnectQueueItem = {
+type ConnectRequest = {
   address: string;
-  reason: ConnectQueueReason;
+  reason: ConnectReason;
   activateOnSuccess: boolean;
   quiet: boolean;
 };
@@ -76,24 +92,27 @@ export function initConnectController(deps: ConnectControllerDeps) {
   const suppressedAutoPairedToastAddresses = new Set<string>();
   let eventRefreshInFlight = false;
   let eventRefreshPending = false;
-  const connectQueue: ConnectQueueItem[] = [];
-  let connectInFlight: ConnectQueueItem | null = null;
+  // Queueing and concurrency live in the backend; this only remembers why each connect was asked for.
+  const pendingConnects = new Map<string, ConnectRequest>();
 
   function getDeviceLabel(address: string) {
     const device = devices.find((d) => isSameAddress(d.address, address));
     if (device?.name) return device.name;
     const paired = getRegisteredDevices().find((d) => isSameAddress(d.address, address));
-    return paired?.name ?? address;
+    return paired ? getRegisteredDeviceLabel(paired) : address;
+  }
+
+  // The registry keeps what the speaker advertises; `name` may already be the user's alias.
+  function getAdvertisedName(address: string) {
+    const device = devices.find((d) => isSameAddress(d.address, address));
+    return device ? device.raw_name ?? device.name : undefined;
   }
 
   function registerDevice(address: string, preferredName?: string) {
     if (!address) return;
     const normalized = normalizeAddress(address);
     const alreadyRegistered = getRegisteredDevices().some((d) => isSameAddress(d.address, address));
-    const latestName =
-      preferredName ??
-      devices.find((d) => isSameAddress(d.address, address))?.name ??
-      address;
+    const latestName = preferredName ?? getAdvertisedName(address) ?? address;
     upsertRegisteredDeviceFromStore(address, latestName);
     if (!alreadyRegistered) {
       const suppressToast = suppressedAutoPairedToastAddresses.delete(normalized);
@@ -120,37 +139,12 @@ export function initConnectController(deps: ConnectControllerDeps) {
       eventRefreshInFlight = false;
       if (eventRefreshPending) {
         eventRefreshPending = false;
-        void refreshDevicesFromEvent().catch((err) => deps.logLine(String(err), "SYS"));
+        void refreshDevicesFromEvent().catch((err) => deps.logLine(errorMessage(err), "SYS"));
       }
     }
   }
 
-  function dequeueNextConnect() {
-    if (connectInFlight || connectQueue.length === 0) return;
-
-    const next = connectQueue.shift();
-    if (!next) return;
-
-    connectInFlight = next;
-    setDeviceConnectionState(next.address, "connecting", { lastError: null });
-
-    invoke("connect_device_async", { address: next.address })
-      .catch((err) => {
-        const message = String(err);
-        setDeviceDisconnected(next.address, { lastError: message });
-        suppressedAutoPairedToastAddresses.delete(normalizeAddress(next.address));
-        if (registerPending && isSameAddress(registerPending, next.address)) {
-          deps.logLine(message, "SYS");
-          registerPending = null;
-        } else if (!next.quiet) {
-          deps.logLine(message, "SYS");
-        }
-        connectInFlight = null;
-        dequeueNextConnect();
-      });
-  }
-
-  function enqueueConnect(item: ConnectQueueItem) {
+  function enqueueConnect(item: ConnectRequest) {
     const address = item.address;
     const current = getDeviceConnection(address);
 
@@ -167,36 +161,49 @@ export function initConnectController(deps: ConnectControllerDeps) {
       return;
     }
 
-    if (connectInFlight && isSameAddress(connectInFlight.address, address)) {
+    const key = normalizeAddress(address);
+    const pending = pendingConnects.get(key);
+    if (pending) {
       if (!item.quiet) deps.logLine("Connect already in progress", "SYS");
+      if (item.activateOnSuccess) pending.activateOnSuccess = true;
+      if (!item.quiet) pending.quiet = false;
       return;
     }
-    if (connectQueue.some((queued) => isSameAddress(queued.address, address))) {
-      if (!item.quiet) deps.logLine("Connect already queued", "SYS");
-      return;
-    }
 
-    connectQueue.push(item);
-    dequeueNextConnect();
+    pendingConnects.set(key, item);
+    setDeviceConnectionState(address, "connecting", { lastError: null });
+
+    invoke("connect_device_async", { address })
+      .catch((err) => {
+        const message = errorMessage(err);
+        pendingConnects.delete(key);
+        setDeviceDisconnected(address, { lastError: message });
+        suppressedAutoPairedToastAddresses.delete(key);
+        if (registerPending && isSameAddress(registerPending, address)) {
+          deps.logLine(message, "SYS");
+          registerPending = null;
+        } else if (!item.quiet) {
+          deps.logLine(message, "SYS");
+        }
+      });
   }
 
   function handleConnectResult(result: ConnectResultEvent) {
-    const current = connectInFlight && isSameAddress(connectInFlight.address, result.address)
-      ? connectInFlight
-      : null;
+    const current = pendingConnects.get(normalizeAddress(result.address)) ?? null;
+    pendingConnects.delete(normalizeAddress(result.address));
     const cachedName = devices.find((d) => isSameAddress(d.address, result.address))?.name;
-    const message = result.error ?? "Connect failed";
-    const cancelled = message === "Connect cancelled";
+    const message = result.error?.message ?? "Connect failed";
+    const cancelled = result.error?.kind === "connect_cancelled";
 
     if (result.ok) {
       setDeviceConnected(result.address);
-      void refreshDevices().catch((err) => deps.logLine(String(err), "SYS"));
+      void refreshDevices().catch((err) => deps.logLine(errorMessage(err), "SYS"));
 
       const resolvedName =
         devices.find((d) => isSameAddress(d.address, result.address))?.name ??
         cachedName ??
         result.address;
-      registerDevice(result.address, resolvedName);
+      registerDevice(result.address, getAdvertisedName(result.address) ?? resolvedName);
 
       if (registerPending && isSameAddress(registerPending, result.address)) {
         deps.logLine(`Device paired: ${resolvedName}`, "SYS");
@@ -225,10 +232,21 @@ export function initConnectController(deps: ConnectControllerDeps) {
         deps.logLine(message, "SYS");
       }
     }
+  }
 
-    if (current) {
-      connectInFlight = null;
-      dequeueNextConnect();
+  function handleConnectProgress(progress: ConnectProgressEvent) {
+    const current = pendingConnects.get(normalizeAddress(progress.address));
+    setDeviceConnectAttempt(progress.address, {
+      attempt: progress.attempt,
+      maxAttempts: progress.max_attempts,
+      nextRetryAt: progress.next_retry_at ?? null,
+    });
+    if (progress.next_retry_at && current && !current.quiet) {
+      const seconds = Math.max(0, Math.round((progress.next_retry_at - Date.now()) / 1000));
+      deps.logLine(
+        `${progress.error?.message ?? "Connect failed"}; retrying in ${seconds}s (${progress.attempt + 1}/${progress.max_attempts})`,
+        "SYS"
+      );
     }
   }
 
@@ -248,7 +266,7 @@ export function initConnectController(deps: ConnectControllerDeps) {
         }
       }
     } catch (err) {
-      deps.logLine(String(err), "SYS");
+      deps.logLine(errorMessage(err), "SYS");
     }
   }
 
@@ -330,6 +348,16 @@ export function initConnectController(deps: ConnectControllerDeps) {
     });
   }
 
+  async function cancelConnect(address: string) {
+    if (!address) return false;
+    try {
+      return await invoke<boolean>("cancel_connect", { address });
+    } catch (err) {
+      deps.logLine(errorMessage(err), "SYS");
+      return false;
+    }
+  }
+
   async function disconnectAddress(address: string) {
     if (!address) return;
     setDeviceConnectionState(address, "disconnecting");
@@ -338,7 +366,7 @@ export function initConnectController(deps: ConnectControllerDeps) {
       setDeviceDisconnected(address, { lastError: null });
       deps.logLine("Disconnected", "SYS");
     } catch (err) {
-      const message = String(err);
+      const message = errorMessage(err);
       deps.logLine(message, "SYS");
       try {
         const infos = await invoke<ConnectionInfo[]>("get_connection_infos");
@@ -361,6 +389,16 @@ export function initConnectController(deps: ConnectControllerDeps) {
 
   function handleDeviceEvent(payload: DeviceStateEvent) {
     const { address, connected } = payload;
+    if (payload.health) {
+      setDeviceDegraded(address, payload.health !== "healthy");
+      deps.logLine(
+        payload.health === "healthy"
+          ? `${getDeviceLabel(address)} is answering again`
+          : `${getDeviceLabel(address)} stopped answering`,
+        "SYS"
+      );
+      return;
+    }
     const target = devices.find((d) => isSameAddress(d.address, address));
     if (target) target.connected = connected;
 
@@ -371,7 +409,7 @@ export function initConnectController(deps: ConnectControllerDeps) {
     }
 
     if (!target) {
-      void refreshDevicesFromEvent().catch((err) => deps.logLine(String(err), "SYS"));
+      void refreshDevicesFromEvent().catch((err) => deps.logLine(errorMessage(err), "SYS"));
     }
 
     if (connected) {
@@ -396,7 +434,7 @@ export function initConnectController(deps: ConnectControllerDeps) {
             deps.logLine(`Device paired: ${found.name ?? address}`, "SYS");
           }
         } catch (err) {
-          deps.logLine(String(err), "SYS");
+          deps.logLine(errorMessage(err), "SYS");
         }
       })();
     }
@@ -406,12 +444,14 @@ export function initConnectController(deps: ConnectControllerDeps) {
     getDevices: () => devices,
     connectAddress,
     addDevice,
+    cancelConnect,
     disconnectAddress,
     refreshDevices,
     autoRegisterConnectedGaiaDevices,
     autoConnectRegisteredDevices,
     syncBackendConnections,
     handleConnectResult,
+    handleConnectProgress,
     handleDeviceEvent,
     getDeviceLabel,
   };
@@ -0,0 +1,41 @@
+import { invoke } from "@tauri-apps/api/core";
+import { logLine } from "../utils/formatter";
+import type { StoneError } from "./errors";
+
+const PT_VENDOR_ID = 0x5054;
+
+export type BroadcastOutcome = {
+  address: string;
+  ok: boolean;
+  sent: boolean;
+  status: number | null;
+  payload: number[] | null;
+  latency_ms: number | null;
+  error: StoneError | null;
+};
+
+export type BroadcastReport = {
+  vendor_id: number;
+  command_id: number;
+  succeeded: number;
+  failed: number;
+  results: BroadcastOutcome[];
+};
+
+// Sends one PT command to all addresses in a single backend call and logs the devices that
+// did not ACK. Resolves to the addresses that did.
+export async function broadcastPt(addresses: string[], commandId: number, payload: number[]) {
+  if (addresses.length === 0) return [];
+  const report = await invoke<BroadcastReport>("send_gaia_broadcast", {
+    target: addresses,
+    vendorId: PT_VENDOR_ID,
+    commandId,
+    payload,
+  });
+  report.results.forEach((result) => {
+    if (!result.ok) {
+      logLine(`${result.address}: ${result.error?.message ?? "No ACK"}`, "SYS");
+    }
+  });
+  return report.results.filter((result) => result.ok).map((result) => result.address);
+}
@@ -2,6 +2,7 @@ import { invoke } from "@tauri-apps/api/core";
 import { getVersion } from "@tauri-apps/api/app";
 import { getSelectedSingleDeviceAddress } from "../state/registry";
 import { logLine, toHex } from "../utils/formatter";
+import { errorMessage } from "./errors";
 
 type DeviceInfoState = {
   name: string | null;
@@ -88,7 +89,7 @@ async function requestDeviceInfo(commandId: number) {
     await invoke("send_gaia_command", { address, vendorId: 0x5054, commandId, payload: [] });
     logLine(`Device info request (${toHex(0x5054, 4)} ${toHex(commandId, 4)})`, "OUT");
   } catch (err) {
-    logLine(String(err), "SYS");
+    logLine(errorMessage(err), "SYS");
   }
 }
 
@@ -113,6 +114,18 @@ async function loadAppVersion() {
   }
 }
 
+export function applyDeviceInfoState(address: string, patch: Partial<DeviceInfoState>) {
+  const state = getOrCreateState(address);
+  if (patch.name) state.name = patch.name;
+  if (patch.firmware) state.firmware = patch.firmware;
+  if (patch.mac) state.mac = patch.mac;
+  if (patch.rssi !== null && patch.rssi !== undefined) state.rssi = patch.rssi;
+  if (patch.wheel !== null && patch.wheel !== undefined) state.wheel = patch.wheel;
+  if (getSelectedSingleDeviceAddress()?.toLowerCase() === address.toLowerCase()) {
+    renderDeviceInfo(address);
+  }
+}
+
 export function handleDeviceInfoPacket(address: string, command: number, dataPayload: number[]) {
   if (!address) return;
   const state = getOrCreateState(address);
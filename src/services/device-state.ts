@@ -0,0 +1,50 @@
+import { invoke } from "@tauri-apps/api/core";
+import { getDeviceData, updateDeviceData, type DeviceData } from "../state/telemetry";
+import { applyDeviceInfoState } from "./device-info";
+import { rgbToSlider } from "./lamp";
+
+export type DeviceStateSnapshot = {
+  address: string;
+  connected: boolean;
+  battery_step: number | null;
+  battery_level: number | null;
+  dc_state: number | null;
+  volume: number | null;
+  lamp_on: boolean | null;
+  lamp_brightness: number | null;
+  lamp_type: number | null;
+  lamp_color: { r: number; g: number; b: number } | null;
+  name: string | null;
+  firmware: string | null;
+  mac: string | null;
+  rssi: number | null;
+  wheel_count: number | null;
+};
+
+export async function restoreDeviceState(address: string) {
+  const state = await invoke<DeviceStateSnapshot>("get_device_state", { address });
+  const patch: Partial<DeviceData> = {};
+  if (state.battery_step !== null) patch.batteryStep = state.battery_step;
+  if (state.battery_level !== null) patch.batteryLevel = state.battery_level;
+  if (state.dc_state !== null) patch.dcState = state.dc_state;
+  if (state.volume !== null) patch.volume = state.volume;
+  if (state.lamp_on !== null) patch.lampOn = state.lamp_on;
+  if (state.lamp_type !== null) patch.lampType = state.lamp_type;
+  if (state.lamp_color) {
+    patch.lampHue = rgbToSlider(state.lamp_color.r, state.lamp_color.g, state.lamp_color.b);
+  }
+  if (state.lamp_brightness !== null) {
+    patch.lampBrightness = state.lamp_brightness;
+    patch.lampLastNonZero = state.lamp_brightness > 0
+      ? state.lamp_brightness
+      : getDeviceData(address).lampLastNonZero;
+  }
+  updateDeviceData(address, patch);
+  applyDeviceInfoState(address, {
+    name: state.name,
+    firmware: state.firmware,
+    mac: state.mac,
+    rssi: state.rssi,
+    wheel: state.wheel_count,
+  });
+}
@@ -0,0 +1,56 @@
+export type StoneErrorKind =
+  | "not_supported"
+  | "not_connected"
+  | "device_not_found"
+  | "invalid_address"
+  | "adapter_unavailable"
+  | "bluetooth_disabled"
+  | "permission_denied"
+  | "pairing_cancelled"
+  | "pairing_timeout"
+  | "pairing_failed"
+  | "connect_cancelled"
+  | "connect_failed"
+  | "busy"
+  | "timeout"
+  | "io_bluetooth"
+  | "protocol"
+  | "decode"
+  | "io"
+  | "invalid_parameter"
+  | "request_dropped"
+  | "other";
+
+export type StoneError = {
+  kind: StoneErrorKind;
+  message: string;
+  timeout_ms?: number;
+  code?: number;
+  name?: string;
+  context?: string;
+  status?: number;
+};
+
+export function isStoneError(err: unknown): err is StoneError {
+  return (
+    typeof err === "object" &&
+    err !== null &&
+    typeof (err as StoneError).kind === "string" &&
+    typeof (err as StoneError).message === "string"
+  );
+}
+
+// Normalizes anything a command can reject with into a StoneError.
+export function toStoneError(err: unknown): StoneError {
+  if (isStoneError(err)) return err;
+  if (err instanceof Error) return { kind: "other", message: err.message };
+  return { kind: "other", message: String(err) };
+}
+
+export function errorMessage(err: unknown) {
+  return toStoneError(err).message;
+}
+
+export function errorKind(err: unknown): StoneErrorKind {
+  return toStoneError(err).kind;
+}
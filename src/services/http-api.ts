@@ -0,0 +1,87 @@
+import { invoke } from "@tauri-apps/api/core";
+import { logLine } from "../utils/formatter";
+import { errorMessage } from "./errors";
+
+export type HttpApiStatus = {
+  enabled: boolean;
+  port: number;
+  token: string;
+  listening: boolean;
+  error: string | null;
+};
+
+export type EventStreamStatus = {
+  enabled: boolean;
+  port: number;
+  listening: boolean;
+  clients: number;
+  error: string | null;
+};
+
+let toggleEl: HTMLInputElement | null = null;
+let infoEl: HTMLElement | null = null;
+let status: HttpApiStatus | null = null;
+let streamToggleEl: HTMLInputElement | null = null;
+let streamInfoEl: HTMLElement | null = null;
+let streamStatus: EventStreamStatus | null = null;
+
+function render(next: HttpApiStatus) {
+  status = next;
+  if (toggleEl) toggleEl.checked = next.enabled;
+  if (!infoEl) return;
+  infoEl.textContent = next.listening
+    ? `127.0.0.1:${next.port} · 토큰 ${next.token}`
+    : next.error ?? "--";
+}
+
+function renderStream(next: EventStreamStatus) {
+  streamStatus = next;
+  if (streamToggleEl) streamToggleEl.checked = next.enabled;
+  if (!streamInfoEl) return;
+  streamInfoEl.textContent = next.listening
+    ? `ws://127.0.0.1:${next.port}/events`
+    : next.error ?? "--";
+}
+
+async function setStreamEnabled(enabled: boolean) {
+  const port = streamStatus?.port ?? 8788;
+  const next = await invoke<EventStreamStatus>("set_event_stream_config", { enabled, port });
+  renderStream(next);
+  logLine(
+    enabled ? `Event stream on ws://127.0.0.1:${next.port}/events` : "Event stream stopped",
+    "SYS"
+  );
+}
+
+async function setEnabled(enabled: boolean) {
+  const port = status?.port ?? 8787;
+  const next = await invoke<HttpApiStatus>("set_http_api_config", { enabled, port });
+  render(next);
+  logLine(enabled ? `HTTP API listening on 127.0.0.1:${next.port}` : "HTTP API stopped", "SYS");
+}
+
+export function initHttpApi() {
+  toggleEl = document.querySelector<HTMLInputElement>("#settingsHttpApiToggle");
+  infoEl = document.querySelector<HTMLElement>("#settingsHttpApiInfo");
+  toggleEl?.addEventListener("change", () => {
+    setEnabled(!!toggleEl?.checked).catch((err) => {
+      logLine(errorMessage(err), "SYS");
+      if (status) render(status);
+    });
+  });
+  invoke<HttpApiStatus>("get_http_api_config")
+    .then(render)
+    .catch((err) => logLine(errorMessage(err), "SYS"));
+
+  streamToggleEl = document.querySelector<HTMLInputElement>("#settingsEventStreamToggle");
+  streamInfoEl = document.querySelector<HTMLElement>("#settingsEventStreamInfo");
+  streamToggleEl?.addEventListener("change", () => {
+    setStreamEnabled(!!streamToggleEl?.checked).catch((err) => {
+      logLine(errorMessage(err), "SYS");
+      if (streamStatus) renderStream(streamStatus);
+    });
+  });
+  invoke<EventStreamStatus>("get_event_stream_config")
+    .then(renderStream)
+    .catch((err) => logLine(errorMessage(err), "SYS"));
+}
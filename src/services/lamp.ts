@@ -1,4 +1,5 @@
 import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
 import { getSelectedSingleDeviceAddress } from "../state/registry";
 import { getDeviceData, updateDeviceData } from "../state/telemetry";
 import { getSelectionAnchorDeviceData, updateSelectionAnchorDeviceData } from "../state/active";
@@ -6,6 +7,8 @@ import { getControlTargetAddresses } from "../state/multi-control";
 import { updateRangeFill } from "../components/range";
 import { bindSelect } from "../components/select";
 import { logLine } from "../utils/formatter";
+import { errorMessage } from "./errors";
+import { broadcastPt } from "./broadcast";
 
 let lampToggleEl: HTMLInputElement | null = null;
 let lampBrightnessEl: HTMLInputElement | null = null;
@@ -13,19 +16,20 @@ let lampHueEl: HTMLInputElement | null = null;
 let lampSettingsEl: HTMLElement | null = null;
 let lampHueContainerEl: HTMLElement | null = null;
 let lampTypeSelect: ReturnType<typeof bindSelect> | null = null;
+let lampEffectSelect: ReturnType<typeof bindSelect> | null = null;
 const LAMP_BRIGHTNESS_SEND_BUCKET_COUNT = 30;
 const LAMP_HUE_SEND_BUCKET_COUNT = 24;
 const lastLampBrightnessBucketByAddress = new Map<string, number>();
 const lastLampColorBucketByAddress = new Map<string, number>();
 
-async function runBroadcast(tasks: Promise<unknown>[]) {
-  const results = await Promise.allSettled(tasks);
-  results.forEach((result) => {
-    if (result.status === "rejected") {
-      logLine(String(result.reason), "SYS");
-    }
-  });
-}
+type LampEffectStatus = {
+  id: number;
+  spec: { kind: string };
+  addresses: string[];
+  started_at: number;
+};
+
+let runningEffects: LampEffectStatus[] = [];
 
 function toLampBrightnessBucket(value: number) {
   const clamped = Math.max(0, Math.min(100, value));
@@ -47,6 +51,42 @@ function fromHueBucket(bucket: number) {
   return (clampedBucket / LAMP_HUE_SEND_BUCKET_COUNT) * 360;
 }
 
+// The effect driving any of the lamps currently under control, if one is running.
+function findTargetEffect() {
+  const targets = getControlTargetAddresses().map((address) => address.toLowerCase());
+  return runningEffects.find((effect) =>
+    effect.addresses.some((address) => targets.includes(address.toLowerCase()))
+  );
+}
+
+function syncLampEffectSelect() {
+  lampEffectSelect?.setValue(findTargetEffect()?.spec.kind ?? "none");
+}
+
+async function startLampEffect(kind: string) {
+  const addresses = getControlTargetAddresses();
+  if (addresses.length === 0) return;
+  const data = getSelectionAnchorDeviceData();
+  const [r, g, b] = sliderToRgb(data.lampHue);
+  const brightness = Math.round(
+    data.lampBrightness !== null && data.lampBrightness > 0 ? data.lampBrightness : data.lampLastNonZero
+  );
+  await invoke("start_lamp_effect", {
+    target: addresses,
+    spec: { kind, color: { r, g, b }, brightness },
+  });
+  // The effect moves the lamps away from whatever the sliders last sent.
+  lastLampBrightnessBucketByAddress.clear();
+  lastLampColorBucketByAddress.clear();
+}
+
+// Manual lamp control takes over from a running effect.
+async function stopTargetEffect() {
+  const effect = findTargetEffect();
+  if (!effect) return;
+  await invoke("stop_lamp_effect", { id: effect.id });
+}
+
 export function initLamp() {
   lampToggleEl = document.querySelector<HTMLInputElement>("#lampToggle");
   lampBrightnessEl = document.querySelector<HTMLInputElement>("#lampBrightness");
@@ -65,12 +105,31 @@ export function initLamp() {
           await setLampColor(data.lampHue, { force: true });
         }
       } catch (err) {
-        logLine(String(err), "SYS");
+        logLine(errorMessage(err), "SYS");
       }
     })();
     updateLampUI();
   });
 
+  lampEffectSelect = bindSelect("lampEffect", (value) => {
+    const task = value === "none" ? stopTargetEffect() : startLampEffect(value);
+    task.catch((err) => {
+      logLine(errorMessage(err), "SYS");
+      syncLampEffectSelect();
+    });
+  });
+
+  listen<LampEffectStatus[]>("lamp_effects_changed", (event) => {
+    runningEffects = event.payload;
+    syncLampEffectSelect();
+  });
+  invoke<LampEffectStatus[]>("get_lamp_effects_state")
+    .then((effects) => {
+      runningEffects = effects;
+      syncLampEffectSelect();
+    })
+    .catch((err) => logLine(errorMessage(err), "SYS"));
+
   lampToggleEl?.addEventListener("change", () => {
     if (!lampToggleEl) return;
     const currentData = getSelectionAnchorDeviceData();
@@ -86,9 +145,9 @@ export function initLamp() {
     updateLampUI();
     if (!updated || getControlTargetAddresses().length === 0) return;
     if (updated.lampOn) {
-      runLamp(nextValue, updated.lampType, updated.lampHue).catch((err) => logLine(String(err), "SYS"));
+      runLamp(nextValue, updated.lampType, updated.lampHue).catch((err) => logLine(errorMessage(err), "SYS"));
     } else {
-      stopLamp().catch((err) => logLine(String(err), "SYS"));
+      stopLamp().catch((err) => logLine(errorMessage(err), "SYS"));
     }
   });
 
@@ -101,7 +160,7 @@ export function initLamp() {
     });
     updateLampUI();
     if (updated && updated.lampOn && getControlTargetAddresses().length > 0) {
-      setLampBrightness(value).catch((err) => logLine(String(err), "SYS"));
+      setLampBrightness(value).catch((err) => logLine(errorMessage(err), "SYS"));
     }
   });
 
@@ -112,7 +171,7 @@ export function initLamp() {
     updateRangeFill(lampHueEl);
     if (!updated || !updated.lampOn || getControlTargetAddresses().length === 0) return;
     if (updated.lampType === 1) {
-      setLampColor(updated.lampHue).catch((err) => logLine(String(err), "SYS"));
+      setLampColor(updated.lampHue).catch((err) => logLine(errorMessage(err), "SYS"));
     }
   });
 }
@@ -124,6 +183,7 @@ export function updateLampUI() {
   updateRangeFill(lampBrightnessEl);
   lampToggleEl.checked = data.lampOn;
   lampTypeSelect?.setValue(data.lampType);
+  syncLampEffectSelect();
   lampHueEl.value = String(data.lampHue);
   updateRangeFill(lampHueEl);
   if (lampSettingsEl) {
@@ -181,76 +241,53 @@ export async function requestLampState() {
     await invoke("send_gaia_command", { address, vendorId: 0x5054, commandId: 0x0411, payload: [] });
     logLine("Lamp request (5054 0411)", "OUT");
   } catch (err) {
-    logLine(String(err), "SYS");
+    logLine(errorMessage(err), "SYS");
   }
 }
 
 async function setLampBrightness(value: number) {
   const addresses = getControlTargetAddresses();
   if (addresses.length === 0) return;
+  await stopTargetEffect();
   const bucket = toLampBrightnessBucket(value);
   const quantized = fromLampBrightnessBucket(bucket);
   const rounded = Math.round(quantized);
-  const tasks = addresses.map(async (address) => {
-    const normalizedAddress = address.toLowerCase();
-    if (lastLampBrightnessBucketByAddress.get(normalizedAddress) === bucket) {
-      return;
-    }
-    await invoke("send_gaia_command", { address, vendorId: 0x5054, commandId: 0x0202, payload: [rounded] });
-    lastLampBrightnessBucketByAddress.set(normalizedAddress, bucket);
-  });
-  await runBroadcast(tasks);
+  const pending = addresses.filter(
+    (address) => lastLampBrightnessBucketByAddress.get(address.toLowerCase()) !== bucket
+  );
+  const acked = await broadcastPt(pending, 0x0202, [rounded]);
+  acked.forEach((address) => lastLampBrightnessBucketByAddress.set(address.toLowerCase(), bucket));
 }
 
 async function setLampType(value: number) {
-  const addresses = getControlTargetAddresses();
-  if (addresses.length === 0) return;
-  const tasks = addresses.map((address) =>
-    invoke("send_gaia_command", { address, vendorId: 0x5054, commandId: 0x0203, payload: [value] })
-  );
-  await runBroadcast(tasks);
+  await stopTargetEffect();
+  await broadcastPt(getControlTargetAddresses(), 0x0203, [value]);
 }
 
 async function setLampColor(hue: number, options?: { force?: boolean }) {
   const addresses = getControlTargetAddresses();
   if (addresses.length === 0) return;
+  await stopTargetEffect();
   const bucket = toHueBucket(hue);
   const quantizedHue = fromHueBucket(bucket);
   const [r, g, b] = sliderToRgb(quantizedHue);
-  const tasks = addresses.map(async (address) => {
-    const normalizedAddress = address.toLowerCase();
-    if (!options?.force && lastLampColorBucketByAddress.get(normalizedAddress) === bucket) {
-      return;
-    }
-    await invoke("send_gaia_command", { address, vendorId: 0x5054, commandId: 0x0204, payload: [r, g, b] });
-    lastLampColorBucketByAddress.set(normalizedAddress, bucket);
-  });
-  await runBroadcast(tasks);
+  const pending = addresses.filter(
+    (address) => options?.force || lastLampColorBucketByAddress.get(address.toLowerCase()) !== bucket
+  );
+  const acked = await broadcastPt(pending, 0x0204, [r, g, b]);
+  acked.forEach((address) => lastLampColorBucketByAddress.set(address.toLowerCase(), bucket));
 }
 
 async function runLamp(mood: number, type: number, hue: number) {
-  const addresses = getControlTargetAddresses();
-  if (addresses.length === 0) return;
+  await stopTargetEffect();
   const [r, g, b] = sliderToRgb(hue);
   const rounded = Math.round(mood);
-  const tasks = addresses.map((address) =>
-    invoke("send_gaia_command", {
-      address,
-      vendorId: 0x5054,
-      commandId: 0x0212,
-      payload: [rounded, type, r, g, b],
-    })
-  );
-  await runBroadcast(tasks);
+  await broadcastPt(getControlTargetAddresses(), 0x0212, [rounded, type, r, g, b]);
 }
 
 async function stopLamp() {
-  const addresses = getControlTargetAddresses();
-  if (addresses.length === 0) return;
-  const tasks = addresses.map((address) =>
-    invoke("send_gaia_command", { address, vendorId: 0x5054, commandId: 0x0213, payload: [] })
-  );
-  await runBroadcast(tasks);
+  await stopTargetEffect();
+  await broadcastPt(getControlTargetAddresses(), 0x0213, []);
 }
 
 export function handleLampStatePacket(connectedAddress: string, dataPayload: number[]) {
@@ -0,0 +1,81 @@
+import { invoke } from "@tauri-apps/api/core";
+import { bindSelect } from "../components/select";
+import { setDeviceReconnectState } from "../state/connection";
+import { logLine } from "../utils/formatter";
+import { errorMessage, type StoneError } from "./errors";
+
+export type ReconnectMode = "always" | "while_visible" | "never";
+
+export type SupervisorStateEvent = {
+  address: string;
+  phase: "idle" | "waiting" | "reconnecting" | "paused";
+  round: number;
+  next_attempt_at?: number | null;
+  last_error?: StoneError | null;
+};
+
+const RECONNECT_MODE_KEY = "stone.reconnect_mode_v1";
+let reconnectModeSelect: ReturnType<typeof bindSelect> | null = null;
+let reconnectMode = loadReconnectModePreference();
+
+function normalizeReconnectMode(value: string | number | null | undefined): ReconnectMode {
+  const raw = String(value ?? "");
+  if (raw === "always" || raw === "while_visible" || raw === "never") return raw;
+  return "always";
+}
+
+function loadReconnectModePreference() {
+  try {
+    return normalizeReconnectMode(window.localStorage.getItem(RECONNECT_MODE_KEY));
+  } catch {
+    return "always";
+  }
+}
+
+function saveReconnectModePreference(next: ReconnectMode) {
+  try {
+    window.localStorage.setItem(RECONNECT_MODE_KEY, next);
+  } catch {
+  }
+}
+
+function pushReconnectMode(mode: ReconnectMode) {
+  invoke("set_reconnect_policy", { policy: { mode } }).catch((err) =>
+    logLine(errorMessage(err), "SYS")
+  );
+}
+
+function syncAppVisible() {
+  void invoke("set_app_visible", { visible: document.visibilityState === "visible" });
+}
+
+export function handleSupervisorState(event: SupervisorStateEvent) {
+  if (event.phase === "idle") {
+    setDeviceReconnectState(event.address, null);
+    return;
+  }
+  setDeviceReconnectState(event.address, {
+    phase: event.phase,
+    round: event.round,
+    nextAttemptAt: event.next_attempt_at ?? null,
+  });
+}
+
+export function initReconnect() {
+  reconnectModeSelect = bindSelect("settingsReconnectMode", (value) => {
+    const next = normalizeReconnectMode(value);
+    reconnectMode = next;
+    saveReconnectModePreference(next);
+    reconnectModeSelect?.setValue(next, false);
+    pushReconnectMode(next);
+  });
+  reconnectModeSelect?.setValue(reconnectMode, false);
+  pushReconnectMode(reconnectMode);
+
+  syncAppVisible();
+  document.addEventListener("visibilitychange", syncAppVisible);
+
+  invoke<SupervisorStateEvent[]>("get_supervisor_states")
+    .then((states) => (states ?? []).forEach(handleSupervisorState))
+    .catch((err) => logLine(errorMessage(err), "SYS"));
+}
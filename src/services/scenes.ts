@@ -0,0 +1,95 @@
+import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+import { bindSelect } from "../components/select";
+import { getControlTargetAddresses } from "../state/multi-control";
+import { logLine } from "../utils/formatter";
+import { errorMessage, type StoneError } from "./errors";
+
+export type Scene = {
+  name: string;
+  devices: { address: string; volume: number }[];
+  saved_at: number;
+};
+
+export type SceneReport = {
+  name: string;
+  verified: number;
+  failed: number;
+  results: { address: string; verified: boolean; attempts: number; error: StoneError | null }[];
+};
+
+let sceneSelect: ReturnType<typeof bindSelect> | null = null;
+let sceneNameEl: HTMLInputElement | null = null;
+let scenes: Scene[] = [];
+let selectedScene = "";
+
+function escapeHtml(value: string) {
+  return value
+    .replace(/&/g, "&amp;")
+    .replace(/</g, "&lt;")
+    .replace(/>/g, "&gt;")
+    .replace(/"/g, "&quot;");
+}
+
+// Options are keyed by position so scene names never end up in markup attributes.
+function renderScenes(next: Scene[]) {
+  scenes = next;
+  let index = scenes.findIndex((scene) => scene.name === selectedScene);
+  if (index < 0) {
+    index = 0;
+    selectedScene = scenes[0]?.name ?? "";
+  }
+  const options = scenes.length > 0
+    ? scenes.map((scene, i) => ({
+        value: i,
+        label: `${escapeHtml(scene.name)} (${scene.devices.length}대)`,
+      }))
+    : [{ value: "none", label: "저장된 장면 없음" }];
+  sceneSelect?.setOptions(options, scenes.length > 0 ? index : "none");
+}
+
+async function saveScene() {
+  const name = sceneNameEl?.value.trim() ?? "";
+  const addresses = getControlTargetAddresses();
+  if (!name || addresses.length === 0) return;
+  const scene = await invoke<Scene>("save_scene", { name, target: addresses });
+  selectedScene = scene.name;
+  renderScenes(scenes);
+  if (sceneNameEl) sceneNameEl.value = "";
+  logLine(`Scene saved: ${scene.name} (${scene.devices.length} devices)`, "SYS");
+}
+
+async function applyScene() {
+  if (!selectedScene) return;
+  const report = await invoke<SceneReport>("apply_scene", { name: selectedScene });
+  report.results.forEach((result) => {
+    if (!result.verified) {
+      logLine(`${result.address}: ${result.error?.message ?? "State did not match the scene"}`, "SYS");
+    }
+  });
+  logLine(`Scene applied: ${report.name} (${report.verified}/${report.results.length} verified)`, "SYS");
+}
+
+async function deleteScene() {
+  if (!selectedScene) return;
+  await invoke("delete_scene", { name: selectedScene });
+}
+
+export function initScenes() {
+  sceneNameEl = document.querySelector<HTMLInputElement>("#sceneName");
+  sceneSelect = bindSelect("sceneSelect", (value) => {
+    selectedScene = scenes[Number(value)]?.name ?? "";
+  });
+
+  const run = (task: () => Promise<void>) => () => {
+    task().catch((err) => logLine(errorMessage(err), "SYS"));
+  };
+  document.querySelector("#sceneSave")?.addEventListener("click", run(saveScene));
+  document.querySelector("#sceneApply")?.addEventListener("click", run(applyScene));
+  document.querySelector("#sceneDelete")?.addEventListener("click", run(deleteScene));
+
+  listen<Scene[]>("scenes_changed", (event) => renderScenes(event.payload));
+  invoke<Scene[]>("list_scenes")
+    .then(renderScenes)
+    .catch((err) => logLine(errorMessage(err), "SYS"));
+}
@@ -0,0 +1,67 @@
+import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+import { bindSelect } from "../components/select";
+import { getControlTargetAddresses } from "../state/multi-control";
+import { logLine } from "../utils/formatter";
+import { errorMessage } from "./errors";
+
+export type SleepTimerStatus = {
+  addresses: string[];
+  started_at: number;
+  ends_at: number;
+  remaining_ms: number;
+};
+
+let sleepTimerSelect: ReturnType<typeof bindSelect> | null = null;
+let remainingEl: HTMLElement | null = null;
+let sleepTimer: SleepTimerStatus | null = null;
+let remainingTimer: ReturnType<typeof setInterval> | null = null;
+
+function renderRemaining() {
+  if (!remainingEl) return;
+  if (!sleepTimer) {
+    remainingEl.textContent = "--";
+    return;
+  }
+  const minutes = Math.max(0, Math.ceil((sleepTimer.ends_at - Date.now()) / 60000));
+  remainingEl.textContent = `${minutes}분 남음`;
+}
+
+function applyStatus(status: SleepTimerStatus | null) {
+  sleepTimer = status;
+  if (!status) sleepTimerSelect?.setValue("off");
+  if (remainingTimer) clearInterval(remainingTimer);
+  remainingTimer = status ? setInterval(renderRemaining, 15000) : null;
+  renderRemaining();
+}
+
+async function setSleepTimer(value: string) {
+  if (value === "off") {
+    await invoke("cancel_sleep_timer");
+    return;
+  }
+  const addresses = getControlTargetAddresses();
+  if (addresses.length === 0) {
+    sleepTimerSelect?.setValue("off");
+    return;
+  }
+  const status = await invoke<SleepTimerStatus>("start_sleep_timer", {
+    target: addresses,
+    durationMs: Number(value) * 60000,
+  });
+  logLine(`Sleep timer: ${value} min (${status.addresses.length} devices)`, "SYS");
+}
+
+export function initSleepTimer() {
+  remainingEl = document.querySelector<HTMLElement>("#sleepTimerRemaining");
+  sleepTimerSelect = bindSelect("sleepTimer", (value) => {
+    setSleepTimer(String(value)).catch((err) => {
+      logLine(errorMessage(err), "SYS");
+      if (!sleepTimer) sleepTimerSelect?.setValue("off");
+    });
+  });
+  listen<SleepTimerStatus | null>("sleep_timer_changed", (event) => applyStatus(event.payload));
+  invoke<SleepTimerStatus | null>("get_sleep_timer_status")
+    .then(applyStatus)
+    .catch((err) => logLine(errorMessage(err), "SYS"));
+}
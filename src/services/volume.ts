@@ -5,20 +5,13 @@ import { getSelectionAnchorDeviceData, updateSelectionAnchorDeviceData } from ".
 import { getControlTargetAddresses } from "../state/multi-control";
 import { updateRangeFill } from "../components/range";
 import { logLine } from "../utils/formatter";
+import { errorMessage } from "./errors";
+import { broadcastPt } from "./broadcast";
 
 let volumeSliderEl: HTMLInputElement | null = null;
 const VOLUME_SEND_BUCKET_COUNT = 30;
 const lastVolumeBucketByAddress = new Map<string, number>();
 
-async function runBroadcast(tasks: Promise<unknown>[]) {
-  const results = await Promise.allSettled(tasks);
-  results.forEach((result) => {
-    if (result.status === "rejected") {
-      logLine(String(result.reason), "SYS");
-    }
-  });
-}
-
 function toVolumeBucket(value: number) {
   const clamped = Math.max(0, Math.min(30, value));
   return Math.round((clamped / 30) * VOLUME_SEND_BUCKET_COUNT);
@@ -56,7 +49,7 @@ export async function requestVolume() {
     await invoke("send_gaia_command", { address, vendorId: 0x5054, commandId: 0x0401, payload: [] });
     logLine("Volume request (5054 0401)", "OUT");
   } catch (err) {
-    logLine(String(err), "SYS");
+    logLine(errorMessage(err), "SYS");
   }
 }
 
@@ -67,17 +60,13 @@ async function setVolume(value: number) {
     const bucket = toVolumeBucket(value);
     const quantized = fromVolumeBucket(bucket);
     const rounded = Math.round(quantized);
-    const sendTasks = addresses.map(async (address) => {
-      const normalizedAddress = address.toLowerCase();
-      if (lastVolumeBucketByAddress.get(normalizedAddress) === bucket) {
-        return;
-      }
-      await invoke("send_gaia_command", { address, vendorId: 0x5054, commandId: 0x0201, payload: [rounded] });
-      lastVolumeBucketByAddress.set(normalizedAddress, bucket);
-    });
-    await runBroadcast(sendTasks);
+    const pending = addresses.filter(
+      (address) => lastVolumeBucketByAddress.get(address.toLowerCase()) !== bucket
+    );
+    const acked = await broadcastPt(pending, 0x0201, [rounded]);
+    acked.forEach((address) => lastVolumeBucketByAddress.set(address.toLowerCase(), bucket));
   } catch (err) {
-    logLine(String(err), "SYS");
+    logLine(errorMessage(err), "SYS");
   }
 }
 
@@ -1,4 +1,5 @@
 import {
+  getRegisteredDeviceLabel,
   getRegisteredDevices,
   getSelectedSingleDeviceAddress,
 } from "./registry";
@@ -43,5 +44,5 @@ export function getSelectedDeviceLabel() {
   const registered = getRegisteredDevices().find(
     (device) => device.address === address
   );
-  return registered?.name ?? address;
+  return registered ? getRegisteredDeviceLabel(registered) : address;
 }
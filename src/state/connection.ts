@@ -1,10 +1,25 @@
 export type ConnectionState = "idle" | "connecting" | "connected" | "disconnecting";
 
+export type ConnectAttempt = {
+  attempt: number;
+  maxAttempts: number;
+  nextRetryAt: number | null;
+};
+
+export type ReconnectState = {
+  phase: "waiting" | "reconnecting" | "paused";
+  round: number;
+  nextAttemptAt: number | null;
+};
+
 export type DeviceConnectionState = {
   state: ConnectionState;
   link: boolean;
   rfcomm: boolean;
+  degraded: boolean;
   lastError: string | null;
+  attempt: ConnectAttempt | null;
+  reconnect: ReconnectState | null;
   updatedAt: number;
 };
 
@@ -26,7 +41,10 @@ function defaultState(): DeviceConnectionState {
     state: "idle",
     link: false,
     rfcomm: false,
+    degraded: false,
     lastError: null,
+    attempt: null,
+    reconnect: null,
     updatedAt: now(),
   };
 }
@@ -68,7 +86,9 @@ export function setDeviceConnected(address: string) {
     state: "connected",
     link: true,
     rfcomm: true,
+    degraded: false,
     lastError: null,
+    attempt: null,
   });
 }
 
@@ -80,10 +100,24 @@ export function setDeviceDisconnected(
     state: "idle",
     link: options?.link ?? false,
     rfcomm: options?.rfcomm ?? false,
+    degraded: false,
     lastError: options?.lastError ?? null,
+    attempt: null,
   });
 }
 
+export function setDeviceConnectAttempt(address: string, attempt: ConnectAttempt) {
+  return upsert(address, { state: "connecting", attempt });
+}
+
+export function setDeviceDegraded(address: string, degraded: boolean) {
+  return upsert(address, { degraded });
+}
+
+export function setDeviceReconnectState(address: string, reconnect: ReconnectState | null) {
+  return upsert(address, { reconnect });
+}
+
 export function setDeviceLinkState(address: string, connected: boolean) {
   const current = getDeviceConnection(address) ?? defaultState();
   if (!connected) {
@@ -118,7 +152,10 @@ export function replaceConnectionInfos(
           : "idle",
       link: info.link,
       rfcomm: info.rfcomm,
+      degraded: info.rfcomm ? current.degraded : false,
       lastError: info.rfcomm ? null : current.lastError,
+      attempt: info.rfcomm ? null : current.attempt,
+      reconnect: current.reconnect,
       updatedAt: now(),
     };
   }
@@ -1,8 +1,17 @@
+import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+import { errorMessage } from "../services/errors";
+import { logLine } from "../utils/formatter";
+
 export type RegisteredDevice = {
   address: string;
   name: string;
+  alias?: string;
+  icon?: string;
+  groups?: string[];
 };
 
+
 export const MULTI_CONTROL_SELECT_VALUE = "__multi_control__";
 
 export type SelectedTarget =
@@ -10,29 +19,33 @@ export type SelectedTarget =
   | { kind: "multi" }
   | null;
 
-const STORAGE_KEY = "stone_paired_devices";
+// Where the webview kept the list before the backend owned it; read once to migrate.
+const LEGACY_STORAGE_KEY = "stone_paired_devices";
 
-let devices: RegisteredDevice[] = loadDevices();
-let selectedTarget: SelectedTarget = devices[0]?.address
-  ? { kind: "single", address: devices[0].address }
-  : null;
+let devices: RegisteredDevice[] = [];
+let selectedTarget: SelectedTarget = null;
 const listeners = new Set<(list: RegisteredDevice[]) => void>();
 const selectedListeners = new Set<(target: SelectedTarget) => void>();
 
-function loadDevices(): RegisteredDevice[] {
+function loadLegacyDevices(): RegisteredDevice[] {
   try {
-    const raw = localStorage.getItem(STORAGE_KEY);
+    const raw = localStorage.getItem(LEGACY_STORAGE_KEY);
     if (!raw) return [];
     const parsed = JSON.parse(raw);
     if (!Array.isArray(parsed)) return [];
-    return parsed.filter((item) => item && typeof item.address === "string");
+    return parsed
+      .filter((item) => item && typeof item.address === "string")
+      .map((item) => ({
+        address: item.address,
+        name: typeof item.name === "string" ? item.name : item.address,
+      }));
   } catch {
     return [];
   }
 }
 
-function persistDevices(next: RegisteredDevice[]) {
-  localStorage.setItem(STORAGE_KEY, JSON.stringify(next));
+function reportRegistryError(err: unknown) {
+  logLine(`Registry: ${errorMessage(err)}`, "SYS");
 }
 
 function normalizeAddress(address: string | null | undefined) {
@@ -81,6 +94,36 @@ function notifySelected() {
   selectedListeners.forEach((listener) => listener(selectedTarget));
 }
 
+function applyDevices(next: RegisteredDevice[]) {
+  devices = next;
+  const fallbackAddress = getDefaultRegisteredDeviceAddress();
+  const nextSelected = coerceSelectedTarget(
+    selectedTarget ?? (fallbackAddress ? { kind: "single", address: fallbackAddress } : null)
+  );
+  const selectedChanged = !sameSelectedTarget(selectedTarget, nextSelected);
+  selectedTarget = nextSelected;
+  notify();
+  if (selectedChanged) notifySelected();
+}
+
+// Loads the backend registry, migrating the old localStorage list on first run.
+export async function loadRegistry() {
+  try {
+    const legacy = loadLegacyDevices();
+    let next = await invoke<RegisteredDevice[]>("get_registered_devices");
+    if (legacy.length > 0) {
+      next = await invoke<RegisteredDevice[]>("import_registered_devices", { devices: legacy });
+      localStorage.removeItem(LEGACY_STORAGE_KEY);
+    }
+    applyDevices(next ?? []);
+  } catch (err) {
+    reportRegistryError(err);
+  }
+  await listen<RegisteredDevice[]>("registry_changed", (event) => {
+    applyDevices(event.payload ?? []);
+  });
+}
+
 export function getRegisteredDevices() {
   return devices;
 }
@@ -98,7 +141,7 @@ export function subscribeSelectedTarget(listener: (target: SelectedTarget) => vo
 export function upsertRegisteredDevice(address: string, name: string) {
   const existing = devices.findIndex((d) => normalizeAddress(d.address) === normalizeAddress(address));
   if (existing >= 0) {
-    devices[existing] = { address: devices[existing].address, name };
+    devices[existing] = { ...devices[existing], name };
   } else {
     devices = [{ address, name }, ...devices];
   }
@@ -110,9 +153,9 @@ export function upsertRegisteredDevice(address: string, name: string) {
   const selectedChanged = !sameSelectedTarget(selectedTarget, nextSelected);
   selectedTarget = nextSelected;
 
-  persistDevices(devices);
   notify();
   if (selectedChanged) notifySelected();
+  invoke("register_device", { address, name }).catch(reportRegistryError);
 }
 
 export function removeRegisteredDevice(address: string) {
@@ -123,9 +166,33 @@ export function removeRegisteredDevice(address: string) {
   const selectedChanged = !sameSelectedTarget(selectedTarget, nextSelected);
   selectedTarget = nextSelected;
 
-  persistDevices(devices);
   notify();
   if (selectedChanged) notifySelected();
+  invoke("unregister_device", { address }).catch(reportRegistryError);
+}
+
+export function getRegisteredDeviceLabel(device: RegisteredDevice) {
+  return device.alias ?? device.name;
+}
+
+export function setRegisteredDeviceAlias(address: string, alias: string | null) {
+  invoke("set_device_alias", { address, alias }).catch(reportRegistryError);
+}
+
+export function setRegisteredDeviceIcon(address: string, icon: string | null) {
+  invoke("set_device_icon", { address, icon }).catch(reportRegistryError);
+}
+
+export function setRegisteredDeviceGroups(address: string, groups: string[]) {
+  invoke("set_device_groups", { address, groups }).catch(reportRegistryError);
+}
+
+export function renameRegisteredDevice(address: string, name: string) {
+  invoke("rename_device", { address, name }).catch(reportRegistryError);
+}
+
+export function reorderRegisteredDevices(addresses: string[]) {
+  invoke("reorder_devices", { addresses }).catch(reportRegistryError);
 }
 
 export function isSelectedTargetMulti() {